@@ -8,8 +8,8 @@ use bfprotocols::{
         objective::{ObjectiveId, ObjectiveKind},
     },
     perf::PerfInner,
-    shots::{Dead, Who},
-    stats::{DetectionSource, EnId, Pos, Stat},
+    shots::{Credit, Dead, Engagement, Who},
+    stats::{Debrief, DetectionSource, EnId, Pos, RoundEndReason, Stat, StatEntry},
 };
 use chrono::prelude::*;
 use dcso3::{
@@ -31,26 +31,88 @@ use smallvec::{smallvec, SmallVec};
 use std::{ops::Deref, path::Path, str::FromStr, sync::Arc, time::Duration};
 use tokio::task;
 use uuid::Uuid;
-use yats::Tree;
+use yats::{Tree, KV};
+
+mod acmi;
+mod leaderboard;
+mod query;
+mod replay;
+mod upgrade;
+
+pub(crate) use acmi::export_acmi;
+use leaderboard::LeaderboardCache;
+pub(crate) use leaderboard::{Board, LeaderboardQuery, Scope};
+pub(crate) use query::{Page, PilotQuery, SortieQuery};
+pub(crate) use replay::Replay;
 
 db_id!(KillId);
 db_id!(RoundId);
 db_id!(SortieId);
+db_id!(EngagementId);
+
+/// stats are ordered by the time they were recorded
+pub(crate) type SeqId = DateTime<Utc>;
+
+/// sessions are identified by their start time
+pub(crate) type SessionId = DateTime<Utc>;
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub(crate) struct Aggregates {
     pub(crate) air_kills: u32,
     pub(crate) ground_kills: u32,
+    pub(crate) assists: u32,
     pub(crate) captures: u32,
     pub(crate) repairs: u32,
     pub(crate) supply_transfers: u32,
     pub(crate) troops: u32,
+    pub(crate) cargo_lost: u32,
+    pub(crate) rescues: u32,
     pub(crate) farps: u32,
     pub(crate) deploys: u32,
     pub(crate) actions: u32,
     pub(crate) deaths: u32,
     pub(crate) hours: f32,
     pub(crate) donated_points: u32,
+    pub(crate) points: u32,
+}
+
+impl Aggregates {
+    fn merge(&mut self, other: &Aggregates) {
+        let Aggregates {
+            air_kills,
+            ground_kills,
+            assists,
+            captures,
+            repairs,
+            supply_transfers,
+            troops,
+            cargo_lost,
+            rescues,
+            farps,
+            deploys,
+            actions,
+            deaths,
+            hours,
+            donated_points,
+            points,
+        } = other;
+        self.air_kills += air_kills;
+        self.ground_kills += ground_kills;
+        self.assists += assists;
+        self.captures += captures;
+        self.repairs += repairs;
+        self.supply_transfers += supply_transfers;
+        self.troops += troops;
+        self.cargo_lost += cargo_lost;
+        self.rescues += rescues;
+        self.farps += farps;
+        self.deploys += deploys;
+        self.actions += actions;
+        self.deaths += deaths;
+        self.hours += hours;
+        self.donated_points += donated_points;
+        self.points += points;
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -120,14 +182,32 @@ pub(crate) struct Objective {
     pub(crate) fuel: u8,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Ownership {
+    pub(crate) owner: Side,
+    pub(crate) by: SmallVec<[Ucid; 1]>,
+}
+
+/// how well a weapon does when fired from an airframe
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub(crate) struct WeaponEffect {
+    pub(crate) fired: u32,
+    pub(crate) hits: u32,
+    /// hits from weapons that were never seen being fired, e.g. guns
+    pub(crate) untracked_hits: u32,
+}
+
 #[derive(Clone)]
 struct Pilots {
     db: Db,
     pilots: Tree<Ucid, Pilot>,
     aggregates: Tree<(Ucid, Vehicle, RoundId), Aggregates>,
+    round_aggregates: Tree<(RoundId, Ucid), Aggregates>,
+    session_aggregates: Tree<(RoundId, SessionId, Ucid), Aggregates>,
     by_name: Tree<String, ArrayVec<Ucid, 8>>,
     by_token: Tree<Uuid, Ucid>,
     sortie: Tree<(Ucid, RoundId, SortieId), Sortie>,
+    debrief: Tree<(Ucid, RoundId, DateTime<Utc>), Debrief>,
     round_info: Tree<(Ucid, RoundId), PilotRoundInfo>,
 }
 
@@ -137,9 +217,12 @@ impl Pilots {
             db: db.clone(),
             pilots: Tree::open(db, "pilots")?,
             aggregates: Tree::open(db, "aggregates")?,
+            round_aggregates: Tree::open(db, "round_aggregates")?,
+            session_aggregates: Tree::open(db, "session_aggregates")?,
             by_name: Tree::open(db, "by_name")?,
             by_token: Tree::open(db, "by_token")?,
             sortie: Tree::open(db, "sortie")?,
+            debrief: Tree::open(db, "debrief")?,
             round_info: Tree::open(db, "pilot_round_info")?,
         })
     }
@@ -157,35 +240,64 @@ impl Pilots {
         Ok(())
     }
 
-    fn with_aggregates<F: FnMut(&mut Aggregates)>(
-        &self,
-        k: (Ucid, Vehicle, RoundId),
-        mut f: F,
-    ) -> Result<()> {
-        self.aggregates
-            .fetch_and_update(&k, |a| match a {
-                None => None,
-                Some(mut a) => {
-                    f(&mut a);
-                    Some(a)
-                }
-            })?
-            .ok_or_else(|| anyhow!("aggregates {k:?} is missing"))?;
+    fn with_aggregates<K, F>(tree: &Tree<K, Aggregates>, k: &K, mut f: F) -> Result<()>
+    where
+        K: KV,
+        F: FnMut(&mut Aggregates),
+    {
+        tree.update_and_fetch(k, |a| {
+            let mut a = a.unwrap_or_default();
+            f(&mut a);
+            Some(a)
+        })?;
         Ok(())
     }
 
-    fn with_pilot_and_aggregates<F, G>(&self, ucid: Ucid, round: RoundId, f: F, g: G) -> Result<()>
+    /// update the pilot, and their aggregates for the current
+    /// vehicle, round, and session
+    fn with_pilot_and_aggregates<F, G>(
+        &self,
+        ucid: Ucid,
+        ctx: &StatCtxInner,
+        f: F,
+        mut g: G,
+    ) -> Result<()>
     where
         F: FnMut(&mut Pilot),
         G: FnMut(&mut Aggregates),
     {
+        let round = ctx.round;
         let vehicle = self
             .round_info
             .get(&(ucid, round))?
             .and_then(|ri| ri.slot.and_then(|s| s.vehicle));
         self.with_pilot(ucid, f)?;
         if let Some(vehicle) = vehicle {
-            self.with_aggregates((ucid, vehicle, round), g)?
+            Self::with_aggregates(&self.aggregates, &(ucid, vehicle, round), &mut g)?
+        }
+        Self::with_aggregates(&self.round_aggregates, &(round, ucid), &mut g)?;
+        if let Some(session) = ctx.session {
+            Self::with_aggregates(&self.session_aggregates, &(round, session, ucid), &mut g)?
+        }
+        Ok(())
+    }
+
+    /// credit the pilot with the time they spent on a sortie
+    fn flight_time(
+        &self,
+        ucid: Ucid,
+        ctx: &StatCtxInner,
+        sid: SortieId,
+        end: DateTime<Utc>,
+    ) -> Result<()> {
+        if let Some(sortie) = self.sortie.get(&(ucid, ctx.round, sid))? {
+            let hours = (end - sortie.takeoff).num_seconds().max(0) as f32 / 3600.;
+            self.with_pilot_and_aggregates(
+                ucid,
+                ctx,
+                |p| p.total.hours += hours,
+                |a| a.hours += hours,
+            )?
         }
         Ok(())
     }
@@ -304,7 +416,8 @@ pub(crate) struct Group {
 struct StatCtxInner {
     sortie: Scenario,
     round: RoundId,
-    seq: DateTime<Utc>
+    session: Option<SessionId>,
+    seq: SeqId,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -324,27 +437,47 @@ impl StatCtx {
             None => bail!("expected to see NewSession before stats"),
         }
     }
+
+    /// wrap a live stat, the seq must always move forward even if
+    /// the clock doesn't
+    fn entry(&self, now: DateTime<Utc>, kind: Stat) -> StatEntry {
+        let seq = match &self.0 {
+            Some(inner) if inner.seq >= now => inner.seq + chrono::Duration::nanoseconds(1),
+            Some(_) | None => now,
+        };
+        StatEntry {
+            seq,
+            time: now,
+            kind,
+        }
+    }
 }
 
-#[derive(Clone)]
 pub(crate) struct StatsDbInner {
-    subscriber: Subscriber,
-    base: NetidxPath,
-    include: Option<Regex>,
-    exclude: Option<Regex>,
     db: Db,
     pilots: Pilots,
-    seq: Tree<(Scenario, RoundId), DateTime<Utc>>,
+    seq: Tree<(Scenario, RoundId), SeqId>,
     round: Tree<(Scenario, RoundId), Round>,
+    round_end_reason: Tree<(Scenario, RoundId), RoundEndReason>,
     session: Tree<(RoundId, DateTime<Utc>), Session>,
     kills: Tree<(EnId, RoundId, KillId), Dead>,
     shared_kills: Tree<KillId, SmallVec<[EnId; 2]>>,
+    engagements: Tree<(EnId, RoundId, EngagementId), Engagement>,
+    engagement_seq: Tree<(Scenario, SeqId), EngagementId>,
+    weapon_effect: Tree<(Vehicle, String), WeaponEffect>,
     units: Tree<(RoundId, EnId), Unit>,
     groups: Tree<(RoundId, GroupId), Group>,
     detected: Tree<(RoundId, EnId), BitFlags<DetectionSource>>,
     objectives: Tree<(RoundId, ObjectiveId), Objective>,
+    ownership: Tree<(RoundId, ObjectiveId, DateTime<Utc>), Ownership>,
     equipment: Tree<(RoundId, ObjectiveId, String), u32>,
     liquids: Tree<(RoundId, ObjectiveId, LiquidType), u32>,
+    tickets: Tree<(RoundId, Side), u32>,
+    production_equipment: Tree<(RoundId, Side, String), u32>,
+    production_liquids: Tree<(RoundId, Side, LiquidType), u32>,
+    runway_craters: Tree<(RoundId, ObjectiveId, DateTime<Utc>, u32), Option<Ucid>>,
+    airfield_closures: Tree<(RoundId, ObjectiveId, DateTime<Utc>), Option<DateTime<Utc>>>,
+    leaderboards: LeaderboardCache,
 }
 
 pub(crate) struct StatsDb(Arc<StatsDbInner>);
@@ -377,68 +510,87 @@ fn txn_err(e: TransactionError<anyhow::Error>) -> anyhow::Error {
 }
 
 impl StatsDb {
-    pub(crate) fn new<P: AsRef<Path>>(
-        subscriber: Subscriber,
-        db: P,
-        base: NetidxPath,
-        include: Option<Regex>,
-        exclude: Option<Regex>,
-    ) -> Result<Self> {
+    /// open the database without subscribing to any stats
+    pub(crate) fn open<P: AsRef<Path>>(db: P) -> Result<Self> {
         let db = sled::open(db.as_ref())?;
-        let t = Self(Arc::new(StatsDbInner {
-            subscriber,
-            base,
-            include,
-            exclude,
+        upgrade::upgrade(&db)?;
+        Ok(Self(Arc::new(StatsDbInner {
             db: db.clone(),
             pilots: Pilots::new(&db)?,
             seq: Tree::open(&db, "seq")?,
             round: Tree::open(&db, "round")?,
+            round_end_reason: Tree::open(&db, "round_end_reason")?,
             session: Tree::open(&db, "session")?,
             kills: Tree::open(&db, "kills")?,
             shared_kills: Tree::open(&db, "shared_kills")?,
+            engagements: Tree::open(&db, "engagements")?,
+            engagement_seq: Tree::open(&db, "engagement_seq")?,
+            weapon_effect: Tree::open(&db, "weapon_effect")?,
             units: Tree::open(&db, "units")?,
             groups: Tree::open(&db, "groups")?,
             detected: Tree::open(&db, "detected")?,
             objectives: Tree::open(&db, "objectives")?,
+            ownership: Tree::open(&db, "ownership")?,
             equipment: Tree::open(&db, "equipment")?,
             liquids: Tree::open(&db, "liquids")?,
-        }));
+            tickets: Tree::open(&db, "tickets")?,
+            production_equipment: Tree::open(&db, "production_equipment")?,
+            production_liquids: Tree::open(&db, "production_liquids")?,
+            runway_craters: Tree::open(&db, "runway_craters")?,
+            airfield_closures: Tree::open(&db, "airfield_closures")?,
+            leaderboards: LeaderboardCache::default(),
+        })))
+    }
+
+    pub(crate) fn new<P: AsRef<Path>>(
+        subscriber: Subscriber,
+        db: P,
+        base: NetidxPath,
+        include: Option<Regex>,
+        exclude: Option<Regex>,
+    ) -> Result<Self> {
+        let t = Self::open(db)?;
         let _t = t.clone();
         task::spawn(async move {
-            if let Err(e) = _t.background_loop().await {
+            if let Err(e) = _t.background_loop(subscriber, base, include, exclude).await {
                 error!("background task failed {e:?}")
             }
         });
         Ok(t)
     }
 
-    async fn background_loop(self) -> Result<()> {
+    async fn background_loop(
+        self,
+        subscriber: Subscriber,
+        base: NetidxPath,
+        include: Option<Regex>,
+        exclude: Option<Regex>,
+    ) -> Result<()> {
         use futures::{channel::mpsc, prelude::*, select_biased};
         use netidx::{
             resolver_client::ChangeTracker,
             subscriber::{Dval, Event, SubId, UpdatesFlags, Value},
         };
         use tokio::time;
-        let resolver = self.subscriber.resolver();
+        let resolver = subscriber.resolver();
         let mut timer = time::interval(Duration::from_secs(1));
         let mut ctx: FxHashMap<SubId, (Dval, StatCtx)> = FxHashMap::default();
         let mut by_path: FxHashMap<NetidxPath, SubId> = FxHashMap::default();
-        let mut ct = ChangeTracker::new(self.base.clone());
+        let mut ct = ChangeTracker::new(base.clone());
         let (tx_res, mut rx_res) = mpsc::channel(10);
         loop {
             select_biased! {
                 _ = timer.tick().fuse() => match resolver.check_changed(&mut ct).await {
                     Err(e) => error!("failed to check changed {e:?}"),
                     Ok(false) => (),
-                    Ok(true) => for path in resolver.list(self.base.clone()).await?.drain(..) {
+                    Ok(true) => for path in resolver.list(base.clone()).await?.drain(..) {
                         if let Some(sortie) = NetidxPath::basename(&path) {
-                            if self.include.as_ref().map(|r| r.is_match(sortie)).unwrap_or(true)
-                                && !self.exclude.as_ref().map(|r| r.is_match(sortie)).unwrap_or(false)
+                            if include.as_ref().map(|r| r.is_match(sortie)).unwrap_or(true)
+                                && !exclude.as_ref().map(|r| r.is_match(sortie)).unwrap_or(false)
                             {
                                 let path = path.append("stats");
                                 if !by_path.contains_key(&path) {
-                                    let dv = self.subscriber.subscribe(path.clone());
+                                    let dv = subscriber.subscribe(path.clone());
                                     dv.updates(UpdatesFlags::empty(), tx_res.clone());
                                     let id = dv.id();
                                     ctx.insert(id, (dv, StatCtx::default()));
@@ -452,13 +604,14 @@ impl StatsDb {
                     for (id, ev) in ev.drain(..) {
                         if let Some((_dv, ctx)) = ctx.get_mut(&id) {
                             if let Event::Update(Value::String(v)) = ev {
-                                let st: Stat = match serde_json::from_str(&v) {
+                                let kind: Stat = match serde_json::from_str(&v) {
                                     Ok(s) => s,
                                     Err(e) => {
                                         error!("failed to parse stat {v} {e:?}");
                                         continue
                                     }
                                 };
+                                let st = ctx.entry(Utc::now(), kind);
                                 info!("adding stat {st:?}");
                                 if let Err(e) = task::block_in_place(|| self.add_stat(ctx, st)) {
                                     error!("failed to add stat {e:?}")
@@ -491,16 +644,43 @@ impl StatsDb {
         ctx.0 = Some(StatCtxInner {
             sortie,
             round: id,
+            session: None,
             seq: seqnum,
         });
         Ok(())
     }
 
+    /// the context of the latest round of the scenario if it hasn't ended
+    fn open_round(&self, sortie: &Scenario) -> Result<Option<StatCtxInner>> {
+        match self.seq.scan_prefix(sortie)?.next_back().transpose()? {
+            None => Ok(None),
+            Some(((_, round), seq)) => match self.round.get(&(sortie.clone(), round))? {
+                Some(r) if r.end.is_none() => {
+                    let session = self
+                        .session
+                        .scan_prefix(&round)?
+                        .keys()
+                        .next_back()
+                        .transpose()?
+                        .map(|(_, start)| start);
+                    Ok(Some(StatCtxInner {
+                        round,
+                        session,
+                        seq,
+                        sortie: sortie.clone(),
+                    }))
+                }
+                Some(_) | None => Ok(None),
+            },
+        }
+    }
+
     fn round_end(
         &self,
         ctx: &mut StatCtx,
         time: DateTime<Utc>,
         winner: Option<Side>,
+        reason: &RoundEndReason,
     ) -> Result<()> {
         let inner = ctx.get_mut()?;
         let key = (inner.sortie.clone(), inner.round);
@@ -511,6 +691,7 @@ impl StatsDb {
         round.end = Some(time);
         round.winner = winner;
         let _ = self.round.insert(&key, &round)?;
+        let _ = self.round_end_reason.insert(&key, reason)?;
         ctx.0 = None;
         Ok(())
     }
@@ -577,7 +758,7 @@ impl StatsDb {
             Who::Player { ucid, .. } => {
                 self.pilots.with_pilot_and_aggregates(
                     *ucid,
-                    ctx.round,
+                    ctx,
                     |p| p.total.deaths += 1,
                     |a| a.deaths += 1,
                 )?;
@@ -600,6 +781,26 @@ impl StatsDb {
                 a.ground_kills += 1
             }
         };
+        let assist = |a: &mut Aggregates| a.assists += 1;
+        // before credit was recorded everyone who hit got the kill
+        for (ucid, credit) in dead.credit.iter() {
+            match credit {
+                Credit::Kill => self.pilots.with_pilot_and_aggregates(
+                    *ucid,
+                    ctx,
+                    |p| up(&mut p.total),
+                    |a| up(a),
+                )?,
+                Credit::Damage | Credit::Support | Credit::Spotting => {
+                    self.pilots.with_pilot_and_aggregates(
+                        *ucid,
+                        ctx,
+                        |p| assist(&mut p.total),
+                        |a| assist(a),
+                    )?
+                }
+            }
+        }
         for shot in dead.shots.iter() {
             if no_hit && !shot.hit {
                 continue;
@@ -612,12 +813,14 @@ impl StatsDb {
                 | Who::AI {
                     ucid: Some(ucid), ..
                 } => {
-                    self.pilots.with_pilot_and_aggregates(
-                        *ucid,
-                        ctx.round,
-                        |p| up(&mut p.total),
-                        |a| up(a),
-                    )?;
+                    if dead.credit.is_empty() {
+                        self.pilots.with_pilot_and_aggregates(
+                            *ucid,
+                            ctx,
+                            |p| up(&mut p.total),
+                            |a| up(a),
+                        )?;
+                    }
                     EnId::Player(*ucid)
                 }
             };
@@ -631,6 +834,43 @@ impl StatsDb {
         Ok(())
     }
 
+    fn record_engagement(&self, ctx: &StatCtxInner, seq: SeqId, en: Engagement) -> Result<()> {
+        // replaying a log that was already applied must not count the
+        // engagement again
+        let seq = (ctx.sortie.clone(), seq);
+        if self.engagement_seq.contains_key(&seq)? {
+            return Ok(());
+        }
+        let id = EngagementId::new(&self.db)?;
+        let enid = match &en.shooter {
+            Who::AI {
+                ucid: None, uid, ..
+            } => EnId::Unit(*uid),
+            Who::Player { ucid, .. }
+            | Who::AI {
+                ucid: Some(ucid), ..
+            } => EnId::Player(*ucid),
+        };
+        let tracked = en.time_of_flight.is_some();
+        let hit = en.hit;
+        let key = (Vehicle(en.shooter_typ.clone()), en.weapon_name.clone());
+        self.weapon_effect.update_and_fetch(&key, |we| {
+            let mut we = we.unwrap_or_default();
+            match (tracked, hit) {
+                (true, true) => {
+                    we.fired += 1;
+                    we.hits += 1
+                }
+                (true, false) => we.fired += 1,
+                (false, _) => we.untracked_hits += en.hits.max(1),
+            }
+            Some(we)
+        })?;
+        self.engagements.insert(&(enid, ctx.round, id), &en)?;
+        self.engagement_seq.insert(&seq, &id)?;
+        Ok(())
+    }
+
     pub(crate) fn pilots(&self) -> impl Iterator<Item = Result<(Ucid, String)>> {
         self.pilots.pilots.iter().map(|r| {
             let (ucid, pilot) = r?;
@@ -643,35 +883,27 @@ impl StatsDb {
         })
     }
 
-    fn add_stat(&self, ctx: &mut StatCtx, stat: Stat) -> Result<()> {
+    fn add_stat(&self, ctx: &mut StatCtx, stat: StatEntry) -> Result<()> {
         if let Some(ctx) = &ctx.0 {
             if stat.seq <= ctx.seq {
                 return Ok(());
             }
         }
+        self.leaderboards.invalidate();
         if let Stat::NewRound { sortie } = &stat.kind {
             if ctx.0.is_some() {
                 bail!("NewRound should only appear at the beginning of the stats or after RoundEnd")
             }
-            match self.seq.scan_prefix(sortie)?.next_back().transpose()? {
+            match self.open_round(sortie)? {
+                Some(inner) => {
+                    ctx.0 = Some(inner);
+                    return Ok(());
+                }
                 None => return self.new_round(ctx, stat.time, sortie.clone(), stat.seq),
-                Some(((_, round), seq)) => match self.round.get(&(sortie.clone(), round))? {
-                    Some(r) if r.end.is_none() => {
-                        ctx.0 = Some(StatCtxInner {
-                            round,
-                            seq,
-                            sortie: sortie.clone(),
-                        });
-                        return Ok(());
-                    }
-                    Some(_) | None => {
-                        return self.new_round(ctx, stat.time, sortie.clone(), stat.seq)
-                    }
-                },
             }
         }
-        if let Stat::RoundEnd { winner } = &stat.kind {
-            return self.round_end(ctx, stat.time, *winner);
+        if let Stat::RoundEnd { winner, reason } = &stat.kind {
+            return self.round_end(ctx, stat.time, *winner, reason);
         }
         let ctx = ctx.get_mut()?;
         match stat.kind {
@@ -685,6 +917,7 @@ impl StatsDb {
                         end: None,
                     },
                 )?;
+                ctx.session = Some(stat.time);
             }
             Stat::SessionEnd {
                 api_perf,
@@ -716,6 +949,13 @@ impl StatsDb {
                 owner,
                 kind,
             } => {
+                self.ownership.insert(
+                    &(ctx.round, id, stat.time),
+                    &Ownership {
+                        owner,
+                        by: smallvec![],
+                    },
+                )?;
                 self.objectives.insert(
                     &(ctx.round, id),
                     &Objective {
@@ -735,6 +975,19 @@ impl StatsDb {
             Stat::ObjectiveDestroyed { id } => {
                 self.objectives.remove(&(ctx.round, id))?;
             }
+            Stat::RunwayCratered {
+                id,
+                by,
+                craters: _,
+                seq,
+            } => {
+                self.runway_craters
+                    .insert(&(ctx.round, id, stat.time, seq), &by)?;
+            }
+            Stat::AirfieldClosed { id, closed_until } => {
+                self.airfield_closures
+                    .insert(&(ctx.round, id, stat.time), &closed_until)?;
+            }
             Stat::ObjectiveHealth {
                 id,
                 last_change,
@@ -750,15 +1003,22 @@ impl StatsDb {
             Stat::ObjectiveSupply { id, supply, fuel } => {
                 self.with_objective((ctx.round, id), |o| {
                     o.supply = supply;
-                    o.logi = fuel
+                    o.fuel = fuel
                 })?;
             }
             Stat::Capture { id, by, side } => {
                 self.with_objective((ctx.round, id), |o| o.owner = side)?;
+                self.ownership.insert(
+                    &(ctx.round, id, stat.time),
+                    &Ownership {
+                        owner: side,
+                        by: by.clone(),
+                    },
+                )?;
                 for ucid in by {
                     self.pilots.with_pilot_and_aggregates(
                         ucid,
-                        ctx.round,
+                        ctx,
                         |pilot| pilot.total.captures += 1,
                         |agg| agg.captures += 1,
                     )?
@@ -767,7 +1027,7 @@ impl StatsDb {
             Stat::Repair { id: _, by } => {
                 self.pilots.with_pilot_and_aggregates(
                     by,
-                    ctx.round,
+                    ctx,
                     |pilot| pilot.total.repairs += 1,
                     |agg| agg.repairs += 1,
                 )?;
@@ -775,7 +1035,7 @@ impl StatsDb {
             Stat::SupplyTransfer { from: _, to: _, by } => {
                 self.pilots.with_pilot_and_aggregates(
                     by,
-                    ctx.round,
+                    ctx,
                     |pilot| pilot.total.supply_transfers += 1,
                     |agg| agg.supply_transfers += 1,
                 )?;
@@ -791,7 +1051,7 @@ impl StatsDb {
             Stat::Action { by, gid, action } => {
                 self.pilots.with_pilot_and_aggregates(
                     by,
-                    ctx.round,
+                    ctx,
                     |p| p.total.actions += 1,
                     |a| a.actions += 1,
                 )?;
@@ -807,7 +1067,7 @@ impl StatsDb {
             Stat::DeployTroop { by, troop, gid } => {
                 self.pilots.with_pilot_and_aggregates(
                     by,
-                    ctx.round,
+                    ctx,
                     |p| p.total.troops += 1,
                     |a| a.troops += 1,
                 )?;
@@ -818,6 +1078,31 @@ impl StatsDb {
                     }
                 })?;
             }
+            Stat::CargoLost { by, .. } => {
+                self.pilots.with_pilot_and_aggregates(
+                    by,
+                    ctx,
+                    |p| p.total.cargo_lost += 1,
+                    |a| a.cargo_lost += 1,
+                )?;
+            }
+            Stat::Rescue { by, pilot: _ } => {
+                self.pilots.with_pilot_and_aggregates(
+                    by,
+                    ctx,
+                    |p| p.total.rescues += 1,
+                    |a| a.rescues += 1,
+                )?;
+            }
+            Stat::PilotCaptured { .. } => (),
+            Stat::TroopsStranded { by, troop, gid } => {
+                self.with_group((ctx.round, gid), |group| {
+                    group.kind = GroupKind::Troop {
+                        by,
+                        name: troop.clone(),
+                    }
+                })?;
+            }
             Stat::DeployGroup {
                 by,
                 gid,
@@ -825,7 +1110,7 @@ impl StatsDb {
             } => {
                 self.pilots.with_pilot_and_aggregates(
                     by,
-                    ctx.round,
+                    ctx,
                     |p| p.total.troops += 1,
                     |a| a.troops += 1,
                 )?;
@@ -843,7 +1128,7 @@ impl StatsDb {
             } => {
                 self.pilots.with_pilot_and_aggregates(
                     by,
-                    ctx.round,
+                    ctx,
                     |p| p.total.farps += 1,
                     |a| a.farps += 1,
                 )?;
@@ -886,6 +1171,14 @@ impl StatsDb {
                 })?;
             }
             Stat::Deslot { id } => {
+                let in_flight = self
+                    .pilots
+                    .round_info
+                    .get(&(id, ctx.round))?
+                    .and_then(|ri| ri.slot.and_then(|s| s.sortie));
+                if let Some(sid) = in_flight {
+                    self.pilots.flight_time(id, ctx, sid, stat.time)?
+                }
                 self.pilots
                     .with_pilot_round_info(id, ctx.round, |ri| ri.slot = None)?;
                 self.units.remove(&(ctx.round, EnId::Player(id)))?;
@@ -976,6 +1269,7 @@ impl StatsDb {
                 let sid = sid.ok_or_else(|| anyhow!("{id} landed without taking off"))?;
                 self.pilots
                     .with_sortie((id, ctx.round, sid), |s| s.land = Some(stat.time))?;
+                self.pilots.flight_time(id, ctx, sid, stat.time)?;
             }
             Stat::Life { id, lives } => {
                 self.pilots.with_pilot_round_info(id, ctx.round, |ri| {
@@ -985,6 +1279,12 @@ impl StatsDb {
                 })?;
             }
             Stat::Kill(dead) => self.record_kill(ctx, dead)?,
+            Stat::Engagement(en) => self.record_engagement(ctx, stat.seq, en)?,
+            Stat::Debrief { id, debrief } => {
+                self.pilots
+                    .debrief
+                    .insert(&(id, ctx.round, debrief.takeoff), &debrief)?;
+            }
             Stat::Points {
                 id,
                 points,
@@ -992,19 +1292,64 @@ impl StatsDb {
             } => {
                 self.pilots
                     .with_pilot_round_info(id, ctx.round, |ri| ri.points += points)?;
+                if points > 0 {
+                    let points = points as u32;
+                    self.pilots.with_pilot_and_aggregates(
+                        id,
+                        ctx,
+                        |p| p.total.points += points,
+                        |a| a.points += points,
+                    )?;
+                }
+            }
+            Stat::Tickets {
+                side,
+                tickets,
+                change: _,
+                reason: _,
+            } => {
+                self.tickets.insert(&(ctx.round, side), &tickets)?;
+            }
+            Stat::Production {
+                side,
+                equipment,
+                liquids,
+            } => {
+                for (item, amount) in equipment {
+                    self.production_equipment
+                        .insert(&(ctx.round, side, item), &amount)?;
+                }
+                for (item, amount) in liquids {
+                    self.production_liquids
+                        .insert(&(ctx.round, side, item), &amount)?;
+                }
             }
             Stat::PointsTransfer { from, to, points } => {
                 self.pilots
                     .with_pilot_round_info(from, ctx.round, |ri| ri.points -= points as i32)?;
                 self.pilots.with_pilot_and_aggregates(
                     from,
-                    ctx.round,
+                    ctx,
                     |p| p.total.donated_points += points,
                     |a| a.donated_points += points,
                 )?;
                 self.pilots
                     .with_pilot_round_info(to, ctx.round, |ri| ri.points += points as i32)?;
             }
+            Stat::PointsTransferToObjective {
+                from,
+                to: _,
+                points,
+            } => {
+                self.pilots
+                    .with_pilot_round_info(from, ctx.round, |ri| ri.points -= points as i32)?;
+                self.pilots.with_pilot_and_aggregates(
+                    from,
+                    ctx,
+                    |p| p.total.donated_points += points,
+                    |a| a.donated_points += points,
+                )?;
+            }
             Stat::Bind { id, token } => {
                 let token = Uuid::from_str(&token)?;
                 let mut remove = None;
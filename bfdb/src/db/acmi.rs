@@ -0,0 +1,339 @@
+//! convert recorded rounds in the stats logs to tacview acmi files
+use super::replay::Stats;
+use anyhow::{Context, Result};
+use bfprotocols::{
+    cfg::{UnitTag, UnitTags},
+    db::{
+        group::GroupId,
+        objective::{ObjectiveId, ObjectiveKind},
+    },
+    shots::{Dead, Who},
+    stats::{EnId, RoundEndReason, Stat, StatEntry},
+};
+use chrono::prelude::*;
+use dcso3::{coalition::Side, coord::LLPos, net::Ucid};
+use fxhash::FxHashMap;
+use log::{error, info};
+use smallvec::SmallVec;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// escape a property value, acmi uses commas to separate properties
+fn esc(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('\n', "\\\n")
+}
+
+fn coalition(side: Side) -> &'static str {
+    match side {
+        Side::Red => "Coalition=Red,Color=Red",
+        Side::Blue => "Coalition=Blue,Color=Blue",
+        Side::Neutral => "Coalition=Neutral,Color=Grey",
+    }
+}
+
+fn unit_type(tags: UnitTags) -> &'static str {
+    if tags.contains(UnitTag::Helicopter) {
+        "Air+Rotorcraft"
+    } else if tags.contains(UnitTag::Aircraft) {
+        "Air+FixedWing"
+    } else if tags.contains(UnitTag::Boat) {
+        "Sea+Watercraft"
+    } else if tags.contains(UnitTag::Infantry) {
+        "Ground+Light+Human+Infantry"
+    } else if tags.contains(UnitTag::SAM) || tags.contains(UnitTag::AAA) {
+        "Ground+AntiAircraft"
+    } else if tags.contains(UnitTag::Armor) {
+        "Ground+Heavy+Armor+Vehicle+Tank"
+    } else {
+        "Ground+Vehicle"
+    }
+}
+
+fn objective_type(kind: &ObjectiveKind) -> &'static str {
+    match kind {
+        ObjectiveKind::Airbase => "Ground+Static+Aerodrome",
+        ObjectiveKind::Farp { .. } => "Ground+Static+Heliport",
+        ObjectiveKind::Fob
+        | ObjectiveKind::Logistics
+        | ObjectiveKind::Factory
+        | ObjectiveKind::Refinery
+        | ObjectiveKind::Port => "Ground+Static+Building",
+    }
+}
+
+fn transform(pos: &LLPos) -> String {
+    format!("T={}|{}|{}", pos.longitude, pos.latitude, pos.altitude)
+}
+
+fn enid(who: &Who) -> EnId {
+    match who {
+        Who::AI { uid, .. } => EnId::Unit(*uid),
+        Who::Player { ucid, .. } => EnId::Player(*ucid),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Object {
+    id: u64,
+    name: String,
+}
+
+/// the acmi file of the round currently being converted
+struct Acmi {
+    dir: PathBuf,
+    file: Option<BufWriter<File>>,
+    start: DateTime<Utc>,
+    frame: i64,
+    next_id: u64,
+    units: FxHashMap<EnId, Object>,
+    groups: FxHashMap<GroupId, SmallVec<[EnId; 4]>>,
+    objectives: FxHashMap<ObjectiveId, Object>,
+    pilots: FxHashMap<Ucid, String>,
+}
+
+impl Acmi {
+    fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            file: None,
+            start: DateTime::<Utc>::default(),
+            frame: 0,
+            next_id: 1,
+            units: FxHashMap::default(),
+            groups: FxHashMap::default(),
+            objectives: FxHashMap::default(),
+            pilots: FxHashMap::default(),
+        }
+    }
+
+    fn begin(&mut self, title: &str, time: DateTime<Utc>) -> Result<()> {
+        self.finish()?;
+        let name: String = title
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = self
+            .dir
+            .join(format!("{name}-{}.acmi", time.format("%Y%m%d-%H%M%S")));
+        info!("writing {path:?}");
+        let file = File::create(&path).with_context(|| format!("creating {path:?}"))?;
+        let mut file = BufWriter::new(file);
+        writeln!(file, "FileType=text/acmi/tacview")?;
+        writeln!(file, "FileVersion=2.2")?;
+        writeln!(
+            file,
+            "0,ReferenceTime={}",
+            time.format("%Y-%m-%dT%H:%M:%SZ")
+        )?;
+        writeln!(file, "0,Title={}", esc(title))?;
+        writeln!(file, "0,DataSource=bfnext")?;
+        self.file = Some(file);
+        self.start = time;
+        self.frame = 0;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?
+        }
+        self.next_id = 1;
+        self.units.clear();
+        self.groups.clear();
+        self.objectives.clear();
+        Ok(())
+    }
+
+    /// write a line at time, starting a new file if the logs began
+    /// in the middle of a round
+    fn line(&mut self, time: DateTime<Utc>, line: &str) -> Result<()> {
+        if self.file.is_none() {
+            self.begin("continued", time)?
+        }
+        // frames are in hundredths of a second and never go backwards
+        let frame = ((time - self.start).num_milliseconds() / 10).max(self.frame);
+        let file = self.file.as_mut().unwrap();
+        if frame > self.frame {
+            self.frame = frame;
+            writeln!(file, "#{}.{:02}", frame / 100, frame % 100)?
+        }
+        writeln!(file, "{line}")?;
+        Ok(())
+    }
+
+    fn new_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn remove_unit(&mut self, time: DateTime<Utc>, id: &EnId) -> Result<()> {
+        if let Some(obj) = self.units.remove(id) {
+            self.line(time, &format!("-{:x}", obj.id))?
+        }
+        Ok(())
+    }
+
+    fn kill(&mut self, time: DateTime<Utc>, dead: &Dead) -> Result<()> {
+        let victim = match self.units.get(&enid(&dead.victim)) {
+            Some(obj) => obj.clone(),
+            None => return Ok(()),
+        };
+        let shot = dead
+            .shots
+            .iter()
+            .find(|s| s.hit)
+            .or_else(|| dead.shots.last());
+        let mut msg = format!("{} was killed", victim.name);
+        if let Some(shot) = shot {
+            if let Some(shooter) = self.units.get(&enid(&shot.shooter)) {
+                msg.push_str(&format!(" by {}", shooter.name));
+            }
+            if let Some(weapon) = &shot.weapon_name {
+                msg.push_str(&format!(" with {weapon}"));
+            }
+        }
+        self.line(
+            time,
+            &format!("0,Event=Destroyed|{:x}|{}", victim.id, esc(&msg)),
+        )?;
+        self.remove_unit(time, &enid(&dead.victim))
+    }
+
+    fn stat(&mut self, e: StatEntry) -> Result<()> {
+        let time = e.time;
+        match e.kind {
+            Stat::NewRound { sortie } => self.begin(&sortie, time)?,
+            Stat::RoundEnd { winner, reason } => {
+                if self.file.is_some() {
+                    let mut msg = match winner {
+                        Some(side) => format!("{side} won the round"),
+                        None => String::from("the round ended"),
+                    };
+                    if reason != RoundEndReason::Unknown {
+                        msg.push_str(&format!(", {reason}"));
+                    }
+                    self.line(time, &format!("0,Event=Message|{}", esc(&msg)))?;
+                }
+                self.finish()?
+            }
+            Stat::Objective {
+                name,
+                id,
+                pos,
+                owner,
+                kind,
+            } => {
+                let oid = match self.objectives.get(&id) {
+                    Some(obj) => obj.id,
+                    None => self.new_id(),
+                };
+                self.objectives.insert(
+                    id,
+                    Object {
+                        id: oid,
+                        name: name.to_string(),
+                    },
+                );
+                let line = format!(
+                    "{oid:x},{},Type={},Name={},{}",
+                    transform(&pos),
+                    objective_type(&kind),
+                    esc(&name),
+                    coalition(owner)
+                );
+                self.line(time, &line)?
+            }
+            Stat::Capture { id, side, .. } => {
+                if let Some(obj) = self.objectives.get(&id).cloned() {
+                    self.line(time, &format!("{:x},{}", obj.id, coalition(side)))?;
+                    let msg = format!("{side} captured {}", obj.name);
+                    self.line(time, &format!("0,Event=Message|{}", esc(&msg)))?
+                }
+            }
+            Stat::ObjectiveDestroyed { id } => {
+                if let Some(obj) = self.objectives.remove(&id) {
+                    self.line(time, &format!("-{:x}", obj.id))?
+                }
+            }
+            Stat::Register { name, id, .. } => {
+                self.pilots.insert(id, name.to_string());
+            }
+            Stat::Unit {
+                id,
+                gid,
+                owner,
+                typ,
+                pos,
+            } => {
+                let pilot = match &id {
+                    EnId::Player(ucid) => self.pilots.get(ucid).cloned(),
+                    EnId::Unit(_) => None,
+                };
+                let uid = match self.units.get(&id) {
+                    Some(obj) => obj.id,
+                    None => self.new_id(),
+                };
+                self.units.insert(
+                    id,
+                    Object {
+                        id: uid,
+                        name: pilot.clone().unwrap_or_else(|| typ.typ.to_string()),
+                    },
+                );
+                if let Some(gid) = gid {
+                    let units = self.groups.entry(gid).or_default();
+                    if !units.contains(&id) {
+                        units.push(id)
+                    }
+                }
+                let mut line = format!(
+                    "{uid:x},{},Type={},Name={},{}",
+                    transform(&pos.pos),
+                    unit_type(typ.tags),
+                    esc(&typ.typ.0),
+                    coalition(owner)
+                );
+                if let Some(pilot) = pilot {
+                    line.push_str(&format!(",Pilot={}", esc(&pilot)));
+                }
+                if let Some(gid) = gid {
+                    line.push_str(&format!(",Group={gid}"));
+                }
+                self.line(time, &line)?
+            }
+            Stat::Position { id, pos } => {
+                if let Some(obj) = self.units.get(&id) {
+                    let line = format!("{:x},{}", obj.id, transform(&pos.pos));
+                    self.line(time, &line)?
+                }
+            }
+            Stat::GroupDeleted { id } => {
+                for uid in self.groups.remove(&id).unwrap_or_default() {
+                    self.remove_unit(time, &uid)?
+                }
+            }
+            Stat::Deslot { id } => self.remove_unit(time, &EnId::Player(id))?,
+            Stat::Kill(dead) => self.kill(time, &dead)?,
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+/// write each round in the stats logs to an acmi file in dir
+pub(crate) fn export_acmi(paths: &[PathBuf], dir: &Path) -> Result<()> {
+    let mut acmi = Acmi::new(dir);
+    for e in Stats::new(paths)? {
+        match e {
+            Ok(e) => acmi.stat(e)?,
+            Err(e) => error!("{e:?}"),
+        }
+    }
+    acmi.finish()
+}
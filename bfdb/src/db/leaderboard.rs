@@ -0,0 +1,203 @@
+use super::{Aggregates, RoundId, SessionId, StatsDb};
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+use dcso3::{net::Ucid, String};
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering as MemOrdering},
+        Arc, Mutex,
+    },
+};
+
+/// how long a cached board that is out of date may still be served
+const MAX_STALE: chrono::Duration = chrono::Duration::seconds(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Board {
+    AirKills,
+    GroundKills,
+    Assists,
+    Points,
+    Captures,
+    Repairs,
+    SupplyTransfers,
+}
+
+impl FromStr for Board {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "air_kills" => Self::AirKills,
+            "ground_kills" => Self::GroundKills,
+            "assists" => Self::Assists,
+            "points" => Self::Points,
+            "captures" => Self::Captures,
+            "repairs" => Self::Repairs,
+            "supply_transfers" => Self::SupplyTransfers,
+            s => bail!("unknown leaderboard {s}"),
+        })
+    }
+}
+
+impl Board {
+    fn score(&self, a: &Aggregates) -> u32 {
+        match self {
+            Self::AirKills => a.air_kills,
+            Self::GroundKills => a.ground_kills,
+            Self::Assists => a.assists,
+            Self::Points => a.points,
+            Self::Captures => a.captures,
+            Self::Repairs => a.repairs,
+            Self::SupplyTransfers => a.supply_transfers,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Scope {
+    AllTime,
+    Round(RoundId),
+    Session(RoundId, SessionId),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Rank {
+    rank: usize,
+    ucid: Ucid,
+    name: String,
+    score: u32,
+    hours: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Leaderboard {
+    board: Board,
+    computed: DateTime<Utc>,
+    ranks: Vec<Rank>,
+}
+
+struct Cached {
+    generation: u64,
+    board: Arc<Leaderboard>,
+}
+
+/// computed boards are kept until the stats change, and then at
+/// least until they are MAX_STALE old
+#[derive(Default)]
+pub(super) struct LeaderboardCache {
+    generation: AtomicU64,
+    boards: Mutex<FxHashMap<(Scope, Board), Cached>>,
+}
+
+impl LeaderboardCache {
+    pub(super) fn invalidate(&self) {
+        self.generation.fetch_add(1, MemOrdering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub(crate) struct LeaderboardQuery {
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+impl StatsDb {
+    fn compute_leaderboard(&self, scope: Scope, board: Board) -> Result<Leaderboard> {
+        let mut scores: Vec<(Ucid, Aggregates)> = vec![];
+        match scope {
+            Scope::AllTime => {
+                for r in self.pilots.pilots.iter() {
+                    let (ucid, pilot) = r?;
+                    scores.push((ucid, pilot.total))
+                }
+            }
+            Scope::Round(round) => {
+                for r in self.pilots.round_aggregates.scan_prefix(&round)? {
+                    let ((_, ucid), agg) = r?;
+                    scores.push((ucid, agg))
+                }
+            }
+            Scope::Session(round, session) => {
+                for r in self
+                    .pilots
+                    .session_aggregates
+                    .scan_prefix(&(round, session))?
+                {
+                    let ((_, _, ucid), agg) = r?;
+                    scores.push((ucid, agg))
+                }
+            }
+        }
+        scores.retain(|(_, a)| board.score(a) > 0);
+        // the same score in less time ranks higher
+        scores.sort_by(|(_, a0), (_, a1)| {
+            board
+                .score(a1)
+                .cmp(&board.score(a0))
+                .then_with(|| a0.hours.partial_cmp(&a1.hours).unwrap_or(Ordering::Equal))
+        });
+        let mut ranks = Vec::with_capacity(scores.len());
+        for (i, (ucid, agg)) in scores.into_iter().enumerate() {
+            let name = self
+                .pilots
+                .pilots
+                .get(&ucid)?
+                .and_then(|p| p.name.last().cloned())
+                .unwrap_or_default();
+            ranks.push(Rank {
+                rank: i + 1,
+                ucid,
+                name,
+                score: board.score(&agg),
+                hours: agg.hours,
+            })
+        }
+        Ok(Leaderboard {
+            board,
+            computed: Utc::now(),
+            ranks,
+        })
+    }
+
+    pub(crate) fn leaderboard(
+        &self,
+        scope: Scope,
+        board: Board,
+        q: &LeaderboardQuery,
+    ) -> Result<Leaderboard> {
+        let generation = self.leaderboards.generation.load(MemOrdering::Relaxed);
+        let cached = {
+            let boards = self.leaderboards.boards.lock().unwrap();
+            boards.get(&(scope, board)).and_then(|c| {
+                let fresh = c.generation == generation || Utc::now() - c.board.computed < MAX_STALE;
+                fresh.then(|| Arc::clone(&c.board))
+            })
+        };
+        let lb = match cached {
+            Some(lb) => lb,
+            None => {
+                let lb = Arc::new(self.compute_leaderboard(scope, board)?);
+                self.leaderboards.boards.lock().unwrap().insert(
+                    (scope, board),
+                    Cached {
+                        generation,
+                        board: Arc::clone(&lb),
+                    },
+                );
+                lb
+            }
+        };
+        let limit = q.limit.unwrap_or(lb.ranks.len());
+        Ok(Leaderboard {
+            board: lb.board,
+            computed: lb.computed,
+            ranks: lb.ranks.iter().take(limit).cloned().collect(),
+        })
+    }
+}
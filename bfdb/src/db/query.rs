@@ -0,0 +1,524 @@
+use super::{
+    Aggregates, EngagementId, Group, KillId, Objective, Ownership, PilotRoundInfo, Round, RoundId,
+    Scenario, SortieId, StatsDb, Unit, WeaponEffect,
+};
+use anyhow::Result;
+use arrayvec::ArrayVec;
+use bfprotocols::{
+    cfg::Vehicle,
+    db::{group::GroupId, objective::ObjectiveId},
+    perf::PerfStat,
+    shots::{Engagement, Who},
+    stats::{Debrief, EnId, RoundEndReason},
+};
+use chrono::prelude::*;
+use dcso3::{
+    coalition::Side, net::Ucid, perf::PerfStat as ApiPerfStat, warehouse::LiquidType, String,
+};
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+fn default_limit() -> usize {
+    DEFAULT_LIMIT
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub(crate) struct Page {
+    #[serde(default)]
+    offset: usize,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+impl Page {
+    fn take<T>(&self, iter: impl Iterator<Item = Result<T>>) -> Result<Paged<T>> {
+        let limit = self.limit.clamp(1, MAX_LIMIT);
+        let mut items = iter
+            .skip(self.offset)
+            .take(limit + 1)
+            .collect::<Result<Vec<_>>>()?;
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            Some(self.offset + limit)
+        } else {
+            None
+        };
+        Ok(Paged {
+            offset: self.offset,
+            next,
+            items,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Paged<T> {
+    offset: usize,
+    /// the offset of the next page, if there is one
+    next: Option<usize>,
+    items: Vec<T>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct PilotQuery {
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct SortieQuery {
+    #[serde(default)]
+    round: Option<RoundId>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PilotSummary {
+    ucid: Ucid,
+    name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PilotCareer {
+    ucid: Ucid,
+    names: ArrayVec<String, 8>,
+    total: Aggregates,
+    rounds: Vec<RoundId>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct VehicleAggregates {
+    vehicle: Vehicle,
+    aggregates: Aggregates,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PilotRound {
+    ucid: Ucid,
+    round: RoundId,
+    info: PilotRoundInfo,
+    total: Aggregates,
+    by_vehicle: Vec<VehicleAggregates>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Kill {
+    id: KillId,
+    time: DateTime<Utc>,
+    victim: Who,
+    weapon: Option<String>,
+    /// everyone who gets credit for this kill
+    shared: SmallVec<[EnId; 2]>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SortieSummary {
+    id: SortieId,
+    round: RoundId,
+    vehicle: Vehicle,
+    takeoff: DateTime<Utc>,
+    land: Option<DateTime<Utc>>,
+    kills: Vec<Kill>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DebriefSummary {
+    round: RoundId,
+    #[serde(flatten)]
+    debrief: Debrief,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct EngagementSummary {
+    id: EngagementId,
+    round: RoundId,
+    #[serde(flatten)]
+    engagement: Engagement,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct WeaponEffectSummary {
+    vehicle: Vehicle,
+    weapon: String,
+    #[serde(flatten)]
+    effect: WeaponEffect,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RoundSummary {
+    id: RoundId,
+    scenario: Scenario,
+    round: Round,
+    reason: Option<RoundEndReason>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SessionSummary {
+    start: DateTime<Utc>,
+    stop_time: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    perf: Option<PerfStat>,
+    api_perf: Option<ApiPerfStat>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RoundDetail {
+    #[serde(flatten)]
+    summary: RoundSummary,
+    sessions: Vec<SessionSummary>,
+    /// the tickets each side had left, if tickets were in use
+    tickets: Vec<(Side, u32)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ObjectiveSummary {
+    id: ObjectiveId,
+    #[serde(flatten)]
+    objective: Objective,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct OwnershipChange {
+    time: DateTime<Utc>,
+    #[serde(flatten)]
+    ownership: Ownership,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ObjectiveDetail {
+    #[serde(flatten)]
+    summary: ObjectiveSummary,
+    history: Vec<OwnershipChange>,
+    equipment: Vec<(String, u32)>,
+    liquids: Vec<(LiquidType, u32)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct UnitSummary {
+    id: EnId,
+    #[serde(flatten)]
+    unit: Unit,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct GroupSummary {
+    id: GroupId,
+    #[serde(flatten)]
+    group: Group,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct GroupDetail {
+    #[serde(flatten)]
+    summary: GroupSummary,
+    members: Vec<UnitSummary>,
+}
+
+impl StatsDb {
+    pub(crate) fn pilot_list(&self, q: &PilotQuery, page: &Page) -> Result<Paged<PilotSummary>> {
+        match &q.name {
+            None => page.take(
+                self.pilots()
+                    .map(|r| r.map(|(ucid, name)| PilotSummary { ucid, name })),
+            ),
+            Some(name) => {
+                let ids = self.pilots.by_name.get(name)?.unwrap_or_default();
+                page.take(ids.into_iter().map(|ucid| {
+                    Ok(PilotSummary {
+                        ucid,
+                        name: name.clone(),
+                    })
+                }))
+            }
+        }
+    }
+
+    pub(crate) fn pilot_career(&self, ucid: Ucid) -> Result<Option<PilotCareer>> {
+        let pilot = match self.pilots.pilots.get(&ucid)? {
+            None => return Ok(None),
+            Some(p) => p,
+        };
+        let rounds = self
+            .pilots
+            .round_info
+            .scan_prefix(&ucid)?
+            .keys()
+            .map(|r| r.map(|(_, round)| round))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(PilotCareer {
+            ucid,
+            names: pilot.name,
+            total: pilot.total,
+            rounds,
+        }))
+    }
+
+    pub(crate) fn pilot_round(&self, ucid: Ucid, round: RoundId) -> Result<Option<PilotRound>> {
+        let info = match self.pilots.round_info.get(&(ucid, round))? {
+            None => return Ok(None),
+            Some(ri) => ri,
+        };
+        let mut total = Aggregates::default();
+        let mut by_vehicle = vec![];
+        for r in self.pilots.aggregates.scan_prefix(&ucid)? {
+            let ((_, vehicle, rid), aggregates) = r?;
+            if rid == round {
+                total.merge(&aggregates);
+                by_vehicle.push(VehicleAggregates {
+                    vehicle,
+                    aggregates,
+                })
+            }
+        }
+        Ok(Some(PilotRound {
+            ucid,
+            round,
+            info,
+            total,
+            by_vehicle,
+        }))
+    }
+
+    fn sortie_kills(
+        &self,
+        ucid: Ucid,
+        round: RoundId,
+        takeoff: DateTime<Utc>,
+        land: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Kill>> {
+        let mut kills = vec![];
+        for r in self.kills.scan_prefix(&(EnId::Player(ucid), round))? {
+            let ((_, _, id), dead) = r?;
+            if dead.time < takeoff || land.map(|l| dead.time > l).unwrap_or(false) {
+                continue;
+            }
+            let weapon = dead
+                .shots
+                .iter()
+                .find(|s| s.hit && s.shooter.ucid() == Some(&ucid))
+                .and_then(|s| s.weapon_name.clone());
+            let shared = self.shared_kills.get(&id)?.unwrap_or_default();
+            kills.push(Kill {
+                id,
+                time: dead.time,
+                victim: dead.victim,
+                weapon,
+                shared,
+            })
+        }
+        Ok(kills)
+    }
+
+    pub(crate) fn pilot_sorties(
+        &self,
+        ucid: Ucid,
+        q: &SortieQuery,
+        page: &Page,
+    ) -> Result<Paged<SortieSummary>> {
+        let iter = match q.round {
+            Some(round) => self.pilots.sortie.scan_prefix(&(ucid, round))?,
+            None => self.pilots.sortie.scan_prefix(&ucid)?,
+        };
+        page.take(iter.map(|r| {
+            let ((_, round, id), sortie) = r?;
+            let kills = self.sortie_kills(ucid, round, sortie.takeoff, sortie.land)?;
+            Ok(SortieSummary {
+                id,
+                round,
+                vehicle: sortie.vehicle,
+                takeoff: sortie.takeoff,
+                land: sortie.land,
+                kills,
+            })
+        }))
+    }
+
+    pub(crate) fn pilot_debriefs(
+        &self,
+        ucid: Ucid,
+        q: &SortieQuery,
+        page: &Page,
+    ) -> Result<Paged<DebriefSummary>> {
+        let iter = match q.round {
+            Some(round) => self.pilots.debrief.scan_prefix(&(ucid, round))?,
+            None => self.pilots.debrief.scan_prefix(&ucid)?,
+        };
+        page.take(iter.map(|r| {
+            let ((_, round, _), debrief) = r?;
+            Ok(DebriefSummary { round, debrief })
+        }))
+    }
+
+    pub(crate) fn pilot_engagements(
+        &self,
+        ucid: Ucid,
+        q: &SortieQuery,
+        page: &Page,
+    ) -> Result<Paged<EngagementSummary>> {
+        let iter = match q.round {
+            Some(round) => self.engagements.scan_prefix(&(EnId::Player(ucid), round))?,
+            None => self.engagements.scan_prefix(&EnId::Player(ucid))?,
+        };
+        page.take(iter.map(|r| {
+            let ((_, round, id), engagement) = r?;
+            Ok(EngagementSummary {
+                id,
+                round,
+                engagement,
+            })
+        }))
+    }
+
+    pub(crate) fn weapon_effects(&self, page: &Page) -> Result<Paged<WeaponEffectSummary>> {
+        page.take(self.weapon_effect.iter().map(|r| {
+            let ((vehicle, weapon), effect) = r?;
+            Ok(WeaponEffectSummary {
+                vehicle,
+                weapon,
+                effect,
+            })
+        }))
+    }
+
+    pub(crate) fn round_list(&self, page: &Page) -> Result<Paged<RoundSummary>> {
+        page.take(self.round.iter().map(|r| {
+            let ((scenario, id), round) = r?;
+            let reason = self.round_end_reason.get(&(scenario.clone(), id))?;
+            Ok(RoundSummary {
+                id,
+                scenario,
+                round,
+                reason,
+            })
+        }))
+    }
+
+    pub(crate) fn round_sessions(&self, round: RoundId) -> Result<Vec<SessionSummary>> {
+        self.session
+            .scan_prefix(&round)?
+            .map(|r| {
+                let ((_, start), session) = r?;
+                let (end, perf, api_perf) = match &session.end {
+                    None => (None, None, None),
+                    Some(end) => (
+                        Some(end.time),
+                        Some(end.engine.stat(&end.frame)),
+                        Some(end.api.stat()),
+                    ),
+                };
+                Ok(SessionSummary {
+                    start,
+                    stop_time: session.stop_time,
+                    end,
+                    perf,
+                    api_perf,
+                })
+            })
+            .collect()
+    }
+
+    pub(crate) fn round_detail(&self, id: RoundId) -> Result<Option<RoundDetail>> {
+        for r in self.round.iter() {
+            let ((scenario, rid), round) = r?;
+            if rid == id {
+                let reason = self.round_end_reason.get(&(scenario.clone(), id))?;
+                return Ok(Some(RoundDetail {
+                    summary: RoundSummary {
+                        id,
+                        scenario,
+                        round,
+                        reason,
+                    },
+                    sessions: self.round_sessions(id)?,
+                    tickets: self
+                        .tickets
+                        .scan_prefix(&id)?
+                        .map(|r| {
+                            let ((_, side), tickets) = r?;
+                            Ok((side, tickets))
+                        })
+                        .collect::<Result<_>>()?,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn round_objectives(&self, round: RoundId) -> Result<Vec<ObjectiveSummary>> {
+        self.objectives
+            .scan_prefix(&round)?
+            .map(|r| {
+                let ((_, id), objective) = r?;
+                Ok(ObjectiveSummary { id, objective })
+            })
+            .collect()
+    }
+
+    pub(crate) fn objective_detail(
+        &self,
+        round: RoundId,
+        id: ObjectiveId,
+    ) -> Result<Option<ObjectiveDetail>> {
+        let objective = match self.objectives.get(&(round, id))? {
+            None => return Ok(None),
+            Some(o) => o,
+        };
+        let history = self
+            .ownership
+            .scan_prefix(&(round, id))?
+            .map(|r| {
+                let ((_, _, time), ownership) = r?;
+                Ok(OwnershipChange { time, ownership })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let equipment = self
+            .equipment
+            .scan_prefix(&(round, id))?
+            .map(|r| r.map(|((_, _, item), n)| (item, n)))
+            .collect::<Result<Vec<_>>>()?;
+        let liquids = self
+            .liquids
+            .scan_prefix(&(round, id))?
+            .map(|r| r.map(|((_, _, item), n)| (item, n)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(ObjectiveDetail {
+            summary: ObjectiveSummary { id, objective },
+            history,
+            equipment,
+            liquids,
+        }))
+    }
+
+    pub(crate) fn round_units(&self, round: RoundId, page: &Page) -> Result<Paged<UnitSummary>> {
+        page.take(self.units.scan_prefix(&round)?.map(|r| {
+            let ((_, id), unit) = r?;
+            Ok(UnitSummary { id, unit })
+        }))
+    }
+
+    pub(crate) fn round_groups(&self, round: RoundId, page: &Page) -> Result<Paged<GroupSummary>> {
+        page.take(self.groups.scan_prefix(&round)?.map(|r| {
+            let ((_, id), group) = r?;
+            Ok(GroupSummary { id, group })
+        }))
+    }
+
+    pub(crate) fn group_detail(&self, round: RoundId, id: GroupId) -> Result<Option<GroupDetail>> {
+        let group = match self.groups.get(&(round, id))? {
+            None => return Ok(None),
+            Some(g) => g,
+        };
+        let mut members = vec![];
+        for uid in group.units.iter() {
+            if let Some(unit) = self.units.get(&(round, *uid))? {
+                members.push(UnitSummary { id: *uid, unit })
+            }
+        }
+        Ok(Some(GroupDetail {
+            summary: GroupSummary { id, group },
+            members,
+        }))
+    }
+}
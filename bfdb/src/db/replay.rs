@@ -0,0 +1,200 @@
+use super::{Scenario, SeqId, StatCtx, StatsDb};
+use anyhow::{anyhow, Context, Result};
+use bfprotocols::stats::StatEntry;
+use log::{error, info, warn};
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+    vec,
+};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// what to feed back through add_stat
+#[derive(Debug, Clone)]
+pub(crate) struct Replay {
+    /// stats log files, or directories containing them
+    pub(crate) paths: Vec<PathBuf>,
+    /// continue the open round of this scenario if the logs don't
+    /// start with a NewRound
+    pub(crate) sortie: Option<Scenario>,
+    /// skip every stat at or before this seq
+    pub(crate) resume_from: Option<SeqId>,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    applied: usize,
+    skipped: usize,
+    failed: usize,
+    last: Option<SeqId>,
+}
+
+type LogReader = BufReader<zstd::stream::Decoder<'static, BufReader<File>>>;
+
+fn open(path: &Path) -> Result<LogReader> {
+    let file = File::open(path).with_context(|| format!("opening {path:?}"))?;
+    Ok(BufReader::new(zstd::stream::Decoder::new(file)?))
+}
+
+/// the seq of the first entry in the file, if any. A file that can't
+/// be read or parsed has none, and is skipped
+fn first_seq(path: &Path) -> Result<Option<SeqId>> {
+    let mut line = String::new();
+    match open(path)?.read_line(&mut line) {
+        Ok(0) => Ok(None),
+        Err(e) => {
+            warn!("could not read {path:?}, {e:?}");
+            Ok(None)
+        }
+        Ok(_) => match serde_json::from_str::<StatEntry>(&line) {
+            Ok(e) => Ok(Some(e.seq)),
+            Err(e) => {
+                warn!("could not parse the first stat of {path:?}, {e:?}");
+                Ok(None)
+            }
+        },
+    }
+}
+
+/// expand directories and put the logs in the order they were written
+fn collect(paths: &[PathBuf]) -> Result<Vec<(SeqId, PathBuf)>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            for ent in fs::read_dir(path)? {
+                let ent = ent?;
+                let path = ent.path();
+                if path.is_file() && path.extension().map(|e| e == "zst").unwrap_or(false)
+                {
+                    files.push(path)
+                }
+            }
+        } else {
+            files.push(path.clone())
+        }
+    }
+    let mut res = vec![];
+    for path in files {
+        match first_seq(&path)? {
+            Some(seq) => res.push((seq, path)),
+            None => info!("skipping {path:?}, it has no stats"),
+        }
+    }
+    res.sort_by_key(|(seq, _)| *seq);
+    Ok(res)
+}
+
+/// every stat in a set of stats logs, in the order they were written
+pub(super) struct Stats {
+    files: vec::IntoIter<(SeqId, PathBuf)>,
+    file: Option<(PathBuf, LogReader, usize)>,
+    line: String,
+}
+
+impl Stats {
+    pub(super) fn new(paths: &[PathBuf]) -> Result<Self> {
+        Ok(Self {
+            files: collect(paths)?.into_iter(),
+            file: None,
+            line: String::new(),
+        })
+    }
+}
+
+impl Iterator for Stats {
+    /// a log that can't be opened or a stat that can't be parsed is an
+    /// error, reading continues after it
+    type Item = Result<StatEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, file, lineno) = match &mut self.file {
+                Some(f) => f,
+                None => {
+                    let (_, path) = self.files.next()?;
+                    info!("reading {path:?}");
+                    match open(&path) {
+                        Ok(file) => self.file = Some((path, file, 0)),
+                        Err(e) => return Some(Err(e)),
+                    }
+                    continue;
+                }
+            };
+            self.line.clear();
+            match file.read_line(&mut self.line) {
+                Ok(0) => {
+                    self.file = None;
+                    continue;
+                }
+                Ok(_) => *lineno += 1,
+                Err(e) => {
+                    warn!("{path:?} is truncated after line {lineno}, {e:?}");
+                    self.file = None;
+                    continue;
+                }
+            }
+            return Some(
+                serde_json::from_str(&self.line)
+                    .with_context(|| format!("{path:?}:{lineno} failed to parse stat")),
+            );
+        }
+    }
+}
+
+impl StatsDb {
+    /// rebuild the database from stats logs written by bflib
+    pub(crate) fn replay(&self, r: &Replay) -> Result<()> {
+        let mut ctx = StatCtx::default();
+        if let Some(sortie) = &r.sortie {
+            ctx.0 = Some(
+                self.open_round(sortie)?
+                    .ok_or_else(|| anyhow!("{sortie} has no round in progress"))?,
+            );
+        }
+        let mut progress = Progress::default();
+        let mut reported = Instant::now();
+        for e in Stats::new(&r.paths)? {
+            let e = match e {
+                Ok(e) => e,
+                Err(e) => {
+                    error!("{e:?}");
+                    progress.failed += 1;
+                    continue;
+                }
+            };
+            let done = ctx.0.as_ref().map(|c| e.seq <= c.seq).unwrap_or(false);
+            if done || r.resume_from.map(|seq| e.seq <= seq).unwrap_or(false) {
+                progress.skipped += 1;
+                continue;
+            }
+            let seq = e.seq;
+            match self.add_stat(&mut ctx, e) {
+                Ok(()) => progress.applied += 1,
+                Err(e) => {
+                    error!("failed to add stat {} {e:?}", seq.to_rfc3339());
+                    progress.failed += 1
+                }
+            }
+            progress.last = Some(seq);
+            if reported.elapsed() >= PROGRESS_INTERVAL {
+                reported = Instant::now();
+                info!(
+                    "applied {}, skipped {}, failed {}, last seq {:?}",
+                    progress.applied, progress.skipped, progress.failed, progress.last
+                )
+            }
+        }
+        self.db.flush()?;
+        info!(
+            "replay done, applied {}, skipped {}, failed {}",
+            progress.applied, progress.skipped, progress.failed
+        );
+        if let Some(seq) = progress.last {
+            info!("to continue from here use --resume-from {}", seq.to_rfc3339())
+        }
+        Ok(())
+    }
+}
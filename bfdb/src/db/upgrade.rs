@@ -0,0 +1,243 @@
+//! Records are stored with bincode, which is positional, so adding a
+//! field to a stored struct makes every existing record unreadable.
+//! When a stored struct changes the old layout is kept here, and a new
+//! version rewrites the trees that hold it.
+
+use super::{Aggregates, KillId, Pilot, RoundId};
+use anyhow::{bail, Context, Result};
+use arrayvec::ArrayVec;
+use bfprotocols::{
+    cfg::Vehicle,
+    shots::{Dead, Shot, Who},
+    stats::EnId,
+};
+use chrono::prelude::*;
+use dcso3::{net::Ucid, String};
+use log::info;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use uuid::Uuid;
+use yats::{Batch, Tree, KV};
+
+/// the current version of the database
+const VERSION: u32 = 2;
+
+/// the layouts of version 0
+mod v0 {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+    pub(super) struct Aggregates {
+        pub(super) air_kills: u32,
+        pub(super) ground_kills: u32,
+        pub(super) captures: u32,
+        pub(super) repairs: u32,
+        pub(super) supply_transfers: u32,
+        pub(super) troops: u32,
+        pub(super) farps: u32,
+        pub(super) deploys: u32,
+        pub(super) actions: u32,
+        pub(super) deaths: u32,
+        pub(super) hours: f32,
+        pub(super) donated_points: u32,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(super) struct Pilot {
+        pub(super) name: ArrayVec<String, 8>,
+        pub(super) total: Aggregates,
+        pub(super) token: ArrayVec<Uuid, 4>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(super) struct Dead {
+        pub(super) victim: Who,
+        pub(super) time: DateTime<Utc>,
+        pub(super) shots: Vec<Shot>,
+    }
+}
+
+impl From<v0::Aggregates> for Aggregates {
+    fn from(a: v0::Aggregates) -> Self {
+        let v0::Aggregates {
+            air_kills,
+            ground_kills,
+            captures,
+            repairs,
+            supply_transfers,
+            troops,
+            farps,
+            deploys,
+            actions,
+            deaths,
+            hours,
+            donated_points,
+        } = a;
+        Self {
+            air_kills,
+            ground_kills,
+            captures,
+            repairs,
+            supply_transfers,
+            troops,
+            farps,
+            deploys,
+            actions,
+            deaths,
+            hours,
+            donated_points,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<v0::Pilot> for Pilot {
+    fn from(p: v0::Pilot) -> Self {
+        Self { name: p.name, total: p.total.into(), token: p.token }
+    }
+}
+
+impl From<v0::Dead> for Dead {
+    fn from(d: v0::Dead) -> Self {
+        Self {
+            victim: d.victim,
+            time: d.time,
+            shots: d.shots,
+            spotters: vec![],
+            credit: vec![],
+        }
+    }
+}
+
+/// rewrite every record in the tree name from the old layout O to
+/// the new layout N
+fn rewrite<K: KV, O: KV, N: KV + From<O>>(db: &Db, name: &str) -> Result<()> {
+    let old: Tree<K, O> = Tree::open(db, name)?;
+    let new: Tree<K, N> = Tree::open(db, name)?;
+    let mut batch = Batch::default();
+    let mut n = 0;
+    for r in old.iter() {
+        let (k, v) = r.with_context(|| format!("reading {name}"))?;
+        batch.insert(&k, &N::from(v))?;
+        n += 1;
+    }
+    new.apply_batch(batch)?;
+    info!("rewrote {n} records in {name}");
+    Ok(())
+}
+
+/// bring db up to the current version
+pub(super) fn upgrade(db: &Db) -> Result<()> {
+    let meta: Tree<String, u32> = Tree::open(db, "meta")?;
+    let key = String::from("version");
+    let version = meta.get(&key)?.unwrap_or(0);
+    if version > VERSION {
+        bail!("database version {version} is newer than this bfdb, {VERSION}")
+    }
+    if version < 1 {
+        info!("upgrading database to version 1, new pilot aggregates");
+        rewrite::<Ucid, v0::Pilot, Pilot>(db, "pilots")?;
+        rewrite::<(Ucid, Vehicle, RoundId), v0::Aggregates, Aggregates>(
+            db,
+            "aggregates",
+        )?;
+    }
+    if version < 2 {
+        info!("upgrading database to version 2, kill spotters and credit");
+        rewrite::<(EnId, RoundId, KillId), v0::Dead, Dead>(db, "kills")?;
+    }
+    if version < VERSION {
+        meta.insert(&key, &VERSION)?;
+        db.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bfprotocols::db::group::{GroupId, UnitId};
+    use dcso3::coalition::Side;
+
+    fn ucid(n: u8) -> Ucid {
+        format!("{n:032x}").parse().unwrap()
+    }
+
+    #[test]
+    fn upgrades_version_0_aggregates() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let total = v0::Aggregates {
+            air_kills: 1,
+            ground_kills: 2,
+            captures: 3,
+            repairs: 4,
+            supply_transfers: 5,
+            troops: 6,
+            farps: 7,
+            deploys: 8,
+            actions: 9,
+            deaths: 10,
+            hours: 1.5,
+            donated_points: 11,
+        };
+        let pilots: Tree<Ucid, v0::Pilot> = Tree::open(&db, "pilots").unwrap();
+        let pilot = v0::Pilot {
+            name: ArrayVec::from_iter([String::from("pilot")]),
+            total,
+            token: ArrayVec::new(),
+        };
+        pilots.insert(&ucid(1), &pilot).unwrap();
+        let aggregates: Tree<(Ucid, Vehicle, RoundId), v0::Aggregates> =
+            Tree::open(&db, "aggregates").unwrap();
+        let key = (ucid(1), Vehicle::from("FA-18C_hornet"), RoundId::new(&db).unwrap());
+        aggregates.insert(&key, &total).unwrap();
+        upgrade(&db).unwrap();
+        let check = |a: &Aggregates| {
+            assert_eq!(a.air_kills, 1);
+            assert_eq!(a.deaths, 10);
+            assert_eq!(a.hours, 1.5);
+            assert_eq!(a.donated_points, 11);
+            assert_eq!(a.points, 0);
+        };
+        let pilots: Tree<Ucid, Pilot> = Tree::open(&db, "pilots").unwrap();
+        let pilot = pilots.get(&ucid(1)).unwrap().unwrap();
+        assert_eq!(pilot.name[0].as_str(), "pilot");
+        check(&pilot.total);
+        let aggregates: Tree<(Ucid, Vehicle, RoundId), Aggregates> =
+            Tree::open(&db, "aggregates").unwrap();
+        check(&aggregates.get(&key).unwrap().unwrap());
+        // upgrading again must not touch the new records
+        upgrade(&db).unwrap();
+        check(&aggregates.get(&key).unwrap().unwrap());
+    }
+
+    #[test]
+    fn upgrades_version_1_kills() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let meta: Tree<String, u32> = Tree::open(&db, "meta").unwrap();
+        meta.insert(&String::from("version"), &1).unwrap();
+        let victim = Who::AI {
+            unit: serde_json::from_str(r#"{"id":1,"class":"Unit"}"#).unwrap(),
+            side: Side::Red,
+            gid: GroupId::new(),
+            uid: UnitId::new(),
+            ucid: None,
+        };
+        let kills: Tree<(EnId, RoundId, KillId), v0::Dead> =
+            Tree::open(&db, "kills").unwrap();
+        let key = (
+            EnId::Player(ucid(1)),
+            RoundId::new(&db).unwrap(),
+            KillId::new(&db).unwrap(),
+        );
+        let time = Utc::now();
+        kills.insert(&key, &v0::Dead { victim, time, shots: vec![] }).unwrap();
+        upgrade(&db).unwrap();
+        let kills: Tree<(EnId, RoundId, KillId), Dead> =
+            Tree::open(&db, "kills").unwrap();
+        let dead = kills.get(&key).unwrap().unwrap();
+        assert_eq!(dead.time, time);
+        assert_eq!(*dead.victim.side(), Side::Red);
+        assert!(dead.spotters.is_empty() && dead.credit.is_empty());
+    }
+}
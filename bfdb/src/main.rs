@@ -1,13 +1,18 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use bfprotocols::db::{group::GroupId, objective::ObjectiveId};
 use clap::Parser;
-use db::StatsDb;
+use db::{
+    export_acmi, Board, LeaderboardQuery, Page, PilotQuery, Replay, RoundId, Scope, SeqId,
+    SessionId, SortieQuery, StatsDb,
+};
+use dcso3::net::Ucid;
 use netidx::{config::Config, path::Path as NetidxPath, subscriber::SubscriberBuilder};
 use regex::Regex;
-use std::{future, net::SocketAddr, path::PathBuf};
+use serde::Serialize;
+use std::{net::SocketAddr, path::PathBuf};
 use tokio::task;
 use warp::{
-    filters::BoxedFilter,
-    reject::{Reject, Rejection},
+    http::StatusCode,
     reply::{Reply, Response},
     Filter,
 };
@@ -20,11 +25,11 @@ mod db_id;
 #[command(version, about, long_about = None)]
 struct Args {
     /// The base path to find and subscribe to the stats
-    #[arg(short, long)]
-    base: NetidxPath,
+    #[arg(short, long, required_unless_present = "replay")]
+    base: Option<NetidxPath>,
     /// The path to the database
-    #[arg(short, long)]
-    db: PathBuf,
+    #[arg(short, long, required_unless_present = "acmi")]
+    db: Option<PathBuf>,
     /// The certificate to use for TLS
     #[arg(short, long)]
     cert: Option<PathBuf>,
@@ -38,8 +43,22 @@ struct Args {
     #[arg(long)]
     exclude: Option<Regex>,
     /// The web address to listen on
-    #[arg(long)]
-    listen_address: SocketAddr,
+    #[arg(long, required_unless_present = "replay")]
+    listen_address: Option<SocketAddr>,
+    /// Rebuild the database from these stats logs (or directories of
+    /// them) and exit instead of serving
+    #[arg(long, num_args = 1..)]
+    replay: Vec<PathBuf>,
+    /// When replaying, continue the open round of this scenario
+    #[arg(long, requires = "replay")]
+    sortie: Option<String>,
+    /// When replaying, skip every stat at or before this seq
+    #[arg(long, requires = "replay")]
+    resume_from: Option<SeqId>,
+    /// Instead of rebuilding the database, convert each round in the
+    /// replayed stats logs to a tacview acmi file in this directory
+    #[arg(long, requires = "replay")]
+    acmi: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -47,7 +66,8 @@ struct Error(anyhow::Error);
 
 impl Reply for Error {
     fn into_response(self) -> Response {
-        Response::new(format!("{:?}", self.0).into())
+        warp::reply::with_status(format!("{:?}", self.0), StatusCode::INTERNAL_SERVER_ERROR)
+            .into_response()
     }
 }
 
@@ -57,46 +77,175 @@ impl From<anyhow::Error> for Error {
     }
 }
 
-async fn pilots(db: StatsDb) -> std::result::Result<impl warp::Reply, Error> {
-    let buf = task::block_in_place(|| -> Result<String> {
-        use std::fmt::Write;
-        let mut buf = String::new();
-        for r in db.pilots() {
-            let (ucid, name) = r?;
-            write!(buf, "{ucid}: {name}\n").unwrap()
-        }
-        Ok(buf)
-    })?;
-    Ok(buf)
+/// run a query against the db and reply with the result as json, or
+/// 404 if the query found nothing
+async fn query<T, F>(db: StatsDb, f: F) -> std::result::Result<Response, Error>
+where
+    T: Serialize,
+    F: FnOnce(&StatsDb) -> Result<Option<T>>,
+{
+    let res = task::block_in_place(|| f(&db))?;
+    Ok(match res {
+        Some(t) => warp::reply::json(&t).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    })
 }
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
     env_logger::init();
     let args = Args::parse();
+    if let Some(dir) = &args.acmi {
+        return task::block_in_place(|| export_acmi(&args.replay, dir));
+    }
+    let db_path = args.db.ok_or_else(|| anyhow!("--db is required"))?;
+    if !args.replay.is_empty() {
+        let db = StatsDb::open(&db_path)?;
+        let replay = Replay {
+            paths: args.replay,
+            sortie: args.sortie.map(|s| s.into()),
+            resume_from: args.resume_from,
+        };
+        return task::block_in_place(|| db.replay(&replay));
+    }
+    let base = args.base.ok_or_else(|| anyhow!("--base is required"))?;
+    let listen_address = args
+        .listen_address
+        .ok_or_else(|| anyhow!("--listen-address is required"))?;
     let subscriber = SubscriberBuilder::new()
         .config(Config::load_default()?)
         .build()?;
     let db = StatsDb::new(
         subscriber.clone(),
-        args.db,
-        args.base,
+        db_path,
+        base,
         args.include,
         args.exclude,
     )?;
-    let pilots = warp::path("pilots").then({
-        let db = db.clone();
-        move || pilots(db.clone())
-    });
-    let routes = warp::get().and(pilots);
+    let with_db = warp::any().map(move || db.clone());
+    let pilots = warp::path!("pilots")
+        .and(warp::query::<PilotQuery>())
+        .and(warp::query::<Page>())
+        .and(with_db.clone())
+        .then(|q: PilotQuery, page: Page, db| {
+            query(db, move |db| db.pilot_list(&q, &page).map(Some))
+        });
+    let pilot = warp::path!("pilots" / Ucid)
+        .and(with_db.clone())
+        .then(|ucid, db| query(db, move |db| db.pilot_career(ucid)));
+    let pilot_round = warp::path!("pilots" / Ucid / "rounds" / RoundId)
+        .and(with_db.clone())
+        .then(|ucid, round, db| query(db, move |db| db.pilot_round(ucid, round)));
+    let pilot_sorties = warp::path!("pilots" / Ucid / "sorties")
+        .and(warp::query::<SortieQuery>())
+        .and(warp::query::<Page>())
+        .and(with_db.clone())
+        .then(|ucid, q: SortieQuery, page: Page, db| {
+            query(db, move |db| db.pilot_sorties(ucid, &q, &page).map(Some))
+        });
+    let pilot_debriefs = warp::path!("pilots" / Ucid / "debriefs")
+        .and(warp::query::<SortieQuery>())
+        .and(warp::query::<Page>())
+        .and(with_db.clone())
+        .then(|ucid, q: SortieQuery, page: Page, db| {
+            query(db, move |db| db.pilot_debriefs(ucid, &q, &page).map(Some))
+        });
+    let pilot_engagements = warp::path!("pilots" / Ucid / "engagements")
+        .and(warp::query::<SortieQuery>())
+        .and(warp::query::<Page>())
+        .and(with_db.clone())
+        .then(|ucid, q: SortieQuery, page: Page, db| {
+            query(db, move |db| {
+                db.pilot_engagements(ucid, &q, &page).map(Some)
+            })
+        });
+    let weapons = warp::path!("weapons")
+        .and(warp::query::<Page>())
+        .and(with_db.clone())
+        .then(|page: Page, db| query(db, move |db| db.weapon_effects(&page).map(Some)));
+    let rounds = warp::path!("rounds")
+        .and(warp::query::<Page>())
+        .and(with_db.clone())
+        .then(|page: Page, db| query(db, move |db| db.round_list(&page).map(Some)));
+    let round = warp::path!("rounds" / RoundId)
+        .and(with_db.clone())
+        .then(|round, db| query(db, move |db| db.round_detail(round)));
+    let sessions = warp::path!("rounds" / RoundId / "sessions")
+        .and(with_db.clone())
+        .then(|round, db| query(db, move |db| db.round_sessions(round).map(Some)));
+    let objectives = warp::path!("rounds" / RoundId / "objectives")
+        .and(with_db.clone())
+        .then(|round, db| query(db, move |db| db.round_objectives(round).map(Some)));
+    let objective = warp::path!("rounds" / RoundId / "objectives" / ObjectiveId)
+        .and(with_db.clone())
+        .then(|round, oid, db| query(db, move |db| db.objective_detail(round, oid)));
+    let units = warp::path!("rounds" / RoundId / "units")
+        .and(warp::query::<Page>())
+        .and(with_db.clone())
+        .then(|round, page: Page, db| query(db, move |db| db.round_units(round, &page).map(Some)));
+    let groups = warp::path!("rounds" / RoundId / "groups")
+        .and(warp::query::<Page>())
+        .and(with_db.clone())
+        .then(|round, page: Page, db| query(db, move |db| db.round_groups(round, &page).map(Some)));
+    let group = warp::path!("rounds" / RoundId / "groups" / GroupId)
+        .and(with_db.clone())
+        .then(|round, gid, db| query(db, move |db| db.group_detail(round, gid)));
+    let leaderboard = warp::path!("leaderboards" / Board)
+        .and(warp::query::<LeaderboardQuery>())
+        .and(with_db.clone())
+        .then(|board, q: LeaderboardQuery, db| {
+            query(db, move |db| {
+                db.leaderboard(Scope::AllTime, board, &q).map(Some)
+            })
+        });
+    let round_leaderboard = warp::path!("rounds" / RoundId / "leaderboards" / Board)
+        .and(warp::query::<LeaderboardQuery>())
+        .and(with_db.clone())
+        .then(|round, board, q: LeaderboardQuery, db| {
+            query(db, move |db| {
+                db.leaderboard(Scope::Round(round), board, &q).map(Some)
+            })
+        });
+    let session_leaderboard =
+        warp::path!("rounds" / RoundId / "sessions" / SessionId / "leaderboards" / Board)
+            .and(warp::query::<LeaderboardQuery>())
+            .and(with_db.clone())
+            .then(|round, session, board, q: LeaderboardQuery, db| {
+                query(db, move |db| {
+                    db.leaderboard(Scope::Session(round, session), board, &q)
+                        .map(Some)
+                })
+            });
+    let routes = warp::get()
+        .and(
+            pilots
+                .or(pilot)
+                .or(pilot_round)
+                .or(pilot_sorties)
+                .or(pilot_debriefs)
+                .or(pilot_engagements)
+                .or(weapons)
+                .or(rounds)
+                .or(round)
+                .or(sessions)
+                .or(objectives)
+                .or(objective)
+                .or(units)
+                .or(groups)
+                .or(group)
+                .or(leaderboard)
+                .or(round_leaderboard)
+                .or(session_leaderboard),
+        )
+        .with(warp::cors().allow_any_origin().allow_method("GET"));
     match (&args.cert, &args.key) {
-        (_, None) | (None, _) => warp::serve(routes).run(args.listen_address).await,
+        (_, None) | (None, _) => warp::serve(routes).run(listen_address).await,
         (Some(cert), Some(key)) => {
             warp::serve(routes)
                 .tls()
                 .cert_path(cert)
                 .key_path(key)
-                .run(args.listen_address)
+                .run(listen_address)
                 .await
         }
     }
@@ -17,7 +17,11 @@ for more details.
 use crate::{
     Context,
     bg::Task,
-    db::{Db, SetS, group::DeployKind},
+    db::{
+        Db, SetS,
+        griefing::{GriefAction, GriefEntry},
+        group::DeployKind,
+    },
     msgq::MsgTyp,
     objective_mut, return_lives,
     spawnctx::{SpawnCtx, SpawnLoc},
@@ -27,7 +31,7 @@ use bfprotocols::{
     cfg::{Cfg, DeployableKind},
     db::{group::GroupId, objective::ObjectiveId},
     perf::Perf,
-    stats::Stat,
+    stats::{RoundEndReason, Stat},
 };
 use chrono::prelude::*;
 use compact_str::format_compact;
@@ -107,6 +111,7 @@ pub enum AdminCommand {
     Spawn {
         key: String,
     },
+    SpawnAt(SpawnSpec),
     SideSwitch {
         side: Side,
         player: String,
@@ -156,6 +161,13 @@ pub enum AdminCommand {
     Remark {
         objective: String,
     },
+    Grief {
+        player: String,
+    },
+    Forgive {
+        player: String,
+        id: u32,
+    },
     Reset {
         winner: Option<Side>,
     },
@@ -189,6 +201,8 @@ impl AdminCommand {
             "delete <groupid>: delete deployed group, now with 100% less mess",
             "deslot <player>: force <player> to spectators",
             "remark <obj>: force refresh the markup on objective",
+            "grief <player>: list <player>'s offenses against their own side and the penalties applied",
+            "forgive <id> <player>: reverse entry <id> in <player>'s grief log",
             "reset [winner]: shutdown the server and reset the campaign state",
             "shutdown: shutdown the server",
         ]
@@ -329,6 +343,16 @@ impl FromStr for AdminCommand {
             Ok(Self::Remark {
                 objective: s.into(),
             })
+        } else if let Some(s) = s.strip_prefix("grief ") {
+            Ok(Self::Grief { player: s.into() })
+        } else if let Some(s) = s.strip_prefix("forgive ") {
+            match s.split_once(" ") {
+                None => bail!("forgive <id> <player>"),
+                Some((id, player)) => Ok(Self::Forgive {
+                    id: id.parse::<u32>()?,
+                    player: player.into(),
+                }),
+            }
         } else if let Some(s) = s.strip_prefix("reset") {
             let winner = if s == "" {
                 None
@@ -342,35 +366,152 @@ impl FromStr for AdminCommand {
     }
 }
 
-fn admin_spawn(ctx: &mut Context, lua: MizLua, id: Option<PlayerId>, key: String) -> Result<()> {
-    let mut to_remove: SmallVec<[MarkId; 8]> = smallvec![];
-    let act = Trigger::singleton(lua)?.action()?;
-    let spctx = SpawnCtx::new(lua)?;
-    let key = format_compact!("{} ", key);
-    let ucid = match id {
-        None => Ucid::default(),
-        Some(id) => {
-            ctx.connected
-                .get(&id)
-                .ok_or_else(|| anyhow!("unknown admin"))?
-                .ucid
+#[derive(Debug, Clone, Copy)]
+pub enum SpawnKind {
+    Troop,
+    Deployable,
+}
+
+impl FromStr for SpawnKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::prelude::v1::Result<Self, Self::Err> {
+        match s {
+            "troop" => Ok(SpawnKind::Troop),
+            "deployable" => Ok(SpawnKind::Deployable),
+            s => bail!("invalid kind, expected troop or deployable got {s}"),
         }
-    };
-    enum Kind {
-        Troop,
-        Deployable,
     }
-    impl FromStr for Kind {
-        type Err = anyhow::Error;
+}
 
-        fn from_str(s: &str) -> std::prelude::v1::Result<Self, Self::Err> {
-            match s {
-                "troop" => Ok(Kind::Troop),
-                "deployable" => Ok(Kind::Deployable),
-                s => bail!("invalid kind, expected troop or deployable got {s}"),
+/// a troop or deployable to spawn at a position
+#[derive(Debug, Clone)]
+pub struct SpawnSpec {
+    pub kind: SpawnKind,
+    pub side: Side,
+    pub name: String,
+    pub pos: Vector2,
+    /// radians
+    pub heading: f64,
+}
+
+fn caller_ucid(ctx: &Context, id: Option<PlayerId>) -> Result<Ucid> {
+    match id {
+        None => Ok(Ucid::default()),
+        Some(id) => Ok(ctx
+            .connected
+            .get(&id)
+            .ok_or_else(|| anyhow!("unknown admin"))?
+            .ucid),
+    }
+}
+
+fn spawn_at(
+    ctx: &mut Context,
+    lua: MizLua,
+    spctx: &SpawnCtx,
+    ucid: Ucid,
+    spec: &SpawnSpec,
+) -> Result<()> {
+    let SpawnSpec {
+        kind,
+        side,
+        name,
+        pos,
+        heading,
+    } = spec;
+    let (side, pos, heading) = (*side, *pos, *heading);
+    let loc = SpawnLoc::AtPos {
+        pos,
+        offset_direction: pointing_towards2(heading),
+        group_heading: heading,
+    };
+    match kind {
+        SpawnKind::Troop => {
+            let specs = ctx
+                .db
+                .ephemeral
+                .cfg
+                .troops
+                .get(&side)
+                .ok_or_else(|| anyhow!("no troops on {side}"))?;
+            let spec = specs
+                .iter()
+                .find(|tr| &tr.name == name)
+                .ok_or_else(|| anyhow!("no troop called {name} on {side}"))?
+                .clone();
+            let origin = DeployKind::Troop {
+                player: ucid,
+                moved_by: None,
+                spec: spec.clone(),
+                origin: None,
+                cost_fraction: 1.,
+            };
+            ctx.db
+                .add_and_queue_group(
+                    spctx,
+                    &ctx.idx,
+                    side,
+                    loc,
+                    &spec.template,
+                    origin,
+                    BitFlags::empty(),
+                    None,
+                )
+                .context("adding group")?;
+        }
+        SpawnKind::Deployable => {
+            let specs = ctx
+                .db
+                .ephemeral
+                .cfg
+                .deployables
+                .get(&side)
+                .ok_or_else(|| anyhow!("no deployables on {side}"))?;
+            let spec = specs
+                .iter()
+                .find(|dp| dp.path.ends_with(std::slice::from_ref(name)))
+                .ok_or_else(|| anyhow!("no deployable called {name} on {side}"))?
+                .clone();
+            match &spec.kind {
+                DeployableKind::Objective(parts) => {
+                    ctx.db
+                        .add_farp(lua, spctx, &ctx.idx, side, pos, &spec, parts)
+                        .context("adding farp")?;
+                }
+                DeployableKind::Group { template } => {
+                    let origin = DeployKind::Deployed {
+                        player: ucid,
+                        moved_by: None,
+                        spec: spec.clone(),
+                        origin: None,
+                        cost_fraction: 1.,
+                    };
+                    ctx.db
+                        .add_and_queue_group(
+                            spctx,
+                            &ctx.idx,
+                            side,
+                            loc,
+                            template,
+                            origin,
+                            BitFlags::empty(),
+                            None,
+                        )
+                        .context("adding group")?;
+                }
             }
         }
     }
+    Ok(())
+}
+
+fn admin_spawn(ctx: &mut Context, lua: MizLua, id: Option<PlayerId>, key: String) -> Result<()> {
+    let mut to_remove: SmallVec<[MarkId; 8]> = smallvec![];
+    let act = Trigger::singleton(lua)?.action()?;
+    let spctx = SpawnCtx::new(lua)?;
+    let key = format_compact!("{} ", key);
+    let ucid = caller_ucid(ctx, id)?;
     for mk in World::singleton(lua)?
         .get_mark_panels()
         .context("getting marks")?
@@ -388,7 +529,7 @@ fn admin_spawn(ctx: &mut Context, lua: MizLua, id: Option<PlayerId>, key: String
                         spec
                     )
                 })?
-                .parse::<Kind>()?;
+                .parse::<SpawnKind>()?;
             let side = iter
                 .next()
                 .ok_or_else(|| anyhow!("spawn mark {} missing side", spec))?;
@@ -404,89 +545,14 @@ fn admin_spawn(ctx: &mut Context, lua: MizLua, id: Option<PlayerId>, key: String
             let name = iter
                 .next()
                 .ok_or_else(|| anyhow!("spawn mark {} missing name of the thing to spawn", spec))?;
-            let pos = Vector2::new(mk.pos.x, mk.pos.z);
-            let loc = SpawnLoc::AtPos {
-                pos,
-                offset_direction: pointing_towards2(heading),
-                group_heading: heading,
+            let spec = SpawnSpec {
+                kind,
+                side,
+                name: name.into(),
+                pos: Vector2::new(mk.pos.x, mk.pos.z),
+                heading,
             };
-            match kind {
-                Kind::Troop => {
-                    let specs = ctx
-                        .db
-                        .ephemeral
-                        .cfg
-                        .troops
-                        .get(&side)
-                        .ok_or_else(|| anyhow!("no troops on {side}"))?;
-                    let spec = specs
-                        .iter()
-                        .find(|tr| tr.name.as_str() == name)
-                        .ok_or_else(|| anyhow!("no troop called {name} on {side}"))?
-                        .clone();
-                    let origin = DeployKind::Troop {
-                        player: ucid,
-                        moved_by: None,
-                        spec: spec.clone(),
-                        origin: None,
-                        cost_fraction: 1.,
-                    };
-                    ctx.db
-                        .add_and_queue_group(
-                            &spctx,
-                            &ctx.idx,
-                            side,
-                            loc,
-                            &spec.template,
-                            origin,
-                            BitFlags::empty(),
-                            None,
-                        )
-                        .context("adding group")?;
-                }
-                Kind::Deployable => {
-                    let specs = ctx
-                        .db
-                        .ephemeral
-                        .cfg
-                        .deployables
-                        .get(&side)
-                        .ok_or_else(|| anyhow!("no deployables on {side}"))?;
-                    let spec = specs
-                        .iter()
-                        .find(|dp| dp.path.ends_with(&[String::from(name)]))
-                        .ok_or_else(|| anyhow!("no deployable called {name} on {side}"))?
-                        .clone();
-                    match &spec.kind {
-                        DeployableKind::Objective(parts) => {
-                            ctx.db
-                                .add_farp(lua, &spctx, &ctx.idx, side, pos, &spec, parts)
-                                .context("adding farp")?;
-                        }
-                        DeployableKind::Group { template } => {
-                            let origin = DeployKind::Deployed {
-                                player: ucid,
-                                moved_by: None,
-                                spec: spec.clone(),
-                                origin: None,
-                                cost_fraction: 1.,
-                            };
-                            ctx.db
-                                .add_and_queue_group(
-                                    &spctx,
-                                    &ctx.idx,
-                                    side,
-                                    loc,
-                                    &template,
-                                    origin,
-                                    BitFlags::empty(),
-                                    None,
-                                )
-                                .context("adding group")?;
-                        }
-                    }
-                }
-            }
+            spawn_at(ctx, lua, &spctx, ucid, &spec)?
         }
     }
     for id in to_remove {
@@ -591,11 +657,21 @@ fn admin_ban(
     name: &String,
 ) -> Result<()> {
     let ucid = get_player_ucid(ctx, name.as_str())?;
+    ban(ctx, lua, ucid, until)
+}
+
+/// add the player to the ban list and kick them if they are connected
+pub(super) fn ban(
+    ctx: &mut Context,
+    lua: MizLua,
+    ucid: Ucid,
+    until: Option<DateTime<Utc>>,
+) -> Result<()> {
     let name = ctx
         .db
         .player(&ucid)
         .map(|p| p.name.clone())
-        .unwrap_or_else(|| name.clone());
+        .unwrap_or_else(|| String::from(ucid.to_string()));
     with_mut_cfg(ctx, |cfg| {
         cfg.banned.insert(ucid.clone(), (until, name));
         Ok(())
@@ -703,7 +779,7 @@ fn admin_reset_lives(ctx: &mut Context, player: &String) -> Result<()> {
 pub(super) fn admin_shutdown(
     ctx: &mut Context,
     lua: MizLua,
-    reset: Option<Option<Side>>,
+    reset: Option<(Option<Side>, RoundEndReason)>,
 ) -> Result<AdminResult> {
     let wait = Arc::new((Mutex::new(false), Condvar::new()));
     let se = {
@@ -715,10 +791,10 @@ pub(super) fn admin_shutdown(
             api_perf: (*api_perf.0).clone(),
         }
     };
-    if let Some(winner) = reset {
+    if let Some((winner, reason)) = reset {
         ctx.do_bg_task(Task::ResetState(ctx.miz_state_path.clone()));
         ctx.do_bg_task(Task::Stat(se));
-        ctx.do_bg_task(Task::Stat(Stat::RoundEnd { winner }));
+        ctx.do_bg_task(Task::Stat(Stat::RoundEnd { winner, reason }));
     } else {
         return_lives(lua, ctx, DateTime::<Utc>::MAX_UTC);
         ctx.do_bg_task(Task::SaveState(
@@ -799,6 +875,7 @@ fn delete(ctx: &mut Context, id: &GroupId) -> Result<()> {
         DeployKind::Crate { .. }
         | DeployKind::Deployed { .. }
         | DeployKind::Troop { .. }
+        | DeployKind::Convoy { .. }
         | DeployKind::Action { .. } => ctx.db.delete_group(id),
     }
 }
@@ -823,16 +900,45 @@ fn remark(ctx: &mut Context, objective: &String) -> Result<()> {
     Ok(())
 }
 
+fn grief_log(ctx: &Context, player: &String) -> Result<Vec<(u32, GriefEntry)>> {
+    let ucid = get_player_ucid(ctx, player)?;
+    ctx.db.grief_log(&ucid)
+}
+
+fn forgive(ctx: &mut Context, player: &String, id: u32) -> Result<()> {
+    let ucid = get_player_ucid(ctx, player)?;
+    if let GriefAction::Banned(until) = ctx.db.forgive(&ucid, id)? {
+        // leave a ban an admin placed since alone
+        with_mut_cfg(ctx, |cfg| {
+            if cfg.banned.get(&ucid).is_some_and(|(u, _)| *u == until) {
+                cfg.banned.remove(&ucid);
+            }
+            Ok(())
+        })?
+    }
+    Ok(())
+}
+
+/// only connected players on the admin list may run admin commands
+pub(super) fn is_admin(ctx: &Context, id: &PlayerId) -> bool {
+    ctx.connected
+        .get(id)
+        .map(|ifo| ctx.db.ephemeral.cfg.admins.contains_key(&ifo.ucid))
+        .unwrap_or(false)
+}
+
 #[derive(Debug)]
 pub(super) enum Caller {
     Player(PlayerId),
-    External(oneshot::Sender<NetIdxValue>),
+    /// an rpc, or a lua api call, which may be made on behalf of a
+    /// player
+    External(Option<PlayerId>, oneshot::Sender<NetIdxValue>),
 }
 
 pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<AdminResult> {
     let mut cmds = mem::take(&mut ctx.admin_commands);
     while let Some((cmd, ch)) = ctx.external_admin_commands.pop() {
-        cmds.push((Caller::External(ch), cmd));
+        cmds.push((Caller::External(None, ch), cmd));
     }
     let mut result = AdminResult::Continue;
     for (caller, cmd) in cmds.drain(..) {
@@ -843,7 +949,7 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<Admin
                     Caller::Player(id) => {
                         ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), format_compact!($($arg),+))
                     },
-                    Caller::External(_) => {
+                    Caller::External(..) => {
                         replies.push(NetIdxValue::from(format!($($arg),+)));
                     }
                 }
@@ -856,7 +962,7 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<Admin
                     Caller::Player(id) => {
                         ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), format_compact!($($arg),+))
                     },
-                    Caller::External(_) => {
+                    Caller::External(..) => {
                         replies.push(NetIdxValue::Error(format!($($arg),+).into()));
                     }
                 }
@@ -931,12 +1037,26 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<Admin
             AdminCommand::Spawn { key } => {
                 let id = match &caller {
                     Caller::Player(id) => Some(*id),
-                    Caller::External(_) => None,
+                    Caller::External(id, _) => *id,
                 };
                 if let Err(e) = admin_spawn(ctx, lua, id, key) {
                     reply_ok!("could not spawn {:?}", e)
                 }
             }
+            AdminCommand::SpawnAt(spec) => {
+                let id = match &caller {
+                    Caller::Player(id) => Some(*id),
+                    Caller::External(id, _) => *id,
+                };
+                let res = caller_ucid(ctx, id).and_then(|ucid| {
+                    let spctx = SpawnCtx::new(lua)?;
+                    spawn_at(ctx, lua, &spctx, ucid, &spec)
+                });
+                match res {
+                    Ok(()) => reply_ok!("spawned {}", spec.name),
+                    Err(e) => reply_err!("could not spawn {} {e:?}", spec.name),
+                }
+            }
             AdminCommand::SideSwitch { side, player } => {
                 if let Err(e) = admin_sideswitch(ctx, side, player.clone()) {
                     reply_err!("could not sideswitch {:?}", e)
@@ -981,7 +1101,7 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<Admin
                 }
             }
             AdminCommand::Logdesc => match &caller {
-                Caller::External(_) => reply_err!("external clients can't be in a plane"),
+                Caller::External(..) => reply_err!("external clients can't be in a plane"),
                 Caller::Player(id) => match ctx.connected.get(&id) {
                     None => reply_err!("no player {id}"),
                     Some(ifo) => match admin_log_desc(ctx, lua, &ifo.ucid) {
@@ -1029,17 +1149,32 @@ pub(super) fn run_admin_commands(ctx: &mut Context, lua: MizLua) -> Result<Admin
                 Ok(()) => reply_ok!("{objective} remark queued"),
                 Err(e) => reply_err!("could not remark {objective} {e:?}"),
             },
-            AdminCommand::Reset { winner } => match admin_shutdown(ctx, lua, Some(winner)) {
-                Ok(s) => {
-                    result = s;
-                    reply_ok!("the state has been reset");
+            AdminCommand::Grief { player } => match grief_log(ctx, &player) {
+                Ok(log) if log.is_empty() => reply_ok!("{player} has a clean record"),
+                Ok(log) => {
+                    for (id, entry) in log {
+                        reply_ok!("{id} {entry}")
+                    }
                 }
-                Err(e) => reply_err!("the state could not be reset {e:?}"),
+                Err(e) => reply_err!("could not get {player}'s grief log {e:?}"),
+            },
+            AdminCommand::Forgive { player, id } => match forgive(ctx, &player, id) {
+                Ok(()) => reply_ok!("{player}'s grief log entry {id} reversed"),
+                Err(e) => reply_err!("could not reverse {player}'s grief log entry {id} {e:?}"),
             },
+            AdminCommand::Reset { winner } => {
+                match admin_shutdown(ctx, lua, Some((winner, RoundEndReason::Admin))) {
+                    Ok(s) => {
+                        result = s;
+                        reply_ok!("the state has been reset");
+                    }
+                    Err(e) => reply_err!("the state could not be reset {e:?}"),
+                }
+            }
         }
         match caller {
             Caller::Player(_) => (),
-            Caller::External(ch) => {
+            Caller::External(_, ch) => {
                 if replies.len() == 1 {
                     let _ = ch.send(replies.pop().unwrap());
                 } else {
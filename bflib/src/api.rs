@@ -0,0 +1,507 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! The lua api, exported from the module as `api`. It mirrors the
+//! netidx rpcs and the admin commands so bots and mission scripts can
+//! drive the campaign. Every function takes the caller as its first
+//! argument. The caller is the id of a connected player, who must be
+//! an admin, exactly as for chat admin commands. The caller may be nil,
+//! meaning the server itself, only if `trusted_lua_api` is set in the
+//! config.
+//!
+//! Any script can name any player as the caller, so commands and
+//! actions, which change the campaign, are refused unless
+//! `trusted_lua_api` is set. Without it only the read only queries are
+//! available.
+//!
+//! Queries return their result directly. Commands are queued and run
+//! on the next timed event, like the rpcs they return a handle, and
+//! `api.result(caller, handle)` returns nil until the command has run,
+//! then a list of `{ ok = bool, msg = string }` replies. Only the caller
+//! that queued a command can collect it's result. Results that aren't
+//! collected within ten minutes are dropped.
+
+use crate::{
+    Context,
+    admin::{self, AdminCommand, Caller, SpawnKind, SpawnSpec, WarehouseKind},
+    db::logistics::Inventory,
+};
+use anyhow::{Result, anyhow, bail};
+use bfprotocols::{
+    cfg::LifeType,
+    db::{
+        group::GroupId,
+        objective::{ObjectiveId, ObjectiveKind},
+    },
+};
+use chrono::{Duration, prelude::*};
+use dcso3::{
+    String, Vector2,
+    coalition::Side,
+    degrees_to_radians, lua_err,
+    net::{PlayerId, Ucid},
+};
+use fxhash::FxHashMap;
+use log::info;
+use mlua::{LuaSerdeExt, prelude::*};
+use netidx::publisher::Value as NetIdxValue;
+use regex::RegexBuilder;
+use serde::Serialize;
+use std::str::FromStr;
+use tokio::sync::oneshot::{self, error::TryRecvError};
+
+/// how long the result of a command is kept if nobody collects it
+const RESULT_TTL: Duration = Duration::minutes(10);
+
+/// when a command was queued, who queued it, and it's result
+type Queued = (
+    DateTime<Utc>,
+    Option<PlayerId>,
+    oneshot::Receiver<NetIdxValue>,
+);
+
+/// admin commands queued from lua whose results haven't been collected
+#[derive(Debug, Default)]
+pub(super) struct Pending {
+    next: u64,
+    results: FxHashMap<u64, Queued>,
+}
+
+#[derive(Serialize)]
+struct Reply {
+    ok: bool,
+    msg: std::string::String,
+}
+
+#[derive(Serialize)]
+struct ObjectiveInfo<'a> {
+    id: ObjectiveId,
+    name: &'a str,
+    kind: &'a ObjectiveKind,
+    owner: Side,
+    health: u8,
+    logi: u8,
+    supply: u8,
+    fuel: u8,
+    points: i32,
+}
+
+#[derive(Serialize)]
+struct Stored {
+    stored: u32,
+    capacity: u32,
+}
+
+#[derive(Serialize)]
+struct InventoryInfo {
+    equipment: FxHashMap<String, Stored>,
+    liquids: FxHashMap<std::string::String, Stored>,
+}
+
+#[derive(Serialize)]
+struct ConnectedInfo<'a> {
+    id: PlayerId,
+    ucid: Ucid,
+    name: &'a str,
+    side: Option<Side>,
+    points: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct PlayerInfo<'a> {
+    ucid: Ucid,
+    name: &'a str,
+    side: Side,
+    points: i32,
+    lives: FxHashMap<LifeType, u8>,
+    connected: Option<PlayerId>,
+}
+
+#[derive(Serialize)]
+struct Stats<'a> {
+    sortie: &'a str,
+    round_start: Option<DateTime<Utc>>,
+    owned: FxHashMap<Side, usize>,
+    tickets: FxHashMap<Side, u32>,
+    players: usize,
+    connected: usize,
+    nukes_used: u32,
+}
+
+fn authorize(ctx: &Context, caller: Option<PlayerId>) -> Result<()> {
+    match caller {
+        None if ctx.db.ephemeral.cfg.trusted_lua_api => Ok(()),
+        None => bail!("the caller must be an admin unless trusted_lua_api is set"),
+        Some(id) if admin::is_admin(ctx, &id) => Ok(()),
+        Some(id) => bail!("player {id} is not an admin"),
+    }
+}
+
+/// the caller can't be verified, so anything that changes the campaign
+/// also requires trusted_lua_api
+fn authorize_command(ctx: &Context, caller: Option<PlayerId>) -> Result<()> {
+    if !ctx.db.ephemeral.cfg.trusted_lua_api {
+        bail!("commands from lua require trusted_lua_api")
+    }
+    authorize(ctx, caller)
+}
+
+fn queue(ctx: &mut Context, caller: Option<PlayerId>, cmd: AdminCommand) -> Result<u64> {
+    authorize_command(ctx, caller)?;
+    info!(
+        "queueing admin command {:?} from lua caller {:?}",
+        cmd, caller
+    );
+    let now = Utc::now();
+    ctx.api
+        .results
+        .retain(|_, (ts, _, _)| now - *ts < RESULT_TTL);
+    let (tx, rx) = oneshot::channel();
+    ctx.admin_commands.push((Caller::External(caller, tx), cmd));
+    let handle = ctx.api.next;
+    ctx.api.next += 1;
+    ctx.api.results.insert(handle, (now, caller, rx));
+    Ok(handle)
+}
+
+fn reply(v: NetIdxValue) -> Reply {
+    match v {
+        NetIdxValue::Error(e) => Reply {
+            ok: false,
+            msg: e.to_string(),
+        },
+        NetIdxValue::String(s) => Reply {
+            ok: true,
+            msg: s.to_string(),
+        },
+        v => Reply {
+            ok: true,
+            msg: v.to_string(),
+        },
+    }
+}
+
+fn result(ctx: &mut Context, caller: Option<PlayerId>, handle: u64) -> Result<Option<Vec<Reply>>> {
+    authorize(ctx, caller)?;
+    let rx = match ctx.api.results.get_mut(&handle) {
+        Some((_, queued_by, rx)) if *queued_by == caller => rx,
+        _ => bail!("unknown handle {handle}"),
+    };
+    match rx.try_recv() {
+        Err(TryRecvError::Empty) => Ok(None),
+        Err(TryRecvError::Closed) => {
+            ctx.api.results.remove(&handle);
+            bail!("command {handle} failed")
+        }
+        Ok(v) => {
+            ctx.api.results.remove(&handle);
+            Ok(Some(match v {
+                NetIdxValue::Array(a) => a.iter().cloned().map(reply).collect(),
+                v => vec![reply(v)],
+            }))
+        }
+    }
+}
+
+/// run f, raising any error in lua
+fn lua_result<T, F: FnOnce() -> Result<T>>(f: F) -> LuaResult<T> {
+    f().map_err(lua_err)
+}
+
+fn side(s: &str) -> Result<Side> {
+    Side::from_str(s)
+}
+
+fn objectives(ctx: &Context) -> Vec<ObjectiveInfo<'_>> {
+    ctx.db
+        .objectives()
+        .map(|(id, obj)| ObjectiveInfo {
+            id: *id,
+            name: obj.name(),
+            kind: obj.kind(),
+            owner: obj.owner(),
+            health: obj.health(),
+            logi: obj.logi(),
+            supply: obj.supply(),
+            fuel: obj.fuel(),
+            points: obj.points,
+        })
+        .collect()
+}
+
+fn inventory(ctx: &Context, objective: &str) -> Result<InventoryInfo> {
+    let oid = ctx.db.persisted.find_objective(objective)?;
+    let obj = ctx.db.objective(&oid)?;
+    let stored = |inv: &Inventory| Stored {
+        stored: inv.stored,
+        capacity: inv.capacity,
+    };
+    Ok(InventoryInfo {
+        equipment: obj
+            .equipment()
+            .map(|(name, inv)| (name.clone(), stored(inv)))
+            .collect(),
+        liquids: obj
+            .liquids()
+            .map(|(name, inv)| (format!("{name:?}"), stored(inv)))
+            .collect(),
+    })
+}
+
+fn connected(ctx: &Context) -> Vec<ConnectedInfo<'_>> {
+    ctx.connected
+        .info_by_player_id
+        .iter()
+        .map(|(id, ifo)| {
+            let player = ctx.db.player(&ifo.ucid);
+            ConnectedInfo {
+                id: *id,
+                ucid: ifo.ucid,
+                name: ifo.name.as_str(),
+                side: player.map(|p| p.side),
+                points: player.map(|p| p.points),
+            }
+        })
+        .collect()
+}
+
+fn player<'a>(ctx: &'a Context, key: &str) -> Result<PlayerInfo<'a>> {
+    let ucid = match admin::get_player_ucid(ctx, key) {
+        Ok(ucid) => ucid,
+        Err(_) => ctx.db.persisted.find_player(key)?,
+    };
+    let player = ctx
+        .db
+        .player(&ucid)
+        .ok_or_else(|| anyhow!("no such player {key}"))?;
+    Ok(PlayerInfo {
+        ucid,
+        name: player.name.as_str(),
+        side: player.side,
+        points: player.points,
+        lives: player
+            .lives
+            .into_iter()
+            .map(|(lt, (_, n))| (*lt, *n))
+            .collect(),
+        connected: ctx.connected.id_by_ucid.get(&ucid).copied(),
+    })
+}
+
+fn tickets(ctx: &Context) -> FxHashMap<Side, u32> {
+    [Side::Red, Side::Blue]
+        .into_iter()
+        .filter_map(|side| Some((side, ctx.db.tickets(side)?)))
+        .collect()
+}
+
+fn stats(ctx: &Context) -> Stats<'_> {
+    let mut owned: FxHashMap<Side, usize> = FxHashMap::default();
+    for (_, obj) in ctx.db.objectives() {
+        *owned.entry(obj.owner()).or_default() += 1;
+    }
+    Stats {
+        sortie: ctx.sortie.as_str(),
+        round_start: ctx.db.persisted.round_start,
+        owned,
+        tickets: tickets(ctx),
+        players: ctx.db.persisted.players().len(),
+        connected: ctx.connected.info_by_player_id.len(),
+        nukes_used: ctx.db.persisted.nukes_used,
+    }
+}
+
+/// define a lua function that runs a query as caller and returns the
+/// result converted to lua
+macro_rules! query {
+    ($t:expr, $lua:expr, $name:literal, |$ctx:ident $(, $arg:ident: $typ:ty)*| $body:expr) => {
+        $t.set(
+            $name,
+            $lua.create_function(
+                |lua, (caller, $($arg),*): (Option<PlayerId>, $($typ),*)| {
+                    let $ctx = unsafe { Context::get_mut() };
+                    authorize($ctx, caller).map_err(lua_err)?;
+                    let res = lua_result(|| Ok($body))?;
+                    lua.to_value(&res)
+                },
+            )?,
+        )?
+    };
+}
+
+/// define a lua function that queues an admin command as caller and
+/// returns a handle to it's result
+macro_rules! command {
+    ($t:expr, $lua:expr, $name:literal, || $cmd:expr) => {
+        command!($t, $lua, $name, | | $cmd)
+    };
+    ($t:expr, $lua:expr, $name:literal, |$($arg:ident: $typ:ty),*| $cmd:expr) => {
+        $t.set(
+            $name,
+            $lua.create_function(
+                |_, (caller, $($arg),*): (Option<PlayerId>, $($typ),*)| {
+                    let ctx = unsafe { Context::get_mut() };
+                    let cmd: AdminCommand = lua_result(|| Ok($cmd))?;
+                    queue(ctx, caller, cmd).map_err(lua_err)
+                },
+            )?,
+        )?
+    };
+}
+
+pub(super) fn create(lua: &Lua) -> LuaResult<LuaTable<'_>> {
+    let api = lua.create_table()?;
+    query!(api, lua, "objectives", |ctx| objectives(ctx));
+    query!(api, lua, "inventory", |ctx, objective: String| inventory(
+        ctx, &objective
+    )?);
+    query!(api, lua, "connected", |ctx| connected(ctx));
+    query!(api, lua, "player", |ctx, key: String| player(ctx, &key)?);
+    query!(api, lua, "tickets", |ctx| tickets(ctx));
+    query!(api, lua, "stats", |ctx| stats(ctx));
+    api.set(
+        "result",
+        lua.create_function(|lua, (caller, handle): (Option<PlayerId>, u64)| {
+            let ctx = unsafe { Context::get_mut() };
+            match result(ctx, caller, handle).map_err(lua_err)? {
+                None => Ok(LuaValue::Nil),
+                Some(replies) => lua.to_value(&replies),
+            }
+        })?,
+    )?;
+    api.set(
+        "action",
+        lua.create_function(|_, (caller, cmd): (Option<PlayerId>, String)| {
+            let ctx = unsafe { Context::get_mut() };
+            let id = caller.ok_or_else(|| lua_err("actions must be started by a player"))?;
+            authorize_command(ctx, Some(id)).map_err(lua_err)?;
+            ctx.action_commands.push((id, cmd));
+            Ok(())
+        })?,
+    )?;
+    command!(api, lua, "command", |cmd: String| cmd
+        .parse::<AdminCommand>()?);
+    command!(
+        api,
+        lua,
+        "reduceInventory",
+        |airbase: String, amount: u8| AdminCommand::ReduceInventory { airbase, amount }
+    );
+    command!(api, lua, "transferSupply", |from: String, to: String| {
+        AdminCommand::TransferSupply { from, to }
+    });
+    command!(api, lua, "logisticsTickNow", || {
+        AdminCommand::LogisticsTickNow
+    });
+    command!(api, lua, "logisticsDeliverNow", || {
+        AdminCommand::LogisticsDeliverNow
+    });
+    command!(api, lua, "repair", |airbase: String| AdminCommand::Repair {
+        airbase
+    });
+    command!(
+        api,
+        lua,
+        "tim",
+        |key: String, size: Option<usize>, alt: Option<isize>| {
+            AdminCommand::Tim {
+                key,
+                size: size.unwrap_or(3000),
+                alt,
+            }
+        }
+    );
+    command!(api, lua, "spawn", |key: String| AdminCommand::Spawn { key });
+    command!(
+        api,
+        lua,
+        "spawnAt",
+        |kind: String, s: String, name: String, x: f64, y: f64, heading: u32| {
+            AdminCommand::SpawnAt(SpawnSpec {
+                kind: kind.parse::<SpawnKind>()?,
+                side: side(&s)?,
+                name,
+                pos: Vector2::new(x, y),
+                heading: degrees_to_radians(heading as f64),
+            })
+        }
+    );
+    command!(api, lua, "sideSwitch", |player: String, s: String| {
+        AdminCommand::SideSwitch {
+            side: side(&s)?,
+            player,
+        }
+    });
+    command!(api, lua, "ban", |player: String, seconds: Option<i64>| {
+        AdminCommand::Ban {
+            player,
+            until: seconds.map(|s| Utc::now() + Duration::seconds(s)),
+        }
+    });
+    command!(api, lua, "unban", |player: String| AdminCommand::Unban {
+        player
+    });
+    command!(api, lua, "kick", |player: String| AdminCommand::Kick {
+        player
+    });
+    command!(api, lua, "banned", || AdminCommand::Banned);
+    command!(api, lua, "search", |expr: String| AdminCommand::Search {
+        expr: RegexBuilder::new(&expr).case_insensitive(true).build()?,
+    });
+    command!(api, lua, "logWarehouse", |kind: String, airbase: String| {
+        AdminCommand::LogWarehouse {
+            kind: kind.parse::<WarehouseKind>()?,
+            airbase,
+        }
+    });
+    command!(api, lua, "resetLives", |player: String| {
+        AdminCommand::ResetLives { player }
+    });
+    command!(api, lua, "addAdmin", |player: String| {
+        AdminCommand::AddAdmin { player }
+    });
+    command!(api, lua, "removeAdmin", |player: String| {
+        AdminCommand::RemoveAdmin { player }
+    });
+    command!(api, lua, "balance", |player: String| {
+        AdminCommand::Balance { player }
+    });
+    command!(api, lua, "setPoints", |player: String, amount: i32| {
+        AdminCommand::SetPoints { amount, player }
+    });
+    command!(api, lua, "delete", |group: i64| AdminCommand::Delete {
+        group: GroupId::from(group)
+    });
+    command!(api, lua, "deslot", |player: String| AdminCommand::Deslot {
+        player
+    });
+    command!(api, lua, "remark", |objective: String| {
+        AdminCommand::Remark { objective }
+    });
+    command!(api, lua, "grief", |player: String| AdminCommand::Grief {
+        player
+    });
+    command!(api, lua, "forgive", |player: String, id: u32| {
+        AdminCommand::Forgive { player, id }
+    });
+    command!(api, lua, "reset", |winner: Option<String>| {
+        AdminCommand::Reset {
+            winner: winner.map(|s| side(&s)).transpose()?,
+        }
+    });
+    command!(api, lua, "shutdown", || AdminCommand::Shutdown);
+    Ok(api)
+}
@@ -17,9 +17,15 @@ for more details.
 mod logpub;
 mod perf;
 mod rpcs;
+mod statsfile;
 mod statspub;
+mod supplypub;
+mod ticketpub;
 
-use crate::{admin::AdminCommand, db::persisted::Persisted};
+use crate::{
+    admin::AdminCommand,
+    db::{logistics::SupplyNetwork, persisted::Persisted},
+};
 use anyhow::{Context, Result, anyhow, bail};
 use bfprotocols::{
     cfg::Cfg,
@@ -46,6 +52,7 @@ use perf::PubPerf;
 use rpcs::Rpcs;
 use serde::Serialize;
 use simplelog::{LevelFilter, WriteLogger};
+use statsfile::StatsFile;
 use statspub::Statspub;
 use std::{
     cell::RefCell,
@@ -56,6 +63,8 @@ use std::{
     sync::Arc,
     thread,
 };
+use supplypub::PubSupply;
+use ticketpub::PubTickets;
 use tokio::{
     fs::File,
     io::AsyncWriteExt,
@@ -102,7 +111,7 @@ fn encode<T: Serialize>(db: &T) -> Result<BytesMut> {
     })
 }
 
-fn rotate_state(path: &Path) -> Result<()> {
+pub(crate) fn rotate_state(path: &Path) -> Result<()> {
     if path.exists() {
         let name = path
             .file_name()
@@ -115,9 +124,11 @@ fn rotate_state(path: &Path) -> Result<()> {
         write!(backup, "{}", now.timestamp()).unwrap();
         with_ts.set_file_name(backup);
         fs::rename(path, with_ts)?;
-        let dir = path
-            .parent()
-            .ok_or_else(|| anyhow!("path has no parent dir"))?;
+        let dir = match path.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+            Some(dir) => dir,
+            None => bail!("path has no parent dir"),
+        };
         let mut by_age: FxHashMap<i64, Vec<(i64, PathBuf)>> = FxHashMap::default();
         for file in fs::read_dir(dir)? {
             let file = file?;
@@ -253,6 +264,11 @@ pub(super) enum Task {
         perf: Perf,
         api_perf: ApiPerf,
     },
+    LogSupply(SupplyNetwork),
+    LogTickets {
+        red: u32,
+        blue: u32,
+    },
     Shutdown(Arc<(Mutex<bool>, Condvar)>),
     Stat(Stat),
 }
@@ -261,13 +277,18 @@ enum Logs {
     Netidx {
         publisher: Publisher,
         perf: PubPerf,
+        supply: PubSupply,
+        tickets: PubTickets,
         stats: Statspub,
+        stats_log: Option<StatsFile>,
         log: LogPublisher,
     },
     Files {
         log_path: PathBuf,
         log_file: Option<File>,
         stats_path: PathBuf,
+        stats_log_path: PathBuf,
+        stats_log: Option<StatsFile>,
     },
 }
 
@@ -279,6 +300,8 @@ impl Logs {
                 log_path,
                 log_file,
                 stats_path: _,
+                stats_log_path,
+                stats_log,
             } => {
                 *log_file = Some(
                     File::options()
@@ -287,6 +310,9 @@ impl Logs {
                         .open(&log_path)
                         .await?,
                 );
+                if stats_log.is_none() {
+                    *stats_log = Some(StatsFile::new(stats_log_path)?);
+                }
                 Ok(())
             }
         }
@@ -295,11 +321,14 @@ impl Logs {
     async fn new(write_dir: &Path) -> Result<Self> {
         let stats_path = write_dir.join("Logs").join("stats");
         let log_path = write_dir.join("Logs").join("bfnext.txt");
+        let stats_log_path = write_dir.join("Logs").join("bfstats.zst");
         rotate_log(&log_path);
         let mut t = Self::Files {
             log_file: None,
             log_path,
             stats_path,
+            stats_log_path,
+            stats_log: None,
         };
         t.open_files().await?;
         Ok(t)
@@ -318,8 +347,22 @@ impl Logs {
 
     fn write_stat(&mut self, stat: &Stat) -> Result<()> {
         match self {
-            Self::Files { .. } => Ok(()),
-            Self::Netidx { stats, .. } => stats.append(Utc::now(), stat),
+            Self::Files {
+                stats_log: Some(stats_log),
+                ..
+            } => stats_log.append(Utc::now(), stat),
+            Self::Files { .. } => bail!("stats log is closed"),
+            Self::Netidx {
+                stats, stats_log, ..
+            } => {
+                let now = Utc::now();
+                // bfdb replays from the stats log, so write it even
+                // when the stats are archived by netidx
+                if let Some(stats_log) = stats_log {
+                    stats_log.append(now, stat)?
+                }
+                stats.append(now, stat)
+            }
         }
     }
 
@@ -338,6 +381,34 @@ impl Logs {
         }
     }
 
+    async fn log_supply(&mut self, net: &SupplyNetwork) {
+        match self {
+            Self::Files { .. } => (),
+            Self::Netidx {
+                publisher, supply, ..
+            } => {
+                let mut batch = publisher.start_batch();
+                if let Err(e) = supply.update(publisher, &mut batch, net) {
+                    error!("failed to publish the supply network {e:?}")
+                }
+                batch.commit(None).await
+            }
+        }
+    }
+
+    async fn log_tickets(&self, red: u32, blue: u32) {
+        match self {
+            Self::Files { .. } => (),
+            Self::Netidx {
+                publisher, tickets, ..
+            } => {
+                let mut batch = publisher.start_batch();
+                tickets.update(&mut batch, red, blue);
+                batch.commit(None).await
+            }
+        }
+    }
+
     async fn switch_to_netidx(
         &mut self,
         publisher: Publisher,
@@ -350,6 +421,8 @@ impl Logs {
                 log_path,
                 log_file,
                 stats_path,
+                stats_log_path: _,
+                stats_log,
             } => {
                 drop(log_file.take());
                 let go = || async {
@@ -361,6 +434,10 @@ impl Logs {
                         &ApiPerfStat::default(),
                     )
                     .context("starting pubperf")?;
+                    let supply =
+                        PubSupply::new(&publisher, &base).context("starting supply pub")?;
+                    let tickets =
+                        PubTickets::new(&publisher, &base).context("starting tickets pub")?;
                     let stats = Statspub::new(
                         publisher.clone(),
                         &cfg,
@@ -374,12 +451,18 @@ impl Logs {
                     Ok::<_, anyhow::Error>(Self::Netidx {
                         publisher: publisher.clone(),
                         perf,
+                        supply,
+                        tickets,
                         stats,
+                        stats_log: None,
                         log,
                     })
                 };
                 match go().await {
-                    Ok(t) => {
+                    Ok(mut t) => {
+                        if let Self::Netidx { stats_log: l, .. } = &mut t {
+                            *l = stats_log.take();
+                        }
                         *self = t;
                         Ok(())
                     }
@@ -396,22 +479,41 @@ impl Logs {
 
     fn flush_stats(&mut self) -> Result<()> {
         match self {
+            Self::Files {
+                stats_log: Some(stats_log),
+                ..
+            } => stats_log.flush(),
             Self::Files { .. } => Ok(()),
-            Self::Netidx { stats, .. } => task::block_in_place(|| stats.flush()),
+            Self::Netidx {
+                stats, stats_log, ..
+            } => {
+                if let Some(stats_log) = stats_log {
+                    stats_log.flush()?
+                }
+                task::block_in_place(|| stats.flush())
+            }
         }
     }
 
     async fn shutdown(&mut self) {
         match self {
-            Self::Files { .. } => (),
+            Self::Files { stats_log, .. } => {
+                if let Some(stats_log) = stats_log.take() {
+                    let _ = stats_log.finish();
+                }
+            }
             Self::Netidx {
                 publisher,
                 log,
                 stats,
+                stats_log,
                 ..
             } => {
                 let _ = log.close().await;
                 let _ = task::block_in_place(|| stats.flush());
+                if let Some(stats_log) = stats_log.take() {
+                    let _ = stats_log.finish();
+                }
                 publisher.clone().shutdown().await
             }
         }
@@ -504,6 +606,8 @@ async fn background_loop(write_dir: PathBuf, mut rx: UnboundedReceiver<Task>) {
             } => {
                 logs.log_perf(players, &perf.stat(), &api_perf.stat()).await;
             }
+            Task::LogSupply(net) => logs.log_supply(&net).await,
+            Task::LogTickets { red, blue } => logs.log_tickets(red, blue).await,
             Task::Shutdown(a) => {
                 println!("starting netidx shutdown");
                 logs.shutdown().await;
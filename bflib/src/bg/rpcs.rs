@@ -44,6 +44,8 @@ pub struct Rpcs {
     _delete: Proc,
     _deslot: Proc,
     _remark: Proc,
+    _grief: Proc,
+    _forgive: Proc,
     _reset: Proc,
     _shutdown: Proc,
 }
@@ -416,6 +418,35 @@ impl Rpcs {
             objective: Chars = Value::Null; "The objective to remark"
         )?;
         let _q = Arc::clone(&q);
+        let grief = define_rpc!(
+            publisher,
+            base.append("grief"),
+            "List a player's offenses against their own side and the penalties applied",
+            |c: RpcCall, player: Chars| {
+                let (tx, rx) = oneshot::channel();
+                let cmd = AdminCommand::Grief { player: player.as_ref().into() };
+                _q.push((cmd, tx));
+                Some((c, rx))
+            },
+            Some(wait.clone()),
+            player: Chars = Value::Null; "The player"
+        )?;
+        let _q = Arc::clone(&q);
+        let forgive = define_rpc!(
+            publisher,
+            base.append("forgive"),
+            "Reverse an entry in a player's grief log",
+            |c: RpcCall, player: Chars, id: u32| {
+                let (tx, rx) = oneshot::channel();
+                let cmd = AdminCommand::Forgive { player: player.as_ref().into(), id };
+                _q.push((cmd, tx));
+                Some((c, rx))
+            },
+            Some(wait.clone()),
+            player: Chars = Value::Null; "The player",
+            id: u32 = Value::Null; "The grief log entry to reverse"
+        )?;
+        let _q = Arc::clone(&q);
         let reset = define_rpc!(
             publisher,
             base.append("reset"),
@@ -472,6 +503,8 @@ impl Rpcs {
             _delete: delete,
             _deslot: deslot,
             _remark: remark,
+            _grief: grief,
+            _forgive: forgive,
             _reset: reset,
             _shutdown: shutdown,
         })
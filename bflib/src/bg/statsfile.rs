@@ -0,0 +1,74 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use anyhow::Result;
+use bfprotocols::stats::{Stat, StatEntry};
+use chrono::{Duration, prelude::*};
+use std::{fs::File, io::Write, path::Path};
+use tokio::task;
+
+use super::{encode, rotate_log};
+
+/// stats written as zstd compressed json lines for bfdb to replay,
+/// alongside the netidx archive when stats are also published
+pub(super) struct StatsFile {
+    file: zstd::stream::Encoder<'static, File>,
+    last: DateTime<Utc>,
+}
+
+impl StatsFile {
+    pub(super) fn new(path: &Path) -> Result<Self> {
+        rotate_log(path);
+        let file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self {
+            file: zstd::stream::Encoder::new(file, 9)?,
+            last: DateTime::<Utc>::MIN_UTC,
+        })
+    }
+
+    pub(super) fn append(&mut self, ts: DateTime<Utc>, stat: &Stat) -> Result<()> {
+        let seq = if ts > self.last {
+            ts
+        } else {
+            self.last + Duration::nanoseconds(1)
+        };
+        self.last = seq;
+        let mut buf = encode(&StatEntry {
+            seq,
+            time: ts,
+            kind: stat,
+        })?;
+        buf.extend_from_slice(b"\n");
+        task::block_in_place(|| Ok(self.file.write_all(&buf)?))
+    }
+
+    /// flush the compressed stream so everything written so far can
+    /// be read back even if we crash
+    pub(super) fn flush(&mut self) -> Result<()> {
+        task::block_in_place(|| Ok(self.file.flush()?))
+    }
+
+    pub(super) fn finish(self) -> Result<()> {
+        task::block_in_place(|| {
+            self.file.finish()?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,114 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use crate::db::logistics::{SupplyNetwork, SupplyNode};
+use anyhow::Result;
+use bfprotocols::db::objective::ObjectiveId;
+use fxhash::{FxHashMap, FxHashSet};
+use netidx::{
+    path::Path,
+    publisher::{Publisher, UpdateBatch, Val, Value},
+};
+
+struct PubNode {
+    name: String,
+    owner: Val,
+    hub: Val,
+    supplier: Val,
+    supply: Val,
+    fuel: Val,
+    pending: Val,
+    in_transit: Val,
+}
+
+impl PubNode {
+    fn new(publisher: &Publisher, base: &Path, node: &SupplyNode, supplier: Value) -> Result<Self> {
+        let base = base.append(&Path::escape(node.name.as_str()));
+        Ok(Self {
+            name: node.name.to_string(),
+            owner: publisher.publish(base.append("owner"), node.owner.to_string())?,
+            hub: publisher.publish(base.append("hub"), node.hub)?,
+            supplier: publisher.publish(base.append("supplier"), supplier)?,
+            supply: publisher.publish(base.append("supply"), node.supply)?,
+            fuel: publisher.publish(base.append("fuel"), node.fuel)?,
+            pending: publisher.publish(base.append("pending"), node.pending)?,
+            in_transit: publisher.publish(base.append("in_transit"), node.in_transit)?,
+        })
+    }
+
+    fn update(&self, batch: &mut UpdateBatch, node: &SupplyNode, supplier: Value) {
+        self.owner.update_changed(batch, node.owner.to_string());
+        self.hub.update_changed(batch, node.hub);
+        self.supplier.update_changed(batch, supplier);
+        self.supply.update_changed(batch, node.supply);
+        self.fuel.update_changed(batch, node.fuel);
+        self.pending.update_changed(batch, node.pending);
+        self.in_transit.update_changed(batch, node.in_transit);
+    }
+}
+
+/// publishes the supply network under base/logistics
+pub(super) struct PubSupply {
+    base: Path,
+    next_tick: Val,
+    ticks_to_delivery: Val,
+    nodes: FxHashMap<ObjectiveId, PubNode>,
+}
+
+impl PubSupply {
+    pub(super) fn new(publisher: &Publisher, base: &Path) -> Result<Self> {
+        let base = base.append("logistics");
+        Ok(Self {
+            next_tick: publisher.publish(base.append("next_tick"), Value::Null)?,
+            ticks_to_delivery: publisher.publish(base.append("ticks_to_delivery"), 0u32)?,
+            nodes: FxHashMap::default(),
+            base: base.append("objectives"),
+        })
+    }
+
+    pub(super) fn update(
+        &mut self,
+        publisher: &Publisher,
+        batch: &mut UpdateBatch,
+        net: &SupplyNetwork,
+    ) -> Result<()> {
+        self.next_tick.update_changed(batch, net.next_tick);
+        self.ticks_to_delivery
+            .update_changed(batch, net.ticks_to_delivery);
+        let names: FxHashMap<ObjectiveId, &str> =
+            net.nodes.iter().map(|n| (n.id, n.name.as_str())).collect();
+        let mut present: FxHashSet<ObjectiveId> = FxHashSet::default();
+        for node in &net.nodes {
+            present.insert(node.id);
+            let supplier = node
+                .supplier
+                .and_then(|id| names.get(&id))
+                .map(|n| Value::from(n.to_string()))
+                .unwrap_or(Value::Null);
+            match self.nodes.get(&node.id) {
+                // objectives (e.g. farps) can be renamed when they are
+                // rebuilt, republish them in that case
+                Some(pn) if pn.name == node.name.as_str() => pn.update(batch, node, supplier),
+                Some(_) | None => {
+                    let pn = PubNode::new(publisher, &self.base, node, supplier)?;
+                    self.nodes.insert(node.id, pn);
+                }
+            }
+        }
+        self.nodes.retain(|id, _| present.contains(id));
+        Ok(())
+    }
+}
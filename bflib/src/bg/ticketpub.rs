@@ -0,0 +1,42 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use anyhow::Result;
+use netidx::{
+    path::Path,
+    publisher::{Publisher, UpdateBatch, Val, Value},
+};
+
+/// publishes the tickets of each side under base/tickets
+pub(super) struct PubTickets {
+    red: Val,
+    blue: Val,
+}
+
+impl PubTickets {
+    pub(super) fn new(publisher: &Publisher, base: &Path) -> Result<Self> {
+        let base = base.append("tickets");
+        Ok(Self {
+            red: publisher.publish(base.append("red"), Value::Null)?,
+            blue: publisher.publish(base.append("blue"), Value::Null)?,
+        })
+    }
+
+    pub(super) fn update(&self, batch: &mut UpdateBatch, red: u32, blue: u32) {
+        self.red.update_changed(batch, red);
+        self.blue.update_changed(batch, blue);
+    }
+}
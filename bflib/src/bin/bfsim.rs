@@ -0,0 +1,68 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use anyhow::{Context, Result};
+use bflib::sim::{Sim, SimScript};
+use bfprotocols::cfg::Cfg;
+use chrono::prelude::*;
+use clap::Parser;
+use simplelog::{LevelFilter, SimpleLogger};
+use std::{fs::File, io, path::PathBuf};
+
+/// Run a campaign save forward in time without dcs
+#[derive(Parser)]
+struct Args {
+    /// the campaign config, defaults to the example config
+    #[clap(long)]
+    cfg: Option<PathBuf>,
+    /// the save file to start from
+    #[clap(long)]
+    save: PathBuf,
+    /// the script of events to run
+    #[clap(long)]
+    script: PathBuf,
+    /// write the final state of the campaign to this save file
+    #[clap(long)]
+    output: Option<PathBuf>,
+    /// don't include the stats log in the report
+    #[clap(long)]
+    no_stats: bool,
+}
+
+fn main() -> Result<()> {
+    SimpleLogger::init(LevelFilter::Warn, simplelog::Config::default())?;
+    let args = Args::parse();
+    let cfg: Cfg = match args.cfg.as_ref() {
+        None => Cfg::default(),
+        Some(path) => serde_json::from_reader(File::open(path).context("opening cfg")?)
+            .context("decoding cfg")?,
+    };
+    let script: SimScript =
+        serde_json::from_reader(File::open(&args.script).context("opening script")?)
+            .context("decoding script")?;
+    let start = script.start.unwrap_or_else(Utc::now);
+    let mut sim = Sim::new(cfg, &args.save, start)?;
+    let mut report = sim.run(&script)?;
+    if let Some(path) = args.output.as_ref() {
+        sim.save(path)?
+    }
+    if args.no_stats {
+        report.stats.clear()
+    }
+    serde_json::to_writer_pretty(io::stdout().lock(), &report)?;
+    println!();
+    Ok(())
+}
@@ -142,7 +142,7 @@ fn admin_command(ctx: &mut Context, id: PlayerId, cmd: &str) {
         Some(ifo) => ifo,
         None => return,
     };
-    if !ctx.db.ephemeral.cfg.admins.contains_key(&ifo.ucid) {
+    if !admin::is_admin(ctx, &id) {
         return;
     }
     match cmd.parse::<AdminCommand>() {
@@ -242,6 +242,36 @@ fn transfer_command(ctx: &mut Context, id: PlayerId, s: &str) {
     }
 }
 
+fn supply_command(ctx: &mut Context, id: PlayerId, s: &str, now: DateTime<Utc>) {
+    let side = match ctx
+        .connected
+        .get(&id)
+        .and_then(|ifo| ctx.db.player(&ifo.ucid))
+    {
+        Some(player) => player.side,
+        None => return,
+    };
+    let name = s.trim();
+    let lines = if name.is_empty() {
+        ctx.db.supply_report(side, now)
+    } else {
+        let explain = || -> Result<Vec<CompactString>> {
+            let oid = admin::get_airbase(&ctx.db, name)?;
+            if ctx.db.objective(&oid)?.owner() != side {
+                bail!("{name} is not held by your side")
+            }
+            ctx.db.explain_supply(&oid, now)
+        };
+        match explain() {
+            Ok(lines) => lines,
+            Err(e) => vec![format_compact!("can't explain the supply at {name}, {e}")],
+        }
+    };
+    for line in lines {
+        ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), line)
+    }
+}
+
 fn delete_command(ctx: &mut Context, id: PlayerId, s: &str) {
     macro_rules! reply {
         ($msg:tt) => {
@@ -268,6 +298,7 @@ fn delete_command(ctx: &mut Context, id: PlayerId, s: &str) {
                     DeployKind::Objective { .. } | DeployKind::ObjectiveDeprecated => {
                         reply!("can't delete an objective group")
                     }
+                    DeployKind::Convoy { .. } => reply!("can't delete a supply convoy"),
                     DeployKind::Crate { .. } => match ctx.db.delete_group(&id) {
                         Err(e) => reply!("could not delete group {id} {e:?}"),
                         Ok(()) => reply!("deleted {id}"),
@@ -744,6 +775,7 @@ fn help_command(ctx: &mut Context, id: PlayerId) {
         " -balance: show your points balance",
         " -transfer <amount> [<player> | objective:<objective>]: transfer points to another player or objective",
         " -delete <groupid>: delete a group you deployed for a partial refund",
+        " -supply [objective]: show your side's supply network, or why an objective is low",
         " -action <name> <args>: perform an action, -action help for a list of actions",
         " -bind <token>: bind your ucid to the specified token (for the web gui)",
         " -jtac <jtid> <cmd>",
@@ -790,6 +822,12 @@ pub(super) fn process(
     } else if let Some(s) = msg.strip_prefix("-transfer ") {
         transfer_command(ctx, id, s);
         Ok("".into())
+    } else if msg.eq_ignore_ascii_case("-supply") {
+        supply_command(ctx, id, "", now);
+        Ok("".into())
+    } else if let Some(s) = msg.strip_prefix("-supply ") {
+        supply_command(ctx, id, s, now);
+        Ok("".into())
     } else if let Some(s) = msg.strip_prefix("-delete ") {
         delete_command(ctx, id, s);
         Ok("".into())
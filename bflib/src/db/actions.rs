@@ -1126,6 +1126,9 @@ impl Db {
         if group.side != side {
             bail!("can't move an enemy unit")
         }
+        if let DeployKind::Convoy { .. } = &group.origin {
+            bail!("can't move a supply convoy")
+        }
         self.ephemeral
             .groups_with_move_missions
             .insert(args.group, args.pos);
@@ -1144,6 +1147,7 @@ impl Db {
                 | DeployKind::Crate { .. }
                 | DeployKind::Objective { .. }
                 | DeployKind::ObjectiveDeprecated
+                | DeployKind::Convoy { .. }
                 | DeployKind::Troop { .. }
                 | DeployKind::Deployed { .. } => (),
             }
@@ -1772,12 +1776,13 @@ impl Db {
                 && match args.cfg.kind {
                     AiPlaneKind::Helicopter => true,
                     AiPlaneKind::FixedWing => {
-                        o.is_airbase()
+                        (o.is_airbase()
                             || self
                                 .ephemeral
                                 .cfg
                                 .extra_fixed_wing_objectives
-                                .contains(&o.name)
+                                .contains(&o.name))
+                            && o.runway_usable()
                     }
                 }
                 && na::distance_squared(&args.pos.into(), &o.zone.pos().into()) > 100_000_000.
@@ -2040,6 +2045,7 @@ impl Db {
             | DeployKind::Deployed { .. }
             | DeployKind::Objective { .. }
             | DeployKind::ObjectiveDeprecated
+            | DeployKind::Convoy { .. }
             | DeployKind::Troop { .. } => bail!("not a race tracker"),
         };
         let responsible = player
@@ -14,7 +14,10 @@ FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
 for more details.
 */
 
-use super::{Db, ephemeral::DeployableIndex, group::SpawnedGroup, objective::Objective};
+use super::{
+    Db, MapS, csar::DownedPilot, ephemeral::DeployableIndex, group::SpawnedGroup,
+    objective::Objective, site::Site,
+};
 use crate::{
     db::group::DeployKind,
     group, maybe, objective,
@@ -23,7 +26,10 @@ use crate::{
 };
 use anyhow::{Result, anyhow, bail};
 use bfprotocols::{
-    cfg::{CargoConfig, Crate, Deployable, DeployableKind, LimitEnforceTyp, Troop, Vehicle},
+    cfg::{
+        CargoConfig, Crate, Deployable, DeployableKind, LimitEnforceTyp, StructureKind, Troop,
+        Vehicle,
+    },
     db::{
         group::GroupId,
         objective::{ObjectiveId, ObjectiveKind},
@@ -38,15 +44,18 @@ use dcso3::{
     env::miz::MizIndex,
     land::Land,
     net::{SlotId, Ucid},
+    object::DcsOid,
     radians_to_degrees,
     trigger::Trigger,
+    unit::ClassUnit,
 };
 use enumflags2::BitFlags;
-use fxhash::FxHashMap;
-use log::debug;
+use fxhash::{FxHashMap, FxHashSet};
+use log::{debug, error};
+use rand::{Rng, thread_rng};
 use serde_derive::{Deserialize, Serialize};
 use smallvec::{SmallVec, smallvec};
-use std::{cmp::max, fmt, sync::Arc};
+use std::{cmp::max, fmt, mem, sync::Arc};
 
 #[derive(Debug, Clone, Copy)]
 pub struct NearbyCrate<'a> {
@@ -64,7 +73,141 @@ pub enum Unpakistan {
     UnpackedFarp(String),
     Repaired(String),
     RepairedBase(String, u8),
+    RepairedStructure(String, StructureKind),
+    RepairedRunway(String, u8),
     TransferedSupplies(String, String),
+    Staged(String, u32, u32),
+    Upgraded(String, String),
+    Resupplied(String, GroupId),
+}
+
+/// a crate that may be unpacked along with the crates near it
+#[derive(Debug, Clone)]
+pub(super) struct Cifo {
+    pub(super) pos: Vector2,
+    pub(super) group: GroupId,
+    pub(super) origin: ObjectiveId,
+    pub(super) crate_def: Crate,
+}
+
+impl<'a> From<NearbyCrate<'a>> for Cifo {
+    fn from(nc: NearbyCrate<'a>) -> Self {
+        Self {
+            pos: nc.pos,
+            group: nc.group.id,
+            origin: nc.origin,
+            crate_def: nc.crate_def.clone(),
+        }
+    }
+}
+
+/// a deployable that nearby crates can build, or deliver to a site
+#[derive(Debug, Clone)]
+pub(super) struct Candidate {
+    pub(super) dep: String,
+    /// every required crate is here
+    pub(super) complete: bool,
+    pub(super) have: FxHashMap<String, Vec<Cifo>>,
+}
+
+/// the deployables that nearby crates can build, complete ones before
+/// staged ones, and otherwise by name
+pub(super) fn buildable(
+    nearby: &[Cifo],
+    didx: &DeployableIndex,
+) -> std::result::Result<Vec<Candidate>, SmallVec<[CompactString; 2]>> {
+    let mut deps: SmallVec<[&String; 4]> = smallvec![];
+    for cr in nearby {
+        let name = &cr.crate_def.name;
+        let subs = didx.deployables_by_substitute.get(name);
+        for dep in didx
+            .deployables_by_crates
+            .get(name)
+            .into_iter()
+            .chain(subs.into_iter().flat_map(|d| d.iter()))
+        {
+            if !deps.contains(&dep) {
+                deps.push(dep)
+            }
+        }
+    }
+    let mut candidates: Vec<Candidate> = vec![];
+    let mut reasons = smallvec![];
+    for dep in deps {
+        let spec = &didx.deployables_by_name[dep];
+        let mut used: FxHashSet<GroupId> = FxHashSet::default();
+        let mut have: FxHashMap<String, Vec<Cifo>> = FxHashMap::default();
+        let mut missing = None;
+        for req in &spec.crates {
+            let subs = spec.recipe.substitutes.get(&req.name);
+            let is_sub = |cr: &&Cifo| subs.is_some_and(|s| s.contains(&cr.crate_def.name));
+            // use the required crate before any substitutes
+            let crs = nearby
+                .iter()
+                .filter(|cr| cr.crate_def.name == req.name)
+                .chain(nearby.iter().filter(is_sub))
+                .filter(|cr| used.insert(cr.group))
+                .take(req.required as usize)
+                .cloned()
+                .collect::<Vec<_>>();
+            if crs.len() < req.required as usize && missing.is_none() {
+                missing = Some(req.name.clone());
+            }
+            if !crs.is_empty() {
+                have.insert(req.name.clone(), crs);
+            }
+        }
+        match missing {
+            None => candidates.push(Candidate {
+                dep: dep.clone(),
+                complete: true,
+                have,
+            }),
+            // staged deployables take whatever crates are here
+            Some(_) if spec.recipe.staged && !have.is_empty() => candidates.push(Candidate {
+                dep: dep.clone(),
+                complete: false,
+                have,
+            }),
+            Some(name) => reasons.push(format_compact!("can't spawn {dep} missing {name}\n")),
+        }
+    }
+    if candidates.is_empty() {
+        Err(reasons)
+    } else {
+        candidates.sort_by(|c0, c1| {
+            c1.complete
+                .cmp(&c0.complete)
+                .then_with(|| c0.dep.cmp(&c1.dep))
+        });
+        Ok(candidates)
+    }
+}
+
+/// the crates the site still needs out of have, and whether they will
+/// complete it
+pub(super) fn take_for_site(
+    db: &Db,
+    site: Option<u32>,
+    spec: &Deployable,
+    have: &FxHashMap<String, Vec<Cifo>>,
+) -> (FxHashMap<String, Vec<Cifo>>, bool) {
+    let site = site.and_then(|id| db.persisted.sites.get(&id));
+    let mut take: FxHashMap<String, Vec<Cifo>> = FxHashMap::default();
+    let mut complete = true;
+    for req in &spec.crates {
+        let delivered = site.map(|s| s.delivered(&req.name)).unwrap_or(0);
+        let needed = req.required.saturating_sub(delivered) as usize;
+        let crs = have
+            .get(&req.name)
+            .map(|crs| crs.iter().take(needed).cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        complete &= crs.len() >= needed;
+        if !crs.is_empty() {
+            take.insert(req.name.clone(), crs);
+        }
+    }
+    (take, complete)
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -83,9 +226,21 @@ impl fmt::Display for Unpakistan {
             ),
             Self::Repaired(unit) => write!(f, "repaired a {unit}"),
             Self::RepairedBase(base, logi) => write!(f, "repaired logistics at {base} to %{logi}"),
+            Self::RepairedStructure(base, kind) => {
+                write!(f, "repaired the {} at {base}", kind.name())
+            }
+            Self::RepairedRunway(base, 0) => write!(f, "repaired the runway at {base}"),
+            Self::RepairedRunway(base, n) => {
+                write!(f, "repaired the runway at {base}, {n} more crates needed")
+            }
             Self::TransferedSupplies(from, to) => {
                 write!(f, "transfered supplies from {from} to {to}")
             }
+            Self::Staged(dep, have, need) => {
+                write!(f, "{dep} site has {have} of {need} crates delivered")
+            }
+            Self::Upgraded(base, dep) => write!(f, "upgraded a {base} to a {dep}"),
+            Self::Resupplied(dep, gid) => write!(f, "resupplied {dep} {gid}"),
         }
     }
 }
@@ -96,6 +251,19 @@ pub struct InternalTroop {
     pub origin: Option<ObjectiveId>,
     pub cost_fraction: f32,
     pub troop: Troop,
+    /// the troop is a downed pilot being rescued
+    #[serde(default)]
+    pub rescue: Option<DownedPilot>,
+}
+
+/// troops on board an aircraft that crashed, they may survive it
+#[derive(Debug, Clone)]
+pub(super) struct CrashedTroop {
+    by: Ucid,
+    side: Side,
+    position: Position3,
+    speed: f64,
+    troop: InternalTroop,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -208,7 +376,7 @@ impl Db {
             bail!("move away from other crates or pick up the existing crate")
         }
         let to_delete = self.ephemeral.cfg.max_crates.and_then(|max_crates| {
-            let crates = &self.persisted.players[&st.ucid].crates;
+            let crates = &self.persisted.players.get(&st.ucid)?.crates;
             if crates.len() < max_crates as usize {
                 None
             } else {
@@ -284,6 +452,9 @@ impl Db {
     ) -> Result<SmallVec<[NearbyCrate<'a>; 4]>> {
         let mut res: SmallVec<[NearbyCrate; 4]> = smallvec![];
         for gid in &self.persisted.crates {
+            if self.is_slung(gid) {
+                continue;
+            }
             let group = group!(self, gid)?;
             let (oid, crate_def) = match &group.origin {
                 DeployKind::Crate {
@@ -295,6 +466,7 @@ impl Db {
                 | DeployKind::Troop { .. }
                 | DeployKind::Objective { .. }
                 | DeployKind::ObjectiveDeprecated
+                | DeployKind::Convoy { .. }
                 | DeployKind::Action { .. } => {
                     bail!("group {:?} is listed in crates but isn't a crate", gid)
                 }
@@ -327,6 +499,15 @@ impl Db {
         self.list_crates_near_point(st.point, max_dist)
     }
 
+    /// the crates within max_dist of point, closest first
+    pub(super) fn crates_near(&self, point: Vector2, max_dist: f64) -> Result<SmallVec<[Cifo; 8]>> {
+        Ok(self
+            .list_crates_near_point(point, max_dist)?
+            .into_iter()
+            .map(Cifo::from)
+            .collect())
+    }
+
     pub fn destroy_nearby_crate(&mut self, lua: MizLua, slot: &SlotId) -> Result<()> {
         let st = SlotStats::get(self, lua, slot)?;
         if st.in_air {
@@ -398,6 +579,44 @@ impl Db {
         Ok((n, oldest))
     }
 
+    /// fail if the player and origin can't afford dep, or if the limit
+    /// would deny it. Sites other than site count against the limit.
+    pub(super) fn check_deploy_limits(
+        &self,
+        side: Side,
+        spec: &Deployable,
+        dep: &str,
+        origin: ObjectiveId,
+        ucid: &Ucid,
+        site: Option<u32>,
+    ) -> Result<()> {
+        if let Some(player) = self.persisted.players.get(ucid)
+            && let Some(obj) = self.persisted.objectives.get(&origin)
+        {
+            let player_points = max(0, player.points);
+            if spec.cost as i32 > player_points + obj.points {
+                bail!(
+                    "there are {} available points, this deployable costs {} points to unpack",
+                    player_points,
+                    spec.cost
+                )
+            }
+        }
+        let (n, _) = self.number_deployed(side, dep)?;
+        let sites = self
+            .persisted
+            .sites
+            .into_iter()
+            .filter(|(id, s)| Some(**id) != site && s.side == side && s.deployable.as_str() == dep)
+            .count();
+        if n + sites >= spec.limit as usize
+            && let LimitEnforceTyp::DenyCrate = spec.limit_enforce
+        {
+            bail!("the max number of {:?} are already deployed", dep)
+        }
+        Ok(())
+    }
+
     pub fn deployable_by_crate<'a>(
         &'a self,
         side: &Side,
@@ -439,23 +658,6 @@ impl Db {
     }
 
     pub fn unpakistan(&mut self, lua: MizLua, idx: &MizIndex, slot: &SlotId) -> Result<Unpakistan> {
-        #[derive(Clone)]
-        struct Cifo {
-            pos: Vector2,
-            group: GroupId,
-            origin: ObjectiveId,
-            crate_def: Crate,
-        }
-        impl<'a> From<NearbyCrate<'a>> for Cifo {
-            fn from(nc: NearbyCrate<'a>) -> Self {
-                Self {
-                    pos: nc.pos,
-                    group: nc.group.id,
-                    origin: nc.origin,
-                    crate_def: nc.crate_def.clone(),
-                }
-            }
-        }
         fn nearby(db: &Db, st: &SlotStats) -> Result<SmallVec<[Cifo; 8]>> {
             let nearby_player = db
                 .list_nearby_crates(st)?
@@ -468,59 +670,13 @@ impl Db {
                 let sp = db.ephemeral.cfg.crate_spread as f64;
                 let mut crates = FxHashMap::default();
                 for cr in &nearby_player {
-                    for cr in db
-                        .list_crates_near_point(cr.pos, sp)?
-                        .into_iter()
-                        .map(Cifo::from)
-                    {
+                    for cr in db.crates_near(cr.pos, sp)? {
                         crates.entry(cr.group).or_insert(cr);
                     }
                 }
-                Ok(crates.into_iter().map(|(_, cr)| cr).collect())
-            }
-        }
-        fn buildable(
-            nearby: &SmallVec<[Cifo; 8]>,
-            didx: &DeployableIndex,
-        ) -> std::result::Result<
-            FxHashMap<String, FxHashMap<String, Vec<Cifo>>>,
-            SmallVec<[CompactString; 2]>,
-        > {
-            let mut candidates: FxHashMap<String, FxHashMap<String, Vec<Cifo>>> =
-                FxHashMap::default();
-            let mut reasons = smallvec![];
-            for cr in nearby {
-                if let Some(dep) = didx.deployables_by_crates.get(&cr.crate_def.name) {
-                    candidates
-                        .entry(dep.clone())
-                        .or_default()
-                        .entry(cr.crate_def.name.clone())
-                        .or_default()
-                        .push(cr.clone());
-                }
-            }
-            candidates.retain(|dep, have| {
-                let spec = &didx.deployables_by_name[dep];
-                for req in &spec.crates {
-                    match have.get_mut(&req.name) {
-                        Some(ids) if ids.len() >= req.required as usize => {
-                            while ids.len() > req.required as usize {
-                                ids.pop();
-                            }
-                        }
-                        Some(_) | None => {
-                            reasons
-                                .push(format_compact!("can't spawn {dep} missing {}\n", req.name));
-                            return false;
-                        }
-                    }
-                }
-                true
-            });
-            if candidates.is_empty() {
-                Err(reasons)
-            } else {
-                Ok(candidates)
+                let mut crates: SmallVec<[Cifo; 8]> = crates.into_values().collect();
+                crates.sort_by_key(|cr| cr.group);
+                Ok(crates)
             }
         }
         fn base_repairable(
@@ -551,6 +707,13 @@ impl Db {
                 smallvec![]
             }
         }
+        fn resuppliable(db: &Db, side: Side, nearby: &SmallVec<[Cifo; 8]>) -> Option<Cifo> {
+            let cr = &db.ephemeral.cfg.upkeep.as_ref()?.resupply_crate[&side];
+            nearby
+                .iter()
+                .find(|ci| ci.crate_def.name == cr.name)
+                .cloned()
+        }
         fn repairable(
             db: &Db,
             nearby: &SmallVec<[Cifo; 8]>,
@@ -585,6 +748,7 @@ impl Db {
                             | DeployKind::Crate { .. }
                             | DeployKind::Objective { .. }
                             | DeployKind::ObjectiveDeprecated
+                            | DeployKind::Convoy { .. }
                             | DeployKind::Troop { .. }
                             | DeployKind::Action { .. } => (),
                         }
@@ -701,33 +865,269 @@ impl Db {
             dep: &String,
             origin: ObjectiveId,
             ucid: &Ucid,
+            site: Option<u32>,
         ) -> Result<ObjectiveId> {
-            if let Some(player) = db.persisted.players.get(ucid)
-                && let Some(obj) = db.persisted.objectives.get(&origin)
+            db.check_deploy_limits(side, spec, dep, origin, ucid, site)?;
+            let (n, oldest) = db.number_deployed(side, &**dep)?;
+            if n >= spec.limit as usize
+                && let LimitEnforceTyp::DeleteOldest = spec.limit_enforce
             {
-                let player_points = max(0, player.points);
-                if spec.cost as i32 > player_points + obj.points {
-                    bail!(
-                        "there are {} available points, this deployable costs {} points to unpack",
-                        player_points,
-                        spec.cost
-                    )
+                match oldest {
+                    Some(Oldest::Group(gid)) => db.delete_group(&gid)?,
+                    Some(Oldest::Objective(oid)) => db.delete_objective(&oid)?,
+                    None => (),
                 }
             }
-            let (n, oldest) = db.number_deployed(side, &**dep)?;
-            if n >= spec.limit as usize {
-                match spec.limit_enforce {
-                    LimitEnforceTyp::DenyCrate => {
-                        bail!("the max number of {:?} are already deployed", dep)
+            Ok(origin)
+        }
+        fn upgrade_base(
+            db: &Db,
+            side: Side,
+            base: &str,
+            centroid: Vector2,
+            max_dist: f64,
+        ) -> Option<(GroupId, Vector2)> {
+            let max_dist = max_dist.powi(2);
+            let bases = db.persisted.deployed.into_iter().filter_map(|gid| {
+                let group = db.persisted.groups.get(gid)?;
+                match &group.origin {
+                    DeployKind::Deployed { spec, .. }
+                        if group.side == side
+                            && spec.path.last().map(|s| s.as_str()) == Some(base) =>
+                    {
+                        let pos = centroid2d(
+                            group
+                                .units
+                                .into_iter()
+                                .filter_map(|uid| db.persisted.units.get(uid))
+                                .filter(|u| !u.dead)
+                                .map(|u| u.pos),
+                        );
+                        let d = na::distance_squared(&pos.into(), &centroid.into());
+                        (d <= max_dist).then_some((*gid, pos, d))
                     }
-                    LimitEnforceTyp::DeleteOldest => match oldest {
-                        Some(Oldest::Group(gid)) => db.delete_group(&gid)?,
-                        Some(Oldest::Objective(oid)) => db.delete_objective(&oid)?,
-                        None => (),
-                    },
+                    DeployKind::Deployed { .. }
+                    | DeployKind::Crate { .. }
+                    | DeployKind::Objective { .. }
+                    | DeployKind::ObjectiveDeprecated
+                    | DeployKind::Convoy { .. }
+                    | DeployKind::Troop { .. }
+                    | DeployKind::Action { .. } => None,
                 }
+            });
+            bases
+                .min_by(|(_, _, d0), (_, _, d1)| d0.total_cmp(d1))
+                .map(|(gid, pos, _)| (gid, pos))
+        }
+        fn stage(
+            db: &mut Db,
+            st: &SlotStats,
+            site: Option<u32>,
+            dep: String,
+            spec: &Deployable,
+            take: FxHashMap<String, Vec<Cifo>>,
+            centroid: Vector2,
+        ) -> Result<Unpakistan> {
+            let now = Utc::now();
+            let id = match site {
+                Some(id) => id,
+                None => {
+                    let mut origins = take
+                        .values()
+                        .flat_map(|crs| crs.iter())
+                        .map(|cr| cr.origin)
+                        .collect::<SmallVec<[_; 8]>>();
+                    origins.sort();
+                    origins.dedup();
+                    let origin =
+                        origins
+                            .iter()
+                            .fold(Err(anyhow!("no crates to deliver")), |res, oid| match res {
+                                Ok(oid) => Ok(oid),
+                                Err(_) => db
+                                    .check_deploy_limits(st.side, spec, &dep, *oid, &st.ucid, None)
+                                    .map(|()| *oid),
+                            })?;
+                    db.add_site(Site {
+                        deployable: dep.clone(),
+                        side: st.side,
+                        pos: centroid,
+                        player: st.ucid,
+                        origin,
+                        delivered: MapS::default(),
+                        last_delivery: now,
+                    })
+                }
+            };
+            for (name, crs) in take {
+                for cr in &crs {
+                    db.delete_group(&cr.group)?
+                }
+                let site = db
+                    .persisted
+                    .sites
+                    .get_mut_cow(&id)
+                    .ok_or_else(|| anyhow!("no such site {id}"))?;
+                *site.delivered.get_or_insert_cow(name, || 0) += crs.len() as u32;
+                site.last_delivery = now;
             }
-            Ok(origin)
+            let (have, need) = maybe!(db.persisted.sites, id, "site")?.progress(spec);
+            db.mark_site(id)?;
+            db.ephemeral.dirty();
+            Ok(Unpakistan::Staged(dep, have, need))
+        }
+        fn build(
+            db: &mut Db,
+            lua: MizLua,
+            idx: &MizIndex,
+            slot: &SlotId,
+            st: &SlotStats,
+            spec: Deployable,
+            dep: String,
+            mut have: FxHashMap<String, Vec<Cifo>>,
+        ) -> Result<Unpakistan> {
+            let max_dist = db.ephemeral.cfg.crate_spread as f64;
+            let mut centroid = centroid2d(have.values().flat_map(|c| c.iter()).map(|c| c.pos));
+            let too_close = too_close(db, st.side, centroid, spec.kind.is_objective(), || {
+                have.values().flat_map(|c| c.iter())
+            });
+            if too_close {
+                if spec.kind.is_group() {
+                    bail!("can't unpack that here while enemies are close")
+                } else {
+                    bail!("can't unpack that here")
+                }
+            }
+            let base = match &spec.recipe.upgrade_from {
+                None => None,
+                Some(base) => match upgrade_base(db, st.side, base, centroid, max_dist) {
+                    Some(base) => Some(base),
+                    None => bail!("{dep} must be unpacked within {max_dist}m of a {base}"),
+                },
+            };
+            let mut origins = have
+                .values()
+                .flat_map(|crs| crs.iter())
+                .map(|cr| cr.origin)
+                .collect::<SmallVec<[_; 8]>>();
+            let mut site = None;
+            if spec.recipe.staged {
+                site = db.site_near(st.side, &dep, centroid, max_dist);
+                let (take, complete) = take_for_site(db, site, &spec, &have);
+                if take.is_empty() {
+                    bail!("the {dep} site doesn't need any more of those crates")
+                }
+                if !complete {
+                    return stage(db, st, site, dep, &spec, take, centroid);
+                }
+                have = take;
+                if let Some(site) = site.and_then(|id| db.persisted.sites.get(&id)) {
+                    centroid = site.pos;
+                    origins.push(site.origin);
+                }
+            }
+            origins.sort();
+            origins.dedup();
+            let from_obj = origins
+                .iter()
+                .fold(Err(anyhow!("")), |res, oid| match res {
+                    Ok(oid) => Ok(oid),
+                    Err(_) => enforce_deploy_limits(db, st.side, &spec, &dep, *oid, &st.ucid, site),
+                })?;
+            let spctx = SpawnCtx::new(lua)?;
+            let res = match &spec.kind {
+                DeployableKind::Objective(parts) => {
+                    for cr in have.values().flat_map(|c| c.iter()) {
+                        db.delete_group(&cr.group)?
+                    }
+                    let oid = db.add_farp(lua, &spctx, idx, st.side, centroid, &spec, parts)?;
+                    db.ephemeral.stat(Stat::DeployFarp {
+                        oid,
+                        by: st.ucid,
+                        deployable: dep,
+                    });
+                    db.charge_for_item(&st.ucid, from_obj, spec.cost, "for farp spawn");
+                    let name = objective!(db, oid)?.name.clone();
+                    Unpakistan::UnpackedFarp(name)
+                }
+                DeployableKind::Group { template } => {
+                    let pos = db.ephemeral.slot_instance_pos(lua, slot)?;
+                    let spawnloc = match base {
+                        // an upgrade replaces the base where it stands
+                        Some((_, pos_base)) => SpawnLoc::AtPos {
+                            pos: pos_base,
+                            offset_direction: Vector2::default(),
+                            group_heading: azumith3d(pos.x.0),
+                        },
+                        None => compute_positions(db, &have, centroid, azumith3d(pos.x.0))?,
+                    };
+                    let origin = DeployKind::Deployed {
+                        player: st.ucid,
+                        moved_by: None,
+                        spec: spec.clone(),
+                        cost_fraction: 1.,
+                        origin: Some(from_obj),
+                    };
+                    let gid = db.add_and_queue_group(
+                        &spctx,
+                        idx,
+                        st.side,
+                        spawnloc,
+                        template,
+                        origin,
+                        BitFlags::empty(),
+                        None,
+                    )?;
+                    for cr in have.values().flat_map(|c| c.iter()) {
+                        db.delete_group(&cr.group)?
+                    }
+                    db.ephemeral.stat(Stat::DeployGroup {
+                        gid,
+                        by: st.ucid,
+                        deployable: dep.clone(),
+                    });
+                    let frac = db.charge_for_item(
+                        &st.ucid,
+                        from_obj,
+                        spec.cost,
+                        &format_compact!("for {dep} unpack"),
+                    );
+                    if let DeployKind::Deployed { cost_fraction, .. } =
+                        &mut db.persisted.groups[&gid].origin
+                    {
+                        *cost_fraction = frac;
+                    }
+                    match base {
+                        Some((base_gid, _)) => {
+                            let base = spec.recipe.upgrade_from.clone().unwrap_or_default();
+                            // the base is traded in, refund what was paid for it
+                            let paid = match &group!(db, base_gid)?.origin {
+                                DeployKind::Deployed {
+                                    player,
+                                    spec,
+                                    origin: Some(oid),
+                                    cost_fraction,
+                                    ..
+                                } => Some((*player, *oid, spec.cost, *cost_fraction)),
+                                _ => None,
+                            };
+                            db.delete_group(&base_gid)?;
+                            if let Some((player, oid, cost, frac)) = paid
+                                && cost > 0
+                            {
+                                let msg = format_compact!("for {base} upgrade");
+                                db.refund_points(&player, oid, cost, frac, &msg);
+                            }
+                            Unpakistan::Upgraded(base, dep)
+                        }
+                        None => Unpakistan::Unpacked(dep),
+                    }
+                }
+            };
+            if let Some(id) = site {
+                db.delete_site(id)
+            }
+            Ok(res)
         }
         let st = SlotStats::get(self, lua, slot)?;
         if st.in_air {
@@ -754,24 +1154,21 @@ impl Db {
             });
             if let Some(oid) = oid {
                 let obj = objective!(self, oid)?;
-                if obj.logi == 100 {
+                if obj.destroyed().next().is_some() {
+                    let kind = self.structure_repair(lua, &st.ucid, oid)?;
+                    self.delete_group(base_repairs.keys().next().unwrap())?;
+                    let obj = objective!(self, oid)?;
+                    return Ok(Unpakistan::RepairedStructure(obj.name.clone(), kind));
+                } else if obj.runway.closed_until.is_some() {
+                    let needed = self.runway_repair(&st.ucid, oid)?;
+                    self.delete_group(base_repairs.keys().next().unwrap())?;
+                    let obj = objective!(self, oid)?;
+                    return Ok(Unpakistan::RepairedRunway(obj.name.clone(), needed));
+                } else if obj.logi == 100 {
                     reasons.push("objective logistics are completely repaired".into());
                 } else {
-                    self.repair_one_logi_step(st.side, Utc::now(), oid)?;
+                    self.logistics_repair(&st.ucid, oid, Utc::now())?;
                     self.delete_group(base_repairs.keys().next().unwrap())?;
-                    self.ephemeral.stat(Stat::Repair {
-                        id: oid,
-                        by: st.ucid,
-                    });
-                    if let Some(amount) = self
-                        .ephemeral
-                        .cfg
-                        .points
-                        .as_ref()
-                        .map(|p| p.logistics_repair)
-                    {
-                        self.adjust_points(&st.ucid, amount as i32, "for logistics repair");
-                    }
                     let obj = objective!(self, oid)?;
                     return Ok(Unpakistan::RepairedBase(obj.name.clone(), obj.logi()));
                 }
@@ -817,112 +1214,33 @@ impl Db {
                 reasons.push("not close enough to a friendly objective".into());
             }
         }
+        if let Some(cr) = resuppliable(self, st.side, &nearby) {
+            match self.resupply_near(st.side, cr.pos, max_dist) {
+                Some((gid, dep)) => {
+                    self.delete_group(&cr.group)?;
+                    return Ok(Unpakistan::Resupplied(dep, gid));
+                }
+                None => reasons.push("not close enough to a deployed group to resupply".into()),
+            }
+        }
         match buildable(&nearby, &didx) {
             Err(mut build_reasons) => reasons.append(&mut build_reasons),
-            Ok(mut candidates) => {
-                let (dep, have) = candidates.drain().next().unwrap();
+            Ok(candidates) => {
+                let Candidate { dep, have, .. } = candidates.into_iter().next().unwrap();
                 let spec = maybe!(didx.deployables_by_name, dep, "deployable")?.clone();
-                let centroid = centroid2d(have.values().flat_map(|c| c.iter()).map(|c| c.pos));
-                let too_close =
-                    too_close(self, st.side, centroid, spec.kind.is_objective(), || {
-                        have.values().flat_map(|c| c.iter())
-                    });
-                if too_close {
-                    if spec.kind.is_group() {
-                        reasons.push("can't unpack that here while enemies are close".into());
-                    } else {
-                        reasons.push("can't unpack that here".into())
-                    }
-                } else {
-                    let spctx = SpawnCtx::new(lua)?;
-                    let origins = {
-                        let mut oids = have
-                            .values()
-                            .flat_map(|crs| crs.iter())
-                            .map(|cr| cr.origin)
-                            .collect::<SmallVec<[_; 8]>>();
-                        oids.sort();
-                        oids.dedup();
-                        oids
-                    };
-                    let can_deploy = origins.iter().fold(Err(anyhow!("")), |res, oid| match res {
-                        Ok(oid) => Ok(oid),
-                        Err(_) => enforce_deploy_limits(self, st.side, &spec, &dep, *oid, &st.ucid),
-                    });
-                    match can_deploy {
-                        Err(e) => reasons.push(format_compact!("{e}")),
-                        Ok(from_obj) => match &spec.kind {
-                            DeployableKind::Objective(parts) => {
-                                for cr in have.values().flat_map(|c| c.iter()) {
-                                    self.delete_group(&cr.group)?
-                                }
-                                let oid = self
-                                    .add_farp(lua, &spctx, idx, st.side, centroid, &spec, parts)?;
-                                self.ephemeral.stat(Stat::DeployFarp {
-                                    oid,
-                                    by: st.ucid,
-                                    deployable: dep,
-                                });
-                                self.charge_for_item(
-                                    &st.ucid,
-                                    from_obj,
-                                    spec.cost,
-                                    "for farp spawn",
-                                );
-                                let name = objective!(self, oid)?.name.clone();
-                                return Ok(Unpakistan::UnpackedFarp(name));
-                            }
-                            DeployableKind::Group { template } => {
-                                let pos = self.ephemeral.slot_instance_pos(lua, slot)?;
-                                let spawnloc =
-                                    compute_positions(self, &have, centroid, azumith3d(pos.x.0))?;
-                                let origin = DeployKind::Deployed {
-                                    player: st.ucid.clone(),
-                                    moved_by: None,
-                                    spec: spec.clone(),
-                                    cost_fraction: 1.,
-                                    origin: Some(from_obj),
-                                };
-                                let gid = self.add_and_queue_group(
-                                    &spctx,
-                                    idx,
-                                    st.side,
-                                    spawnloc,
-                                    template,
-                                    origin,
-                                    BitFlags::empty(),
-                                    None,
-                                )?;
-                                for cr in have.values().flat_map(|c| c.iter()) {
-                                    self.delete_group(&cr.group)?
-                                }
-                                self.ephemeral.stat(Stat::DeployGroup {
-                                    gid,
-                                    by: st.ucid,
-                                    deployable: dep.clone(),
-                                });
-                                let frac = self.charge_for_item(
-                                    &st.ucid,
-                                    from_obj,
-                                    spec.cost,
-                                    &format_compact!("for {dep} unpack"),
-                                );
-                                if let DeployKind::Deployed { cost_fraction, .. } =
-                                    &mut self.persisted.groups[&gid].origin
-                                {
-                                    *cost_fraction = frac;
-                                }
-                                return Ok(Unpakistan::Unpacked(dep));
-                            }
-                        },
-                    }
+                match build(self, lua, idx, slot, &st, spec, dep, have) {
+                    Ok(res) => return Ok(res),
+                    Err(e) => reasons.push(format_compact!("{e}")),
                 }
             }
         }
         match repairable(self, &nearby, &didx, max_dist) {
             Err(mut rep_reasons) => reasons.append(&mut rep_reasons),
-            Ok(mut candidates) => {
-                let (dep, (gid, have)) = candidates.drain().next().unwrap();
+            Ok(candidates) => {
+                let (dep, (gid, have)) = candidates
+                    .into_iter()
+                    .min_by(|(d0, _), (d1, _)| d0.cmp(d1))
+                    .unwrap();
                 let spec = maybe!(didx.deployables_by_name, dep, "deployable")?.clone();
                 let player = maybe!(self.persisted.players, &st.ucid, "player")?;
                 let centroid = centroid2d(have.iter().map(|c| c.pos));
@@ -1141,6 +1459,7 @@ impl Db {
             origin: Some(origin),
             cost_fraction,
             troop: troop_cfg.clone(),
+            rescue: None,
         });
         Trigger::singleton(lua)?
             .action()?
@@ -1230,11 +1549,16 @@ impl Db {
             None,
         ) {
             Ok(gid) => {
+                if let Some(pilot) = it.rescue {
+                    self.persisted.csar.insert_cow(gid, pilot);
+                    self.mark_group(&gid)?;
+                }
                 self.ephemeral.stat(Stat::DeployTroop {
                     gid,
                     troop: it.troop.name.clone(),
                     by: it.player,
                 });
+                self.with_sortie(&it.player, |s| s.troops += 1);
                 Ok((it.troop, gid, oid))
             }
             Err(e) => {
@@ -1293,6 +1617,8 @@ impl Db {
             self.persisted
                 .troops
                 .into_iter()
+                // ai offensive troops can't be picked up
+                .filter(|gid| self.persisted.offensives.get(gid).is_none())
                 .filter_map(|gid| self.persisted.groups.get(gid).map(|g| (*gid, g)))
                 .find_map(|(gid, g)| {
                     if let DeployKind::Troop {
@@ -1319,6 +1645,7 @@ impl Db {
                                         origin: *origin,
                                         cost_fraction: *cost_fraction,
                                         troop: spec.clone(),
+                                        rescue: None,
                                     },
                                 ));
                             }
@@ -1335,11 +1662,298 @@ impl Db {
             bail!("you already have a full load onboard")
         }
         let troop_cfg = it.troop.clone();
-        cargo.troops.push(it);
+        let rescue = self.persisted.csar.get(&gid).cloned();
+        cargo.troops.push(InternalTroop { rescue, ..it });
         Trigger::singleton(lua)?
             .action()?
             .set_unit_internal_cargo(unit_name, cargo.weight() as i64)?;
         self.delete_group(&gid)?;
         Ok(troop_cfg)
     }
+
+    /// the share of its deployable's cost that one crate carries
+    fn crate_cost(&self, side: Side, cr: &Crate) -> u32 {
+        let dep = self.ephemeral.deployable_idx.get(&side).and_then(|idx| {
+            idx.deployables_by_crates
+                .get(&cr.name)
+                .and_then(|name| idx.deployables_by_name.get(name))
+        });
+        match dep {
+            None => 0,
+            Some(dep) => {
+                let n: u32 = dep.crates.iter().map(|cr| cr.required).sum();
+                (dep.cost as f32 / max(1, n) as f32).round() as u32
+            }
+        }
+    }
+
+    fn troops_lost(&mut self, by: Ucid, it: InternalTroop) {
+        self.ephemeral.stat(Stat::CargoLost {
+            by,
+            origin: it.origin,
+            cargo: it.troop.name,
+            troops: true,
+            cost: it.troop.cost,
+        });
+    }
+
+    /// the aircraft went down with cargo on board. Crates are lost and
+    /// charged to their origin, troops may survive the crash if cargo
+    /// loss is configured
+    pub(super) fn cargo_lost(&mut self, id: &DcsOid<ClassUnit>) {
+        let slot = match self.ephemeral.slot_by_object_id.get(id) {
+            Some(slot) => *slot,
+            None => return,
+        };
+        let by = match self.ephemeral.player_in_slot(&slot) {
+            Some(ucid) => *ucid,
+            None => return,
+        };
+        let cargo = match self.ephemeral.cargo.remove(&slot) {
+            Some(cargo) => cargo,
+            None => return,
+        };
+        let (side, inst) = match self.persisted.players.get(&by) {
+            Some(player) => (
+                player.side,
+                player
+                    .current_slot
+                    .as_ref()
+                    .and_then(|(_, inst)| inst.clone()),
+            ),
+            None => return,
+        };
+        for (oid, cr) in cargo.crates {
+            let supply = self
+                .ephemeral
+                .cfg
+                .warehouse
+                .as_ref()
+                .and_then(|whcfg| whcfg.supply_transfer_crate.get(&side))
+                .is_some_and(|scr| scr.name == cr.name);
+            let cost = self.crate_cost(side, &cr);
+            if supply {
+                self.ephemeral.lost_supply.push(oid);
+            } else if cost > 0 {
+                self.charge_for_item(&by, oid, cost, "for cargo lost");
+            }
+            self.ephemeral.stat(Stat::CargoLost {
+                by,
+                origin: Some(oid),
+                cargo: cr.name,
+                troops: false,
+                cost,
+            });
+        }
+        for it in cargo.troops {
+            match (&self.ephemeral.cfg.cargo_loss, &inst) {
+                (Some(_), Some(inst)) => self.ephemeral.crashed_troops.push(CrashedTroop {
+                    by,
+                    side,
+                    position: inst.position,
+                    speed: inst.velocity.magnitude(),
+                    troop: it,
+                }),
+                (None, _) | (_, None) => self.troops_lost(by, it),
+            }
+        }
+    }
+
+    /// lost supply transfer crates are taken out of their origin's
+    /// warehouse, and troops that survive a crash are stranded at the
+    /// crash site until someone extracts them
+    pub fn run_lost_cargo(&mut self, lua: MizLua, idx: &MizIndex) -> Result<()> {
+        let size = self
+            .ephemeral
+            .cfg
+            .warehouse
+            .as_ref()
+            .map(|whcfg| whcfg.supply_transfer_size)
+            .unwrap_or(0);
+        for oid in mem::take(&mut self.ephemeral.lost_supply) {
+            if let Err(e) = self.reduce_supply(lua, oid, size) {
+                error!("could not take lost supply from {oid} {e:?}")
+            }
+        }
+        if self.ephemeral.crashed_troops.is_empty() {
+            return Ok(());
+        }
+        let crashed = mem::take(&mut self.ephemeral.crashed_troops);
+        let cfg = self.ephemeral.cfg.cargo_loss.clone();
+        let land = Land::singleton(lua)?;
+        let spctx = SpawnCtx::new(lua)?;
+        for ct in crashed {
+            let point = Vector2::new(ct.position.p.x, ct.position.p.z);
+            let agl = ct.position.p.y - land.get_height(LuaVec2(point))?;
+            let survived = cfg.as_ref().is_some_and(|cfg| {
+                agl <= cfg.survivable_agl as f64
+                    && ct.speed * 3.6 <= cfg.survivable_speed as f64
+                    && thread_rng().gen_range(0..100) < cfg.troop_survival
+            });
+            let (n, _) = self.number_troops_deployed(ct.side, &ct.troop.troop.name)?;
+            if !survived || n >= ct.troop.troop.limit as usize {
+                self.troops_lost(ct.by, ct.troop);
+                continue;
+            }
+            let spawnpos = SpawnLoc::AtPos {
+                pos: point,
+                offset_direction: Vector2::new(ct.position.x.x, ct.position.x.z),
+                group_heading: azumith3d(ct.position.x.0),
+            };
+            let it = ct.troop;
+            let dk = DeployKind::Troop {
+                player: it.player,
+                moved_by: None,
+                spec: it.troop.clone(),
+                origin: it.origin,
+                cost_fraction: it.cost_fraction,
+            };
+            match self.add_and_queue_group(
+                &spctx,
+                idx,
+                ct.side,
+                spawnpos,
+                &it.troop.template,
+                dk,
+                BitFlags::empty(),
+                None,
+            ) {
+                Ok(gid) => {
+                    if let Some(pilot) = it.rescue.clone() {
+                        self.persisted.csar.insert_cow(gid, pilot);
+                        self.mark_group(&gid)?;
+                    }
+                    self.ephemeral.stat(Stat::TroopsStranded {
+                        by: ct.by,
+                        troop: it.troop.name.clone(),
+                        gid,
+                    });
+                    let near =
+                        Db::objective_near_point(&self.persisted.objectives, point, |_| true)
+                            .map(|(_, _, obj)| format_compact!(" near {}", obj.name))
+                            .unwrap_or_default();
+                    let msg = format_compact!(
+                        "{} troops survived a crash and are stranded{near}, they need to be extracted",
+                        it.troop.name
+                    );
+                    self.ephemeral.msgs().panel_to_side(10, false, ct.side, msg);
+                }
+                Err(e) => {
+                    error!("could not spawn stranded troops {e:?}");
+                    self.troops_lost(ct.by, it)
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::sim::test::{db, group, objective, ucid};
+    use bfprotocols::cfg::Cfg;
+
+    #[test]
+    fn recipes_prefer_complete_deployables_and_stage_the_rest() {
+        let cfg = Cfg::default();
+        let template = cfg.deployables[&Side::Blue][0].clone();
+        let (mut db, _from_db) = db(cfg);
+        let player = ucid(1);
+        db.register_player(player, "blue".into(), Side::Blue)
+            .unwrap();
+        let krate = |name: &str, required: u32| Crate {
+            name: name.into(),
+            required,
+            ..template.crates[0].clone()
+        };
+        let (a, b, c) = (krate("A", 2), krate("B", 1), krate("C", 1));
+        let mut tent = Deployable {
+            path: vec!["Tent".into()],
+            crates: vec![a.clone()],
+            limit: 1,
+            limit_enforce: LimitEnforceTyp::DenyCrate,
+            cost: 0,
+            ..template.clone()
+        };
+        tent.recipe.staged = true;
+        tent.recipe.substitutes.insert("A".into(), vec!["B".into()]);
+        let tbox = Deployable {
+            path: vec!["Box".into()],
+            crates: vec![c.clone()],
+            cost: 0,
+            ..template.clone()
+        };
+        let mut didx = DeployableIndex::default();
+        for dep in [&tent, &tbox] {
+            let name = dep.path.last().unwrap();
+            didx.deployables_by_name.insert(name.clone(), dep.clone());
+            for cr in &dep.crates {
+                didx.deployables_by_crates
+                    .insert(cr.name.clone(), name.clone());
+            }
+        }
+        didx.deployables_by_substitute
+            .insert("B".into(), smallvec::smallvec!["Tent".into()]);
+        let oid = objective(
+            &mut db,
+            "hub",
+            ObjectiveKind::Logistics,
+            Side::Blue,
+            Vector2::new(0., 0.),
+        );
+        let pos = Vector2::new(5000., 0.);
+        let spawn = |db: &mut Db, spec: &Crate| {
+            let dk = DeployKind::Crate {
+                origin: oid,
+                player,
+                spec: spec.clone(),
+            };
+            group(db, Side::Blue, dk, pos, 1);
+        };
+        spawn(&mut db, &a);
+        spawn(&mut db, &c);
+        let nearby = db.crates_near(pos, 100.).unwrap();
+        let candidates = buildable(&nearby, &didx).unwrap();
+        let found: Vec<_> = candidates
+            .iter()
+            .map(|c| (c.dep.as_str(), c.complete))
+            .collect();
+        assert_eq!(found, vec![("Box", true), ("Tent", false)]);
+        let have = candidates[1].have.clone();
+        // one A starts a site, which then only needs one more A
+        let (take, complete) = take_for_site(&db, None, &tent, &have);
+        assert!(!complete);
+        assert_eq!(take["A"].len(), 1);
+        let site = db.add_site(Site {
+            deployable: "Tent".into(),
+            side: Side::Blue,
+            pos,
+            player,
+            origin: oid,
+            delivered: MapS::from_iter([("A".into(), 1)]),
+            last_delivery: Utc::now(),
+        });
+        let (_, complete) = take_for_site(&db, Some(site), &tent, &have);
+        assert!(complete);
+        // the open site counts against the limit, except when finishing it
+        assert!(
+            db.check_deploy_limits(Side::Blue, &tent, "Tent", oid, &player, None)
+                .is_err()
+        );
+        assert!(
+            db.check_deploy_limits(Side::Blue, &tent, "Tent", oid, &player, Some(site))
+                .is_ok()
+        );
+        // a substitute completes the tent
+        spawn(&mut db, &b);
+        let nearby = db.crates_near(pos, 100.).unwrap();
+        let candidates = buildable(&nearby, &didx).unwrap();
+        let tent = candidates
+            .iter()
+            .find(|c| c.dep.as_str() == "Tent")
+            .unwrap();
+        assert!(tent.complete);
+        assert_eq!(tent.have["A"].len(), 2);
+    }
 }
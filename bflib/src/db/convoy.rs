@@ -0,0 +1,376 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use super::{
+    Db,
+    group::DeployKind,
+    logistics::Shipment,
+    offensive::{alarm_state, waypoint},
+};
+use crate::{
+    group, objective,
+    spawnctx::{SpawnCtx, SpawnLoc},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use bfprotocols::{
+    cfg::{ConvoyCfg, UnitTag},
+    db::{group::GroupId, objective::ObjectiveId},
+};
+use chrono::{Duration, prelude::*};
+use compact_str::format_compact;
+use dcso3::{
+    MizLua, String, Time, Vector2, azumith2d,
+    controller::{AlarmState, Task, VehicleFormation},
+    env::miz::MizIndex,
+    group::Group,
+    land::Land,
+};
+use enumflags2::BitFlags;
+use log::error;
+use smallvec::SmallVec;
+use std::{mem, sync::Arc};
+
+/// how far behind the trucks the escort starts
+const ESCORT_SPACING: f64 = 150.;
+
+impl Db {
+    /// the points a convoy from source to target drives through,
+    /// ending at the target
+    fn convoy_route(
+        &self,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        cfg: &ConvoyCfg,
+        source: &ObjectiveId,
+        target: &ObjectiveId,
+    ) -> Result<SmallVec<[Vector2; 8]>> {
+        let src = objective!(self, source)?;
+        let dst = objective!(self, target)?;
+        let mut route = SmallVec::new();
+        for zone in cfg.route(&src.name, &dst.name).unwrap_or_default() {
+            route.push(spctx.get_trigger_zone(idx, zone)?.pos()?);
+        }
+        route.push(dst.zone.pos());
+        Ok(route)
+    }
+
+    /// give a convoy group its route. Groups that were already on the
+    /// road when the server restarted pick it up at the next waypoint.
+    /// Returns false if the group has not spawned yet.
+    fn task_convoy(
+        &self,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        cfg: &ConvoyCfg,
+        gid: &GroupId,
+    ) -> Result<bool> {
+        let group = group!(self, gid)?;
+        let (source, target, escort) = match &group.origin {
+            DeployKind::Convoy {
+                origin,
+                target,
+                cargo,
+                ..
+            } => (origin, target, cargo.is_empty()),
+            _ => bail!("{gid} is not a convoy"),
+        };
+        let dcs_group = match Group::get_by_name(spctx.lua(), &group.name) {
+            Ok(g) => g,
+            Err(_) => return Ok(false),
+        };
+        let pos = self.group_center(gid)?;
+        let mut route = self.convoy_route(spctx, idx, cfg, source, target)?;
+        let next = route
+            .iter()
+            .enumerate()
+            .min_by(|(_, p0), (_, p1)| {
+                let d0 = na::distance(&pos.into(), &(**p0).into());
+                let d1 = na::distance(&pos.into(), &(**p1).into());
+                d0.total_cmp(&d1)
+            })
+            .map(|(i, p)| match route.get(i + 1) {
+                // skip the closest point if we are already past it
+                Some(q)
+                    if na::distance(&pos.into(), &(*q).into())
+                        < na::distance(&(*p).into(), &(*q).into()) =>
+                {
+                    i + 1
+                }
+                Some(_) | None => i,
+            })
+            .unwrap_or(0);
+        route.drain(..next);
+        let land = Land::singleton(spctx.lua())?;
+        let transit = if cfg.use_roads {
+            VehicleFormation::OnRoad
+        } else {
+            VehicleFormation::OffRoad
+        };
+        // trucks keep driving under fire, the escort fights back
+        let state = if escort {
+            AlarmState::Auto
+        } else {
+            AlarmState::Green
+        };
+        let mut start = waypoint(
+            &land,
+            pos,
+            transit.clone(),
+            Task::ComboTask(vec![alarm_state(state)]),
+        )?;
+        start.eta = Some(Time(0.));
+        start.eta_locked = Some(true);
+        let mut mission = vec![start];
+        for p in route {
+            mission.push(waypoint(
+                &land,
+                p,
+                transit.clone(),
+                Task::ComboTask(vec![]),
+            )?);
+        }
+        if let Some(last) = mission.last_mut() {
+            last.name = Some(String::from("convoy"));
+        }
+        dcs_group.get_controller()?.set_task(Task::Mission {
+            airborne: Some(false),
+            route: mission,
+        })?;
+        Ok(true)
+    }
+
+    /// load the shipments from source to target onto a new convoy
+    fn dispatch_convoy(
+        &mut self,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        cfg: &ConvoyCfg,
+        (source, target): (ObjectiveId, ObjectiveId),
+        shipments: Vec<Shipment>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let side = objective!(self, source)?.owner;
+        if objective!(self, target)?.owner != side {
+            // the target changed hands since the transfer was planned
+            return Ok(());
+        }
+        let template = match cfg.template.get(&side) {
+            Some(t) => t.clone(),
+            None => return Ok(()),
+        };
+        let route = self.convoy_route(spctx, idx, cfg, &source, &target)?;
+        let src = objective!(self, source)?;
+        let from = src.zone.pos();
+        // the first waypoint may be inside the source
+        let dir = route
+            .iter()
+            .find_map(|p| (p - from).try_normalize(1.))
+            .unwrap_or(Vector2::new(1., 0.));
+        let start = from + dir * src.zone.radius();
+        let group_heading = azumith2d(dir);
+        let cargo = self.ship_from(spctx.lua(), source, &shipments)?;
+        if cargo.is_empty() {
+            return Ok(());
+        }
+        let kind = |cargo| DeployKind::Convoy {
+            origin: source,
+            target,
+            cargo,
+            time: now,
+        };
+        self.add_and_queue_group(
+            spctx,
+            idx,
+            side,
+            SpawnLoc::AtPos {
+                pos: start,
+                offset_direction: dir,
+                group_heading,
+            },
+            &template,
+            kind(cargo),
+            BitFlags::from(UnitTag::Driveable),
+            None,
+        )
+        .context("adding convoy")?;
+        if let Some(escort) = cfg.escort.get(&side) {
+            self.add_and_queue_group(
+                spctx,
+                idx,
+                side,
+                SpawnLoc::AtPos {
+                    pos: start - dir * ESCORT_SPACING,
+                    offset_direction: dir,
+                    group_heading,
+                },
+                escort,
+                kind(vec![]),
+                BitFlags::from(UnitTag::Driveable),
+                None,
+            )
+            .context("adding convoy escort")?;
+        }
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// the part of cargo carried by the units of gid that are still alive
+    fn surviving_cargo(&self, gid: &GroupId, cargo: &[Shipment]) -> Result<Vec<Shipment>> {
+        let (alive, total) = self.group_health(gid)?;
+        Ok(cargo
+            .iter()
+            .map(|s| Shipment {
+                item: s.item.clone(),
+                amount: (s.amount as u64 * alive as u64 / total.max(1) as u64) as u32,
+            })
+            .filter(|s| s.amount > 0)
+            .collect())
+    }
+
+    /// unload whatever survived the trip at the target and remove the convoy
+    fn convoy_arrived(&mut self, lua: MizLua, gid: &GroupId) -> Result<()> {
+        let group = group!(self, gid)?;
+        let side = group.side;
+        if let DeployKind::Convoy {
+            origin,
+            target,
+            cargo,
+            ..
+        } = &group.origin
+            && !cargo.is_empty()
+        {
+            let (origin, target) = (*origin, *target);
+            let cargo = self.surviving_cargo(gid, cargo)?;
+            let dst = objective!(self, target)?;
+            if dst.owner == side {
+                self.receive_at(lua, target, &cargo)?;
+            } else {
+                let src = objective!(self, origin)
+                    .map(|o| o.name.clone())
+                    .unwrap_or_default();
+                let msg = format_compact!(
+                    "{} fell before the supply convoy from {src} arrived",
+                    dst.name
+                );
+                self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+            }
+        }
+        self.delete_group(gid)
+    }
+
+    /// a convoy that took too long is assumed stuck, its cargo goes back
+    /// to where it came from
+    fn convoy_stuck(&mut self, lua: MizLua, gid: &GroupId) -> Result<()> {
+        let group = group!(self, gid)?;
+        if let DeployKind::Convoy { origin, cargo, .. } = &group.origin
+            && objective!(self, origin)
+                .map(|o| o.owner == group.side)
+                .unwrap_or(false)
+        {
+            let origin = *origin;
+            let cargo = self.surviving_cargo(gid, cargo)?;
+            self.receive_at(lua, origin, &cargo)?;
+        }
+        self.delete_group(gid)
+    }
+
+    /// called when every unit in a convoy is dead, the cargo is lost
+    pub(super) fn convoy_lost(&mut self, gid: &GroupId) -> Result<()> {
+        let group = group!(self, gid)?;
+        if let DeployKind::Convoy {
+            origin,
+            target,
+            cargo,
+            ..
+        } = &group.origin
+            && !cargo.is_empty()
+        {
+            let name = |oid: &ObjectiveId| {
+                objective!(self, oid)
+                    .map(|o| o.name.clone())
+                    .unwrap_or_default()
+            };
+            let msg = format_compact!(
+                "the supply convoy from {} to {} was destroyed",
+                name(origin),
+                name(target)
+            );
+            self.ephemeral
+                .msgs()
+                .panel_to_side(10, false, group.side, msg);
+        }
+        self.delete_group(gid)
+    }
+
+    /// check one convoy for arrival, give it its orders once it has spawned
+    fn advance_convoy(
+        &mut self,
+        lua: MizLua,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        cfg: &ConvoyCfg,
+        gid: &GroupId,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let (target, time) = match &group!(self, gid)?.origin {
+            DeployKind::Convoy { target, time, .. } => (*target, *time),
+            _ => bail!("{gid} is not a convoy"),
+        };
+        if now - time > Duration::minutes(cfg.timeout as i64) {
+            return self.convoy_stuck(lua, gid);
+        }
+        let dst = match self.persisted.objectives.get(&target) {
+            Some(obj) => obj,
+            // the target was a farp that has since been removed
+            None => return self.delete_group(gid),
+        };
+        if dst.zone.contains(self.group_center(gid)?) {
+            return self.convoy_arrived(lua, gid);
+        }
+        if !self.ephemeral.convoys_tasked.contains(gid) && self.task_convoy(spctx, idx, cfg, gid)? {
+            self.ephemeral.convoys_tasked.insert(*gid);
+        }
+        Ok(())
+    }
+
+    /// load queued shipments onto convoys and move the convoys on the
+    /// road along. Only does anything when convoys are configured.
+    pub fn run_convoys(&mut self, lua: MizLua, idx: &MizIndex, now: DateTime<Utc>) -> Result<()> {
+        let gcfg = Arc::clone(&self.ephemeral.cfg);
+        let cfg = match gcfg.warehouse.as_ref().and_then(|w| w.convoy.as_ref()) {
+            Some(cfg) => cfg,
+            None => return Ok(()),
+        };
+        let spctx = SpawnCtx::new(lua)?;
+        for (route, shipments) in mem::take(&mut self.ephemeral.convoy_queue) {
+            if let Err(e) = self.dispatch_convoy(&spctx, idx, cfg, route, shipments, now) {
+                error!("could not dispatch convoy {route:?} {e:?}")
+            }
+        }
+        let convoys = &self.persisted.convoys;
+        self.ephemeral
+            .convoys_tasked
+            .retain(|gid| convoys.contains(gid));
+        let convoys: SmallVec<[GroupId; 16]> =
+            self.persisted.convoys.into_iter().copied().collect();
+        for gid in convoys {
+            if let Err(e) = self.advance_convoy(lua, &spctx, idx, cfg, &gid, now) {
+                error!("could not advance convoy {gid} {e:?}")
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! combat search and rescue. A player who ejects is spawned where
+//! they land as a downed pilot troop group, which friendly helicopters
+//! extract with the troop cargo menu and land at a friendly objective
+
+use super::{Db, group::DeployKind};
+use crate::{
+    group,
+    spawnctx::{SpawnCtx, SpawnLoc},
+};
+use anyhow::{Result, anyhow};
+use bfprotocols::{
+    cfg::{LifeType, LimitEnforceTyp, PersistTyp, Troop, UnitTag},
+    db::group::GroupId,
+    stats::Stat,
+};
+use chrono::{Duration, prelude::*};
+use compact_str::format_compact;
+use dcso3::{
+    LuaVec2, MizLua, String, Vector2, azumith3d,
+    coalition::Side,
+    env::miz::MizIndex,
+    land::{Land, SurfaceType},
+    net::{SlotId, Ucid},
+    object::{DcsObject, Object},
+    trigger::Trigger,
+    unit::Unit,
+};
+use enumflags2::BitFlags;
+use serde_derive::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+/// the name of the troop that stands in for a downed pilot
+const DOWNED_PILOT: &str = "Downed Pilot";
+/// forget an ejected pilot that hasn't landed after this long
+const EJECTED_TIMEOUT: Duration = Duration::minutes(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownedPilot {
+    pub ucid: Ucid,
+    /// the life the pilot lost when they took off, if any
+    pub life_type: Option<LifeType>,
+    pub time: DateTime<Utc>,
+}
+
+impl Db {
+    /// remember who a player's ejected pilot belongs to until it lands
+    pub fn pilot_ejected(&mut self, unit: &Unit, pilot: &Object) -> Result<()> {
+        if self.ephemeral.cfg.csar.is_none() {
+            return Ok(());
+        }
+        let ucid = match self.player_in_unit(false, &unit.object_id()?) {
+            Some(ucid) => ucid,
+            None => return Ok(()),
+        };
+        let player = self
+            .player(&ucid)
+            .ok_or_else(|| anyhow!("no such player {ucid}"))?;
+        let now = Utc::now();
+        let downed = DownedPilot {
+            ucid,
+            life_type: player.airborne,
+            time: now,
+        };
+        let side = player.side;
+        self.ephemeral
+            .ejected
+            .retain(|_, (_, p)| now - p.time < EJECTED_TIMEOUT);
+        self.ephemeral
+            .ejected
+            .insert(pilot.object_id()?, (side, downed));
+        Ok(())
+    }
+
+    /// spawn a downed pilot where an ejected pilot landed, unless they
+    /// landed in the water
+    pub fn pilot_landed(&mut self, lua: MizLua, idx: &MizIndex, pilot: &Object) -> Result<()> {
+        let (side, downed) = match self.ephemeral.ejected.remove(&pilot.object_id()?) {
+            Some(ejected) => ejected,
+            None => return Ok(()),
+        };
+        let cfg = match self.ephemeral.cfg.csar.as_ref() {
+            Some(cfg) => cfg.clone(),
+            None => return Ok(()),
+        };
+        let template = match cfg.template.get(&side) {
+            Some(template) => template.clone(),
+            None => return Ok(()),
+        };
+        let pos = pilot.get_position()?;
+        let point = Vector2::new(pos.p.x, pos.p.z);
+        match Land::singleton(lua)?.get_surface_type(LuaVec2(point))? {
+            SurfaceType::Water | SurfaceType::ShallowWater => return Ok(()),
+            SurfaceType::Land | SurfaceType::Road | SurfaceType::Runway => (),
+        }
+        let spawnpos = SpawnLoc::AtPos {
+            pos: point,
+            offset_direction: Vector2::new(pos.x.x, pos.x.z),
+            group_heading: azumith3d(pos.x.0),
+        };
+        let spec = Troop {
+            name: String::from(DOWNED_PILOT),
+            template: template.clone(),
+            persist: PersistTyp::Forever,
+            can_capture: false,
+            limit: u32::MAX,
+            limit_enforce: LimitEnforceTyp::DeleteOldest,
+            weight: cfg.weight,
+            cost: 0,
+            jtac: None,
+        };
+        let ucid = downed.ucid;
+        let dk = DeployKind::Troop {
+            player: ucid,
+            moved_by: None,
+            spec,
+            origin: None,
+            cost_fraction: 0.,
+        };
+        let gid = self.add_and_queue_group(
+            &SpawnCtx::new(lua)?,
+            idx,
+            side,
+            spawnpos,
+            &template,
+            dk,
+            BitFlags::empty(),
+            None,
+        )?;
+        self.persisted.csar.insert_cow(gid, downed);
+        self.mark_group(&gid)?;
+        let name = self
+            .player(&ucid)
+            .map(|p| p.name.clone())
+            .unwrap_or_default();
+        let msg = format_compact!("{name} is down, pilot {gid} needs to be rescued");
+        self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// downed pilots on board the helicopter in slot are rescued if it
+    /// has landed at a friendly objective
+    pub fn deliver_rescued_pilots(
+        &mut self,
+        lua: MizLua,
+        slot: &SlotId,
+        position: Vector2,
+    ) -> Result<()> {
+        let by = match self.ephemeral.player_in_slot(slot) {
+            Some(ucid) => *ucid,
+            None => return Ok(()),
+        };
+        let side = match self.player(&by) {
+            Some(player) => player.side,
+            None => return Ok(()),
+        };
+        let cargo = match self.ephemeral.cargo.get_mut(slot) {
+            Some(cargo) if cargo.troops.iter().any(|it| it.rescue.is_some()) => cargo,
+            Some(_) | None => return Ok(()),
+        };
+        let at_friendly = self
+            .persisted
+            .objectives
+            .into_iter()
+            .any(|(_, obj)| obj.owner == side && obj.zone.contains(position));
+        if !at_friendly {
+            return Ok(());
+        }
+        let mut rescued: SmallVec<[DownedPilot; 2]> = SmallVec::new();
+        cargo.troops.retain(|it| match &it.rescue {
+            Some(pilot) => {
+                rescued.push(pilot.clone());
+                false
+            }
+            None => true,
+        });
+        let weight = cargo.weight();
+        let unit_name = self
+            .ephemeral
+            .get_slot_info(slot)
+            .ok_or_else(|| anyhow!("no slot info for {slot:?}"))?
+            .unit_name
+            .clone();
+        Trigger::singleton(lua)?
+            .action()?
+            .set_unit_internal_cargo(unit_name, weight)?;
+        for pilot in rescued {
+            self.pilot_rescued(&by, pilot)
+        }
+        Ok(())
+    }
+
+    fn pilot_rescued(&mut self, by: &Ucid, pilot: DownedPilot) {
+        let rescue_points = self
+            .ephemeral
+            .cfg
+            .csar
+            .as_ref()
+            .map(|cfg| cfg.rescue_points)
+            .unwrap_or(0);
+        let rescuer = self.player(by).map(|p| p.name.clone()).unwrap_or_default();
+        let player = match self.persisted.players.get_mut_cow(&pilot.ucid) {
+            Some(player) => player,
+            None => return,
+        };
+        let name = player.name.clone();
+        let returned = match pilot.life_type {
+            Some(lt) => match player.lives.get_mut_cow(&lt) {
+                Some((_, lives)) => {
+                    *lives += 1;
+                    if *lives >= self.ephemeral.cfg.default_lives[&lt].0 {
+                        player.lives.remove_cow(&lt);
+                    }
+                    true
+                }
+                None => false,
+            },
+            None => false,
+        };
+        if returned {
+            self.ephemeral.stat(Stat::Life {
+                id: pilot.ucid,
+                lives: player.lives.clone(),
+            });
+        }
+        self.ephemeral.stat(Stat::Rescue {
+            by: *by,
+            pilot: pilot.ucid,
+        });
+        // no points for rescuing yourself
+        if *by != pilot.ucid && rescue_points > 0 {
+            let msg = format_compact!("for rescuing {name}");
+            self.adjust_points(by, rescue_points as i32, &msg);
+        }
+        let msg = if returned {
+            format_compact!("{rescuer} rescued you, your life was returned")
+        } else {
+            format_compact!("{rescuer} rescued you")
+        };
+        self.ephemeral
+            .panel_to_player(&self.persisted, 10, &pilot.ucid, msg);
+        let msg = format_compact!("you rescued {name}");
+        self.ephemeral.panel_to_player(&self.persisted, 10, by, msg);
+        self.ephemeral.dirty();
+    }
+
+    /// true if an enemy ground unit is within range of pos
+    fn enemy_ground_near(&self, side: Side, pos: Vector2, range: f64) -> bool {
+        let range = range.powi(2);
+        let enemy = match self.persisted.groups_by_side.get(&side.opposite()) {
+            Some(groups) => groups,
+            None => return false,
+        };
+        enemy
+            .into_iter()
+            .filter(|gid| self.persisted.csar.get(gid).is_none())
+            .filter_map(|gid| self.persisted.groups.get(gid))
+            .flat_map(|group| group.units.into_iter())
+            .filter_map(|uid| self.persisted.units.get(uid))
+            .any(|unit| {
+                !unit.dead
+                    && !unit.tags.contains(UnitTag::Aircraft)
+                    && !unit.tags.contains(UnitTag::Helicopter)
+                    && na::distance_squared(&unit.pos.into(), &pos.into()) <= range
+            })
+    }
+
+    /// capture downed pilots enemy ground units have reached, and give
+    /// up on those who have waited too long
+    pub fn run_csar(&mut self, now: DateTime<Utc>) -> Result<()> {
+        let cfg = match self.ephemeral.cfg.csar.as_ref() {
+            Some(cfg) => cfg.clone(),
+            None => return Ok(()),
+        };
+        let mut captured: SmallVec<[GroupId; 4]> = SmallVec::new();
+        let mut expired: SmallVec<[GroupId; 4]> = SmallVec::new();
+        for (gid, pilot) in &self.persisted.csar {
+            let group = match self.persisted.groups.get(gid) {
+                Some(group) => group,
+                None => {
+                    expired.push(*gid);
+                    continue;
+                }
+            };
+            if now - pilot.time >= Duration::minutes(cfg.timeout as i64) {
+                expired.push(*gid);
+                continue;
+            }
+            let pos = group
+                .units
+                .into_iter()
+                .filter_map(|uid| self.persisted.units.get(uid))
+                .find(|unit| !unit.dead)
+                .map(|unit| unit.pos);
+            if let Some(pos) = pos
+                && self.enemy_ground_near(group.side, pos, cfg.capture_distance as f64)
+            {
+                captured.push(*gid);
+            }
+        }
+        for gid in expired {
+            if self.persisted.groups.get(&gid).is_some() {
+                self.delete_group(&gid)?
+            }
+            self.persisted.csar.remove_cow(&gid);
+            self.ephemeral.dirty();
+        }
+        for gid in captured {
+            let side = group!(self, gid)?.side;
+            let pilot = match self.persisted.csar.get(&gid) {
+                Some(pilot) => pilot.clone(),
+                None => continue,
+            };
+            self.delete_group(&gid)?;
+            let name = self
+                .player(&pilot.ucid)
+                .map(|p| p.name.clone())
+                .unwrap_or_default();
+            if cfg.capture_cost > 0 {
+                self.adjust_points(
+                    &pilot.ucid,
+                    -(cfg.capture_cost as i32),
+                    "for being captured",
+                );
+            }
+            self.ephemeral.stat(Stat::PilotCaptured {
+                pilot: pilot.ucid,
+                by: side.opposite(),
+            });
+            let msg = format_compact!("{name} was captured by the enemy");
+            self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+            let msg = format_compact!("we captured the enemy pilot {name}");
+            self.ephemeral
+                .msgs()
+                .panel_to_side(10, false, side.opposite(), msg);
+        }
+        Ok(())
+    }
+}
@@ -15,12 +15,16 @@ for more details.
 */
 
 use super::{
-    cargo::Cargo,
-    group::{SpawnedGroup, SpawnedUnit},
-    logistics::LogiStage,
+    cargo::{Cargo, CrashedTroop},
+    csar::DownedPilot,
+    group::{DeployKind, SpawnedGroup, SpawnedUnit},
+    logistics::{LogiStage, Shipment},
     markup::ObjectiveMarkup,
     objective::Objective,
     persisted::Persisted,
+    runway::FallingWeapon,
+    slingload::SlungCrate,
+    upkeep::SupplyStatus,
 };
 use crate::{
     bg::Task,
@@ -32,15 +36,15 @@ use anyhow::{Context, Result, anyhow, bail};
 use bfprotocols::{
     cfg::{
         ActionKind, AiPlaneCfg, AwacsCfg, BomberCfg, Cfg, Crate, Deployable, DeployableCfg,
-        DeployableKind, DeployableObjective, DroneCfg, Troop, UnitTag, Vehicle, VictoryCondition,
-        WarehouseConfig,
+        DeployableKind, DeployableObjective, DroneCfg, Troop, UnitTag, UpkeepCfg, Vehicle,
+        VictoryCondition, WarehouseConfig,
     },
     db::{
         group::{GroupId, UnitId},
         objective::ObjectiveId,
     },
     perf::PerfInner,
-    stats::Stat,
+    stats::{RoundEndReason, Stat},
 };
 use chrono::prelude::*;
 use compact_str::format_compact;
@@ -53,12 +57,13 @@ use dcso3::{
     env::miz::{self, GroupKind, Miz, MizIndex},
     group::ClassGroup,
     net::{SlotId, Ucid},
-    object::{DcsObject, DcsOid},
+    object::{ClassObject, DcsObject, DcsOid},
     perf::record_perf,
     static_object::ClassStatic,
     trigger::MarkId,
     unit::{ClassUnit, Unit},
     warehouse::LiquidType,
+    weapon::ClassWeapon,
 };
 use fxhash::{FxBuildHasher, FxHashMap, FxHashSet};
 use indexmap::{IndexMap, IndexSet};
@@ -73,6 +78,61 @@ use std::{
 };
 use tokio::sync::mpsc::UnboundedSender;
 
+/// the checks of cfg that don't need the mission
+fn check_cfg(cfg: &Cfg) -> Result<()> {
+    if let Some(vc) = &cfg.auto_reset {
+        check_victory_condition(cfg, &vc.condition)?;
+    }
+    if let Some(icfg) = cfg.warehouse.as_ref().and_then(|w| w.industry.as_ref())
+        && icfg.base_production > 100
+    {
+        bail!("industry base_production must be between 0 and 100")
+    }
+    if let Some(acfg) = cfg.points.as_ref().and_then(|p| p.assists.as_ref()) {
+        let total =
+            acfg.killer as u32 + acfg.damage as u32 + acfg.support as u32 + acfg.spotter as u32;
+        if total > 100 {
+            bail!("assists percentages add up to {total}, more than 100")
+        }
+    }
+    Ok(())
+}
+
+fn check_victory_condition(cfg: &Cfg, vc: &VictoryCondition) -> Result<()> {
+    match vc {
+        VictoryCondition::MapOwned { fraction } => {
+            if *fraction > 1. || *fraction < 0. {
+                bail!("auto_reset fraction must be between 0 and 1")
+            }
+        }
+        VictoryCondition::KeyObjectives { objectives } => {
+            if objectives.is_empty() {
+                bail!("auto_reset key objectives must not be empty")
+            }
+        }
+        VictoryCondition::TimeLimit { hours } => {
+            if *hours <= 0. {
+                bail!("auto_reset time limit must be positive")
+            }
+        }
+        VictoryCondition::TicketsExhausted => {
+            if cfg.tickets.is_none() {
+                bail!("auto_reset TicketsExhausted requires tickets to be configured")
+            }
+        }
+        VictoryCondition::All(conditions) | VictoryCondition::Any(conditions) => {
+            if conditions.is_empty() {
+                bail!("auto_reset All and Any must have at least one condition")
+            }
+            for c in conditions {
+                check_victory_condition(cfg, c)?
+            }
+        }
+        VictoryCondition::LogisticsHubsDestroyed | VictoryCondition::Points { .. } => (),
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct SlotInfo {
     pub unit_name: String,
@@ -88,6 +148,8 @@ pub(super) struct DeployableIndex {
     pub(super) deployables_by_name: FxHashMap<String, Deployable>,
     pub(super) deployables_by_crates: FxHashMap<String, String>,
     pub(super) deployables_by_repair: FxHashMap<String, String>,
+    /// the deployables that accept a crate as a substitute
+    pub(super) deployables_by_substitute: FxHashMap<String, SmallVec<[String; 2]>>,
     pub(super) crates_by_name: FxHashMap<String, Crate>,
     pub(super) squads_by_name: FxHashMap<String, Troop>,
     pub(super) pad_templates: FxHashMap<String, FxHashSet<String>>,
@@ -104,6 +166,14 @@ pub(super) struct Production {
     pub(super) liquids: FxHashMap<LiquidType, u32>,
 }
 
+impl Production {
+    /// true if nothing at all is produced
+    pub(super) fn is_nothing(&self) -> bool {
+        self.equipment.values().all(|eq| eq.production == 0)
+            && self.liquids.values().all(|qty| *qty == 0)
+    }
+}
+
 #[derive(Debug)]
 pub struct Ephemeral {
     pub(super) dirty: bool,
@@ -113,6 +183,7 @@ pub struct Ephemeral {
     pub(super) cargo: FxHashMap<SlotId, Cargo>,
     pub(super) deployable_idx: FxHashMap<Side, Arc<DeployableIndex>>,
     pub(super) group_marks: FxHashMap<GroupId, MarkId>,
+    pub(super) site_marks: FxHashMap<u32, MarkId>,
     objective_markup: FxHashMap<ObjectiveId, ObjectiveMarkup>,
     pub(super) object_id_by_uid: FxHashMap<UnitId, DcsOid<ClassUnit>>,
     pub(super) uid_by_object_id: FxHashMap<DcsOid<ClassUnit>, UnitId>,
@@ -129,8 +200,34 @@ pub struct Ephemeral {
     force_to_spectators: BTreeMap<DateTime<Utc>, SmallVec<[Ucid; 1]>>,
     pub(super) units_able_to_move: IndexSet<UnitId, FxBuildHasher>,
     pub(super) groups_with_move_missions: FxHashMap<GroupId, Vector2>,
+    pub(super) offensives_tasked: FxHashSet<GroupId>,
+    pub(super) last_offensive: DateTime<Utc>,
+    pub(super) convoys_tasked: FxHashSet<GroupId>,
+    /// shipments waiting to be loaded onto a convoy, by source and target
+    pub(super) convoy_queue: FxHashMap<(ObjectiveId, ObjectiveId), Vec<Shipment>>,
+    pub(super) falling_weapons: FxHashMap<DcsOid<ClassWeapon>, FallingWeapon>,
+    /// crates hanging under a helicopter
+    pub(super) slung: FxHashMap<GroupId, SlungCrate>,
+    /// troops that may have survived a crash, waiting to be spawned
+    pub(super) crashed_troops: Vec<CrashedTroop>,
+    /// the origins of supply transfer crates lost in a crash, waiting
+    /// to be taken out of the warehouse
+    pub(super) lost_supply: Vec<ObjectiveId>,
+    /// pilots who ejected and haven't landed yet, by the ejected pilot object
+    pub(super) ejected: FxHashMap<DcsOid<ClassObject>, (Side, DownedPilot)>,
+    pub(super) supply_status: FxHashMap<GroupId, SupplyStatus>,
+    /// the group object each supply status was last applied to
+    pub(super) supply_applied: FxHashMap<GroupId, DcsOid<ClassGroup>>,
+    /// when a unit that needs upkeep started firing its guns
+    pub(super) gun_bursts: FxHashMap<DcsOid<ClassUnit>, f32>,
+    pub(super) grief_bans: Vec<(Ucid, Option<DateTime<Utc>>)>,
+    /// sorties that are over but still take late credit, until the
+    /// time, or until takeoff or slot exit if None
+    pub(super) sorties_ending: FxHashMap<Ucid, Option<DateTime<Utc>>>,
     pub(super) units_potentially_close_to_enemies: FxHashSet<UnitId>,
     pub(super) production_by_side: FxHashMap<Side, Arc<Production>>,
+    /// warehouse items that aren't aircraft
+    pub(super) munitions: FxHashSet<String>,
     pub(super) actions_taken: FxHashMap<Side, FxHashMap<String, u32>>,
     pub(super) delayspawnq: BTreeMap<DateTime<Utc>, SmallVec<[GroupId; 8]>>,
     pub(super) awacs_stn: u32,
@@ -139,7 +236,7 @@ pub struct Ephemeral {
     despawnq: VecDeque<(GroupId, Despawn)>,
     sync_warehouse: Vec<(ObjectiveId, Vehicle)>,
     pub(super) msgs: MsgQ,
-    pub(super) victory: Option<(DateTime<Utc>, Side)>,
+    pub(super) victory: Option<(DateTime<Utc>, Side, RoundEndReason)>,
 }
 
 impl Default for Ephemeral {
@@ -152,6 +249,7 @@ impl Default for Ephemeral {
             cargo: FxHashMap::default(),
             deployable_idx: FxHashMap::default(),
             group_marks: FxHashMap::default(),
+            site_marks: FxHashMap::default(),
             objective_markup: FxHashMap::default(),
             object_id_by_uid: FxHashMap::default(),
             uid_by_object_id: FxHashMap::default(),
@@ -168,8 +266,23 @@ impl Default for Ephemeral {
             force_to_spectators: BTreeMap::default(),
             units_able_to_move: IndexSet::default(),
             groups_with_move_missions: FxHashMap::default(),
+            offensives_tasked: FxHashSet::default(),
+            last_offensive: Utc::now(),
+            convoys_tasked: FxHashSet::default(),
+            convoy_queue: FxHashMap::default(),
+            falling_weapons: FxHashMap::default(),
+            slung: FxHashMap::default(),
+            crashed_troops: Vec::default(),
+            lost_supply: Vec::default(),
+            ejected: FxHashMap::default(),
+            supply_status: FxHashMap::default(),
+            supply_applied: FxHashMap::default(),
+            gun_bursts: FxHashMap::default(),
+            grief_bans: Vec::default(),
+            sorties_ending: FxHashMap::default(),
             units_potentially_close_to_enemies: FxHashSet::default(),
             production_by_side: FxHashMap::default(),
+            munitions: FxHashSet::default(),
             actions_taken: FxHashMap::default(),
             delayspawnq: BTreeMap::default(),
             awacs_stn: 0o77777,
@@ -224,7 +337,10 @@ impl Ephemeral {
         moved: &[ObjectiveId],
     ) {
         match self.objective_markup.entry(obj.id) {
-            Entry::Occupied(mut e) => e.get_mut().update(persisted, &mut self.msgs, obj, moved),
+            Entry::Occupied(mut e) => {
+                e.get_mut()
+                    .update(&self.cfg, persisted, &mut self.msgs, obj, moved)
+            }
             Entry::Vacant(e) => {
                 e.insert(ObjectiveMarkup::new(
                     &self.cfg,
@@ -381,6 +497,7 @@ impl Ephemeral {
         side: Side,
         repair_crate: Crate,
         whcfg: &Option<WarehouseConfig>,
+        upkeep: &Option<UpkeepCfg>,
         points: bool,
         deployables: &[Deployable],
     ) -> Result<()> {
@@ -398,6 +515,17 @@ impl Ephemeral {
                 },
             };
         }
+        if let Some(upkeep) = upkeep.as_ref() {
+            match upkeep.resupply_crate.get(&side) {
+                None => bail!("missing resupply crate for {side}"),
+                Some(cr) => match idx.crates_by_name.entry(cr.name.clone()) {
+                    Entry::Occupied(_) => bail!("multiple {} crates for side {side}", cr.name),
+                    Entry::Vacant(e) => {
+                        e.insert(cr.clone());
+                    }
+                },
+            };
+        }
         for dep in deployables.iter() {
             if let DeployableKind::Group { template } = &dep.kind {
                 miz.get_group_by_name(mizidx, GroupKind::Any, side, template)?
@@ -519,6 +647,38 @@ impl Ephemeral {
                 }
             }
         }
+        for dep in deployables.iter() {
+            let name = dep.path.last().unwrap();
+            for (cr, subs) in &dep.recipe.substitutes {
+                if !dep.crates.iter().any(|c| &c.name == cr) {
+                    bail!("{name} has substitutes for {cr}, which it doesn't require")
+                }
+                for sub in subs {
+                    if !idx.crates_by_name.contains_key(sub) {
+                        bail!("{name} substitute crate {sub} does not exist")
+                    }
+                    let deps = idx
+                        .deployables_by_substitute
+                        .entry(sub.clone())
+                        .or_default();
+                    if !deps.contains(name) {
+                        deps.push(name.clone())
+                    }
+                }
+            }
+            if let Some(base) = &dep.recipe.upgrade_from {
+                match idx.deployables_by_name.get(base) {
+                    None => bail!("{name} upgrades {base}, which does not exist"),
+                    Some(base) if !base.kind.is_group() || !dep.kind.is_group() => {
+                        bail!(
+                            "only groups can be upgraded, {name} upgrades {:?}",
+                            base.path
+                        )
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
         Ok(())
     }
 
@@ -672,6 +832,18 @@ impl Ephemeral {
         }
     }
 
+    /// set the config without validating it against a mission
+    pub(super) fn set_cfg_headless(
+        &mut self,
+        cfg: Arc<Cfg>,
+        to_bg: UnboundedSender<Task>,
+    ) -> Result<()> {
+        check_cfg(&cfg)?;
+        self.to_bg = Some(to_bg);
+        self.cfg = cfg;
+        Ok(())
+    }
+
     pub(super) fn set_cfg(
         &mut self,
         miz: &Miz,
@@ -711,16 +883,17 @@ impl Ephemeral {
             }
         };
         check_unit_classification()?;
-        if let Some(VictoryCondition::MapOwned { fraction }) = cfg.auto_reset.map(|vc| vc.condition)
-        {
-            if fraction > 1. || fraction < 0. {
-                bail!("auto_reset fraction must be between 0 and 1")
-            }
-        }
+        check_cfg(&cfg)?;
         for (side, template) in cfg.crate_template.iter() {
             miz.get_group_by_name(mizidx, GroupKind::Any, *side, template)?
                 .ok_or_else(|| anyhow!("missing crate template {:?} {template}", side))?;
         }
+        if let Some(csar) = &cfg.csar {
+            for (side, template) in csar.template.iter() {
+                miz.get_group_by_name(mizidx, GroupKind::Any, *side, template)?
+                    .ok_or_else(|| anyhow!("missing csar template {:?} {template}", side))?;
+            }
+        }
         let points = cfg.points.is_some();
         for (side, deployables) in cfg.deployables.iter() {
             let repair_crate = maybe!(cfg.repair_crate, side, "side repair crate")?.clone();
@@ -730,6 +903,7 @@ impl Ephemeral {
                 *side,
                 repair_crate,
                 &cfg.warehouse,
+                &cfg.upkeep,
                 points,
                 deployables,
             )?
@@ -821,6 +995,31 @@ impl Ephemeral {
                 }
             }
         }
+        if let Some(off) = &cfg.offensives {
+            for (side, name) in &off.troop {
+                let troop = self
+                    .deployable_idx
+                    .get(side)
+                    .and_then(|idx| idx.squads_by_name.get(name))
+                    .ok_or_else(|| anyhow!("missing troop {name} for {side} offensives"))?;
+                if !troop.can_capture {
+                    bail!("offensive troop {name} can't capture")
+                }
+            }
+        }
+        if let Some(convoy) = cfg.warehouse.as_ref().and_then(|w| w.convoy.as_ref()) {
+            for (side, template) in convoy.template.iter().chain(convoy.escort.iter()) {
+                miz.get_group_by_name(mizidx, GroupKind::Any, *side, template)?
+                    .ok_or_else(|| anyhow!("missing convoy template {:?} {template}", side))?;
+            }
+            for route in &convoy.routes {
+                for zone in &route.waypoints {
+                    miz.get_trigger_zone(mizidx, zone)?.ok_or_else(|| {
+                        anyhow!("missing waypoint {zone} on convoy route {}", route.from)
+                    })?;
+                }
+            }
+        }
         self.cfg = cfg;
         Ok(())
     }
@@ -889,6 +1088,12 @@ impl Ephemeral {
                         unit.set_alt(su.position.p.y)?;
                         unit.set_heading(su.heading)?;
                         unit.set_name(su.name.clone())?;
+                        if let DeployKind::Crate { spec, .. } = &group.origin
+                            && self.cfg.sling_load
+                        {
+                            unit.raw_set("canCargo", true)?;
+                            unit.raw_set("mass", spec.weight)?;
+                        }
                         i += 1;
                     }
                 }
@@ -927,3 +1132,42 @@ impl Ephemeral {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bfprotocols::cfg::{AssistCfg, IndustryCfg, IndustryOutput};
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn industry_base_production_is_a_percentage() {
+        let mut cfg = Cfg::default();
+        let whcfg = cfg.warehouse.as_mut().unwrap();
+        let icfg = whcfg.industry.get_or_insert_with(|| IndustryCfg {
+            factory: IndustryOutput::default(),
+            refinery: IndustryOutput::default(),
+            port: IndustryOutput::default(),
+            objectives: FxHashMap::default(),
+            base_production: 100,
+        });
+        icfg.base_production = 101;
+        let (to_bg, _from_db) = mpsc::unbounded_channel();
+        let mut ephemeral = Ephemeral::default();
+        assert!(ephemeral.set_cfg_headless(Arc::new(cfg), to_bg).is_err());
+    }
+
+    #[test]
+    fn assist_percentages_are_at_most_100() {
+        let mut cfg = Cfg::default();
+        cfg.points.as_mut().unwrap().assists = Some(AssistCfg {
+            window: 300,
+            killer: 60,
+            damage: 25,
+            support: 10,
+            spotter: 10,
+        });
+        let (to_bg, _from_db) = mpsc::unbounded_channel();
+        let mut ephemeral = Ephemeral::default();
+        assert!(ephemeral.set_cfg_headless(Arc::new(cfg), to_bg).is_err());
+    }
+}
@@ -0,0 +1,533 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! escalating penalties for teamkills, friendly fire, and destroying
+//! friendly assets
+use super::{Db, group::DeployKind, player::Player};
+use anyhow::{Result, anyhow, bail};
+use bfprotocols::{
+    cfg::{GriefKind, GriefPenalty, GriefingCfg, LifeType},
+    shots::{Dead, Who},
+    stats::Stat,
+};
+use chrono::{Duration, prelude::*};
+use compact_str::format_compact;
+use dcso3::{String, net::Ucid, object::DcsOid, static_object::ClassStatic, unit::ClassUnit};
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::fmt;
+
+/// repeated hits on the same victim within this window are one offense
+const FRIENDLY_FIRE_DEBOUNCE: Duration = Duration::seconds(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GriefAction {
+    Offense { kind: GriefKind, victim: String },
+    Warned,
+    LostLife(LifeType),
+    SlotBlocked(DateTime<Utc>),
+    Banned(Option<DateTime<Utc>>),
+}
+
+impl fmt::Display for GriefAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Offense { kind, victim } => write!(f, "{kind} {victim}"),
+            Self::Warned => write!(f, "warned"),
+            Self::LostLife(lt) => write!(f, "lost a {lt} life"),
+            Self::SlotBlocked(until) => write!(f, "blocked from slotting until {until}"),
+            Self::Banned(None) => write!(f, "banned forever"),
+            Self::Banned(Some(until)) => write!(f, "banned until {until}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GriefEntry {
+    pub time: DateTime<Utc>,
+    pub action: GriefAction,
+    /// reversed by an admin, a reversed offense no longer counts
+    #[serde(default)]
+    pub reversed: bool,
+}
+
+impl fmt::Display for GriefEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.action
+        )?;
+        if self.reversed {
+            write!(f, " (reversed)")?
+        }
+        Ok(())
+    }
+}
+
+fn grief_score(cfg: &GriefingCfg, player: &Player, now: DateTime<Utc>) -> u32 {
+    let window = Duration::hours(cfg.window as i64);
+    player
+        .grief
+        .into_iter()
+        .filter(|(_, e)| !e.reversed && now - e.time <= window)
+        .filter_map(|(_, e)| match &e.action {
+            GriefAction::Offense { kind, .. } => cfg.weight.get(kind).copied(),
+            GriefAction::Warned
+            | GriefAction::LostLife(_)
+            | GriefAction::SlotBlocked(_)
+            | GriefAction::Banned(_) => None,
+        })
+        .sum()
+}
+
+impl Db {
+    fn log_grief(&mut self, ucid: &Ucid, now: DateTime<Utc>, action: GriefAction) -> Result<()> {
+        let player = self
+            .persisted
+            .players
+            .get_mut_cow(ucid)
+            .ok_or_else(|| anyhow!("no such player {ucid}"))?;
+        info!("grief {ucid} {}: {action}", player.name);
+        let id = player
+            .grief
+            .into_iter()
+            .next_back()
+            .map(|(id, _)| id + 1)
+            .unwrap_or(0);
+        player.grief.insert_cow(
+            id,
+            GriefEntry {
+                time: now,
+                action,
+                reversed: false,
+            },
+        );
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// record an offense and apply every penalty the player's score
+    /// reaches because of it
+    pub(super) fn grief(
+        &mut self,
+        ucid: &Ucid,
+        kind: GriefKind,
+        victim: String,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        self.offense(ucid, kind, victim, now, None)
+    }
+
+    /// record an offense. If since is set, friendly fire on the same
+    /// victim since then is part of this offense and is replaced by it
+    fn offense(
+        &mut self,
+        ucid: &Ucid,
+        kind: GriefKind,
+        victim: String,
+        now: DateTime<Utc>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let cfg = match self.ephemeral.cfg.griefing.clone() {
+            Some(cfg) => cfg,
+            None => return Ok(()),
+        };
+        let player = self
+            .player(ucid)
+            .ok_or_else(|| anyhow!("no such player {ucid}"))?;
+        let before = grief_score(&cfg, player, now);
+        let hits: SmallVec<[u32; 4]> = match since {
+            None => SmallVec::new(),
+            Some(since) => player
+                .grief
+                .into_iter()
+                .filter(|(_, e)| match &e.action {
+                    GriefAction::Offense {
+                        kind: GriefKind::FriendlyFire,
+                        victim: v,
+                    } => !e.reversed && *v == victim && e.time >= since,
+                    _ => false,
+                })
+                .map(|(id, _)| *id)
+                .collect(),
+        };
+        if let Some(player) = self.persisted.players.get_mut_cow(ucid) {
+            for id in &hits {
+                player.grief.remove_cow(id);
+            }
+        }
+        let weight = |kind| cfg.weight.get(&kind).copied().unwrap_or(0);
+        self.log_grief(ucid, now, GriefAction::Offense { kind, victim })?;
+        // penalties for the replaced hits were already applied
+        let after = (before + weight(kind))
+            .saturating_sub(weight(GriefKind::FriendlyFire) * hits.len() as u32);
+        for (score, penalty) in &cfg.penalties {
+            if before < *score && *score <= after {
+                self.apply_grief_penalty(ucid, *penalty, now)?
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_grief_penalty(
+        &mut self,
+        ucid: &Ucid,
+        penalty: GriefPenalty,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let player = self
+            .persisted
+            .players
+            .get_mut_cow(ucid)
+            .ok_or_else(|| anyhow!("no such player {ucid}"))?;
+        let (action, msg) = match penalty {
+            GriefPenalty::Warn => (
+                GriefAction::Warned,
+                format_compact!("stop attacking your own side, further offenses will be penalized"),
+            ),
+            GriefPenalty::LoseLife => {
+                let life_type = player
+                    .airborne
+                    .or_else(|| {
+                        player
+                            .current_slot
+                            .as_ref()
+                            .and_then(|(slot, _)| self.ephemeral.slot_info.get(slot))
+                            .and_then(|sifo| self.ephemeral.cfg.life_types.get(&sifo.typ))
+                            .copied()
+                    })
+                    .unwrap_or(LifeType::Standard);
+                let default = match self.ephemeral.cfg.default_lives.get(&life_type) {
+                    Some((n, _)) => *n,
+                    None => {
+                        warn!("no {life_type} lives configured, {ucid} keeps their lives");
+                        return Ok(());
+                    }
+                };
+                let (_, lives) = player.lives.get_or_insert_cow(life_type, || (now, default));
+                *lives = lives.saturating_sub(1);
+                self.ephemeral.stat(Stat::Life {
+                    id: *ucid,
+                    lives: player.lives.clone(),
+                });
+                (
+                    GriefAction::LostLife(life_type),
+                    format_compact!("you lost a {life_type} life for attacking your own side"),
+                )
+            }
+            GriefPenalty::BlockSlot(minutes) => {
+                let until = now + Duration::minutes(minutes as i64);
+                player.slot_blocked_until = Some(until);
+                self.ephemeral.force_player_to_spectators(ucid);
+                (
+                    GriefAction::SlotBlocked(until),
+                    format_compact!(
+                        "you can't slot for {minutes} minutes for attacking your own side"
+                    ),
+                )
+            }
+            GriefPenalty::Ban(hours) => {
+                let until = hours.map(|h| now + Duration::hours(h as i64));
+                self.ephemeral.grief_bans.push((*ucid, until));
+                (
+                    GriefAction::Banned(until),
+                    format_compact!("you are banned for attacking your own side"),
+                )
+            }
+        };
+        self.ephemeral
+            .panel_to_player(&self.persisted, 30, ucid, msg);
+        self.log_grief(ucid, now, action)
+    }
+
+    /// a player hit a unit on their own side
+    pub fn friendly_fire(
+        &mut self,
+        now: DateTime<Utc>,
+        shooter: &DcsOid<ClassUnit>,
+        target: &DcsOid<ClassUnit>,
+    ) -> Result<()> {
+        if self.ephemeral.cfg.griefing.is_none() || shooter == target {
+            return Ok(());
+        }
+        let ucid = match self.player_in_unit(false, shooter) {
+            Some(ucid) => ucid,
+            None => return Ok(()),
+        };
+        let side = match self.player(&ucid) {
+            Some(player) => player.side,
+            None => return Ok(()),
+        };
+        let (target_side, victim) = match self.player_in_unit(false, target) {
+            Some(tucid) => match self.player(&tucid) {
+                Some(player) => (player.side, player.name.clone()),
+                None => return Ok(()),
+            },
+            None => match self.ephemeral.get_uid_by_object_id(target) {
+                Some(uid) => {
+                    let unit = self.unit(uid)?;
+                    let owner = match &self.group(&unit.group)?.origin {
+                        DeployKind::Action { player, .. } => *player,
+                        DeployKind::Deployed { player, .. } | DeployKind::Troop { player, .. } => {
+                            Some(*player)
+                        }
+                        DeployKind::Crate { .. }
+                        | DeployKind::Objective { .. }
+                        | DeployKind::Convoy { .. }
+                        | DeployKind::ObjectiveDeprecated => None,
+                    };
+                    (
+                        unit.side,
+                        self.unit_victim(owner.as_ref(), unit.typ.0.as_str()),
+                    )
+                }
+                None => return Ok(()),
+            },
+        };
+        if side != target_side {
+            return Ok(());
+        }
+        // a burst of hits on the same victim is one offense
+        let repeat = self.player(&ucid).is_some_and(|p| {
+            p.grief.into_iter().any(|(_, e)| match &e.action {
+                GriefAction::Offense {
+                    kind: GriefKind::FriendlyFire,
+                    victim: v,
+                } => *v == victim && now - e.time <= FRIENDLY_FIRE_DEBOUNCE,
+                _ => false,
+            })
+        });
+        if !repeat {
+            self.grief(&ucid, GriefKind::FriendlyFire, victim, now)?
+        }
+        Ok(())
+    }
+
+    /// players who hit a friendly that died are charged with killing it
+    pub fn friendly_kill(&mut self, dead: &Dead) -> Result<()> {
+        if self.ephemeral.cfg.griefing.is_none() {
+            return Ok(());
+        }
+        let side = *dead.victim.side();
+        let typ = dead
+            .shots
+            .iter()
+            .find(|s| s.target_typ.trim() != "")
+            .map(|s| s.target_typ.as_str())
+            .unwrap_or_default();
+        let (kind, victim) = match &dead.victim {
+            Who::Player { ucid, .. } => match self.player(ucid) {
+                Some(p) => (GriefKind::TeamKill, p.name.clone()),
+                None => return Ok(()),
+            },
+            Who::AI { ucid, .. } => (
+                GriefKind::FriendlyAsset,
+                self.unit_victim(ucid.as_ref(), typ),
+            ),
+        };
+        let mut by: SmallVec<[(Ucid, DateTime<Utc>); 2]> = SmallVec::new();
+        for shot in dead.shots.iter().filter(|s| s.hit) {
+            if let Who::Player { ucid, side: s, .. } = &shot.shooter
+                && *s == side
+                && Some(ucid) != dead.victim.ucid()
+                && !by.iter().any(|(u, _)| u == ucid)
+            {
+                by.push((*ucid, shot.time))
+            }
+        }
+        for (ucid, since) in by {
+            self.offense(&ucid, kind, victim.clone(), dead.time, Some(since))?
+        }
+        Ok(())
+    }
+
+    /// how a unit is named in the grief log, deployed units are named
+    /// after the player who deployed them
+    fn unit_victim(&self, owner: Option<&Ucid>, typ: &str) -> String {
+        match owner.and_then(|ucid| self.player(ucid)) {
+            Some(p) => format_compact!("{}'s {typ}", p.name).into(),
+            None => String::from(typ),
+        }
+    }
+
+    /// a player destroyed a static object, if it was a friendly crate
+    /// or deployable it is an offense
+    pub fn friendly_static_destroyed(
+        &mut self,
+        now: DateTime<Utc>,
+        shooter: &DcsOid<ClassUnit>,
+        target: &DcsOid<ClassStatic>,
+    ) -> Result<()> {
+        if self.ephemeral.cfg.griefing.is_none() {
+            return Ok(());
+        }
+        let ucid = match self.player_in_unit(false, shooter) {
+            Some(ucid) => ucid,
+            None => return Ok(()),
+        };
+        let uid = match self.ephemeral.uid_by_static.get(target) {
+            Some(uid) => *uid,
+            None => return Ok(()),
+        };
+        let unit = self.unit(&uid)?;
+        let group = self.group(&unit.group)?;
+        let owner = match &group.origin {
+            DeployKind::Crate { player, .. }
+            | DeployKind::Deployed { player, .. }
+            | DeployKind::Troop { player, .. } => *player,
+            DeployKind::Action { .. }
+            | DeployKind::Objective { .. }
+            | DeployKind::Convoy { .. }
+            | DeployKind::ObjectiveDeprecated => return Ok(()),
+        };
+        let side = self.player(&ucid).map(|p| p.side);
+        if side != Some(group.side) {
+            return Ok(());
+        }
+        let victim = self.unit_victim(Some(&owner), unit.typ.0.as_str());
+        self.grief(&ucid, GriefKind::FriendlyAsset, victim, now)
+    }
+
+    /// true if the player was blocked from slotting by a penalty
+    pub(super) fn slot_blocked(&self, ucid: &Ucid, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.player(ucid)
+            .and_then(|p| p.slot_blocked_until)
+            .filter(|until| *until > now)
+    }
+
+    /// bans applied by the griefing policy since the last call
+    pub fn take_grief_bans(&mut self) -> Vec<(Ucid, Option<DateTime<Utc>>)> {
+        std::mem::take(&mut self.ephemeral.grief_bans)
+    }
+
+    pub fn grief_log(&self, ucid: &Ucid) -> Result<Vec<(u32, GriefEntry)>> {
+        let player = self
+            .player(ucid)
+            .ok_or_else(|| anyhow!("no such player {ucid}"))?;
+        Ok(player
+            .grief
+            .into_iter()
+            .map(|(id, e)| (*id, e.clone()))
+            .collect())
+    }
+
+    /// reverse an entry in the player's grief log. Returns the action
+    /// that was reversed, lifting a ban is up to the caller.
+    pub fn forgive(&mut self, ucid: &Ucid, id: u32) -> Result<GriefAction> {
+        let player = self
+            .persisted
+            .players
+            .get_mut_cow(ucid)
+            .ok_or_else(|| anyhow!("no such player {ucid}"))?;
+        let entry = player
+            .grief
+            .get_mut_cow(&id)
+            .ok_or_else(|| anyhow!("no grief log entry {id}"))?;
+        if entry.reversed {
+            bail!("entry {id} was already reversed")
+        }
+        entry.reversed = true;
+        let action = entry.action.clone();
+        match &action {
+            GriefAction::Offense { .. } | GriefAction::Warned | GriefAction::Banned(_) => (),
+            GriefAction::LostLife(lt) => {
+                if let Some((_, lives)) = player.lives.get_mut_cow(lt) {
+                    *lives = lives.saturating_add(1);
+                }
+                self.ephemeral.stat(Stat::Life {
+                    id: *ucid,
+                    lives: player.lives.clone(),
+                });
+            }
+            GriefAction::SlotBlocked(until) => {
+                if player.slot_blocked_until == Some(*until) {
+                    player.slot_blocked_until = None
+                }
+            }
+        }
+        info!("grief {ucid} {}: reversed {action}", player.name);
+        self.ephemeral.dirty();
+        Ok(action)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::sim::test::{db, ucid};
+    use bfprotocols::{cfg::Cfg, shots::Shot};
+    use dcso3::{coalition::Side, net::SlotId};
+    use fxhash::FxHashMap;
+
+    #[test]
+    fn a_teamkill_counts_once() {
+        let cfg = Cfg {
+            griefing: Some(GriefingCfg {
+                window: 24,
+                weight: FxHashMap::from_iter([
+                    (GriefKind::TeamKill, 4),
+                    (GriefKind::FriendlyFire, 1),
+                ]),
+                penalties: vec![(2, GriefPenalty::Warn)],
+            }),
+            ..Cfg::default()
+        };
+        let (mut db, _from_db) = db(cfg);
+        let (shooter, victim) = (ucid(1), ucid(2));
+        db.register_player(shooter, "one".into(), Side::Blue)
+            .unwrap();
+        db.register_player(victim, "two".into(), Side::Blue)
+            .unwrap();
+        let who = |ucid: Ucid, id: i64| Who::Player {
+            unit: serde_json::from_str(&format!(r#"{{"id":{id},"class":"Unit"}}"#)).unwrap(),
+            side: Side::Blue,
+            ucid,
+            slot: SlotId::Unit(id),
+        };
+        let now = Utc::now();
+        db.grief(&shooter, GriefKind::FriendlyFire, "two".into(), now)
+            .unwrap();
+        let dead = Dead {
+            victim: who(victim, 2),
+            time: now + Duration::seconds(5),
+            shots: vec![Shot {
+                weapon_name: None,
+                weapon: None,
+                shooter: who(shooter, 1),
+                target: who(victim, 2),
+                target_typ: "FA-18C_hornet".into(),
+                time: now,
+                hit: true,
+            }],
+            spotters: vec![],
+            credit: vec![],
+        };
+        db.friendly_kill(&dead).unwrap();
+        let offenses: Vec<_> = db
+            .grief_log(&shooter)
+            .unwrap()
+            .into_iter()
+            .filter_map(|(_, e)| match e.action {
+                GriefAction::Offense { kind, .. } => Some(kind),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(offenses, vec![GriefKind::TeamKill]);
+    }
+}
@@ -14,7 +14,9 @@ FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
 for more details.
 */
 
-use super::{ephemeral::SlotInfo, objective::ObjGroupClass, player::SlotAuth, Db, SetS};
+use super::{
+    ephemeral::SlotInfo, logistics::Shipment, objective::ObjGroupClass, player::SlotAuth, Db, SetS,
+};
 use crate::{
     group, group_by_name, group_health, group_mut, objective,
     spawnctx::{Despawn, SpawnCtx, SpawnLoc},
@@ -24,7 +26,7 @@ use anyhow::{anyhow, bail, Context, Result};
 use bfprotocols::{
     cfg::{Action, ActionKind, Crate, Deployable, Troop, UnitTag, UnitTags, Vehicle},
     db::objective::ObjectiveId,
-    stats::{self, EnId},
+    stats::{self, EnId, SortieEnd},
 };
 use bfprotocols::{
     db::group::{GroupId, UnitId},
@@ -113,6 +115,13 @@ pub enum DeployKind {
         #[serde(skip)]
         ammo: i32,
     },
+    Convoy {
+        origin: ObjectiveId,
+        target: ObjectiveId,
+        /// the supplies on board, escorts carry nothing
+        cargo: Vec<Shipment>,
+        time: DateTime<Utc>,
+    },
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -241,6 +250,10 @@ impl Db {
         if let Some(id) = self.ephemeral.group_marks.remove(gid) {
             self.ephemeral.msgs.delete_mark(id)
         }
+        let supply = self
+            .supply_status(gid)
+            .map(|st| format_compact!("\nsupply: {}", st.name()))
+            .unwrap_or_default();
         let group = group_mut!(self, gid)?;
         let group_center =
             centroid2d(group.units.into_iter().map(|uid| self.persisted.units[uid].pos));
@@ -270,7 +283,8 @@ impl Db {
             DeployKind::Action { name, spec: _, destination, player, marks, .. } => {
                 let pname = player
                     .as_ref()
-                    .map(|p| self.persisted.players[p].name.clone())
+                    .and_then(|p| self.persisted.players.get(p))
+                    .map(|p| p.name.clone())
                     .unwrap_or(String::from("Server"));
                 let pos_msg = format_compact!("{name} {gid} deployed by {pname}");
                 let pos_mark = self.ephemeral.msgs.mark_to_side(
@@ -297,7 +311,12 @@ impl Db {
                 }
             }
             DeployKind::Crate { player, spec, .. } => {
-                let name = self.persisted.players[player].name.clone();
+                let name = self
+                    .persisted
+                    .players
+                    .get(player)
+                    .map(|p| p.name.clone())
+                    .unwrap_or(String::from("Server"));
                 let msg = format_compact!("{} {gid} deployed by {name}", spec.name);
                 Some(self.ephemeral.msgs.mark_to_side(
                     group.side,
@@ -313,16 +332,19 @@ impl Db {
                 cost_fraction: _,
                 origin: _,
             } => {
-                let name = self.persisted.players[player].name.clone();
+                let name = self
+                    .persisted
+                    .players
+                    .get(player)
+                    .map(|p| p.name.clone())
+                    .unwrap_or(String::from("Server"));
                 let resp = moved_by
                     .as_ref()
-                    .map(|(u, _)| {
-                        let name = self.persisted.players[u].name.clone();
-                        format_compact!("\nresponsible party: {name}")
-                    })
+                    .and_then(|(u, _)| self.persisted.players.get(u))
+                    .map(|p| format_compact!("\nresponsible party: {}", p.name))
                     .unwrap_or(CompactString::from(""));
                 let msg = format_compact!(
-                    "{} {gid} deployed by {name}{resp}",
+                    "{} {gid} deployed by {name}{resp}{supply}",
                     spec.path.last().unwrap()
                 );
                 Some(self.ephemeral.msgs.mark_to_side(
@@ -333,15 +355,35 @@ impl Db {
                 ))
             }
             DeployKind::Troop { player, spec, moved_by, origin: _, cost_fraction: _ } => {
-                let name = self.persisted.players[player].name.clone();
+                let name = self
+                    .persisted
+                    .players
+                    .get(player)
+                    .map(|p| p.name.clone())
+                    .unwrap_or(String::from("Server"));
                 let resp = moved_by
                     .as_ref()
-                    .map(|(u, _)| {
-                        let name = self.persisted.players[u].name.clone();
-                        format_compact!("\nresponsible party: {name}")
-                    })
+                    .and_then(|(u, _)| self.persisted.players.get(u))
+                    .map(|p| format_compact!("\nresponsible party: {}", p.name))
                     .unwrap_or(CompactString::from(""));
-                let msg = format_compact!("{} {gid} deployed by {name}{resp}", spec.name);
+                let msg = if self.persisted.csar.get(gid).is_some() {
+                    format_compact!("{} {gid} {name} needs rescue", spec.name)
+                } else {
+                    format_compact!("{} {gid} deployed by {name}{resp}", spec.name)
+                };
+                Some(self.ephemeral.msgs.mark_to_side(
+                    group.side,
+                    group_center,
+                    true,
+                    msg,
+                ))
+            }
+            DeployKind::Convoy { target, cargo, .. } => {
+                let kind = if cargo.is_empty() { "escort" } else { "supply convoy" };
+                let dst = objective!(self, target)
+                    .map(|o| o.name.clone())
+                    .unwrap_or(String::from("unknown"));
+                let msg = format_compact!("{kind} {gid} bound for {dst}");
                 Some(self.ephemeral.msgs.mark_to_side(
                     group.side,
                     group_center,
@@ -376,10 +418,15 @@ impl Db {
             }
             DeployKind::Crate { player, .. } => {
                 self.persisted.crates.remove_cow(gid);
-                self.persisted.players[player].crates.remove_cow(gid);
+                if let Some(player) = self.persisted.players.get_mut_cow(player) {
+                    player.crates.remove_cow(gid);
+                }
             }
             DeployKind::Deployed { spec, .. } => {
                 self.persisted.deployed.remove_cow(gid);
+                self.persisted.upkeep.remove_cow(gid);
+                self.ephemeral.supply_status.remove(gid);
+                self.ephemeral.supply_applied.remove(gid);
                 if spec.jtac.is_some() {
                     self.persisted.jtacs.remove_cow(gid);
                 }
@@ -389,10 +436,15 @@ impl Db {
             }
             DeployKind::Troop { spec, .. } => {
                 self.persisted.troops.remove_cow(gid);
+                self.persisted.csar.remove_cow(gid);
+                self.persisted.offensives.remove_cow(gid);
                 if spec.jtac.is_some() {
                     self.persisted.jtacs.remove_cow(gid);
                 }
             }
+            DeployKind::Convoy { .. } => {
+                self.persisted.convoys.remove_cow(gid);
+            }
         }
         if let Some(id) = self.ephemeral.group_marks.remove(gid) {
             self.ephemeral.msgs.delete_mark(id);
@@ -705,6 +757,7 @@ impl Db {
             }
             SpawnLoc::InAir { .. } => (),
         }
+        let mut units: SmallVec<[SpawnedUnit; 16]> = smallvec![];
         for unit in template.group.units()?.into_iter() {
             let uid = UnitId::new();
             let unit = unit?;
@@ -754,9 +807,23 @@ impl Db {
                 moved: None,
                 airborne_velocity: None,
             };
-            spawned.units.insert_cow(uid);
-            self.persisted.units.insert_cow(uid, spawned_unit);
-            self.persisted.units_by_name.insert_cow(unit_name, uid);
+            units.push(spawned_unit);
+        }
+        self.insert_group(spawned, units)
+    }
+
+    /// record a group and its units that are about to be spawned
+    pub(super) fn insert_group(
+        &mut self,
+        mut spawned: SpawnedGroup,
+        units: SmallVec<[SpawnedUnit; 16]>,
+    ) -> Result<GroupId> {
+        let gid = spawned.id;
+        let side = spawned.side;
+        for unit in units {
+            spawned.units.insert_cow(unit.id);
+            self.persisted.units_by_name.insert_cow(unit.name.clone(), unit.id);
+            self.persisted.units.insert_cow(unit.id, unit);
         }
         match &mut spawned.origin {
             DeployKind::ObjectiveDeprecated | DeployKind::Objective { .. } => (),
@@ -774,7 +841,9 @@ impl Db {
             }
             DeployKind::Crate { player, .. } => {
                 self.persisted.crates.insert_cow(gid);
-                self.persisted.players[player].crates.insert_cow(gid);
+                if let Some(player) = self.persisted.players.get_mut_cow(player) {
+                    player.crates.insert_cow(gid);
+                }
             }
             DeployKind::Deployed { spec, .. } => {
                 self.persisted.deployed.insert_cow(gid);
@@ -791,9 +860,14 @@ impl Db {
                     self.persisted.jtacs.insert_cow(gid);
                 }
             }
+            DeployKind::Convoy { .. } => {
+                self.persisted.convoys.insert_cow(gid);
+            }
         }
+        self.persisted
+            .groups_by_name
+            .insert_cow(spawned.name.clone(), gid);
         self.persisted.groups.insert_cow(gid, spawned);
-        self.persisted.groups_by_name.insert_cow(group_name, gid);
         self.persisted.groups_by_side.get_or_default_cow(side).insert_cow(gid);
         self.ephemeral.dirty();
         self.mark_group(&gid)?;
@@ -947,15 +1021,29 @@ impl Db {
         id: &DcsOid<ClassUnit>,
         now: DateTime<Utc>,
     ) -> Result<()> {
+        self.cargo_lost(id);
         let uid = match self.ephemeral.unit_dead(&self.persisted, id) {
             None => return Ok(()),
             Some((uid, ucid)) => {
                 if let Some(ucid) = ucid {
-                    self.player_deslot(&ucid)
+                    self.end_sortie(&ucid, now, SortieEnd::Died);
+                    self.player_deslot(&ucid);
+                    if let Some(tickets) = self.ephemeral.cfg.tickets.as_ref()
+                        && let Some(player) = self.persisted.players.get(&ucid)
+                    {
+                        let (side, loss) = (player.side, tickets.player_death as i32);
+                        let msg = format_compact!("for the death of {}", player.name);
+                        self.adjust_tickets(side, -loss, &msg)
+                    }
                 }
                 uid
             }
         };
+        self.mark_unit_dead(uid, now)
+    }
+
+    /// record the death of a unit that has already been unlinked from the dcs object
+    pub(super) fn mark_unit_dead(&mut self, uid: UnitId, now: DateTime<Utc>) -> Result<()> {
         match self.persisted.units.get_mut_cow(&uid) {
             None => error!("unit_dead: missing unit {:?}", uid),
             Some(unit) => {
@@ -991,7 +1079,12 @@ impl Db {
                                 moved_by: Some((ucid, p)),
                                 ..
                             } => {
-                                let owner = self.persisted.players[player].name.clone();
+                                let owner = self
+                                    .persisted
+                                    .players
+                                    .get(player)
+                                    .map(|p| p.name.clone())
+                                    .unwrap_or(String::from("Server"));
                                 let ucid = ucid.clone();
                                 let p = -(*p as i32);
                                 let msg = format_compact!(
@@ -1004,11 +1097,16 @@ impl Db {
                             | DeployKind::Action { .. }
                             | DeployKind::Crate { .. }
                             | DeployKind::Objective { .. }
+                            | DeployKind::Convoy { .. }
                             | DeployKind::ObjectiveDeprecated => (),
                         }
+                        self.deployable_lost(&gid)?;
                         self.delete_group(&gid)?
                     }
                 }
+                if self.persisted.convoys.contains(&gid) && health == 0 {
+                    self.convoy_lost(&gid)?
+                }
                 if self.persisted.actions.contains(&gid) {
                     if let DeployKind::Action { player, spec, .. } =
                         &group!(self, gid)?.origin
@@ -1057,6 +1155,7 @@ impl Db {
                         || self.persisted.crates.contains(&gid)
                     {
                         if self.group_health(&gid)?.0 == 0 {
+                            self.deployable_lost(&gid)?;
                             self.delete_group(&gid)?
                         }
                     }
@@ -1066,6 +1165,18 @@ impl Db {
         Ok(())
     }
 
+    /// charge the owning side tickets for a destroyed deployed group or troop
+    fn deployable_lost(&mut self, gid: &GroupId) -> Result<()> {
+        if let Some(tickets) = self.ephemeral.cfg.tickets.as_ref()
+            && (self.persisted.deployed.contains(gid) || self.persisted.troops.contains(gid))
+        {
+            let loss = tickets.deployable_loss as i32;
+            let side = group!(self, gid)?.side;
+            self.adjust_tickets(side, -loss, &format_compact!("for the loss of {gid}"))
+        }
+        Ok(())
+    }
+
     pub fn group_health(&self, gid: &GroupId) -> Result<(usize, usize)> {
         group_health!(self, gid)
     }
@@ -16,6 +16,7 @@ for more details.
 
 use super::{
     ephemeral::{Equipment, Production},
+    group::DeployKind,
     objective::Objective,
     persisted::Persisted,
     Db, Map, MapS, SetS,
@@ -23,7 +24,7 @@ use super::{
 use crate::{admin::WarehouseKind, maybe, objective, objective_mut, Task};
 use anyhow::{anyhow, bail, Context, Result};
 use bfprotocols::{
-    cfg::Vehicle,
+    cfg::{StructureKind, Vehicle},
     db::objective::{ObjectiveId, ObjectiveKind},
     perf::{Perf, PerfInner},
     stats::Stat,
@@ -39,7 +40,7 @@ use dcso3::{
     world::World,
     MizLua, String, Vector2,
 };
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use log::{error, warn};
 use serde_derive::{Deserialize, Serialize};
 use smallvec::{smallvec, SmallVec};
@@ -101,6 +102,13 @@ impl Inventory {
             taken
         }
     }
+
+    /// remove percent of the capacity from stored, returning the amount taken
+    pub fn consume(&mut self, percent: f32) -> u32 {
+        let taken = min(self.stored, (self.capacity as f32 * percent) as u32);
+        self.stored -= taken;
+        taken
+    }
 }
 
 impl AddAssign<u32> for Inventory {
@@ -124,30 +132,37 @@ impl SubAssign<u32> for Inventory {
     }
 }
 
-#[derive(Debug, Clone)]
-enum TransferItem {
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransferItem {
     Equipment(String),
     Liquid(LiquidType),
 }
 
-#[derive(Debug, Clone)]
-pub struct Transfer {
-    source: ObjectiveId,
-    target: ObjectiveId,
-    amount: u32,
-    item: TransferItem,
+/// supplies in transit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Shipment {
+    pub item: TransferItem,
+    pub amount: u32,
 }
 
-impl Transfer {
-    fn execute(&self, db: &mut Persisted, to_bg: &Option<UnboundedSender<Task>>) -> Result<()> {
+impl Shipment {
+    /// remove the shipment from the warehouse of oid, returning what
+    /// was actually there to take
+    pub(super) fn take_from(
+        &self,
+        db: &mut Persisted,
+        to_bg: &Option<UnboundedSender<Task>>,
+        oid: &ObjectiveId,
+    ) -> Result<Shipment> {
         let src = db
             .objectives
-            .get_mut_cow(&self.source)
-            .ok_or_else(|| anyhow!("no such objective {:?}", self.source))?;
-        match &self.item {
+            .get_mut_cow(oid)
+            .ok_or_else(|| anyhow!("no such objective {:?}", oid))?;
+        let amount = match &self.item {
             TransferItem::Equipment(name) => {
                 let d = &mut src.warehouse.equipment[name].stored;
-                *d -= self.amount;
+                let amount = min(*d, self.amount);
+                *d -= amount;
                 if let Some(to_bg) = to_bg.as_ref() {
                     let _ = to_bg.send(Task::Stat(Stat::EquipmentInventory {
                         id: src.id,
@@ -155,10 +170,12 @@ impl Transfer {
                         amount: *d,
                     }));
                 }
+                amount
             }
             TransferItem::Liquid(name) => {
                 let d = &mut src.warehouse.liquids[name].stored;
-                *d -= self.amount;
+                let amount = min(*d, self.amount);
+                *d -= amount;
                 if let Some(to_bg) = to_bg.as_ref() {
                     let _ = to_bg.send(Task::Stat(Stat::LiquidInventory {
                         id: src.id,
@@ -166,12 +183,26 @@ impl Transfer {
                         amount: *d,
                     }));
                 }
+                amount
             }
-        }
+        };
+        Ok(Shipment {
+            item: self.item.clone(),
+            amount,
+        })
+    }
+
+    /// add the shipment to the warehouse of oid
+    pub(super) fn deliver_to(
+        &self,
+        db: &mut Persisted,
+        to_bg: &Option<UnboundedSender<Task>>,
+        oid: &ObjectiveId,
+    ) -> Result<()> {
         let dst = db
             .objectives
-            .get_mut_cow(&self.target)
-            .ok_or_else(|| anyhow!("no such objective {:?}", self.target))?;
+            .get_mut_cow(oid)
+            .ok_or_else(|| anyhow!("no such objective {:?}", oid))?;
         match &self.item {
             TransferItem::Equipment(name) => {
                 let d = &mut dst
@@ -204,6 +235,29 @@ impl Transfer {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    source: ObjectiveId,
+    target: ObjectiveId,
+    amount: u32,
+    item: TransferItem,
+}
+
+impl Transfer {
+    fn shipment(&self) -> Shipment {
+        Shipment {
+            item: self.item.clone(),
+            amount: self.amount,
+        }
+    }
+
+    fn execute(&self, db: &mut Persisted, to_bg: &Option<UnboundedSender<Task>>) -> Result<()> {
+        self.shipment()
+            .take_from(db, to_bg, &self.source)?
+            .deliver_to(db, to_bg, &self.target)
+    }
+}
+
 struct Needed<'a> {
     oid: &'a ObjectiveId,
     obj: &'a Objective,
@@ -220,29 +274,78 @@ pub struct Warehouse {
     pub(super) destination: SetS<ObjectiveId>,
 }
 
-fn sync_obj_to_warehouse(obj: &Objective, warehouse: &warehouse::Warehouse) -> Result<()> {
+/// one objective's place in the supply network
+#[derive(Debug, Clone)]
+pub struct SupplyNode {
+    pub id: ObjectiveId,
+    pub name: String,
+    pub owner: Side,
+    pub hub: bool,
+    /// the logistics hub that resupplies this objective
+    pub supplier: Option<ObjectiveId>,
+    pub supply: u8,
+    pub fuel: u8,
+    /// the number of items queued for delivery in the current tick
+    pub pending: u32,
+    /// the number of items on the road to this objective by convoy
+    pub in_transit: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SupplyNetwork {
+    /// when the next logistics tick is due, None if one is running now
+    pub next_tick: Option<DateTime<Utc>>,
+    /// how many ticks until production is next delivered to the hubs
+    pub ticks_to_delivery: u32,
+    pub nodes: Vec<SupplyNode>,
+}
+
+fn sync_obj_to_warehouse(
+    obj: &Objective,
+    munitions: &FxHashSet<String>,
+    warehouse: &warehouse::Warehouse,
+) -> Result<()> {
     let perf = unsafe { Perf::get_mut() };
     let perf = Arc::make_mut(&mut perf.inner);
+    // stock behind a destroyed structure is kept, but it can't be used
+    let no_rearm = obj.lost(StructureKind::AmmoBunker);
+    let no_fuel = obj.lost(StructureKind::FuelDepot);
     for (item, inv) in &obj.warehouse.equipment {
         perf.logistics_items.insert((item.clone(), obj.id));
+        let stored = if no_rearm && munitions.contains(item) {
+            0
+        } else {
+            inv.stored
+        };
         warehouse
-            .set_item(item.clone(), inv.stored)
+            .set_item(item.clone(), stored)
             .context("setting item")?
     }
     for (name, inv) in &obj.warehouse.liquids {
+        let stored = if no_fuel { 0 } else { inv.stored };
         warehouse
-            .set_liquid_amount(*name, inv.stored)
+            .set_liquid_amount(*name, stored)
             .context("setting liquid")?
     }
     Ok(())
 }
 
-fn sync_warehouse_to_obj(obj: &mut Objective, warehouse: &warehouse::Warehouse) -> Result<()> {
+fn sync_warehouse_to_obj(
+    obj: &mut Objective,
+    munitions: &FxHashSet<String>,
+    warehouse: &warehouse::Warehouse,
+) -> Result<()> {
+    let no_rearm = obj.lost(StructureKind::AmmoBunker);
+    let no_fuel = obj.lost(StructureKind::FuelDepot);
     for (name, inv) in obj.warehouse.equipment.iter_mut_cow() {
-        inv.stored = warehouse.get_item_count(name.clone())?;
+        if !(no_rearm && munitions.contains(name)) {
+            inv.stored = warehouse.get_item_count(name.clone())?;
+        }
     }
-    for (name, inv) in obj.warehouse.liquids.iter_mut_cow() {
-        inv.stored = warehouse.get_liquid_amount(*name)?;
+    if !no_fuel {
+        for (name, inv) in obj.warehouse.liquids.iter_mut_cow() {
+            inv.stored = warehouse.get_liquid_amount(*name)?;
+        }
     }
     Ok(())
 }
@@ -281,6 +384,9 @@ impl Db {
                             .equipment
                             .insert(name.clone(), Equipment { production: qty });
                         let category = typ.category().context("getting category")?;
+                        if !category.is_aircraft() {
+                            self.ephemeral.munitions.insert(name.clone());
+                        }
                         if category.is_aircraft() {
                             let vehicle = Vehicle::from(name.clone());
                             self.ephemeral
@@ -338,6 +444,25 @@ impl Db {
             Some(cfg) => cfg,
             None => return Ok(()),
         };
+        if let Some(icfg) = whcfg.industry.as_ref() {
+            let outputs = [&icfg.factory, &icfg.refinery, &icfg.port]
+                .into_iter()
+                .chain(icfg.objectives.values());
+            for output in outputs {
+                for name in output.equipment.keys() {
+                    let known = self
+                        .ephemeral
+                        .production_by_side
+                        .values()
+                        .any(|p| p.equipment.contains_key(name));
+                    if !known {
+                        warn!(
+                            "industry produces {name} which no supply source has, it will have no capacity"
+                        )
+                    }
+                }
+            }
+        }
         for side in Side::ALL {
             let production = match self.ephemeral.production_by_side.get(&side) {
                 None => continue,
@@ -504,9 +629,12 @@ impl Db {
         self.persisted.logistics_ticks_since_delivery = u32::MAX;
     }
 
+    /// advance the logistics state machine by one step. Without lua
+    /// the dcs warehouses are not synced, only the objective
+    /// inventories are updated.
     pub fn logistics_step(
         &mut self,
-        lua: MizLua,
+        lua: Option<MizLua>,
         perf: &mut PerfInner,
         ts: DateTime<Utc>,
     ) -> Result<()> {
@@ -536,11 +664,13 @@ impl Db {
                 LogiStage::Complete { last_tick: _ } => (),
                 LogiStage::SyncFromWarehouses { objectives } => match objectives.pop() {
                     Some(oid) => {
-                        let start_ts = Utc::now();
-                        if let Err(e) = self.sync_warehouse_to_objective(lua, oid) {
-                            error!("failed to sync objective {oid} from warehouse {:?}", e)
+                        if let Some(lua) = lua {
+                            let start_ts = Utc::now();
+                            if let Err(e) = self.sync_warehouse_to_objective(lua, oid) {
+                                error!("failed to sync objective {oid} from warehouse {:?}", e)
+                            }
+                            record_perf(&mut perf.logistics_sync_from, start_ts);
                         }
-                        record_perf(&mut perf.logistics_sync_from, start_ts);
                     }
                     None => {
                         let sts = Utc::now();
@@ -586,8 +716,33 @@ impl Db {
                 }
                 LogiStage::ExecuteTransfers { transfers } => {
                     let st = Utc::now();
+                    let convoy = self
+                        .ephemeral
+                        .cfg
+                        .warehouse
+                        .as_ref()
+                        .and_then(|w| w.convoy.as_ref());
                     while let Some(tr) = transfers.pop() {
-                        if let Err(e) = tr.execute(&mut self.persisted, &self.ephemeral.to_bg) {
+                        // convoys can only be spawned with lua
+                        let by_road = lua.is_some()
+                            && convoy.is_some_and(|c| {
+                                let name = |oid: &ObjectiveId| {
+                                    self.persisted.objectives.get(oid).map(|o| o.name.as_str())
+                                };
+                                match (name(&tr.source), name(&tr.target)) {
+                                    (Some(src), Some(dst)) => c.route(src, dst).is_some(),
+                                    _ => false,
+                                }
+                            });
+                        if by_road {
+                            self.ephemeral
+                                .convoy_queue
+                                .entry((tr.source, tr.target))
+                                .or_default()
+                                .push(tr.shipment());
+                        } else if let Err(e) =
+                            tr.execute(&mut self.persisted, &self.ephemeral.to_bg)
+                        {
                             error!("executing transfer {:?} {e:?}", tr)
                         }
                         if Utc::now() - st > Duration::milliseconds(6) {
@@ -599,11 +754,13 @@ impl Db {
                 LogiStage::SyncToWarehouses { objectives } => match objectives.pop() {
                     None => self.ephemeral.logistics_stage = LogiStage::Complete { last_tick: ts },
                     Some(oid) => {
-                        let start_ts = Utc::now();
-                        if let Err(e) = self.sync_objective_to_warehouse(lua, oid) {
-                            error!("failed to sync objective {oid} to warehouse {:?}", e)
+                        if let Some(lua) = lua {
+                            let start_ts = Utc::now();
+                            if let Err(e) = self.sync_objective_to_warehouse(lua, oid) {
+                                error!("failed to sync objective {oid} to warehouse {:?}", e)
+                            }
+                            record_perf(&mut perf.logistics_sync_to, start_ts);
                         }
-                        record_perf(&mut perf.logistics_sync_to, start_ts);
                     }
                 },
             }
@@ -612,7 +769,7 @@ impl Db {
         Ok(())
     }
 
-    pub(super) fn capture_warehouse(&mut self, lua: MizLua, oid: ObjectiveId) -> Result<()> {
+    pub(super) fn capture_warehouse(&mut self, oid: ObjectiveId) -> Result<()> {
         let whcfg = match self.ephemeral.cfg.warehouse.as_ref() {
             Some(cfg) => cfg,
             None => return Ok(()),
@@ -626,24 +783,18 @@ impl Db {
             Some(q) => Arc::clone(q),
             None => return Ok(()),
         };
-        let map = warehouse::Warehouse::get_resource_map(lua).context("getting resource map")?;
         let hub = obj.kind.is_hub();
-        map.for_each(|name, _| {
-            match production.equipment.get(&name) {
-                Some(equip) => {
-                    let inv = obj.warehouse.equipment.get_or_default_cow(name);
-                    inv.capacity = whcfg.capacity(hub, equip.production);
-                }
-                None => {
-                    if let Some(_) = other_production.equipment.get(&name) {
-                        let inv = obj.warehouse.equipment.get_or_default_cow(name);
-                        inv.stored = 0;
-                        inv.capacity = 0;
-                    }
-                }
+        for (name, equip) in &production.equipment {
+            let inv = obj.warehouse.equipment.get_or_default_cow(name.clone());
+            inv.capacity = whcfg.capacity(hub, equip.production);
+        }
+        for (name, _) in &other_production.equipment {
+            if !production.equipment.contains_key(name) {
+                let inv = obj.warehouse.equipment.get_or_default_cow(name.clone());
+                inv.stored = 0;
+                inv.capacity = 0;
             }
-            Ok(())
-        })?;
+        }
         for name in LiquidType::ALL {
             match production.liquids.get(&name) {
                 Some(qty) => {
@@ -689,7 +840,11 @@ impl Db {
         let mut suppliers: SmallVec<[(ObjectiveId, Option<ObjectiveId>); 64]> = smallvec![];
         for (oid, obj) in &self.persisted.objectives {
             match obj.kind {
-                ObjectiveKind::Logistics => (),
+                // industry produces supplies, it doesn't need to be resupplied
+                ObjectiveKind::Logistics
+                | ObjectiveKind::Factory
+                | ObjectiveKind::Refinery
+                | ObjectiveKind::Port => (),
                 ObjectiveKind::Airbase | ObjectiveKind::Farp { .. } | ObjectiveKind::Fob => {
                     let hub = self.compute_supplier(obj)?;
                     suppliers.push((*oid, hub));
@@ -726,15 +881,36 @@ impl Db {
         }
         self.setup_supply_lines()
             .context("setting up supply lines")?;
+        let outputs: SmallVec<[(Side, Production); 2]> = Side::ALL
+            .into_iter()
+            .filter_map(|side| self.production_output(side).map(|p| (side, p)))
+            .filter(|(_, p)| !p.is_nothing())
+            .collect();
+        for (side, production) in &outputs {
+            self.ephemeral.stat(Stat::Production {
+                side: *side,
+                equipment: production
+                    .equipment
+                    .iter()
+                    .map(|(name, eq)| (name.clone(), eq.production))
+                    .collect(),
+                liquids: production
+                    .liquids
+                    .iter()
+                    .map(|(name, qty)| (*name, *qty))
+                    .collect(),
+            });
+        }
+        let mut delivered: SmallVec<[Side; 2]> = smallvec![];
         let mut deliver_produced_supplies = || -> Result<()> {
-            for side in Side::ALL {
-                let production = match self.ephemeral.production_by_side.get(&side) {
-                    Some(e) => e,
-                    None => continue,
-                };
+            for (side, production) in &outputs {
+                let side = *side;
                 for oid in &self.persisted.logistics_hubs {
                     let logi = objective_mut!(self, oid)?;
                     if logi.owner == side {
+                        if !delivered.contains(&side) {
+                            delivered.push(side);
+                        }
                         for (name, inv) in logi.warehouse.equipment.iter_mut_cow() {
                             if let Some(eq) = production.equipment.get(name) {
                                 *inv += eq.production;
@@ -752,10 +928,61 @@ impl Db {
         };
         deliver_produced_supplies().context("delivering produced supplies")?;
         self.ephemeral.dirty();
+        if let Some(tickets) = self.ephemeral.cfg.tickets.as_ref() {
+            let gain = tickets.delivery as i32;
+            for side in delivered {
+                self.adjust_tickets(side, gain, "for delivering production")
+            }
+        }
         self.deliver_supplies_from_logistics_hubs()
             .context("delivering supplies from logistics hubs")
     }
 
+    /// what side produces each delivery. This is the supply_source
+    /// production, or with industry configured the base share of it
+    /// plus the output of the industry side owns scaled by its health
+    fn production_output(&self, side: Side) -> Option<Production> {
+        let base = self.ephemeral.production_by_side.get(&side)?;
+        let icfg = match self
+            .ephemeral
+            .cfg
+            .warehouse
+            .as_ref()
+            .and_then(|w| w.industry.as_ref())
+        {
+            Some(icfg) => icfg,
+            None => return Some((**base).clone()),
+        };
+        let scale = |qty: u32, factor: f32| (qty as f32 * factor) as u32;
+        let base_factor = icfg.base_production as f32 / 100.;
+        let mut out = Production::default();
+        for (name, eq) in &base.equipment {
+            let production = scale(eq.production, base_factor);
+            out.equipment.insert(name.clone(), Equipment { production });
+        }
+        for (name, qty) in &base.liquids {
+            out.liquids.insert(*name, scale(*qty, base_factor));
+        }
+        for (_, obj) in &self.persisted.objectives {
+            if obj.owner != side {
+                continue;
+            }
+            if let Some(output) = icfg.output(&obj.kind, &obj.name) {
+                let health = obj.health as f32 / 100.;
+                for (name, qty) in &output.equipment {
+                    out.equipment
+                        .entry(name.clone())
+                        .or_insert(Equipment { production: 0 })
+                        .production += scale(*qty, health);
+                }
+                for (name, qty) in &output.liquids {
+                    *out.liquids.entry(*name).or_default() += scale(*qty, health);
+                }
+            }
+        }
+        Some(out)
+    }
+
     pub fn sync_vehicle_at_obj(
         &mut self,
         lua: MizLua,
@@ -775,9 +1002,32 @@ impl Db {
         Ok(())
     }
 
+    /// supplies queued for a convoy or on the road by convoy, by
+    /// target and item
+    fn convoy_inbound(&self) -> FxHashMap<(ObjectiveId, TransferItem), u32> {
+        let mut inbound: FxHashMap<(ObjectiveId, TransferItem), u32> = FxHashMap::default();
+        for ((_, target), shipments) in &self.ephemeral.convoy_queue {
+            for s in shipments {
+                *inbound.entry((*target, s.item.clone())).or_default() += s.amount;
+            }
+        }
+        for gid in &self.persisted.convoys {
+            if let Some(DeployKind::Convoy { target, cargo, .. }) =
+                self.persisted.groups.get(gid).map(|g| &g.origin)
+            {
+                for s in cargo {
+                    *inbound.entry((*target, s.item.clone())).or_default() += s.amount;
+                }
+            }
+        }
+        inbound
+    }
+
     pub fn deliver_supplies_from_logistics_hubs(&mut self) -> Result<Vec<Transfer>> {
         self.update_supply_status()
             .context("updating supply status")?;
+        // don't send again what convoys are already bringing
+        let inbound = self.convoy_inbound();
         let mut transfers: Vec<Transfer> = vec![];
         for lid in &self.persisted.logistics_hubs {
             let logi = objective!(self, lid)?;
@@ -808,11 +1058,11 @@ impl Db {
                         let mut total_demanded = 0;
                         for n in &mut needed {
                             let inv = n.obj.$get(name);
-                            let demanded = if inv.stored <= inv.capacity {
-                                inv.capacity - inv.stored
-                            } else {
-                                0
-                            };
+                            let coming = inbound
+                                .get(&(*n.oid, $typ(name.clone())))
+                                .copied()
+                                .unwrap_or(0);
+                            let demanded = inv.capacity.saturating_sub(inv.stored + coming);
                             total_demanded += demanded;
                             n.demanded = demanded;
                             n.allocated = 0;
@@ -939,7 +1189,7 @@ impl Db {
         Ok(())
     }
 
-    fn update_supply_status(&mut self) -> Result<()> {
+    pub(super) fn update_supply_status(&mut self) -> Result<()> {
         for (_, obj) in self.persisted.objectives.iter_mut_cow() {
             let current_supply = obj.supply;
             let current_fuel = obj.fuel;
@@ -988,7 +1238,8 @@ impl Db {
             .context("getting airbase")?
             .get_warehouse()
             .context("getting warehouse")?;
-        sync_warehouse_to_obj(obj, &warehouse).context("syncing warehouse to objective")?;
+        sync_warehouse_to_obj(obj, &self.ephemeral.munitions, &warehouse)
+            .context("syncing warehouse to objective")?;
         Ok((obj, warehouse))
     }
 
@@ -1007,7 +1258,8 @@ impl Db {
             .context("getting airbase")?
             .get_warehouse()
             .context("getting warehouse")?;
-        sync_obj_to_warehouse(obj, &warehouse).context("syncing warehouse to objective")?;
+        sync_obj_to_warehouse(obj, &self.ephemeral.munitions, &warehouse)
+            .context("syncing warehouse to objective")?;
         Ok((obj, warehouse))
     }
 
@@ -1068,8 +1320,9 @@ impl Db {
         for tr in transfers {
             tr.execute(&mut self.persisted, &self.ephemeral.to_bg)?
         }
-        sync_obj_to_warehouse(objective!(self, from)?, &from_wh)?;
-        sync_obj_to_warehouse(objective!(self, to)?, &to_wh)?;
+        let munitions = &self.ephemeral.munitions;
+        sync_obj_to_warehouse(objective!(self, from)?, munitions, &from_wh)?;
+        sync_obj_to_warehouse(objective!(self, to)?, munitions, &to_wh)?;
         self.update_supply_status()
             .context("updating supply status")?;
         Ok(())
@@ -1084,6 +1337,16 @@ impl Db {
         if amount > 100 {
             bail!("enter a percentage")
         }
+        self.reduce_supply(lua, oid, amount)
+    }
+
+    /// take amount percent of every produced item stored at oid
+    pub(super) fn reduce_supply(
+        &mut self,
+        lua: MizLua,
+        oid: ObjectiveId,
+        amount: u8,
+    ) -> Result<()> {
         let percent = amount as f32 / 100.;
         let production = match self
             .ephemeral
@@ -1093,7 +1356,7 @@ impl Db {
             Some(p) => Arc::clone(p),
             None => return Ok(()),
         };
-        let (obj, warehouse) = self
+        let (obj, _) = self
             .sync_warehouse_to_objective(lua, oid)
             .with_context(|| format_compact!("syncing warehouses to {oid}"))?;
         for name in production.equipment.keys() {
@@ -1106,13 +1369,94 @@ impl Db {
                 inv.reduce(percent);
             }
         }
-        sync_obj_to_warehouse(obj, &warehouse).context("syncing from warehouse")?;
+        self.sync_objective_to_warehouse(lua, oid)
+            .context("syncing from warehouse")?;
         self.update_supply_status()
             .context("updating supply status")?;
         self.ephemeral.dirty();
         Ok(())
     }
 
+    /// use up percent of the capacity of every produced item stored at
+    /// oid, e.g. to equip an ai offensive
+    pub(super) fn consume_supply(
+        &mut self,
+        lua: MizLua,
+        oid: ObjectiveId,
+        amount: u8,
+    ) -> Result<()> {
+        let percent = amount as f32 / 100.;
+        let production = match self
+            .ephemeral
+            .production_by_side
+            .get(&objective!(self, oid)?.owner)
+        {
+            Some(p) => Arc::clone(p),
+            None => return Ok(()),
+        };
+        let (obj, _) = self
+            .sync_warehouse_to_objective(lua, oid)
+            .with_context(|| format_compact!("syncing warehouses to {oid}"))?;
+        for name in production.equipment.keys() {
+            if let Some(inv) = obj.warehouse.equipment.get_mut_cow(name) {
+                inv.consume(percent);
+            }
+        }
+        for liq in production.liquids.keys() {
+            if let Some(inv) = obj.warehouse.liquids.get_mut_cow(liq) {
+                inv.consume(percent);
+            }
+        }
+        self.sync_objective_to_warehouse(lua, oid)
+            .context("syncing from warehouse")?;
+        self.update_supply_status()
+            .context("updating supply status")?;
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// load shipments from oid onto a convoy outside of the logistics
+    /// tick, returning what was actually loaded
+    pub(super) fn ship_from(
+        &mut self,
+        lua: MizLua,
+        oid: ObjectiveId,
+        shipments: &[Shipment],
+    ) -> Result<Vec<Shipment>> {
+        self.sync_warehouse_to_objective(lua, oid)
+            .with_context(|| format_compact!("syncing warehouses to {oid}"))?;
+        let mut loaded = vec![];
+        for s in shipments {
+            let s = s.take_from(&mut self.persisted, &self.ephemeral.to_bg, &oid)?;
+            if s.amount > 0 {
+                loaded.push(s)
+            }
+        }
+        self.sync_objective_to_warehouse(lua, oid)
+            .with_context(|| format_compact!("syncing {oid} to warehouses"))?;
+        self.update_supply_status()
+            .context("updating supply status")?;
+        Ok(loaded)
+    }
+
+    /// unload shipments from a convoy into oid outside of the logistics tick
+    pub(super) fn receive_at(
+        &mut self,
+        lua: MizLua,
+        oid: ObjectiveId,
+        shipments: &[Shipment],
+    ) -> Result<()> {
+        self.sync_warehouse_to_objective(lua, oid)
+            .with_context(|| format_compact!("syncing warehouses to {oid}"))?;
+        for s in shipments {
+            s.deliver_to(&mut self.persisted, &self.ephemeral.to_bg, &oid)?;
+        }
+        self.sync_objective_to_warehouse(lua, oid)
+            .with_context(|| format_compact!("syncing {oid} to warehouses"))?;
+        self.update_supply_status()
+            .context("updating supply status")
+    }
+
     pub fn admin_log_inventory(
         &mut self,
         lua: MizLua,
@@ -1167,4 +1511,306 @@ impl Db {
         }
         Ok(())
     }
+
+    /// a snapshot of the supply network for reports and publishing
+    pub fn supply_network(&self) -> SupplyNetwork {
+        let mut pending: FxHashMap<ObjectiveId, u32> = FxHashMap::default();
+        let next_tick = match &self.ephemeral.logistics_stage {
+            LogiStage::Complete { last_tick } => self
+                .ephemeral
+                .cfg
+                .warehouse
+                .as_ref()
+                .map(|w| *last_tick + Duration::minutes(w.tick as i64)),
+            LogiStage::ExecuteTransfers { transfers } => {
+                for tr in transfers {
+                    *pending.entry(tr.target).or_default() += tr.amount;
+                }
+                None
+            }
+            LogiStage::Init
+            | LogiStage::SyncFromWarehouses { .. }
+            | LogiStage::SyncToWarehouses { .. } => None,
+        };
+        let mut in_transit: FxHashMap<ObjectiveId, u32> = FxHashMap::default();
+        for ((_, target), shipments) in &self.ephemeral.convoy_queue {
+            for s in shipments {
+                *pending.entry(*target).or_default() += s.amount;
+            }
+        }
+        for gid in &self.persisted.convoys {
+            if let Some(DeployKind::Convoy { target, cargo, .. }) =
+                self.persisted.groups.get(gid).map(|g| &g.origin)
+            {
+                for s in cargo {
+                    *in_transit.entry(*target).or_default() += s.amount;
+                }
+            }
+        }
+        let ticks_to_delivery = self
+            .ephemeral
+            .cfg
+            .warehouse
+            .as_ref()
+            .map(|w| {
+                w.ticks_per_delivery
+                    .saturating_sub(self.persisted.logistics_ticks_since_delivery)
+            })
+            .unwrap_or(0);
+        let nodes = self
+            .persisted
+            .objectives
+            .into_iter()
+            .map(|(oid, obj)| SupplyNode {
+                id: *oid,
+                name: obj.name.clone(),
+                owner: obj.owner,
+                hub: obj.kind.is_hub(),
+                supplier: obj.warehouse.supplier,
+                supply: obj.supply,
+                fuel: obj.fuel,
+                pending: pending.get(oid).copied().unwrap_or(0),
+                in_transit: in_transit.get(oid).copied().unwrap_or(0),
+            })
+            .collect();
+        SupplyNetwork {
+            next_tick,
+            ticks_to_delivery,
+            nodes,
+        }
+    }
+
+    /// explain where an objective's supplies come from and why it
+    /// might be running low
+    pub fn explain_supply(
+        &self,
+        oid: &ObjectiveId,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<CompactString>> {
+        use std::fmt::Write;
+        let obj = objective!(self, oid)?;
+        let mut lines = vec![format_compact!(
+            "{} supply {}% fuel {}%",
+            obj.name,
+            obj.supply,
+            obj.fuel
+        )];
+        if self.ephemeral.cfg.warehouse.is_none() {
+            lines.push("the logistics system is disabled".into());
+            return Ok(lines);
+        }
+        let net = self.supply_network();
+        let when = match net.next_tick {
+            Some(ts) if ts > now => {
+                format_compact!("in {}", crate::chatcmd::format_duration(ts - now))
+            }
+            Some(_) | None => CompactString::from("now"),
+        };
+        let production = self.ephemeral.production_by_side.get(&obj.owner);
+        let pos = obj.zone.pos();
+        let nearest_hub = self
+            .persisted
+            .logistics_hubs
+            .into_iter()
+            .filter(|id| *id != oid)
+            .filter_map(|id| self.persisted.objectives.get(id))
+            .map(|hub| (na::distance(&pos.into(), &hub.zone.pos().into()), hub))
+            .min_by(|(d0, _), (d1, _)| d0.total_cmp(d1))
+            .map(|(_, hub)| hub);
+        if obj.kind.is_hub() {
+            lines.push(format_compact!(
+                "it is a logistics hub, production arrives in {} ticks",
+                net.ticks_to_delivery
+            ));
+        } else if obj.logistics_detached {
+            lines.push("it is detached from the logistics network".into());
+        } else {
+            match obj
+                .warehouse
+                .supplier
+                .and_then(|id| self.persisted.objectives.get(&id))
+            {
+                None => match nearest_hub {
+                    Some(hub) if hub.owner != obj.owner => lines.push(format_compact!(
+                        "the nearest hub {} is held by {} and no friendly hub can supply it",
+                        hub.name,
+                        hub.owner
+                    )),
+                    Some(_) | None => lines.push("no friendly logistics hub can supply it".into()),
+                },
+                Some(hub) => {
+                    let km = na::distance(&pos.into(), &hub.zone.pos().into()) / 1000.;
+                    lines.push(format_compact!(
+                        "supplied by {} {:.0}km away, supply {}% fuel {}%",
+                        hub.name,
+                        km,
+                        hub.supply,
+                        hub.fuel
+                    ));
+                    if let Some(near) = nearest_hub
+                        && near.id != hub.id
+                        && near.owner != obj.owner
+                    {
+                        lines.push(format_compact!(
+                            "the closer hub {} is held by {}",
+                            near.name,
+                            near.owner
+                        ));
+                    }
+                    let mut out = CompactString::new("");
+                    for (name, inv) in &obj.warehouse.equipment {
+                        if inv.stored < inv.capacity && hub.get_equipment(name).stored == 0 {
+                            if !out.is_empty() {
+                                out.push_str(", ");
+                            }
+                            write!(out, "{name}")?;
+                        }
+                    }
+                    for (name, inv) in &obj.warehouse.liquids {
+                        if inv.stored < inv.capacity && hub.get_liquids(name).stored == 0 {
+                            if !out.is_empty() {
+                                out.push_str(", ");
+                            }
+                            write!(out, "{:?}", name)?;
+                        }
+                    }
+                    if !out.is_empty() {
+                        lines.push(format_compact!("{} is out of {out}", hub.name));
+                    }
+                }
+            }
+        }
+        if let Some(production) = production {
+            let mut nocap = CompactString::new("");
+            for name in production.equipment.keys() {
+                if obj.get_equipment(name).capacity == 0 {
+                    if !nocap.is_empty() {
+                        nocap.push_str(", ");
+                    }
+                    write!(nocap, "{name}")?;
+                }
+            }
+            if !nocap.is_empty() {
+                lines.push(format_compact!("it has no capacity for {nocap}"));
+            }
+        }
+        for (_, kind) in obj.destroyed() {
+            lines.push(format_compact!(
+                "its {} is destroyed, deliver a repair crate to fix it",
+                kind.name()
+            ));
+        }
+        let (pending, in_transit) = net
+            .nodes
+            .iter()
+            .find(|n| &n.id == oid)
+            .map(|n| (n.pending, n.in_transit))
+            .unwrap_or((0, 0));
+        if in_transit > 0 {
+            lines.push(format_compact!(
+                "{in_transit} items are on the road by convoy"
+            ));
+        }
+        if pending > 0 {
+            lines.push(format_compact!("{pending} items are being delivered now"));
+        } else {
+            lines.push(format_compact!("the next logistics tick is {when}"));
+        }
+        Ok(lines)
+    }
+
+    /// a summary of side's supply network, one line per hub and per
+    /// objective that is low on supply or fuel
+    pub fn supply_report(&self, side: Side, now: DateTime<Utc>) -> Vec<CompactString> {
+        let net = self.supply_network();
+        let when = match net.next_tick {
+            Some(ts) if ts > now => crate::chatcmd::format_duration(ts - now),
+            Some(_) | None => CompactString::from("now"),
+        };
+        let mut lines = vec![format_compact!(
+            "next logistics tick {when}, production arrives in {} ticks",
+            net.ticks_to_delivery
+        )];
+        let mut nodes: Vec<&SupplyNode> = net.nodes.iter().filter(|n| n.owner == side).collect();
+        nodes.sort_by(|n0, n1| n0.name.cmp(&n1.name));
+        for hub in nodes.iter().filter(|n| n.hub) {
+            let mut low: SmallVec<[&str; 16]> = smallvec![];
+            let mut fed = 0;
+            for n in nodes.iter().filter(|n| n.supplier == Some(hub.id)) {
+                fed += 1;
+                if n.supply < 50 || n.fuel < 50 {
+                    low.push(n.name.as_str());
+                }
+            }
+            let mut line = format_compact!(
+                "{} supply {}% fuel {}% feeds {fed} objectives",
+                hub.name,
+                hub.supply,
+                hub.fuel
+            );
+            if !low.is_empty() {
+                line.push_str(&format_compact!(", low: {}", low.join(", ")));
+            }
+            lines.push(line);
+        }
+        let unsupplied: SmallVec<[&str; 16]> = nodes
+            .iter()
+            .filter(|n| !n.hub && n.supplier.is_none())
+            .map(|n| n.name.as_str())
+            .collect();
+        if !unsupplied.is_empty() {
+            lines.push(format_compact!("cut off: {}", unsupplied.join(", ")));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::sim::test::{db, objective};
+    use bfprotocols::cfg::Cfg;
+
+    #[test]
+    fn convoy_cargo_counts_against_demand() {
+        let (mut db, _from_db) = db(Cfg::default());
+        let hub = objective(
+            &mut db,
+            "hub",
+            ObjectiveKind::Logistics,
+            Side::Blue,
+            Vector2::zeros(),
+        );
+        let fob = objective(
+            &mut db,
+            "fob",
+            ObjectiveKind::Fob,
+            Side::Blue,
+            Vector2::new(20000., 0.),
+        );
+        let mk82 = String::from("weapons.bombs.Mk_82");
+        let wh = &mut db.persisted.objectives.get_mut_cow(&hub).unwrap().warehouse;
+        wh.destination.insert_cow(fob);
+        let inv = wh.equipment.get_or_default_cow(mk82.clone());
+        inv.capacity = 1000;
+        inv.stored = 1000;
+        let wh = &mut db.persisted.objectives.get_mut_cow(&fob).unwrap().warehouse;
+        wh.supplier = Some(hub);
+        wh.equipment.get_or_default_cow(mk82.clone()).capacity = 100;
+        assert!(
+            !db.deliver_supplies_from_logistics_hubs()
+                .unwrap()
+                .is_empty()
+        );
+        let shipment = Shipment {
+            item: TransferItem::Equipment(mk82),
+            amount: 100,
+        };
+        db.ephemeral.convoy_queue.insert((hub, fob), vec![shipment]);
+        assert!(
+            db.deliver_supplies_from_logistics_hubs()
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
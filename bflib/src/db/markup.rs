@@ -40,6 +40,9 @@ pub(super) struct ObjectiveMarkup {
     supply: u8,
     fuel: u8,
     points: i32,
+    tickets: Option<u32>,
+    destroyed: usize,
+    closed: bool,
     name: String,
     owner_ring: MarkId,
     capturable_ring: MarkId,
@@ -57,8 +60,8 @@ fn text_color(side: Side, a: f32) -> Color {
     }
 }
 
-fn objective_label(name: &str, obj: &Objective) -> CompactString {
-    format_compact!(
+fn objective_label(name: &str, obj: &Objective, tickets: Option<u32>) -> CompactString {
+    let mut label = format_compact!(
         "{}\nHealth: {}\nLogi: {}\nSupply: {}\nFuel: {}\nPoints: {}",
         name,
         obj.health,
@@ -66,7 +69,27 @@ fn objective_label(name: &str, obj: &Objective) -> CompactString {
         obj.supply,
         obj.fuel,
         obj.points
-    )
+    );
+    if let Some(tickets) = tickets {
+        label.push_str(&format_compact!("\nTickets: {tickets}"));
+    }
+    for (i, (_, kind)) in obj.destroyed.into_iter().enumerate() {
+        label.push_str(if i == 0 { "\nDestroyed: " } else { ", " });
+        label.push_str(kind.name());
+    }
+    if obj.runway.closed_until.is_some() {
+        label.push_str("\nRunway: closed");
+    }
+    label
+}
+
+/// logistics hubs show the tickets their owner has left
+fn hub_tickets(cfg: &Cfg, persisted: &Persisted, obj: &Objective) -> Option<u32> {
+    if persisted.logistics_hubs.contains(&obj.id) {
+        persisted.tickets(cfg, obj.owner)
+    } else {
+        None
+    }
 }
 
 fn arrow_coords(obj: &Objective, dst: &Objective) -> (Vector2, Vector2) {
@@ -89,6 +112,9 @@ impl ObjectiveMarkup {
             supply: _,
             fuel: _,
             points: _,
+            tickets: _,
+            destroyed: _,
+            closed: _,
             name: _,
             pos: _,
             owner_ring,
@@ -108,6 +134,7 @@ impl ObjectiveMarkup {
 
     pub(super) fn update(
         &mut self,
+        cfg: &Cfg,
         persisted: &Persisted,
         msgq: &mut MsgQ,
         obj: &Objective,
@@ -122,18 +149,22 @@ impl ObjectiveMarkup {
                 msgq.delete_mark(id);
             }
         }
-        if obj.threatened != self.threatened {
-            self.threatened = obj.threatened;
+        if obj.threat_shown() != self.threatened {
+            self.threatened = obj.threat_shown();
             msgq.set_markup_color(
                 self.threatened_ring,
                 Color::yellow(if self.threatened { 0.75 } else { 0. }),
             );
         }
+        let tickets = hub_tickets(cfg, persisted, obj);
         if self.health != obj.health
             || self.logi != obj.logi
             || self.supply != obj.supply
             || self.fuel != obj.fuel
             || self.points != obj.points
+            || self.tickets != tickets
+            || self.destroyed != obj.destroyed.len()
+            || self.closed != obj.runway.closed_until.is_some()
         {
             if self.logi != obj.logi {
                 msgq.set_markup_color(
@@ -146,7 +177,10 @@ impl ObjectiveMarkup {
             self.supply = obj.supply;
             self.fuel = obj.fuel;
             self.points = obj.points;
-            msgq.set_markup_text(self.label, objective_label(&self.name, obj).into());
+            self.tickets = tickets;
+            self.destroyed = obj.destroyed.len();
+            self.closed = obj.runway.closed_until.is_some();
+            msgq.set_markup_text(self.label, objective_label(&self.name, obj, tickets).into());
         }
         if let Zone::Circle { pos, .. } = obj.zone
             && self.pos != pos
@@ -176,18 +210,24 @@ impl ObjectiveMarkup {
     pub(super) fn new(cfg: &Cfg, msgq: &mut MsgQ, obj: &Objective, persisted: &Persisted) -> Self {
         let text_color = |a| text_color(obj.owner, a);
         let all_spec = match obj.kind {
-            ObjectiveKind::Airbase | ObjectiveKind::Fob | ObjectiveKind::Logistics => {
-                SideFilter::All
-            }
+            ObjectiveKind::Airbase
+            | ObjectiveKind::Fob
+            | ObjectiveKind::Logistics
+            | ObjectiveKind::Factory
+            | ObjectiveKind::Refinery
+            | ObjectiveKind::Port => SideFilter::All,
             ObjectiveKind::Farp { .. } => obj.owner.into(),
         };
         let mut t = ObjectiveMarkup::default();
         t.side = obj.owner;
-        t.threatened = obj.threatened;
+        t.threatened = obj.threat_shown();
         t.health = obj.health;
         t.logi = obj.logi;
         t.supply = obj.supply;
         t.fuel = obj.fuel;
+        t.tickets = hub_tickets(cfg, persisted, obj);
+        t.destroyed = obj.destroyed.len();
+        t.closed = obj.runway.closed_until.is_some();
         t.name = format_compact!("{} {}", obj.name, obj.kind.name()).into();
         t.pos = obj.zone.pos();
         let pos3 = Vector3::new(t.pos.x, 0., t.pos.y);
@@ -199,7 +239,7 @@ impl ObjectiveMarkup {
                     CircleSpec {
                         center: LuaVec3(pos3),
                         radius: (cfg.logistics_exclusion as f64).max($radius * 1.1),
-                        color: Color::yellow(if obj.threatened { 0.75 } else { 0. }),
+                        color: Color::yellow(if t.threatened { 0.75 } else { 0. }),
                         fill_color: Color::white(0.),
                         line_type: LineType::Solid,
                         read_only: true,
@@ -253,7 +293,7 @@ impl ObjectiveMarkup {
                             p1: LuaVec3(Vector3::new(points.p1.x, 0., points.p1.y)),
                             p2: LuaVec3(Vector3::new(points.p2.x, 0., points.p2.y)),
                             p3: LuaVec3(Vector3::new(points.p3.x, 0., points.p3.y)),
-                            color: Color::yellow(if obj.threatened { 0.75 } else { 0. }),
+                            color: Color::yellow(if t.threatened { 0.75 } else { 0. }),
                             fill_color: Color::white(0.),
                             line_type: LineType::Solid,
                             read_only: true,
@@ -307,11 +347,16 @@ impl ObjectiveMarkup {
                 fill_color: Color::black(0.),
                 font_size: 10,
                 read_only: true,
-                text: objective_label(&t.name, obj).into(),
+                text: objective_label(&t.name, obj, t.tickets).into(),
             },
         );
         match obj.kind {
-            ObjectiveKind::Airbase | ObjectiveKind::Farp { .. } | ObjectiveKind::Fob => (),
+            ObjectiveKind::Airbase
+            | ObjectiveKind::Farp { .. }
+            | ObjectiveKind::Fob
+            | ObjectiveKind::Factory
+            | ObjectiveKind::Refinery
+            | ObjectiveKind::Port => (),
             ObjectiveKind::Logistics => {
                 for oid in &obj.warehouse.destination {
                     let id = MarkId::new();
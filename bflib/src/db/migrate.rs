@@ -0,0 +1,250 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! Save files carry a format version. Before a save is decoded every
+//! migration between its version and SAVE_VERSION is run, in order,
+//! on the raw json, so a field can change shape without losing the
+//! campaign. When you change the shape of anything in Persisted, bump
+//! SAVE_VERSION and add a step to MIGRATIONS that converts the old
+//! shape to the new one.
+
+use super::persisted::Persisted;
+use crate::bg::rotate_state;
+use anyhow::{Context, Result, anyhow, bail};
+use log::error;
+use serde_json::{Map, Value, json};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// the save format written by this version of bflib
+pub const SAVE_VERSION: u32 = 1;
+
+/// one step in the migration chain
+pub struct Migration {
+    /// the version this step upgrades from, it produces from + 1
+    pub from: u32,
+    /// what the step does, for the logs
+    pub name: &'static str,
+    run: fn(&mut Map<String, Value>) -> Result<()>,
+}
+
+static MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    name: "objective groups record their origin and owner",
+    run: v0_objective_groups,
+}];
+
+fn field<'a>(v: &'a mut Value, key: &str) -> Result<&'a mut Value> {
+    v.get_mut(key).ok_or_else(|| anyhow!("missing field {key}"))
+}
+
+/// objective groups used to be spawned from DeployKind::ObjectiveDeprecated
+/// and units of the wrong side were left alive on captured objectives.
+/// This used to be the migrated_v0 flag.
+fn v0_objective_groups(save: &mut Map<String, Value>) -> Result<()> {
+    let done = save
+        .remove("migrated_v0")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if done {
+        return Ok(());
+    }
+    let mut fix = vec![];
+    if let Some(objectives) = save.get("objectives").and_then(|o| o.as_object()) {
+        for obj in objectives.values() {
+            let oid = obj
+                .get("id")
+                .cloned()
+                .ok_or_else(|| anyhow!("objective with no id"))?;
+            let owner = obj.get("owner").cloned().unwrap_or(Value::Null);
+            if let Some(groups) = obj.get("groups").and_then(|g| g.as_object()) {
+                for gids in groups.values() {
+                    for gid in gids.as_array().into_iter().flatten() {
+                        fix.push((gid.to_string(), oid.clone(), owner.clone()))
+                    }
+                }
+            }
+        }
+    }
+    for (gid, oid, owner) in fix {
+        let group = save
+            .get_mut("groups")
+            .and_then(|g| g.get_mut(&gid))
+            .ok_or_else(|| anyhow!("no such group {gid}"))?;
+        let origin = field(group, "origin")?;
+        if origin.as_str() == Some("Objective") {
+            *origin = json!({ "ObjectiveV2": { "origin": oid } });
+        }
+        let uids: Vec<String> = field(group, "units")?
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|uid| uid.to_string())
+            .collect();
+        for uid in uids {
+            let unit = save
+                .get_mut("units")
+                .and_then(|u| u.get_mut(&uid))
+                .ok_or_else(|| anyhow!("no such unit {uid}"))?;
+            if field(unit, "side")? != &owner {
+                *field(unit, "dead")? = Value::Bool(true);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the format version of a raw save, saves from before versioning are 0
+pub fn version(save: &Value) -> Result<u32> {
+    match save.get("version") {
+        None => Ok(0),
+        Some(v) => v
+            .as_u64()
+            .map(|v| v as u32)
+            .ok_or_else(|| anyhow!("invalid save version {v}")),
+    }
+}
+
+/// bring a raw save up to SAVE_VERSION, returning the steps that were
+/// applied
+pub fn upgrade(save: &mut Value) -> Result<Vec<&'static Migration>> {
+    upgrade_with(MIGRATIONS, save)
+}
+
+fn upgrade_with(
+    migrations: &'static [Migration],
+    save: &mut Value,
+) -> Result<Vec<&'static Migration>> {
+    let mut applied = vec![];
+    let mut version = version(save)?;
+    if version > SAVE_VERSION {
+        bail!("the save is version {version}, but this bflib only understands up to {SAVE_VERSION}")
+    }
+    while version < SAVE_VERSION {
+        let m = migrations
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or_else(|| anyhow!("no migration from save version {version}"))?;
+        let obj = save
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("the save is not an object"))?;
+        (m.run)(obj).with_context(|| format!("migrating from version {version}, {}", m.name))?;
+        version += 1;
+        obj.insert("version".into(), Value::from(version));
+        applied.push(m);
+    }
+    Ok(applied)
+}
+
+/// read a save file without decoding it
+pub fn read(path: &Path) -> Result<Value> {
+    let file =
+        File::open(path).map_err(|e| anyhow!("failed to open save file {:?}, {:?}", path, e))?;
+    let file = zstd::stream::Decoder::new(BufReader::new(file))?;
+    serde_json::from_reader(file)
+        .map_err(|e| anyhow!("failed to read save file {:?}, {:?}", path, e))
+}
+
+/// decode a save that is already at SAVE_VERSION
+pub fn decode(save: Value) -> Result<Persisted> {
+    match version(&save)? {
+        SAVE_VERSION => (),
+        v => {
+            bail!("the save is version {v}, it must be upgraded to {SAVE_VERSION} before decoding")
+        }
+    }
+    let persisted: Persisted = serde_json::from_value(save)?;
+    Ok(persisted)
+}
+
+/// read, upgrade, and decode a save file
+pub fn load(path: &Path) -> Result<(Persisted, Vec<&'static Migration>)> {
+    let mut save = read(path)?;
+    let applied = upgrade(&mut save).with_context(|| format!("upgrading save file {path:?}"))?;
+    let persisted =
+        decode(save).map_err(|e| anyhow!("failed to decode save file {:?}, {:?}", path, e))?;
+    Ok((persisted, applied))
+}
+
+/// write a raw save. The new file is written next to path and then
+/// renamed over it, so a failure part way through can't destroy the
+/// existing save. The existing save is kept as a backup the same way
+/// the server does it.
+pub fn write(path: &Path, save: &Value) -> Result<()> {
+    let mut tmp = PathBuf::from(path);
+    tmp.set_extension("tmp");
+    let file = File::create(&tmp).with_context(|| format!("creating {tmp:?}"))?;
+    let mut file = zstd::stream::Encoder::new(BufWriter::new(file), 9)?;
+    serde_json::to_writer(&mut file, save).context("encoding save")?;
+    file.finish()?.flush()?;
+    if let Err(e) = rotate_state(path) {
+        error!("failed to rotate backup files {e:?}")
+    }
+    fs::rename(&tmp, path).with_context(|| format!("renaming {tmp:?} to {path:?}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::{
+        group::DeployKind,
+        sim::test::{db, group, objective},
+    };
+    use bfprotocols::{cfg::Cfg, db::objective::ObjectiveKind};
+    use dcso3::{Vector2, coalition::Side};
+
+    #[test]
+    fn upgrades_a_version_0_save() {
+        let (mut db, _from_db) = db(Cfg::default());
+        let pos = Vector2::new(20000., 0.);
+        let oid = objective(&mut db, "fob", ObjectiveKind::Fob, Side::Red, pos);
+        let gid = group(&mut db, Side::Blue, DeployKind::ObjectiveDeprecated, pos, 2);
+        let obj = db.persisted.objectives.get_mut_cow(&oid).unwrap();
+        obj.groups.get_mut_cow(&Side::Blue).unwrap().insert_cow(gid);
+        let mut save = serde_json::to_value(&db.persisted).unwrap();
+        save.as_object_mut().unwrap().remove("version");
+        assert_eq!(version(&save).unwrap(), 0);
+        assert!(decode(save.clone()).is_err());
+        let applied = upgrade(&mut save).unwrap();
+        assert_eq!(applied.len(), SAVE_VERSION as usize);
+        let persisted = decode(save).unwrap();
+        assert_eq!(persisted.version, SAVE_VERSION);
+        let group = &persisted.groups[&gid];
+        assert!(matches!(group.origin, DeployKind::Objective { origin } if origin == oid));
+        // the blue units were left alive on a red objective
+        for uid in &group.units {
+            assert!(persisted.units[uid].dead);
+        }
+    }
+
+    #[test]
+    fn refuses_saves_from_a_newer_version() {
+        let mut save = json!({ "version": SAVE_VERSION + 1 });
+        assert!(upgrade(&mut save).is_err());
+        assert_eq!(save["version"], SAVE_VERSION + 1);
+    }
+
+    #[test]
+    fn fails_when_a_migration_step_is_missing() {
+        let mut save = json!({});
+        assert!(upgrade_with(&[], &mut save).is_err());
+        assert_eq!(version(&save).unwrap(), 0);
+    }
+}
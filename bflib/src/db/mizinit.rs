@@ -22,9 +22,9 @@ use crate::{
     db::{
         MapS,
         logistics::Warehouse,
-        objective::{Objective, Zone},
+        objective::{Objective, RunwayState, Zone},
     },
-    group, group_health, group_mut,
+    group, group_health,
     landcache::LandCache,
     objective_mut,
     spawnctx::{SpawnCtx, SpawnLoc},
@@ -63,6 +63,9 @@ impl Db {
     /// - FO: Fob
     /// - SA: Sam site
     /// - LO: Logistics Objective
+    /// - FA: Factory
+    /// - RE: Refinery
+    /// - PO: Port
     ///
     /// Then a 1 character code for the default owner
     /// followed by the display name
@@ -92,8 +95,17 @@ impl Db {
         } else if let Some(name) = name.strip_prefix("LO") {
             let (side, name) = side_and_name(name)?;
             (ObjectiveKind::Logistics, side, name)
+        } else if let Some(name) = name.strip_prefix("FA") {
+            let (side, name) = side_and_name(name)?;
+            (ObjectiveKind::Factory, side, name)
+        } else if let Some(name) = name.strip_prefix("RE") {
+            let (side, name) = side_and_name(name)?;
+            (ObjectiveKind::Refinery, side, name)
+        } else if let Some(name) = name.strip_prefix("PO") {
+            let (side, name) = side_and_name(name)?;
+            (ObjectiveKind::Port, side, name)
         } else {
-            bail!("invalid objective type for {name}, expected AB, FO, of LO")
+            bail!("invalid objective type for {name}, expected AB, FO, LO, FA, RE, or PO")
         };
         let id = ObjectiveId::new();
         let mut logistics_detached = false;
@@ -141,6 +153,8 @@ impl Db {
             warehouse: Warehouse::default(),
             points: 0,
             logistics_detached,
+            destroyed: MapS::new(),
+            runway: RunwayState::default(),
             last_activate: DateTime::<Utc>::default(),
             // initialized by load
             threat_pos3: Vector3::default(),
@@ -337,6 +351,10 @@ impl Db {
             t.update_objective_status(&id, now)?
         }
         t.init_warehouses(lua).context("initializing warehouses")?;
+        if let Some(vc) = &t.ephemeral.cfg.auto_reset {
+            t.check_victory_objectives(&vc.condition, true)?
+        }
+        t.check_structures(miz, idx)?;
         t.ephemeral.dirty();
         Ok(t)
     }
@@ -351,30 +369,6 @@ impl Db {
         spctx: &SpawnCtx,
     ) -> Result<()> {
         debug!("init slots");
-        // migrate format changes
-        if !self.persisted.migrated_v0 {
-            self.persisted.migrated_v0 = true;
-            self.ephemeral.dirty();
-            for (oid, obj) in &self.persisted.objectives {
-                for (_, groups) in &obj.groups {
-                    for gid in groups {
-                        let g = group_mut!(self, gid)?;
-                        match &g.origin {
-                            DeployKind::ObjectiveDeprecated => {
-                                g.origin = DeployKind::Objective { origin: *oid };
-                            }
-                            _ => (),
-                        }
-                        for uid in &g.units {
-                            let unit = unit_mut!(self, uid)?;
-                            if unit.side != obj.owner {
-                                unit.dead = true;
-                            }
-                        }
-                    }
-                }
-            }
-        }
         for side in Side::ALL {
             let coa = miz.coalition(side)?;
             for country in coa.countries()? {
@@ -394,6 +388,10 @@ impl Db {
                 bail!("extra_fixed_wing_objectives {name} does not match any objective")
             }
         }
+        if let Some(vc) = &self.ephemeral.cfg.auto_reset {
+            self.check_victory_objectives(&vc.condition, false)?
+        }
+        self.check_structures(miz, idx)?;
         let mut spawn_deployed_and_logistics = || -> Result<()> {
             debug!("queue respawn deployables");
             let land = Land::singleton(spctx.lua())?;
@@ -472,6 +470,18 @@ impl Db {
             for gid in groups {
                 self.mark_group(&gid)?
             }
+            let sites = self
+                .persisted
+                .sites
+                .into_iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            for id in sites {
+                if let Err(e) = self.mark_site(id) {
+                    error!("deleting site {id}, could not mark it {e:?}");
+                    self.delete_site(id)
+                }
+            }
             for (_, obj) in &self.persisted.objectives {
                 self.ephemeral.create_objective_markup(&self.persisted, obj)
             }
@@ -505,7 +515,10 @@ impl Db {
             .filter_map(|(ucid, p)| p.airborne.and_then(|lt| Some((ucid.clone(), lt))))
             .collect::<Vec<_>>();
         for (ucid, lt) in airborne_players {
-            let player = &mut self.persisted.players[&ucid];
+            let player = match self.persisted.players.get_mut_cow(&ucid) {
+                Some(player) => player,
+                None => continue,
+            };
             player.airborne = None;
             if let Some((_, lives)) = player.lives.get_mut_cow(&lt) {
                 *lives += 1;
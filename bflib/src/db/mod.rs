@@ -17,7 +17,7 @@ for more details.
 extern crate nalgebra as na;
 use self::{group::DeployKind, persisted::Persisted};
 use crate::{bg::Task, db::ephemeral::Ephemeral, jtac::JtId};
-use anyhow::{Result, anyhow};
+use anyhow::Result;
 use bfprotocols::{
     cfg::{
         Action, ActionKind, AwacsCfg, Cfg, Deployable, DeployableEwr, DeployableJtac, DroneCfg,
@@ -33,19 +33,30 @@ use dcso3::{
     coalition::Side,
     env::miz::{Miz, MizIndex},
 };
-use std::{cmp::max, fs::File, path::Path, sync::Arc};
+use log::info;
+use std::{cmp::max, path::Path, sync::Arc};
 use tokio::sync::mpsc::UnboundedSender;
 
 pub mod actions;
 pub mod cargo;
+pub mod convoy;
+pub mod csar;
 pub mod ephemeral;
+pub mod griefing;
 pub mod group;
 pub mod logistics;
 pub mod markup;
+pub mod migrate;
 pub mod mizinit;
 pub mod objective;
+pub mod offensive;
 pub mod persisted;
 pub mod player;
+pub mod runway;
+pub mod sim;
+pub mod site;
+pub mod slingload;
+pub mod upkeep;
 
 pub type Map<K, V> = immutable_chunkmap::map::Map<K, V, 256>;
 pub type MapM<K, V> = immutable_chunkmap::map::Map<K, V, 64>;
@@ -189,19 +200,32 @@ impl Db {
         cfg: Arc<Cfg>,
         path: &Path,
     ) -> Result<Self> {
-        let file = File::open(&path)
-            .map_err(|e| anyhow!("failed to open save file {:?}, {:?}", path, e))?;
-        let file = zstd::stream::Decoder::new(file)?;
-        let persisted: Persisted = serde_json::from_reader(file)
-            .map_err(|e| anyhow!("failed to decode save file {:?}, {:?}", path, e))?;
+        let mut db = Self::load_persisted(path)?;
+        db.ephemeral.set_cfg(miz, idx, cfg, to_bg)?;
+        Ok(db)
+    }
+
+    /// load a save file without a mission. Only the parts of the
+    /// campaign that don't need dcs will work, see sim.
+    pub fn load_headless(to_bg: UnboundedSender<Task>, cfg: Arc<Cfg>, path: &Path) -> Result<Self> {
+        let mut db = Self::load_persisted(path)?;
+        db.ephemeral.set_cfg_headless(cfg, to_bg)?;
+        Ok(db)
+    }
+
+    fn load_persisted(path: &Path) -> Result<Self> {
+        let (persisted, applied) = migrate::load(path)?;
         let mut db = Db {
             persisted,
             ephemeral: Ephemeral::default(),
         };
+        for m in &applied {
+            info!("migrated save file from version {}, {}", m.from, m.name);
+            db.ephemeral.dirty();
+        }
         ObjectiveId::setseq(max(db.persisted.oid, ObjectiveId::seq()));
         GroupId::setseq(max(db.persisted.gid, GroupId::seq()));
         UnitId::setseq(max(db.persisted.uid, UnitId::seq()));
-        db.ephemeral.set_cfg(miz, idx, cfg, to_bg)?;
         Ok(db)
     }
 
@@ -210,6 +234,7 @@ impl Db {
             self.persisted.oid = ObjectiveId::seq();
             self.persisted.gid = GroupId::seq();
             self.persisted.uid = UnitId::seq();
+            self.persisted.version = migrate::SAVE_VERSION;
             Some(self.persisted.clone())
         } else {
             None
@@ -218,11 +243,15 @@ impl Db {
 
     pub fn ewrs(&self) -> impl Iterator<Item = (Vector3, Side, &DeployableEwr)> {
         self.persisted.ewrs.into_iter().filter_map(|gid| {
+            if self.is_silent(gid) {
+                return None;
+            }
             let group = self.persisted.groups.get(gid)?;
             match &group.origin {
                 DeployKind::Crate { .. }
                 | DeployKind::Objective { .. }
                 | DeployKind::ObjectiveDeprecated
+                | DeployKind::Convoy { .. }
                 | DeployKind::Troop { .. } => None,
                 DeployKind::Action {
                     spec:
@@ -300,6 +329,7 @@ impl Db {
                     | DeployKind::Action { .. }
                     | DeployKind::Objective { .. }
                     | DeployKind::ObjectiveDeprecated
+                    | DeployKind::Convoy { .. }
                     | DeployKind::Troop { .. }
                     | DeployKind::Deployed { .. } => None,
                 }
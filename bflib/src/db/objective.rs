@@ -26,14 +26,14 @@ use crate::{
     spawnctx::{Despawn, SpawnCtx, SpawnLoc},
     unit, unit_mut,
 };
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use bfprotocols::{
-    cfg::{Deployable, DeployableObjective, UnitTag, Vehicle, VictoryCondition},
+    cfg::{Deployable, DeployableObjective, StructureKind, UnitTag, Vehicle, VictoryCondition},
     db::{
         group::{GroupId, UnitId},
         objective::{ObjectiveId, ObjectiveKind},
     },
-    stats::Stat,
+    stats::{RoundEndReason, Stat, VictoryReason},
 };
 use chrono::{Duration, prelude::*};
 use compact_str::format_compact;
@@ -45,7 +45,7 @@ use dcso3::{
     coalition::Side,
     coord::Coord,
     cvt_err,
-    env::miz::{GroupKind, MizIndex},
+    env::miz::{GroupKind, Miz, MizIndex},
     group::Group,
     land::Land,
     net::Ucid,
@@ -60,6 +60,9 @@ use serde_derive::{Deserialize, Serialize};
 use smallvec::{SmallVec, smallvec};
 use std::{cmp::max, str::FromStr, sync::Arc};
 
+/// the capturing side, the objective, and the troops capturing it
+type PendingCapture = (Side, ObjectiveId, Vec<(Ucid, Option<ObjectiveId>, GroupId)>);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum ObjGroupClass {
     Logi,
@@ -269,6 +272,32 @@ impl Zone {
     }
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunwayState {
+    /// craters since the airfield was last open
+    pub(super) craters: u8,
+    /// when a closed airfield reopens on its own, None if it is open
+    pub(super) closed_until: Option<DateTime<Utc>>,
+    /// repair crates still needed to reopen it early
+    pub(super) repairs_needed: u8,
+    /// when the last crater was made or filled in
+    #[serde(default)]
+    pub(super) last_crater: Option<DateTime<Utc>>,
+    /// craters ever made, numbers each crater
+    #[serde(default)]
+    pub(super) seq: u32,
+}
+
+impl RunwayState {
+    /// fill in every crater and open the airfield
+    pub(super) fn clear(&mut self) {
+        *self = Self {
+            seq: self.seq,
+            ..Self::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Objective {
     pub id: ObjectiveId,
@@ -293,6 +322,11 @@ pub struct Objective {
     pub(super) logistics_detached: bool,
     #[serde(default)]
     pub points: i32,
+    /// critical structures that have been destroyed, by name
+    #[serde(default)]
+    pub(super) destroyed: MapS<String, StructureKind>,
+    #[serde(default)]
+    pub(super) runway: RunwayState,
     #[serde(skip)]
     pub(super) spawned: bool,
     #[serde(skip)]
@@ -308,7 +342,6 @@ impl Objective {
         self.name.as_str()
     }
 
-    #[allow(dead_code)]
     pub fn health(&self) -> u8 {
         self.health
     }
@@ -317,6 +350,22 @@ impl Objective {
         self.logi
     }
 
+    pub fn supply(&self) -> u8 {
+        self.supply
+    }
+
+    pub fn fuel(&self) -> u8 {
+        self.fuel
+    }
+
+    pub fn kind(&self) -> &ObjectiveKind {
+        &self.kind
+    }
+
+    pub fn pos(&self) -> Vector2 {
+        self.zone.pos()
+    }
+
     pub fn captureable(&self) -> bool {
         self.logi == 0
     }
@@ -328,17 +377,52 @@ impl Objective {
     pub fn is_farp(&self) -> bool {
         match &self.kind {
             ObjectiveKind::Farp { .. } => true,
-            ObjectiveKind::Airbase | ObjectiveKind::Fob | ObjectiveKind::Logistics => false,
+            ObjectiveKind::Airbase
+            | ObjectiveKind::Fob
+            | ObjectiveKind::Logistics
+            | ObjectiveKind::Factory
+            | ObjectiveKind::Refinery
+            | ObjectiveKind::Port => false,
         }
     }
 
     pub fn is_airbase(&self) -> bool {
         match &self.kind {
             ObjectiveKind::Airbase => true,
-            ObjectiveKind::Farp { .. } | ObjectiveKind::Fob | ObjectiveKind::Logistics => false,
+            ObjectiveKind::Farp { .. }
+            | ObjectiveKind::Fob
+            | ObjectiveKind::Logistics
+            | ObjectiveKind::Factory
+            | ObjectiveKind::Refinery
+            | ObjectiveKind::Port => false,
         }
     }
 
+    /// true if a critical structure of kind has been destroyed
+    pub fn lost(&self, kind: StructureKind) -> bool {
+        self.destroyed.into_iter().any(|(_, k)| *k == kind)
+    }
+
+    /// true if the owner is shown that the objective is threatened,
+    /// which needs a radar
+    pub fn threat_shown(&self) -> bool {
+        self.threatened && !self.lost(StructureKind::Radar)
+    }
+
+    pub fn destroyed(&self) -> impl Iterator<Item = (&String, &StructureKind)> {
+        self.destroyed.into_iter()
+    }
+
+    /// when the airfield reopens if its runway is cratered
+    pub fn closed_until(&self) -> Option<DateTime<Utc>> {
+        self.runway.closed_until
+    }
+
+    /// true if fixed wing aircraft can operate from the objective
+    pub fn runway_usable(&self) -> bool {
+        self.runway.closed_until.is_none() && !self.lost(StructureKind::Runway)
+    }
+
     pub fn get_equipment(&self, name: &str) -> Inventory {
         self.warehouse
             .equipment
@@ -354,6 +438,14 @@ impl Objective {
             .map(|i| *i)
             .unwrap_or_default()
     }
+
+    pub fn equipment(&self) -> impl Iterator<Item = (&String, &Inventory)> {
+        self.warehouse.equipment.into_iter()
+    }
+
+    pub fn liquids(&self) -> impl Iterator<Item = (&LiquidType, &Inventory)> {
+        self.warehouse.liquids.into_iter()
+    }
 }
 
 impl Db {
@@ -389,43 +481,6 @@ impl Db {
         obj.map(|obj| (dist.sqrt(), azumith2d_to(obj.zone.pos(), pos), obj))
     }
 
-    fn compute_objective_status(&self, obj: &Objective) -> Result<(u8, u8)> {
-        obj.groups
-            .get(&obj.owner)
-            .map(|groups| {
-                let mut total = 0;
-                let mut alive = 0;
-                let mut logi_total = 0;
-                let mut logi_alive = 0;
-                for gid in groups {
-                    let group = group!(self, gid)?;
-                    let logi = match &group.class {
-                        ObjGroupClass::Logi => true,
-                        _ => false,
-                    };
-                    for uid in &group.units {
-                        let unit = unit!(self, uid)?;
-                        if !unit.tags.contains(UnitTag::Invincible) {
-                            total += 1;
-                            if logi {
-                                logi_total += 1;
-                            }
-                            if !unit.dead {
-                                alive += 1;
-                                if logi {
-                                    logi_alive += 1;
-                                }
-                            }
-                        }
-                    }
-                }
-                let health = ((alive as f32 / total as f32) * 100.).trunc() as u8;
-                let logi = ((logi_alive as f32 / logi_total as f32) * 100.).trunc() as u8;
-                Ok((health, logi))
-            })
-            .unwrap_or(Ok((0, 0)))
-    }
-
     pub(super) fn delete_objective(&mut self, oid: &ObjectiveId) -> Result<()> {
         let obj = self
             .persisted
@@ -635,6 +690,8 @@ impl Db {
             warehouse: Warehouse::default(),
             logistics_detached: false,
             points: 0,
+            destroyed: MapS::new(),
+            runway: RunwayState::default(),
             last_threatened_ts: now,
             last_change_ts: now,
             last_activate: DateTime::<Utc>::default(),
@@ -696,7 +753,7 @@ impl Db {
     ) -> Result<()> {
         let (kind, health, logi) = {
             let obj = objective!(self, oid)?;
-            let (health, logi) = self.compute_objective_status(obj)?;
+            let (health, logi) = self.persisted.objective_status(obj)?;
             let obj = objective_mut!(self, oid)?;
             obj.health = health;
             obj.logi = logi;
@@ -786,7 +843,7 @@ impl Db {
             .players_by_slot
             .values()
             .filter_map(|ucid| {
-                let player = &self.persisted.players[ucid];
+                let player = self.persisted.players.get(ucid)?;
                 let side = player.side;
                 player
                     .current_slot
@@ -891,8 +948,10 @@ impl Db {
             if spawn {
                 obj.last_activate = now;
             }
+            // without radar the owner isn't warned
+            let warn = !obj.lost(StructureKind::Radar);
             if is_threatened {
-                if !obj.threatened {
+                if !obj.threatened && warn {
                     became_threatened.push(*oid);
                 }
                 obj.threatened = true;
@@ -900,7 +959,7 @@ impl Db {
                 self.ephemeral.dirty = true;
             } else {
                 if now - obj.last_threatened_ts >= cooldown {
-                    if obj.threatened {
+                    if obj.threatened && warn {
                         became_clear.push(*oid);
                     }
                     obj.threatened = false;
@@ -1048,6 +1107,136 @@ impl Db {
         self.update_objective_status(&oid, now)
     }
 
+    /// a player delivered a logistics repair to oid
+    pub fn logistics_repair(
+        &mut self,
+        ucid: &Ucid,
+        oid: ObjectiveId,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let side = objective!(self, oid)?.owner;
+        self.repair_one_logi_step(side, now, oid)?;
+        self.ephemeral.stat(Stat::Repair { id: oid, by: *ucid });
+        if let Some(amount) = self
+            .ephemeral
+            .cfg
+            .points
+            .as_ref()
+            .map(|p| p.logistics_repair)
+        {
+            self.adjust_points(ucid, amount as i32, "for logistics repair");
+        }
+        Ok(())
+    }
+
+    /// the objective and kind of the critical structure called name
+    fn critical_structure(&self, name: &str) -> Option<(ObjectiveId, StructureKind)> {
+        self.ephemeral
+            .cfg
+            .structures
+            .iter()
+            .find_map(|(obj, structures)| {
+                let oid = self.persisted.objectives_by_name.get(obj)?;
+                structures
+                    .iter()
+                    .find(|s| s.name.as_str() == name)
+                    .map(|s| (*oid, s.kind))
+            })
+    }
+
+    /// check that every critical structure belongs to an objective and
+    /// names a static object in the miz. Map scenery is named by id, and
+    /// can't be checked until it dies.
+    pub(super) fn check_structures(&self, miz: &Miz, idx: &MizIndex) -> Result<()> {
+        for (obj, structures) in &self.ephemeral.cfg.structures {
+            if self.persisted.objectives_by_name.get(obj).is_none() {
+                bail!("critical structures of {obj} do not match any objective")
+            }
+            for s in structures {
+                if s.name.parse::<u64>().is_err()
+                    && miz.get_unit_by_name(idx, s.name.as_str())?.is_none()
+                {
+                    bail!(
+                        "critical structure {} of {obj} is not in the mission",
+                        s.name
+                    )
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// change the structures of oid, syncing its warehouse around the
+    /// change so stock behind a destroyed structure is kept
+    fn update_structures<F: FnOnce(&mut Objective)>(
+        &mut self,
+        lua: MizLua,
+        oid: ObjectiveId,
+        f: F,
+    ) -> Result<()> {
+        let sync = self.ephemeral.cfg.warehouse.is_some()
+            && self.ephemeral.airbase_by_oid.contains_key(&oid);
+        if sync {
+            self.sync_warehouse_to_objective(lua, oid)
+                .context("syncing warehouse to objective")?;
+        }
+        f(objective_mut!(self, oid)?);
+        if sync {
+            self.sync_objective_to_warehouse(lua, oid)
+                .context("syncing objective to warehouse")?;
+        }
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// a static or scenery object called name was destroyed, if it was
+    /// a critical structure its objective loses the capability it provides
+    pub fn structure_dead(&mut self, lua: MizLua, name: &str) -> Result<()> {
+        let Some((oid, kind)) = self.critical_structure(name) else {
+            return Ok(());
+        };
+        if objective!(self, oid)?.destroyed.get(name).is_some() {
+            return Ok(());
+        }
+        self.update_structures(lua, oid, |obj| {
+            obj.destroyed.insert_cow(name.into(), kind);
+        })?;
+        let obj = objective!(self, oid)?;
+        let msg = format_compact!("the {} at {} was destroyed", kind.name(), obj.name);
+        self.ephemeral.msgs().panel_to_all(10, false, msg);
+        Ok(())
+    }
+
+    /// a player delivered a repair crate to oid, rebuild one of its
+    /// destroyed structures and return what it was
+    pub fn structure_repair(
+        &mut self,
+        lua: MizLua,
+        ucid: &Ucid,
+        oid: ObjectiveId,
+    ) -> Result<StructureKind> {
+        let (name, kind) = objective!(self, oid)?
+            .destroyed
+            .into_iter()
+            .next()
+            .map(|(name, kind)| (name.clone(), *kind))
+            .ok_or_else(|| anyhow!("no destroyed structures at {oid}"))?;
+        self.update_structures(lua, oid, |obj| {
+            obj.destroyed.remove_cow(&name);
+        })?;
+        self.ephemeral.stat(Stat::Repair { id: oid, by: *ucid });
+        if let Some(amount) = self
+            .ephemeral
+            .cfg
+            .points
+            .as_ref()
+            .map(|p| p.logistics_repair)
+        {
+            self.adjust_points(ucid, amount as i32, "for structure repair");
+        }
+        Ok(kind)
+    }
+
     pub fn maybe_do_repairs(&mut self, now: DateTime<Utc>) -> Result<()> {
         let to_repair = self
             .persisted
@@ -1084,41 +1273,193 @@ impl Db {
         cap
     }
 
-    pub fn check_victory(&mut self, now: DateTime<Utc>) -> Option<Side> {
-        self.ephemeral.cfg.auto_reset.and_then(|vc| {
-            if let Some((vts, side)) = self.ephemeral.victory {
-                let delay = Duration::seconds(vc.delay as i64);
-                let elapsed = now - vts;
-                if elapsed >= delay {
-                    return Some(side);
-                } else {
-                    self.ephemeral.msgs().panel_to_all(
-                        10,
-                        true,
-                        format_compact!(
-                            "{side} has won. The server will reset in {}s",
-                            (delay - elapsed).as_seconds_f64()
-                        ),
-                    );
-                    return None;
+    /// check that the objectives named by vc exist. When a round starts
+    /// also check that both sides own a logistics hub if vc needs them,
+    /// otherwise the condition would be met immediately.
+    pub(super) fn check_victory_objectives(
+        &self,
+        vc: &VictoryCondition,
+        new_round: bool,
+    ) -> Result<()> {
+        match vc {
+            VictoryCondition::KeyObjectives { objectives } => {
+                for name in objectives {
+                    if self.persisted.objectives_by_name.get(name).is_none() {
+                        bail!("auto_reset key objective {name} does not match any objective")
+                    }
+                }
+            }
+            VictoryCondition::LogisticsHubsDestroyed if new_round => {
+                for side in [Side::Red, Side::Blue] {
+                    let has_hub = self.persisted.logistics_hubs.into_iter().any(|oid| {
+                        self.persisted
+                            .objectives
+                            .get(oid)
+                            .map(|obj| obj.owner == side)
+                            .unwrap_or(false)
+                    });
+                    if !has_hub {
+                        bail!(
+                            "auto_reset LogisticsHubsDestroyed requires {side} to start with a logistics hub"
+                        )
+                    }
                 }
             }
-            let VictoryCondition::MapOwned { fraction } = vc.condition;
-            let (blue, red, neutral, total) = self.persisted.objectives.into_iter().fold(
-                (0., 0., 0., 0.),
-                |(blue, red, neutral, total), (_, obj)| match obj.owner {
-                    Side::Blue => (blue + 1., red, neutral, total + 1.),
-                    Side::Red => (blue, red + 1., neutral, total + 1.),
-                    Side::Neutral => (blue, red, neutral + 1., total + 1.),
-                },
-            );
-            if ((blue + neutral) / total) >= fraction {
-                self.ephemeral.victory = Some((now, Side::Blue));
-            } else if ((red + neutral) / total) >= fraction {
-                self.ephemeral.victory = Some((now, Side::Red));
+            VictoryCondition::All(conditions) | VictoryCondition::Any(conditions) => {
+                for c in conditions {
+                    self.check_victory_objectives(c, new_round)?
+                }
             }
-            None
-        })
+            VictoryCondition::LogisticsHubsDestroyed
+            | VictoryCondition::MapOwned { .. }
+            | VictoryCondition::Points { .. }
+            | VictoryCondition::TimeLimit { .. }
+            | VictoryCondition::TicketsExhausted => (),
+        }
+        Ok(())
+    }
+
+    /// the conditions in vc that side meets, or None if it doesn't
+    /// meet vc
+    fn victory_met(
+        &self,
+        vc: &VictoryCondition,
+        side: Side,
+        now: DateTime<Utc>,
+    ) -> Option<SmallVec<[VictoryReason; 2]>> {
+        let owned = |side: Side| {
+            self.persisted
+                .objectives
+                .into_iter()
+                .filter(|(_, obj)| obj.owner == side)
+                .count()
+        };
+        let met = match vc {
+            VictoryCondition::MapOwned { fraction } => {
+                let total = self.persisted.objectives.len() as f64;
+                let held = (owned(side) + owned(Side::Neutral)) as f64;
+                total > 0. && held / total >= *fraction
+            }
+            VictoryCondition::KeyObjectives { objectives } => objectives.iter().all(|name| {
+                self.persisted
+                    .objectives_by_name
+                    .get(name.as_str())
+                    .and_then(|oid| self.persisted.objectives.get(oid))
+                    .map(|obj| obj.owner == side)
+                    .unwrap_or(false)
+            }),
+            VictoryCondition::LogisticsHubsDestroyed => {
+                let enemy = side.opposite();
+                self.persisted.logistics_hubs.len() > 0
+                    && self.persisted.logistics_hubs.into_iter().all(|oid| {
+                        self.persisted
+                            .objectives
+                            .get(oid)
+                            .map(|obj| obj.owner != enemy)
+                            .unwrap_or(true)
+                    })
+            }
+            VictoryCondition::Points { threshold } => {
+                let points: i64 = self
+                    .persisted
+                    .players
+                    .into_iter()
+                    .filter(|(_, p)| p.side == side)
+                    .map(|(_, p)| p.points as i64)
+                    .sum();
+                points >= *threshold
+            }
+            VictoryCondition::TimeLimit { hours } => {
+                let start = self.persisted.round_start.unwrap_or(now);
+                let limit = Duration::seconds((hours * 3600.) as i64);
+                now - start >= limit && owned(side) > owned(side.opposite())
+            }
+            VictoryCondition::TicketsExhausted => self.tickets(side.opposite()) == Some(0),
+            VictoryCondition::All(conditions) => {
+                let mut reasons = smallvec![];
+                for c in conditions {
+                    reasons.extend(self.victory_met(c, side, now)?);
+                }
+                return Some(reasons);
+            }
+            VictoryCondition::Any(conditions) => {
+                return conditions
+                    .iter()
+                    .find_map(|c| self.victory_met(c, side, now));
+            }
+        };
+        let reason = match vc {
+            VictoryCondition::MapOwned { .. } => VictoryReason::MapOwned,
+            VictoryCondition::KeyObjectives { .. } => VictoryReason::KeyObjectives,
+            VictoryCondition::LogisticsHubsDestroyed => VictoryReason::LogisticsHubsDestroyed,
+            VictoryCondition::Points { .. } => VictoryReason::Points,
+            VictoryCondition::TimeLimit { .. } => VictoryReason::TimeLimit,
+            VictoryCondition::TicketsExhausted => VictoryReason::TicketsExhausted,
+            VictoryCondition::All(_) | VictoryCondition::Any(_) => unreachable!(),
+        };
+        met.then(|| smallvec![reason])
+    }
+
+    /// the tickets side has left, or None if tickets are not in use
+    pub fn tickets(&self, side: Side) -> Option<u32> {
+        self.persisted.tickets(&self.ephemeral.cfg, side)
+    }
+
+    /// add amount, which may be negative, to the tickets of side
+    pub(super) fn adjust_tickets(&mut self, side: Side, amount: i32, why: &str) {
+        let max = match &self.ephemeral.cfg.tickets {
+            Some(tc) if amount != 0 => tc.max(),
+            Some(_) | None => return,
+        };
+        let cur = match self.tickets(side) {
+            Some(cur) => cur,
+            None => return,
+        };
+        let new = (cur as i64 + amount as i64).clamp(0, max.max(cur) as i64) as u32;
+        if new == cur {
+            return;
+        }
+        self.persisted.tickets.insert_cow(side, new);
+        self.ephemeral.stat(Stat::Tickets {
+            side,
+            tickets: new,
+            change: new as i32 - cur as i32,
+            reason: why.into(),
+        });
+        if new == 0 {
+            let msg = format_compact!("{side} has run out of tickets");
+            self.ephemeral.msgs().panel_to_all(15, false, msg);
+        }
+        self.ephemeral.dirty();
+    }
+
+    pub fn check_victory(&mut self, now: DateTime<Utc>) -> Option<(Side, RoundEndReason)> {
+        let vc = self.ephemeral.cfg.auto_reset.clone()?;
+        if self.persisted.round_start.is_none() {
+            self.persisted.round_start = Some(now);
+            self.ephemeral.dirty();
+        }
+        if let Some((vts, side, reason)) = &self.ephemeral.victory {
+            let delay = Duration::seconds(vc.delay as i64);
+            let elapsed = now - *vts;
+            if elapsed >= delay {
+                return Some((*side, reason.clone()));
+            } else {
+                let msg = format_compact!(
+                    "{side} has won, {reason}. The server will reset in {}s",
+                    (delay - elapsed).as_seconds_f64()
+                );
+                self.ephemeral.msgs().panel_to_all(10, true, msg);
+                return None;
+            }
+        }
+        for side in [Side::Blue, Side::Red] {
+            if let Some(reasons) = self.victory_met(&vc.condition, side, now) {
+                self.ephemeral.victory = Some((now, side, RoundEndReason::Victory(reasons)));
+                break;
+            }
+        }
+        None
     }
 
     pub fn check_capture(
@@ -1126,6 +1467,41 @@ impl Db {
         lua: MizLua,
         now: DateTime<Utc>,
     ) -> Result<SmallVec<[(Side, ObjectiveId); 1]>> {
+        let pending = self.pending_captures()?;
+        // find every airbase before changing anything, so a missing one
+        // doesn't leave a capture half done
+        let mut airbases: SmallVec<[Airbase; 1]> = smallvec![];
+        for (_, oid, _) in &pending {
+            let abid = self
+                .ephemeral
+                .airbase_by_oid
+                .get(oid)
+                .ok_or_else(|| anyhow!("no airbase for objective {oid}"))?;
+            airbases.push(Airbase::get_instance(lua, abid).context("getting captured airbase")?);
+        }
+        let captured = self.apply_captures(pending, now)?;
+        for ((side, _), airbase) in captured.iter().zip(airbases) {
+            airbase
+                .set_coalition(*side)
+                .context("setting airbase coalition")?;
+        }
+        Ok(captured)
+    }
+
+    /// capture every objective that is held uncontested by capture
+    /// capable troops. This does not touch the dcs airbase, see
+    /// check_capture.
+    pub fn resolve_captures(
+        &mut self,
+        now: DateTime<Utc>,
+    ) -> Result<SmallVec<[(Side, ObjectiveId); 1]>> {
+        let pending = self.pending_captures()?;
+        self.apply_captures(pending, now)
+    }
+
+    /// the objectives held uncontested by capture capable troops, with
+    /// the side capturing them and the troops doing it
+    pub(super) fn pending_captures(&self) -> Result<SmallVec<[PendingCapture; 1]>> {
         let mut captured: FxHashMap<ObjectiveId, Vec<(Side, Ucid, Option<ObjectiveId>, GroupId)>> =
             FxHashMap::default();
         for (oid, obj) in &self.persisted.objectives {
@@ -1156,97 +1532,136 @@ impl Db {
                         | DeployKind::Deployed { .. }
                         | DeployKind::Objective { .. }
                         | DeployKind::ObjectiveDeprecated
+                        | DeployKind::Convoy { .. }
                         | DeployKind::Action { .. }
                         | DeployKind::Troop { .. } => (),
                     }
                 }
             }
         }
-        let mut actually_captured = smallvec![];
-        let mut to_mark: SmallVec<[GroupId; 32]> = smallvec![];
+        let mut pending = smallvec![];
         for (oid, gids) in captured {
             let (side, _, _, _) = gids.first().ok_or_else(|| anyhow!("no guid"))?;
-            if gids.iter().all(|(s, _, _, _)| side == s) {
-                let obj = objective_mut!(self, oid)?;
-                let name = obj.name.clone();
-                let previous_owner = obj.owner;
-                let new_owner = *side;
-                obj.spawned = false;
-                obj.threatened = true;
-                obj.last_threatened_ts = now;
-                obj.last_activate = now;
-                obj.owner = new_owner;
-                actually_captured.push((*side, oid));
-                for gid in obj.groups.get(&obj.owner).unwrap_or(&Set::new()) {
-                    to_mark.push(*gid);
-                }
-                for gid in obj.groups.get(&obj.owner.opposite()).unwrap_or(&Set::new()) {
-                    if let Some(id) = self.ephemeral.group_marks.remove(gid) {
-                        self.ephemeral.msgs.delete_mark(id)
-                    }
-                    for uid in &group!(self, gid)?.units {
-                        if !unit!(self, uid)?.dead {
-                            self.ephemeral
-                                .units_potentially_close_to_enemies
-                                .insert(*uid);
-                        }
-                    }
-                }
-                let abid = self
-                    .ephemeral
-                    .airbase_by_oid
-                    .get(&oid)
-                    .ok_or_else(|| anyhow!("no airbase for objective {}", obj.name))?;
-                let airbase =
-                    Airbase::get_instance(lua, abid).context("getting captured airbase")?;
-                airbase
-                    .set_coalition(*side)
-                    .context("setting airbase coalition")?;
-                self.repair_one_logi_step(*side, now, oid)
-                    .context("repairing captured airbase logi")?;
-                self.repair_services(*side, now, oid)
-                    .context("repairing captured airbase services")?;
-                self.capture_warehouse(lua, oid)
-                    .context("capturing warehouse")?;
-                self.setup_supply_lines().context("setup supply lines")?;
-                self.deliver_supplies_from_logistics_hubs()
-                    .context("delivering supplies")?;
-                let mut ucids: SmallVec<[Ucid; 1]> = smallvec![];
-                for (_, ucid, troop_origin, gid) in gids {
-                    self.delete_group(&gid)
-                        .context("deleting capturing troops")?;
-                    if previous_owner != new_owner || troop_origin != Some(oid) {
-                        if !ucids.contains(&ucid) {
-                            ucids.push(ucid);
-                        }
+            let side = *side;
+            if gids.iter().all(|(s, _, _, _)| &side == s) {
+                let troops = gids
+                    .into_iter()
+                    .map(|(_, ucid, origin, gid)| (ucid, origin, gid))
+                    .collect();
+                pending.push((side, oid, troops));
+            }
+        }
+        Ok(pending)
+    }
+
+    fn apply_captures(
+        &mut self,
+        pending: SmallVec<[PendingCapture; 1]>,
+        now: DateTime<Utc>,
+    ) -> Result<SmallVec<[(Side, ObjectiveId); 1]>> {
+        let mut actually_captured = smallvec![];
+        for (side, oid, troops) in pending {
+            let previous_owner = objective!(self, oid)?.owner;
+            let mut ucids: SmallVec<[Ucid; 1]> = smallvec![];
+            for (ucid, troop_origin, gid) in troops {
+                self.delete_group(&gid)
+                    .context("deleting capturing troops")?;
+                if previous_owner != side || troop_origin != Some(oid) {
+                    // ai offensives are not deployed by a player
+                    if ucid != Ucid::default() && !ucids.contains(&ucid) {
+                        ucids.push(ucid);
                     }
                 }
-                self.ephemeral.stat(Stat::Capture {
-                    id: oid,
-                    side: new_owner,
-                    by: ucids.clone(),
-                });
-                if let Some(points) = self.ephemeral.cfg.points.as_ref() {
-                    let ppp = (points.capture as f32 / ucids.len() as f32).ceil() as i32;
-                    for ucid in &ucids {
-                        self.adjust_points(ucid, ppp, &format!("for capturing {name}"));
-                    }
+            }
+            self.capture_objective(oid, side, ucids, now)?;
+            actually_captured.push((side, oid));
+        }
+        Ok(actually_captured)
+    }
+
+    /// transfer ownership of an objective to side, crediting the
+    /// players in by with the capture
+    pub fn capture_objective(
+        &mut self,
+        oid: ObjectiveId,
+        side: Side,
+        by: SmallVec<[Ucid; 1]>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut to_mark: SmallVec<[GroupId; 32]> = smallvec![];
+        let obj = objective_mut!(self, oid)?;
+        let name = obj.name.clone();
+        let previous_owner = obj.owner;
+        obj.spawned = false;
+        obj.threatened = true;
+        obj.last_threatened_ts = now;
+        obj.last_activate = now;
+        obj.owner = side;
+        // the new owner starts with an open runway
+        let was_closed = obj.runway.closed_until.is_some();
+        obj.runway.clear();
+        if was_closed {
+            self.ephemeral.stat(Stat::AirfieldClosed {
+                id: oid,
+                closed_until: None,
+            });
+        }
+        let obj = objective_mut!(self, oid)?;
+        for gid in obj.groups.get(&obj.owner).unwrap_or(&Set::new()) {
+            to_mark.push(*gid);
+        }
+        for gid in obj.groups.get(&obj.owner.opposite()).unwrap_or(&Set::new()) {
+            if let Some(id) = self.ephemeral.group_marks.remove(gid) {
+                self.ephemeral.msgs.delete_mark(id)
+            }
+            for uid in &group!(self, gid)?.units {
+                if !unit!(self, uid)?.dead {
+                    self.ephemeral
+                        .units_potentially_close_to_enemies
+                        .insert(*uid);
                 }
-                let obj = objective!(self, oid)?;
-                self.ephemeral.create_objective_markup(&self.persisted, obj);
-                self.ephemeral.dirty();
             }
         }
-        if actually_captured.len() > 0 {
-            self.ephemeral.logistics_stage = LogiStage::SyncToWarehouses {
-                objectives: self
-                    .persisted
-                    .objectives
-                    .into_iter()
-                    .map(|(oid, _)| *oid)
-                    .collect(),
-            };
+        self.repair_one_logi_step(side, now, oid)
+            .context("repairing captured airbase logi")?;
+        self.repair_services(side, now, oid)
+            .context("repairing captured airbase services")?;
+        self.capture_warehouse(oid).context("capturing warehouse")?;
+        self.setup_supply_lines().context("setup supply lines")?;
+        self.deliver_supplies_from_logistics_hubs()
+            .context("delivering supplies")?;
+        self.ephemeral.stat(Stat::Capture {
+            id: oid,
+            side,
+            by: by.clone(),
+        });
+        for ucid in &by {
+            self.with_sortie(ucid, |s| s.captures += 1);
+        }
+        if let Some(points) = self.ephemeral.cfg.points.as_ref() {
+            let ppp = (points.capture as f32 / by.len() as f32).ceil() as i32;
+            for ucid in &by {
+                self.adjust_points(ucid, ppp, &format!("for capturing {name}"));
+            }
         }
+        if let Some(tickets) = self.ephemeral.cfg.tickets.as_ref() {
+            let (loss, gain) = (tickets.objective_loss as i32, tickets.capture as i32);
+            if previous_owner != side {
+                self.adjust_tickets(previous_owner, -loss, &format!("for losing {name}"));
+            }
+            self.adjust_tickets(side, gain, &format!("for capturing {name}"));
+        }
+        let obj = objective!(self, oid)?;
+        self.ephemeral.create_objective_markup(&self.persisted, obj);
+        self.ephemeral.dirty();
+        self.ephemeral.logistics_stage = LogiStage::SyncToWarehouses {
+            objectives: self
+                .persisted
+                .objectives
+                .into_iter()
+                .map(|(oid, _)| *oid)
+                .collect(),
+        };
         for gid in to_mark {
             if let Err(e) = self
                 .mark_group(&gid)
@@ -1255,7 +1670,7 @@ impl Db {
                 error!("{e:?}")
             }
         }
-        Ok(actually_captured)
+        Ok(())
     }
 
     pub fn update_objectives_markup(&mut self) -> Result<()> {
@@ -1290,3 +1705,43 @@ impl Db {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::sim::test::{db, objective};
+    use bfprotocols::cfg::Cfg;
+
+    #[test]
+    fn victory_conditions_are_checked_against_the_objectives() {
+        let (mut db, _from_db) = db(Cfg::default());
+        objective(
+            &mut db,
+            "blue hub",
+            ObjectiveKind::Logistics,
+            Side::Blue,
+            Vector2::zeros(),
+        );
+        let key = VictoryCondition::KeyObjectives {
+            objectives: vec!["blue hub".into()],
+        };
+        assert!(db.check_victory_objectives(&key, true).is_ok());
+        let missing = VictoryCondition::Any(vec![VictoryCondition::KeyObjectives {
+            objectives: vec!["nowhere".into()],
+        }]);
+        assert!(db.check_victory_objectives(&missing, false).is_err());
+        let hubs = VictoryCondition::LogisticsHubsDestroyed;
+        // red has no hub, so blue would win immediately
+        assert!(db.check_victory_objectives(&hubs, true).is_err());
+        // but red may lose its hubs during the round
+        assert!(db.check_victory_objectives(&hubs, false).is_ok());
+        objective(
+            &mut db,
+            "red hub",
+            ObjectiveKind::Logistics,
+            Side::Red,
+            Vector2::new(50000., 0.),
+        );
+        assert!(db.check_victory_objectives(&hubs, true).is_ok());
+    }
+}
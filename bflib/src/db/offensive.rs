@@ -0,0 +1,402 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use super::{Db, group::DeployKind};
+use crate::{
+    group, objective,
+    spawnctx::{SpawnCtx, SpawnLoc},
+};
+use anyhow::{Context, Result, anyhow};
+use bfprotocols::{
+    cfg::{OffensiveCfg, UnitTag},
+    db::{group::GroupId, objective::ObjectiveId},
+};
+use chrono::{Duration, prelude::*};
+use compact_str::format_compact;
+use dcso3::{
+    LuaVec2, MizLua, String, Time, Vector2,
+    attribute::Attribute,
+    azumith2d_to,
+    coalition::Side,
+    controller::{
+        ActionTyp, AiOption, AlarmState, AltType, GroundOption, MissionPoint, PointType, Task,
+        VehicleFormation,
+    },
+    env::miz::MizIndex,
+    group::Group,
+    land::Land,
+    net::Ucid,
+};
+use enumflags2::BitFlags;
+use log::{error, info};
+use smallvec::{SmallVec, smallvec};
+use std::{cmp::min, sync::Arc};
+
+/// how far outside the target zone groups form up before the assault
+const STAGING_DISTANCE: f64 = 2_000.;
+
+/// how far routes stay clear of enemy objectives that aren't the target
+const DETOUR_MARGIN: f64 = 1_500.;
+
+/// the spacing between groups launched by the same offensive
+const GROUP_SPACING: f64 = 100.;
+
+pub(super) fn waypoint<'lua>(
+    land: &Land,
+    pos: Vector2,
+    formation: VehicleFormation,
+    task: Task<'lua>,
+) -> Result<MissionPoint<'lua>> {
+    Ok(MissionPoint {
+        action: Some(ActionTyp::Ground(formation)),
+        airdrome_id: None,
+        helipad: None,
+        typ: PointType::TurningPoint,
+        link_unit: None,
+        pos: LuaVec2(pos),
+        alt: land.get_height(LuaVec2(pos))?,
+        alt_typ: Some(AltType::BARO),
+        time_re_fu_ar: None,
+        eta: None,
+        eta_locked: None,
+        speed: 20.,
+        speed_locked: None,
+        name: None,
+        task: Box::new(task),
+    })
+}
+
+pub(super) fn alarm_state<'lua>(state: AlarmState) -> Task<'lua> {
+    Task::WrappedOption(AiOption::Ground(GroundOption::AlarmState(state)))
+}
+
+impl Db {
+    /// the enemy objective closest to pos within range that side isn't
+    /// already attacking
+    fn offensive_target(&self, side: Side, pos: Vector2, range: f64) -> Option<(f64, ObjectiveId)> {
+        self.persisted
+            .objectives
+            .into_iter()
+            .filter(|(oid, obj)| {
+                obj.owner != side
+                    && !self.persisted.offensives.into_iter().any(|(gid, tgt)| {
+                        tgt == *oid && self.persisted.groups.get(gid).map(|g| g.side) == Some(side)
+                    })
+            })
+            .map(|(oid, obj)| (na::distance(&pos.into(), &obj.zone.pos().into()), *oid))
+            .filter(|(d, _)| *d <= range)
+            .min_by(|(d0, _), (d1, _)| d0.total_cmp(d1))
+    }
+
+    /// plan a route from pos to the target that detours around any other
+    /// enemy objectives on the way, and forms up outside the target
+    /// before the assault. The start position is not included.
+    fn plan_offensive_route(
+        &self,
+        side: Side,
+        pos: Vector2,
+        target: &ObjectiveId,
+    ) -> Result<SmallVec<[Vector2; 8]>> {
+        let tgt = objective!(self, target)?;
+        let dst = tgt.zone.pos();
+        let mut route: SmallVec<[Vector2; 8]> = smallvec![];
+        let len = (dst - pos).magnitude();
+        if len < 1. {
+            route.push(dst);
+            return Ok(route);
+        }
+        let dir = (dst - pos) / len;
+        let mut detours: SmallVec<[(f64, Vector2); 4]> = smallvec![];
+        for (oid, obj) in &self.persisted.objectives {
+            if oid == target || obj.owner == side {
+                continue;
+            }
+            let center = obj.zone.pos();
+            let along = (center - pos).dot(&dir);
+            if along <= 0. || along >= len {
+                continue;
+            }
+            let off = pos + dir * along - center;
+            let clearance = obj.zone.radius() + DETOUR_MARGIN;
+            let d = off.magnitude();
+            if d < clearance {
+                let away = if d > 1. {
+                    off / d
+                } else {
+                    Vector2::new(-dir.y, dir.x)
+                };
+                detours.push((along, center + away * clearance));
+            }
+        }
+        detours.sort_by(|(a0, _), (a1, _)| a0.total_cmp(a1));
+        route.extend(detours.into_iter().map(|(_, p)| p));
+        let last = route.last().copied().unwrap_or(pos);
+        let standoff = tgt.zone.radius() + STAGING_DISTANCE;
+        let v = last - dst;
+        if v.magnitude() > standoff {
+            route.push(dst + v.normalize() * standoff);
+        }
+        route.push(dst);
+        Ok(route)
+    }
+
+    /// give an offensive group its orders. Returns false if the group
+    /// has not spawned yet.
+    fn task_offensive(
+        &self,
+        lua: MizLua,
+        cfg: &OffensiveCfg,
+        gid: &GroupId,
+        target: &ObjectiveId,
+    ) -> Result<bool> {
+        let group = group!(self, gid)?;
+        let dcs_group = match Group::get_by_name(lua, &group.name) {
+            Ok(g) => g,
+            Err(_) => return Ok(false),
+        };
+        let pos = self.group_center(gid)?;
+        let route = self.plan_offensive_route(group.side, pos, target)?;
+        let land = Land::singleton(lua)?;
+        let transit = if cfg.use_roads {
+            VehicleFormation::OnRoad
+        } else {
+            VehicleFormation::OffRoad
+        };
+        let engage = Task::EngageTargets {
+            target_types: vec![
+                Attribute::Helicopters,
+                Attribute::AttackHelicopters,
+                Attribute::GroundUnits,
+                Attribute::GroundVehicles,
+                Attribute::ArmedGroundUnits,
+            ],
+            max_dist: Some(2_000.),
+            priority: None,
+        };
+        let mut start = waypoint(
+            &land,
+            pos,
+            transit.clone(),
+            Task::ComboTask(vec![alarm_state(AlarmState::Auto), engage.clone()]),
+        )?;
+        start.eta = Some(Time(0.));
+        start.eta_locked = Some(true);
+        let mut mission = vec![start];
+        let n = route.len();
+        for (i, p) in route.into_iter().enumerate() {
+            let point = if i + 2 < n {
+                waypoint(&land, p, transit.clone(), Task::ComboTask(vec![]))?
+            } else {
+                // the staging point and the objective itself
+                let task = Task::ComboTask(vec![alarm_state(AlarmState::Red), engage.clone()]);
+                waypoint(&land, p, VehicleFormation::OffRoad, task)?
+            };
+            mission.push(point);
+        }
+        if let Some(last) = mission.last_mut() {
+            last.name = Some(String::from("offensive"));
+        }
+        let con = dcs_group.get_controller()?;
+        con.set_task(Task::Mission {
+            airborne: Some(false),
+            route: mission,
+        })?;
+        Ok(true)
+    }
+
+    /// send a new offensive from side's best placed frontline objective
+    fn launch_offensive(
+        &mut self,
+        lua: MizLua,
+        spctx: &SpawnCtx,
+        idx: &MizIndex,
+        cfg: &OffensiveCfg,
+        side: Side,
+    ) -> Result<()> {
+        let active = self
+            .persisted
+            .offensives
+            .into_iter()
+            .filter(|(gid, _)| self.persisted.groups.get(gid).map(|g| g.side) == Some(side))
+            .count() as u32;
+        if active >= cfg.max_active {
+            return Ok(());
+        }
+        let troop = match cfg.troop.get(&side) {
+            None => return Ok(()),
+            Some(name) => self
+                .ephemeral
+                .cfg
+                .troops
+                .get(&side)
+                .and_then(|troops| troops.iter().find(|t| &t.name == name))
+                .ok_or_else(|| anyhow!("no troop called {name} on {side}"))?
+                .clone(),
+        };
+        // how many groups an objective has the supply and fuel to equip
+        let stock = |supply: u8, fuel: u8| -> u32 {
+            min(supply, fuel)
+                .checked_div(cfg.supply_per_group)
+                .map(|n| n as u32)
+                .unwrap_or(cfg.max_groups)
+        };
+        let best = self
+            .persisted
+            .objectives
+            .into_iter()
+            .filter(|(_, obj)| obj.owner == side && stock(obj.supply, obj.fuel) > 0)
+            .filter_map(|(oid, obj)| {
+                self.offensive_target(side, obj.zone.pos(), cfg.range as f64)
+                    .map(|(d, target)| (d, *oid, target))
+            })
+            .min_by(|(d0, _, _), (d1, _, _)| d0.total_cmp(d1));
+        let (origin, target) = match best {
+            None => return Ok(()),
+            Some((_, origin, target)) => (origin, target),
+        };
+        let obj = objective!(self, origin)?;
+        let n = min(
+            min(cfg.max_groups, cfg.max_active - active),
+            stock(obj.supply, obj.fuel),
+        );
+        if n == 0 {
+            return Ok(());
+        }
+        let from = obj.zone.pos();
+        let radius = obj.zone.radius();
+        let origin_name = obj.name.clone();
+        let to = objective!(self, target)?.zone.pos();
+        let target_name = objective!(self, target)?.name.clone();
+        let used = min(100, n * cfg.supply_per_group as u32) as u8;
+        if used > 0 {
+            self.consume_supply(lua, origin, used)
+                .with_context(|| format_compact!("equipping offensive from {origin_name}"))?;
+        }
+        let heading = azumith2d_to(from, to);
+        let dir = (to - from).normalize();
+        let across = Vector2::new(-dir.y, dir.x);
+        for i in 0..n {
+            let spread = (i as f64 - (n - 1) as f64 / 2.) * GROUP_SPACING;
+            let loc = SpawnLoc::AtPos {
+                pos: from + dir * radius + across * spread,
+                offset_direction: dir,
+                group_heading: heading,
+            };
+            // offensive groups belong to no player, they are tracked in
+            // persisted.offensives instead
+            let origin = DeployKind::Troop {
+                player: Ucid::default(),
+                moved_by: None,
+                spec: troop.clone(),
+                origin: Some(origin),
+                cost_fraction: 1.,
+            };
+            let gid = self
+                .add_and_queue_group(
+                    spctx,
+                    idx,
+                    side,
+                    loc,
+                    &troop.template,
+                    origin,
+                    BitFlags::from(UnitTag::Driveable),
+                    None,
+                )
+                .context("adding offensive group")?;
+            self.persisted.offensives.insert_cow(gid, target);
+        }
+        info!("{side} launched {n} groups from {origin_name} against {target_name}");
+        let msg = format_compact!("{origin_name} is launching an offensive against {target_name}");
+        self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// point groups whose target has already fallen at the next enemy
+    /// objective, or leave them to garrison it if there is nothing in range
+    fn retarget_offensives(&mut self, cfg: &OffensiveCfg) -> Result<()> {
+        let mut done: SmallVec<[(GroupId, Option<ObjectiveId>); 4]> = smallvec![];
+        for (gid, target) in &self.persisted.offensives {
+            let group = group!(self, gid)?;
+            // the target may also be a farp that has since been removed
+            let taken = objective!(self, target)
+                .map(|o| o.owner == group.side)
+                .unwrap_or(true);
+            if taken {
+                let pos = self.group_center(gid)?;
+                let next = self.offensive_target(group.side, pos, cfg.range as f64);
+                done.push((*gid, next.map(|(_, oid)| oid)));
+            }
+        }
+        for (gid, next) in done {
+            self.ephemeral.offensives_tasked.remove(&gid);
+            match next {
+                Some(oid) => self.persisted.offensives.insert_cow(gid, oid),
+                None => self.persisted.offensives.remove_cow(&gid),
+            };
+            self.ephemeral.dirty();
+        }
+        Ok(())
+    }
+
+    /// run the ai campaign commander. Groups in the field are kept
+    /// moving, and every interval each side may launch a new offensive.
+    pub fn run_offensives(
+        &mut self,
+        lua: MizLua,
+        idx: &MizIndex,
+        players: usize,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let gcfg = Arc::clone(&self.ephemeral.cfg);
+        let cfg = match gcfg.offensives.as_ref() {
+            Some(cfg) => cfg,
+            None => return Ok(()),
+        };
+        self.retarget_offensives(cfg)?;
+        let offensives = &self.persisted.offensives;
+        self.ephemeral
+            .offensives_tasked
+            .retain(|gid| offensives.get(gid).is_some());
+        for (gid, target) in &self.persisted.offensives {
+            if !self.ephemeral.offensives_tasked.contains(gid) {
+                match self.task_offensive(lua, cfg, gid, target) {
+                    Ok(true) => {
+                        self.ephemeral.offensives_tasked.insert(*gid);
+                    }
+                    Ok(false) => (),
+                    Err(e) => error!("could not task offensive group {gid} {e:?}"),
+                }
+            }
+        }
+        if now - self.ephemeral.last_offensive < Duration::seconds(cfg.interval as i64) {
+            return Ok(());
+        }
+        self.ephemeral.last_offensive = now;
+        if let Some(max) = cfg.max_players
+            && players > max as usize
+        {
+            return Ok(());
+        }
+        let spctx = SpawnCtx::new(lua)?;
+        for side in [Side::Red, Side::Blue] {
+            if let Err(e) = self.launch_offensive(lua, &spctx, idx, cfg, side) {
+                error!("could not launch {side} offensive {e:?}")
+            }
+        }
+        Ok(())
+    }
+}
@@ -15,17 +15,28 @@ for more details.
 */
 
 use super::{
+    csar::DownedPilot,
     group::{SpawnedGroup, SpawnedUnit},
-    objective::Objective,
+    objective::{ObjGroupClass, Objective},
     player::Player,
+    site::Site,
+    upkeep::Upkeep,
     Map, MapM, MapS, Set, SetM, SetS,
 };
-use bfprotocols::db::{
-    group::{GroupId, UnitId},
-    objective::ObjectiveId,
+use crate::{maybe, maybe_mut};
+use anyhow::{anyhow, bail, Result};
+use bfprotocols::{
+    cfg::{Cfg, UnitTag},
+    db::{
+        group::{GroupId, UnitId},
+        objective::ObjectiveId,
+    },
 };
-use dcso3::{coalition::Side, net::Ucid, String};
+use chrono::prelude::*;
+use compact_str::{CompactString, format_compact};
+use dcso3::{coalition::Side, net::Ucid, warehouse::LiquidType, String};
 use serde_derive::{Deserialize, Serialize};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Persisted {
@@ -58,12 +69,337 @@ pub struct Persisted {
     pub gid: i64,
     #[serde(default)]
     pub uid: i64,
+    /// when the current round started
     #[serde(default)]
-    pub migrated_v0: bool,
+    pub round_start: Option<DateTime<Utc>>,
+    /// the tickets each side has left, sides that aren't in here
+    /// still have their initial tickets
+    #[serde(default)]
+    pub tickets: MapS<Side, u32>,
+    /// ai offensive groups and the objective each one is attacking
+    #[serde(default)]
+    pub offensives: MapS<GroupId, ObjectiveId>,
+    /// supply convoys and their escorts that are on the road
+    #[serde(default)]
+    pub convoys: SetS<GroupId>,
+    /// downed pilots waiting to be rescued, by the group that stands
+    /// in for them
+    #[serde(default)]
+    pub csar: MapS<GroupId, DownedPilot>,
+    /// partially built staged deployables
+    #[serde(default)]
+    pub sites: MapS<u32, Site>,
+    /// the ammunition and fuel left to deployed groups that need upkeep
+    #[serde(default)]
+    pub upkeep: MapS<GroupId, Upkeep>,
+    /// the save format version, see migrate
+    #[serde(default)]
+    pub version: u32,
 }
 
 impl Persisted {
     pub fn players(&self) -> &Map<Ucid, Player> {
         &self.players
     }
+
+    /// the tickets side has left, or None if tickets are not in use
+    pub fn tickets(&self, cfg: &Cfg, side: Side) -> Option<u32> {
+        let tc = cfg.tickets.as_ref()?;
+        match side {
+            Side::Neutral => None,
+            Side::Red | Side::Blue => Some(self.tickets.get(&side).copied().unwrap_or(tc.initial)),
+        }
+    }
+
+    /// the (health, logi) of an objective computed from the state of
+    /// the owner's groups
+    pub(super) fn objective_status(&self, obj: &Objective) -> Result<(u8, u8)> {
+        obj.groups
+            .get(&obj.owner)
+            .map(|groups| {
+                let mut total = 0;
+                let mut alive = 0;
+                let mut logi_total = 0;
+                let mut logi_alive = 0;
+                for gid in groups {
+                    let group = maybe!(self.groups, gid, "group")?;
+                    let logi = match &group.class {
+                        ObjGroupClass::Logi => true,
+                        _ => false,
+                    };
+                    for uid in &group.units {
+                        let unit = maybe!(self.units, uid, "unit")?;
+                        if !unit.tags.contains(UnitTag::Invincible) {
+                            total += 1;
+                            if logi {
+                                logi_total += 1;
+                            }
+                            if !unit.dead {
+                                alive += 1;
+                                if logi {
+                                    logi_alive += 1;
+                                }
+                            }
+                        }
+                    }
+                }
+                let health = ((alive as f32 / total as f32) * 100.).trunc() as u8;
+                let logi = ((logi_alive as f32 / logi_total as f32) * 100.).trunc() as u8;
+                Ok((health, logi))
+            })
+            .unwrap_or(Ok((0, 0)))
+    }
+
+    /// find an objective by id or name
+    pub fn find_objective(&self, key: &str) -> Result<ObjectiveId> {
+        if let Ok(oid) = ObjectiveId::from_str(key)
+            && self.objectives.get(&oid).is_some()
+        {
+            return Ok(oid);
+        }
+        self.objectives_by_name
+            .get(key)
+            .copied()
+            .ok_or_else(|| anyhow!("no such objective {key}"))
+    }
+
+    /// find a player by ucid or name
+    pub fn find_player(&self, key: &str) -> Result<Ucid> {
+        if let Ok(ucid) = Ucid::from_str(key)
+            && self.players.get(&ucid).is_some()
+        {
+            return Ok(ucid);
+        }
+        let mut found = self
+            .players
+            .into_iter()
+            .filter(|(_, p)| p.name.as_str() == key || p.alts.contains(key));
+        match (found.next(), found.next()) {
+            (Some((ucid, _)), None) => Ok(*ucid),
+            (None, _) => bail!("no such player {key}"),
+            (Some(_), Some(_)) => bail!("more than one player is named {key}, use the ucid"),
+        }
+    }
+
+    /// change the owner of an objective outside of the game. If
+    /// repair is set the new owner's groups are brought back to life
+    /// where they spawned. The warehouse and supply lines aren't
+    /// touched, init_warehouses sets them up for the new owner when the
+    /// save is next loaded.
+    pub fn set_objective_owner(&mut self, oid: ObjectiveId, side: Side, repair: bool) -> Result<()> {
+        let obj = maybe_mut!(self.objectives, oid, "objective")?;
+        obj.owner = side;
+        obj.last_change_ts = Utc::now();
+        let gids: Vec<GroupId> = obj
+            .groups
+            .get(&side)
+            .map(|s| s.into_iter().copied().collect())
+            .unwrap_or_default();
+        if repair {
+            for gid in gids {
+                let uids = maybe!(self.groups, gid, "group")?.units.clone();
+                for uid in &uids {
+                    let unit = maybe_mut!(self.units, uid, "unit")?;
+                    unit.dead = false;
+                    unit.pos = unit.spawn_pos;
+                    unit.heading = unit.spawn_heading;
+                    unit.position = unit.spawn_position;
+                }
+            }
+        }
+        let (health, logi) = self.objective_status(maybe!(self.objectives, oid, "objective")?)?;
+        let obj = maybe_mut!(self.objectives, oid, "objective")?;
+        obj.health = health;
+        obj.logi = logi;
+        Ok(())
+    }
+
+    /// set the stored amount of an equipment item or liquid at an
+    /// objective. Items that aren't already in the warehouse can only
+    /// be added if a capacity is given.
+    pub fn set_inventory(
+        &mut self,
+        oid: ObjectiveId,
+        item: &str,
+        stored: u32,
+        capacity: Option<u32>,
+    ) -> Result<()> {
+        let obj = maybe_mut!(self.objectives, oid, "objective")?;
+        let liquid = LiquidType::ALL
+            .into_iter()
+            .find(|l| format_compact!("{l:?}").as_str() == item);
+        let current = match liquid {
+            Some(l) => obj.warehouse.liquids.get(&l),
+            None => obj.warehouse.equipment.get(item),
+        };
+        let capacity = match (capacity, current) {
+            (Some(capacity), _) => capacity,
+            (None, Some(inv)) => inv.capacity,
+            (None, None) => bail!("{} has no {item}, give a capacity to add it", obj.name),
+        };
+        if stored > capacity {
+            bail!("{item} stored {stored} is more than its capacity {capacity}")
+        }
+        let inv = match liquid {
+            Some(l) => obj.warehouse.liquids.get_or_default_cow(l),
+            None => obj
+                .warehouse
+                .equipment
+                .get_or_default_cow(String::from(item)),
+        };
+        inv.capacity = capacity;
+        inv.stored = stored;
+        Ok(())
+    }
+
+    pub fn set_player_points(&mut self, ucid: &Ucid, points: i32) -> Result<()> {
+        maybe_mut!(self.players, ucid, "player")?.points = points;
+        Ok(())
+    }
+
+    pub fn reset_player_lives(&mut self, ucid: &Ucid) -> Result<()> {
+        maybe_mut!(self.players, ucid, "player")?.lives = MapS::new();
+        Ok(())
+    }
+
+    /// check that the indexes agree with the groups, units, and
+    /// objectives they point to. Returns a description of each problem.
+    pub fn check(&self) -> Vec<CompactString> {
+        let mut problems = vec![];
+        for (gid, group) in &self.groups {
+            if group.id != *gid {
+                problems.push(format_compact!("group {gid} has id {}", group.id))
+            }
+            if self.groups_by_name.get(&group.name) != Some(gid) {
+                problems.push(format_compact!("group {gid} {} is not indexed by name", group.name))
+            }
+            let by_side = self.groups_by_side.get(&group.side);
+            if !by_side.map(|s| s.contains(gid)).unwrap_or(false) {
+                problems.push(format_compact!("group {gid} is not indexed by side"))
+            }
+            for uid in &group.units {
+                match self.units.get(uid) {
+                    None => problems.push(format_compact!("group {gid} has missing unit {uid}")),
+                    Some(unit) if unit.group != *gid => problems.push(format_compact!(
+                        "unit {uid} is in group {gid} but thinks it is in {}",
+                        unit.group
+                    )),
+                    Some(_) => (),
+                }
+            }
+        }
+        for (uid, unit) in &self.units {
+            if unit.id != *uid {
+                problems.push(format_compact!("unit {uid} has id {}", unit.id))
+            }
+            if self.units_by_name.get(&unit.name) != Some(uid) {
+                problems.push(format_compact!("unit {uid} {} is not indexed by name", unit.name))
+            }
+            if self.groups.get(&unit.group).is_none() {
+                problems.push(format_compact!("unit {uid} is in missing group {}", unit.group))
+            }
+        }
+        for (name, gid) in &self.groups_by_name {
+            if self.groups.get(gid).is_none() {
+                problems.push(format_compact!("group name {name} points to missing group {gid}"))
+            }
+        }
+        for (name, uid) in &self.units_by_name {
+            if self.units.get(uid).is_none() {
+                problems.push(format_compact!("unit name {name} points to missing unit {uid}"))
+            }
+        }
+        for (side, gids) in &self.groups_by_side {
+            for gid in gids {
+                if self.groups.get(gid).map(|g| g.side != *side).unwrap_or(true) {
+                    problems.push(format_compact!("{side} side index has stray group {gid}"))
+                }
+            }
+        }
+        let indexes: [(&str, Vec<&GroupId>); 8] = [
+            ("deployed", self.deployed.into_iter().collect()),
+            ("crates", self.crates.into_iter().collect()),
+            ("troops", self.troops.into_iter().collect()),
+            ("jtacs", self.jtacs.into_iter().collect()),
+            ("ewrs", self.ewrs.into_iter().collect()),
+            ("actions", self.actions.into_iter().collect()),
+            ("offensives", self.offensives.into_iter().map(|(gid, _)| gid).collect()),
+            ("convoys", self.convoys.into_iter().collect()),
+        ];
+        for (name, gids) in indexes {
+            for gid in gids {
+                if self.groups.get(gid).is_none() {
+                    problems.push(format_compact!("{name} has missing group {gid}"))
+                }
+            }
+        }
+        for (oid, obj) in &self.objectives {
+            if obj.id != *oid {
+                problems.push(format_compact!("objective {oid} has id {}", obj.id))
+            }
+            if self.objectives_by_name.get(&obj.name) != Some(oid) {
+                problems.push(format_compact!(
+                    "objective {oid} {} is not indexed by name",
+                    obj.name
+                ))
+            }
+            for (_, gids) in &obj.groups {
+                for gid in gids {
+                    if self.groups.get(gid).is_none() {
+                        problems.push(format_compact!("objective {oid} has missing group {gid}"))
+                    }
+                    if self.objectives_by_group.get(gid) != Some(oid) {
+                        problems.push(format_compact!(
+                            "objective {oid} group {gid} is not indexed by group"
+                        ))
+                    }
+                }
+            }
+        }
+        for (gid, oid) in &self.objectives_by_group {
+            if self.objectives.get(oid).is_none() {
+                problems.push(format_compact!("group {gid} points to missing objective {oid}"))
+            }
+        }
+        for oid in self.farps.into_iter().chain(&self.logistics_hubs) {
+            if self.objectives.get(oid).is_none() {
+                problems.push(format_compact!("missing objective {oid} is a farp or logistics hub"))
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::sim::test::{db, objective};
+    use bfprotocols::db::objective::ObjectiveKind;
+    use dcso3::Vector2;
+
+    #[test]
+    fn set_inventory_checks_capacity() {
+        let (mut db, _from_db) = db(Cfg::default());
+        let oid = objective(
+            &mut db,
+            "fob",
+            ObjectiveKind::Fob,
+            Side::Blue,
+            Vector2::zeros(),
+        );
+        let p = &mut db.persisted;
+        assert!(p.set_inventory(oid, "JetFuel", 10, None).is_err());
+        assert!(p.set_inventory(oid, "JetFuel", 10, Some(5)).is_err());
+        assert!(p.set_inventory(oid, "JetFuel", 5, Some(10)).is_ok());
+        assert!(p.set_inventory(oid, "JetFuel", 11, None).is_err());
+        assert!(p.set_inventory(oid, "JetFuel", 10, None).is_ok());
+        assert!(
+            p.set_inventory(oid, "weapons.bombs.Mk_82", 1, None)
+                .is_err()
+        );
+        let obj = &p.objectives[&oid];
+        assert_eq!(obj.warehouse.equipment.len(), 0);
+        let fuel = obj.warehouse.liquids[&LiquidType::JetFuel];
+        assert_eq!((fuel.stored, fuel.capacity), (10, 10));
+    }
 }
@@ -14,14 +14,14 @@ FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
 for more details.
 */
 
-use super::{Db, MapS, SetS, ephemeral::SlotInfo, group::DeployKind};
+use super::{Db, MapS, SetS, ephemeral::SlotInfo, griefing::GriefEntry, group::DeployKind};
 use crate::{maybe, maybe_mut, objective_mut};
 use anyhow::{Context, Result, anyhow, bail};
 use bfprotocols::{
-    cfg::{LifeType, PointsCfg, UnitTag, Vehicle},
+    cfg::{AssistCfg, LifeType, PointsCfg, UnitTag, Vehicle},
     db::{group::GroupId, objective::ObjectiveId},
-    shots::{Dead, Who},
-    stats::{self, EnId, Stat},
+    shots::{Credit, Dead, Shot, Who},
+    stats::{self, Debrief, EnId, SortieEnd, Stat},
 };
 use chrono::{Duration, prelude::*};
 use compact_str::{CompactString, format_compact};
@@ -47,11 +47,171 @@ struct VictimInfo {
     life_type: Option<LifeType>,
 }
 
+/// (player, provisional, credit, points)
+type Shares = SmallVec<[(Ucid, bool, Credit, i32); 16]>;
+
+fn valid_shots(dead: &Dead) -> impl DoubleEndedIterator<Item = &Shot> {
+    // why are you hitting yourself
+    dead.shots
+        .iter()
+        .filter(|shot| match (&shot.shooter, &shot.target) {
+            (Who::AI { gid: g0, .. }, Who::AI { gid: g1, .. }) => g0 != g1,
+            (Who::Player { ucid: u0, .. }, Who::Player { ucid: u1, .. }) => u0 != u1,
+            (
+                Who::AI {
+                    ucid: Some(u0),
+                    side: s0,
+                    ..
+                },
+                Who::Player {
+                    side: s1, ucid: u1, ..
+                },
+            ) => u0 != u1 && s0 != s1,
+            (Who::Player { ucid: u1, .. }, Who::AI { ucid: Some(u0), .. }) => u0 != u1,
+            (Who::AI { .. }, Who::Player { .. }) | (Who::Player { .. }, Who::AI { .. }) => true,
+        })
+}
+
+/// the player credited with a shot, and whether their points are provisional
+fn shooter(cfg: &PointsCfg, shot: &Shot) -> Option<(Ucid, bool)> {
+    match shot.shooter {
+        Who::Player { ucid, .. } => Some((ucid, cfg.provisional)),
+        Who::AI { ucid, .. } => ucid.map(|ucid| (ucid, false)),
+    }
+}
+
+/// everyone who hit the victim shares the points equally, the last
+/// to hit gets the kill and everyone else an assist
+fn equal_shares(cfg: &PointsCfg, dead: &Dead, total_points: u32) -> Shares {
+    let mut hit_by: SmallVec<[(Ucid, bool); 16]> = smallvec![];
+    for shot in valid_shots(dead) {
+        if let Some(k) = shooter(cfg, shot)
+            && shot.hit
+            && !hit_by.contains(&k)
+        {
+            hit_by.push(k)
+        }
+    }
+    if hit_by.is_empty() {
+        for shot in valid_shots(dead) {
+            if let Some(k) = shooter(cfg, shot)
+                && dead.time - shot.time <= Duration::minutes(3)
+                && !hit_by.contains(&k)
+            {
+                hit_by.push(k);
+            }
+        }
+    }
+    if hit_by.is_empty() {
+        return smallvec![];
+    }
+    let pps = (total_points as f32 / hit_by.len() as f32).ceil() as i32;
+    let killer = valid_shots(dead)
+        .rfind(|s| s.hit)
+        .or_else(|| valid_shots(dead).next_back())
+        .and_then(|s| s.shooter.ucid().copied());
+    hit_by
+        .into_iter()
+        .map(|(ucid, provisional)| {
+            let credit = if killer == Some(ucid) {
+                Credit::Kill
+            } else {
+                Credit::Damage
+            };
+            (ucid, provisional, credit, pps)
+        })
+        .collect()
+}
+
+/// split the points between the last player to hit the victim and
+/// the players who hit, shot at, or spotted it within the window
+fn assist_shares(cfg: &PointsCfg, acfg: &AssistCfg, dead: &Dead, total_points: u32) -> Shares {
+    let window = Duration::seconds(acfg.window as i64);
+    let recent = || valid_shots(dead).filter(move |s| dead.time - s.time <= window);
+    let killer = recent()
+        .filter(|s| s.hit)
+        .filter_map(|s| shooter(cfg, s))
+        .next_back();
+    let is_killer = |ucid: &Ucid| killer.is_some_and(|(k, _)| k == *ucid);
+    let mut hits: SmallVec<[(Ucid, bool, u32); 8]> = smallvec![];
+    for shot in recent().filter(|s| s.hit) {
+        if let Some((ucid, provisional)) = shooter(cfg, shot)
+            && !is_killer(&ucid)
+        {
+            match hits.iter_mut().find(|(u, _, _)| *u == ucid) {
+                Some((_, _, n)) => *n += 1,
+                None => hits.push((ucid, provisional, 1)),
+            }
+        }
+    }
+    let mut misses: SmallVec<[(Ucid, bool); 8]> = smallvec![];
+    for shot in recent().filter(|s| !s.hit) {
+        if let Some((ucid, provisional)) = shooter(cfg, shot)
+            && !is_killer(&ucid)
+            && !hits.iter().any(|(u, _, _)| *u == ucid)
+            && !misses.iter().any(|(u, _)| *u == ucid)
+        {
+            misses.push((ucid, provisional))
+        }
+    }
+    let spotters: SmallVec<[Ucid; 4]> = dead
+        .spotters
+        .iter()
+        .filter(|ucid| {
+            !is_killer(ucid)
+                && !hits.iter().any(|(u, _, _)| u == *ucid)
+                && !misses.iter().any(|(u, _)| u == *ucid)
+        })
+        .copied()
+        .collect();
+    // shares nobody earned go to the killer
+    let mut killer_pct = acfg.killer as u32;
+    if hits.is_empty() {
+        killer_pct += acfg.damage as u32
+    }
+    if misses.is_empty() {
+        killer_pct += acfg.support as u32
+    }
+    if spotters.is_empty() {
+        killer_pct += acfg.spotter as u32
+    }
+    let points = |pct: u32, n: u32, of: u32| {
+        (total_points as f32 * pct as f32 / 100. * n as f32 / of as f32).ceil() as i32
+    };
+    let mut shares = Shares::new();
+    if let Some((ucid, provisional)) = killer {
+        shares.push((ucid, provisional, Credit::Kill, points(killer_pct, 1, 1)))
+    }
+    let total_hits = hits.iter().map(|(_, _, n)| n).sum();
+    for (ucid, provisional, n) in hits {
+        let pps = points(acfg.damage as u32, n, total_hits);
+        shares.push((ucid, provisional, Credit::Damage, pps))
+    }
+    if acfg.support > 0 {
+        for (ucid, provisional) in &misses {
+            let pps = points(acfg.support as u32, 1, misses.len() as u32);
+            shares.push((*ucid, *provisional, Credit::Support, pps))
+        }
+    }
+    if acfg.spotter > 0 {
+        for ucid in &spotters {
+            let pps = points(acfg.spotter as u32, 1, spotters.len() as u32);
+            shares.push((*ucid, false, Credit::Spotting, pps))
+        }
+    }
+    shares
+}
+
 #[derive(Debug, Clone)]
 pub enum SlotAuth {
     Yes(Option<stats::Unit>),
     ObjectiveNotOwned(Side),
     ObjectiveHasNoLogistics,
+    RunwayDestroyed,
+    AirfieldClosed {
+        until: DateTime<Utc>,
+        repairs: u8,
+    },
     NoLives(LifeType),
     NoPoints {
         vehicle: Vehicle,
@@ -60,9 +220,11 @@ pub enum SlotAuth {
     },
     NotRegistered(Side),
     VehicleNotAvailable(Vehicle),
+    Blocked(DateTime<Utc>),
     Denied,
 }
 
+#[derive(Debug)]
 pub enum RegErr {
     AlreadyRegistered(Option<u8>, Side),
     AlreadyOn(Side),
@@ -105,6 +267,10 @@ pub struct Player {
     pub ai_team_kills: SetS<DateTime<Utc>>,
     #[serde(default)]
     pub player_team_kills: MapS<DateTime<Utc>, Ucid>,
+    #[serde(default)]
+    pub grief: MapS<u32, GriefEntry>,
+    #[serde(default)]
+    pub slot_blocked_until: Option<DateTime<Utc>>,
     #[serde(skip)]
     pub current_slot: Option<(SlotId, Option<InstancedPlayer>)>,
     #[serde(skip)]
@@ -113,10 +279,21 @@ pub struct Player {
     pub jtac_or_spectators: bool,
     #[serde(skip)]
     pub provisional_points: i32,
+    #[serde(skip)]
+    pub sortie: Option<Debrief>,
 }
 
+/// how long a sortie that ended in the air waits for kills by weapons
+/// that were still in flight
+const KILL_TIMEOUT: Duration = Duration::minutes(2);
+
 impl Db {
     pub fn player_deslot(&mut self, ucid: &Ucid) {
+        let airborne = self.player(ucid).is_some_and(|p| p.airborne.is_some());
+        self.end_sortie(ucid, Utc::now(), SortieEnd::Left);
+        if !airborne {
+            self.close_sortie(ucid);
+        }
         if let Some(player) = self.persisted.players.get_mut_cow(ucid) {
             player.airborne = None;
             player.provisional_points = 0;
@@ -130,6 +307,103 @@ impl Db {
         }
     }
 
+    /// update the player's current sortie, if they are on one
+    pub fn with_sortie<F: FnOnce(&mut Debrief)>(&mut self, ucid: &Ucid, f: F) {
+        if self
+            .persisted
+            .players
+            .get(ucid)
+            .is_some_and(|p| p.sortie.is_some())
+            && let Some(sortie) = self
+                .persisted
+                .players
+                .get_mut_cow(ucid)
+                .and_then(|p| p.sortie.as_mut())
+        {
+            f(sortie)
+        }
+    }
+
+    /// the player's sortie is over. Credit for it, e.g. kills by
+    /// weapons still in flight or crates unpacked after landing, is
+    /// still counted until takeoff, slot exit, or for a sortie that
+    /// ended in the air, the kill timeout
+    pub(super) fn end_sortie(&mut self, ucid: &Ucid, now: DateTime<Utc>, ended: SortieEnd) {
+        if self.ephemeral.sorties_ending.contains_key(ucid)
+            || self
+                .persisted
+                .players
+                .get(ucid)
+                .is_none_or(|p| p.sortie.is_none())
+        {
+            return;
+        }
+        if let Some(debrief) = self
+            .persisted
+            .players
+            .get_mut_cow(ucid)
+            .and_then(|p| p.sortie.as_mut())
+        {
+            debrief.end = now;
+            debrief.ended = ended;
+            let close_at = match ended {
+                SortieEnd::Landed => None,
+                SortieEnd::Died | SortieEnd::Left => Some(now + KILL_TIMEOUT),
+            };
+            self.ephemeral.sorties_ending.insert(*ucid, close_at);
+        }
+    }
+
+    /// close the debriefs of sorties that are done waiting for late kills
+    pub fn close_sorties(&mut self, now: DateTime<Utc>) {
+        let closed: SmallVec<[Ucid; 4]> = self
+            .ephemeral
+            .sorties_ending
+            .iter()
+            .filter(|(_, close_at)| close_at.is_some_and(|ts| ts <= now))
+            .map(|(ucid, _)| *ucid)
+            .collect();
+        for ucid in closed {
+            self.close_sortie(&ucid)
+        }
+    }
+
+    /// send the player the debrief of their ended sortie, and publish it
+    fn close_sortie(&mut self, ucid: &Ucid) {
+        if self.ephemeral.sorties_ending.remove(ucid).is_none() {
+            return;
+        }
+        let debrief = match self
+            .persisted
+            .players
+            .get_mut_cow(ucid)
+            .and_then(|p| p.sortie.take())
+        {
+            Some(debrief) => debrief,
+            None => return,
+        };
+        let msg = format_compact!(
+            "Sortie debrief, {} for {}\n\
+             kills {}, assists {}\n\
+             points earned {}, spent {}\n\
+             crates {}, troops {}, captures {}\n\
+             lives used {}",
+            debrief.vehicle,
+            crate::chatcmd::format_duration(debrief.end - debrief.takeoff),
+            debrief.kills,
+            debrief.assists,
+            debrief.points_earned,
+            debrief.points_spent,
+            debrief.crates,
+            debrief.troops,
+            debrief.captures,
+            debrief.lives_used
+        );
+        self.ephemeral
+            .panel_to_player(&self.persisted, 30, ucid, msg);
+        self.ephemeral.stat(Stat::Debrief { id: *ucid, debrief });
+    }
+
     pub fn player(&self, ucid: &Ucid) -> Option<&Player> {
         self.persisted.players.get(ucid)
     }
@@ -179,7 +453,9 @@ impl Db {
                     Ok(())
                 }
                 None => {
-                    self.persisted.players[source].points += amount as i32;
+                    if let Some(player) = self.persisted.players.get_mut_cow(source) {
+                        player.points += amount as i32;
+                    }
                     bail!("target player not found")
                 }
             },
@@ -195,7 +471,9 @@ impl Db {
                     Ok(())
                 }
                 None => {
-                    self.persisted.players[source].points += amount as i32;
+                    if let Some(player) = self.persisted.players.get_mut_cow(source) {
+                        player.points += amount as i32;
+                    }
                     bail!("target objective not found")
                 }
             },
@@ -258,6 +536,7 @@ impl Db {
                             DeployKind::Action { player, .. } => player.clone(),
                             DeployKind::Crate { .. }
                             | DeployKind::Objective { .. }
+                            | DeployKind::Convoy { .. }
                             | DeployKind::ObjectiveDeprecated => None,
                         })
                 }
@@ -331,6 +610,7 @@ impl Db {
             None => bail!("no life type for vehicle {:?}", sifo.typ),
             Some(typ) => *typ,
         };
+        let vehicle = sifo.typ.clone();
         let (_, player_lives) = player.lives.get_or_insert_cow(life_type, || {
             (time, self.ephemeral.cfg.default_lives[&life_type].0)
         });
@@ -361,14 +641,25 @@ impl Db {
         } else {
             Ok(TakeoffRes::NoLifeTaken)
         };
+        let owned_objective = owned_objective.map(|(id, _)| *id);
+        self.close_sortie(&ucid);
+        if let Some(player) = self.persisted.players.get_mut_cow(&ucid) {
+            let sortie = player
+                .sortie
+                .get_or_insert_with(|| Debrief::new(vehicle, time));
+            if let Ok(TakeoffRes::TookLife(_)) = &res {
+                sortie.lives_used += 1;
+            }
+        }
         if cost > 0
-            && let Some(oid) = owned_objective.map(|(id, _)| *id)
+            && let Some(oid) = owned_objective
         {
             let frac = self.charge_for_item(&ucid, oid, cost, cost_msg.as_str());
-            let player = &mut self.persisted.players[&ucid];
-            match &mut player.current_slot {
-                Some((_, Some(inst))) => inst.cost_fraction = frac,
-                _ => (),
+            if let Some(player) = self.persisted.players.get_mut_cow(&ucid) {
+                match &mut player.current_slot {
+                    Some((_, Some(inst))) => inst.cost_fraction = frac,
+                    _ => (),
+                }
             }
         };
         self.ephemeral.stat(Stat::Takeoff { id: ucid });
@@ -416,7 +707,7 @@ impl Db {
             obj.points += cost;
         }
         let cost = (cost as f32 * frac).round() as i32;
-        self.adjust_points(ucid, cost, msg);
+        self.change_points(ucid, cost, msg, true);
     }
 
     pub fn land(&mut self, slot: SlotId, position: Vector2, unit: &Unit) -> Option<LifeType> {
@@ -455,6 +746,9 @@ impl Db {
         self.ephemeral.stat(Stat::Land { id: ucid });
         if let Some(oid) = owned_objective {
             *player_lives += 1;
+            if let Some(sortie) = player.sortie.as_mut() {
+                sortie.lives_used = sortie.lives_used.saturating_sub(1);
+            }
             player.airborne = None;
             if *player_lives >= self.ephemeral.cfg.default_lives[&life_type].0 {
                 player.lives.remove_cow(&life_type);
@@ -483,6 +777,7 @@ impl Db {
                 }
             }
             self.ephemeral.dirty();
+            self.end_sortie(&ucid, Utc::now(), SortieEnd::Landed);
             if !self.ephemeral.cfg.limited_lives {
                 None
             } else {
@@ -544,6 +839,13 @@ impl Db {
             player.jtac_or_spectators = true;
             return SlotAuth::Yes(None);
         }
+        if let Some(until) = self.slot_blocked(ucid, time) {
+            return SlotAuth::Blocked(until);
+        }
+        let player = match self.persisted.players.get_mut_cow(ucid) {
+            Some(player) => player,
+            None => return SlotAuth::NotRegistered(slot_side),
+        };
         if slot_side != player.side {
             if self.ephemeral.cfg.lock_sides {
                 return SlotAuth::ObjectiveNotOwned(player.side);
@@ -612,23 +914,41 @@ impl Db {
         if objective.captureable() {
             return SlotAuth::ObjectiveHasNoLogistics;
         }
+        if !objective.runway_usable() {
+            let tags = self
+                .ephemeral
+                .cfg
+                .unit_classification
+                .get(&sifo.typ)
+                .copied()
+                .unwrap_or_default();
+            if !tags.contains(UnitTag::Helicopter) {
+                if let Some(until) = objective.runway.closed_until {
+                    return SlotAuth::AirfieldClosed {
+                        until,
+                        repairs: objective.runway.repairs_needed,
+                    };
+                }
+                return SlotAuth::RunwayDestroyed;
+            }
+        }
         let life_type = self.ephemeral.cfg.life_types[&sifo.typ];
         macro_rules! yes {
-            () => {
+            () => {{
                 if let Some(whcfg) = self.ephemeral.cfg.warehouse.as_ref() {
                     let typ = sifo.typ.as_str();
                     if !whcfg.exempt_airframes.contains(typ) {
                         match objective.warehouse.equipment.get(typ) {
                             Some(inv) if inv.stored > 0 => (),
                             Some(_) | None => {
-                                break SlotAuth::VehicleNotAvailable(sifo.typ.clone());
+                                return SlotAuth::VehicleNotAvailable(sifo.typ.clone());
                             }
                         }
                     }
                 }
                 player.changing_slots = false;
                 player.jtac_or_spectators = false;
-                break SlotAuth::Yes(Some(stats::Unit {
+                return SlotAuth::Yes(Some(stats::Unit {
                     typ: sifo.typ.clone(),
                     tags: self
                         .ephemeral
@@ -637,8 +957,8 @@ impl Db {
                         .get(&sifo.typ)
                         .map(|t| *t)
                         .unwrap_or_default(),
-                }));
-            };
+                }))
+            }};
         }
         if let Some(points) = self.ephemeral.cfg.points.as_ref() {
             let cost = *points.airframe_cost.get(&sifo.typ).unwrap_or(&0) as i32;
@@ -651,26 +971,22 @@ impl Db {
                 };
             }
         }
-        loop {
-            match player.lives.get(&life_type).map(|t| *t) {
-                None => {
-                    yes!();
-                }
-                Some((reset, n)) => {
-                    let reset_after =
-                        Duration::seconds(self.ephemeral.cfg.default_lives[&life_type].1 as i64);
-                    if time - reset >= reset_after {
-                        player.lives.remove_cow(&life_type);
-                        self.ephemeral.stat(Stat::Life {
-                            id: *ucid,
-                            lives: player.lives.clone(),
-                        });
-                        self.ephemeral.dirty = true;
-                    } else if n == 0 {
-                        break SlotAuth::NoLives(life_type);
-                    }
-                    yes!();
+        match player.lives.get(&life_type).map(|t| *t) {
+            None => yes!(),
+            Some((reset, n)) => {
+                let reset_after =
+                    Duration::seconds(self.ephemeral.cfg.default_lives[&life_type].1 as i64);
+                if time - reset >= reset_after {
+                    player.lives.remove_cow(&life_type);
+                    self.ephemeral.stat(Stat::Life {
+                        id: *ucid,
+                        lives: player.lives.clone(),
+                    });
+                    self.ephemeral.dirty = true;
+                } else if n == 0 {
+                    return SlotAuth::NoLives(life_type);
                 }
+                yes!()
             }
         }
     }
@@ -714,11 +1030,14 @@ impl Db {
                         airborne: None,
                         points,
                         provisional_points: 0,
+                        sortie: None,
                         current_slot: None,
                         changing_slots: false,
                         jtac_or_spectators: true,
                         ai_team_kills: SetS::new(),
                         player_team_kills: MapS::new(),
+                        grief: MapS::new(),
+                        slot_blocked_until: None,
                     },
                 );
                 self.ephemeral.stat(Stat::Register {
@@ -1044,8 +1363,8 @@ impl Db {
         shooter: Ucid,
         total_points: u32,
         victim_info: &Option<VictimInfo>,
-    ) -> CompactString {
-        let player = &mut self.persisted.players[&shooter];
+    ) -> Option<CompactString> {
+        let player = self.persisted.players.get_mut_cow(&shooter)?;
         let window = self
             .ephemeral
             .cfg
@@ -1054,7 +1373,7 @@ impl Db {
             .map(|p| p.tk_window as i64)
             .unwrap_or(0);
         let now = Utc::now();
-        match victim_info.as_ref() {
+        let msg = match victim_info.as_ref() {
             None => {
                 let penalty: u32 = player
                     .ai_team_kills
@@ -1166,133 +1485,123 @@ impl Db {
                 }
                 msg
             }
-        }
+        };
+        Some(msg)
     }
 
-    pub fn award_kill_points(&mut self, cfg: &PointsCfg, dead: &Dead) {
-        let mut hit_by: SmallVec<[(Ucid, bool); 16]> = smallvec![];
-        let valid_shots = || {
-            // why are you hitting yourself
-            dead.shots
-                .iter()
-                .filter(|shot| match (&shot.shooter, &shot.target) {
-                    (Who::AI { gid: g0, .. }, Who::AI { gid: g1, .. }) => g0 != g1,
-                    (Who::Player { ucid: u0, .. }, Who::Player { ucid: u1, .. }) => u0 != u1,
-                    (
-                        Who::AI {
-                            ucid: Some(u0),
-                            side: s0,
-                            ..
-                        },
-                        Who::Player {
-                            side: s1, ucid: u1, ..
-                        },
-                    ) => u0 != u1 && s0 != s1,
-                    (Who::Player { ucid: u1, .. }, Who::AI { ucid: Some(u0), .. }) => u0 != u1,
-                    (Who::AI { .. }, Who::Player { .. }) | (Who::Player { .. }, Who::AI { .. }) => {
-                        true
-                    }
-                })
-        };
-        for shot in valid_shots() {
-            let k = match shot.shooter {
-                Who::Player { ucid, .. } => (ucid, cfg.provisional),
-                Who::AI { ucid, .. } => match ucid {
-                    Some(ucid) => (ucid, false),
-                    None => continue,
-                },
-            };
-            if shot.hit && !hit_by.contains(&k) {
-                hit_by.push(k)
-            }
-        }
-        if hit_by.is_empty() {
-            for shot in valid_shots() {
-                let k = match shot.shooter {
-                    Who::Player { ucid, .. } => (ucid, cfg.provisional),
-                    Who::AI { ucid, .. } => match ucid {
-                        Some(ucid) => (ucid, false),
-                        None => continue,
-                    },
-                };
-                if dead.time - shot.time <= Duration::minutes(3) && !hit_by.contains(&k) {
-                    hit_by.push(k);
+    pub fn award_kill_points(&mut self, cfg: &PointsCfg, dead: &mut Dead) {
+        let victim_typ = dead
+            .shots
+            .iter()
+            .find(|s| s.target_typ.trim() != "")
+            .map(|s| s.target_typ.clone());
+        let total_points = victim_typ
+            .as_ref()
+            .and_then(|typ| self.ephemeral.cfg.unit_classification.get(typ.as_str()))
+            .map(|tags| {
+                if tags.contains(UnitTag::LR | UnitTag::TrackRadar | UnitTag::SAM) {
+                    cfg.ground_kill + cfg.lr_sam_bonus
+                } else if tags.contains(UnitTag::Aircraft) || tags.contains(UnitTag::Helicopter) {
+                    cfg.air_kill
+                } else {
+                    cfg.ground_kill
                 }
-            }
-        }
-        if !hit_by.is_empty() {
-            let total_points = (&dead.shots)
-                .into_iter()
-                .find(|s| s.target_typ.trim() != "")
-                .map(|s| &s.target_typ)
-                .and_then(|typ| self.ephemeral.cfg.unit_classification.get(typ.as_str()))
-                .map(|tags| {
-                    if tags.contains(UnitTag::LR | UnitTag::TrackRadar | UnitTag::SAM) {
-                        cfg.ground_kill + cfg.lr_sam_bonus
-                    } else if tags.contains(UnitTag::Aircraft) || tags.contains(UnitTag::Helicopter)
-                    {
-                        cfg.air_kill
-                    } else {
-                        cfg.ground_kill
+            })
+            .unwrap_or(cfg.ground_kill);
+        let shares = match cfg.assists.as_ref() {
+            None => equal_shares(cfg, dead, total_points),
+            Some(acfg) => assist_shares(cfg, acfg, dead, total_points),
+        };
+        let victim_info = match &dead.victim {
+            Who::Player { ucid, .. } => self.persisted.players.get(ucid).map(|p| VictimInfo {
+                ucid: *ucid,
+                name: p.name.clone(),
+                life_type: p.airborne,
+                ai_deployable: false,
+            }),
+            Who::AI { ucid: None, .. } => None,
+            Who::AI { ucid: Some(i), .. } => self.persisted.players.get(i).map(|p| VictimInfo {
+                ucid: *i,
+                name: p.name.clone(),
+                life_type: None,
+                ai_deployable: true,
+            }),
+        };
+        for (ucid, provisional, credit, pps) in shares {
+            if let Some(player) = self.persisted.players.get_mut_cow(&ucid) {
+                let msg = if player.side == *dead.victim.side() {
+                    match credit {
+                        Credit::Kill | Credit::Damage => {
+                            match self.apply_teamkill_penalty(ucid, total_points, &victim_info) {
+                                Some(msg) => msg,
+                                None => continue,
+                            }
+                        }
+                        Credit::Support | Credit::Spotting => continue,
                     }
-                })
-                .unwrap_or(cfg.ground_kill);
-            let pps = (total_points as f32 / hit_by.len() as f32).ceil() as i32;
-            let victim_info = match &dead.victim {
-                Who::Player { ucid, .. } => self.persisted.players.get(ucid).map(|p| VictimInfo {
-                    ucid: *ucid,
-                    name: p.name.clone(),
-                    life_type: p.airborne,
-                    ai_deployable: false,
-                }),
-                Who::AI { ucid: None, .. } => None,
-                Who::AI { ucid: Some(i), .. } => {
-                    self.persisted.players.get(i).map(|p| VictimInfo {
-                        ucid: *i,
-                        name: p.name.clone(),
-                        life_type: None,
-                        ai_deployable: true,
-                    })
-                }
-            };
-            for (ucid, provisional) in hit_by {
-                if let Some(player) = self.persisted.players.get_mut_cow(&ucid) {
-                    let msg = if player.side == *dead.victim.side() {
-                        self.apply_teamkill_penalty(ucid, total_points, &victim_info)
-                    } else {
-                        let tp = if provisional {
-                            player.provisional_points += pps;
-                            player.provisional_points
+                } else {
+                    dead.credit.push((ucid, credit));
+                    if let Some(sortie) = player.sortie.as_mut() {
+                        if credit == Credit::Kill {
+                            sortie.kills += 1
                         } else {
-                            player.points += pps;
-                            player.points
-                        };
-                        let pm = if provisional { " provisional" } else { "" };
-                        match &victim_info {
-                            None => format_compact!("{tp}(+{pps}){pm} points"),
-                            Some(vi) => {
-                                if vi.ai_deployable {
-                                    format_compact!(
-                                        "{tp}(+{pps}){pm} points, killed {}'s deployed ai unit",
-                                        vi.name
-                                    )
-                                } else {
-                                    format_compact!("{tp}(+{pps}){pm} points, killed {}", vi.name)
-                                }
-                            }
+                            sortie.assists += 1
+                        }
+                        if !provisional {
+                            sortie.points_earned += pps as u32
                         }
+                    }
+                    let tp = if provisional {
+                        player.provisional_points += pps;
+                        player.provisional_points
+                    } else {
+                        player.points += pps;
+                        player.points
                     };
-                    debug!("{ucid} kill message: {msg}");
-                    self.ephemeral
-                        .panel_to_player(&self.persisted, 10, &ucid, msg)
-                }
+                    let pm = if provisional { " provisional" } else { "" };
+                    let what = match credit {
+                        Credit::Kill => "killed",
+                        Credit::Damage | Credit::Support => "assisted killing",
+                        Credit::Spotting => "spotted",
+                    };
+                    match &victim_info {
+                        None => format_compact!("{tp}(+{pps}){pm} points"),
+                        Some(vi) => {
+                            if vi.ai_deployable {
+                                format_compact!(
+                                    "{tp}(+{pps}){pm} points, {what} {}'s deployed ai unit",
+                                    vi.name
+                                )
+                            } else {
+                                format_compact!("{tp}(+{pps}){pm} points, {what} {}", vi.name)
+                            }
+                        }
+                    }
+                };
+                debug!("{ucid} kill message: {msg}");
+                self.ephemeral
+                    .panel_to_player(&self.persisted, 10, &ucid, msg)
             }
         }
     }
 
     pub fn adjust_points(&mut self, ucid: &Ucid, amount: i32, why: &str) {
+        self.change_points(ucid, amount, why, false)
+    }
+
+    /// a refund gives back points spent on the sortie, it isn't earned
+    fn change_points(&mut self, ucid: &Ucid, amount: i32, why: &str, refund: bool) {
         if let Some(player) = self.persisted.players.get_mut_cow(ucid) {
             player.points += amount;
+            if let Some(sortie) = player.sortie.as_mut() {
+                if refund {
+                    sortie.points_spent = sortie.points_spent.saturating_sub(amount.unsigned_abs())
+                } else if amount > 0 {
+                    sortie.points_earned += amount as u32
+                } else {
+                    sortie.points_spent += amount.unsigned_abs()
+                }
+            }
             let pp = player.points;
             if amount != 0 {
                 let m = format_compact!("{}({}) points {}", pp, amount, why);
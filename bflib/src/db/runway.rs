@@ -0,0 +1,342 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use super::Db;
+use crate::{objective, objective_mut};
+use anyhow::{Context, Result, anyhow};
+use bfprotocols::{db::objective::ObjectiveId, stats::Stat};
+use chrono::{Duration, prelude::*};
+use compact_str::format_compact;
+use dcso3::{
+    LuaVec2, MizLua, Vector2, Vector3,
+    airbase::{Airbase, Runway},
+    coalition::Side,
+    event::Shot,
+    land::Land,
+    net::Ucid,
+    object::DcsObject,
+    weapon::Weapon,
+};
+use log::error;
+use smallvec::SmallVec;
+
+/// a weapon that may crater a runway, followed until it hits the ground
+#[derive(Debug, Clone)]
+pub(super) struct FallingWeapon {
+    side: Side,
+    by: Option<Ucid>,
+    pos: Vector3,
+    velocity: Vector3,
+}
+
+impl FallingWeapon {
+    /// where the weapon hit the ground, extrapolated from the last
+    /// time it was seen
+    fn impact(&self, land: &Land) -> Result<Vector2> {
+        let pos = Vector2::new(self.pos.x, self.pos.z);
+        let ground = land.get_height(LuaVec2(pos))?;
+        let t = if self.velocity.y < -1. {
+            // it was last seen up to a second before it hit
+            ((self.pos.y - ground) / -self.velocity.y).clamp(0., 1.5)
+        } else {
+            0.
+        };
+        Ok(pos + Vector2::new(self.velocity.x, self.velocity.z) * t)
+    }
+}
+
+fn on_runway(rw: &Runway, pos: Vector2) -> Result<bool> {
+    let center = rw.position()?;
+    // dcs reports the course as the negative of the runway heading
+    let heading = -rw.course()?;
+    let dir = Vector2::new(heading.cos(), heading.sin());
+    let d = pos - Vector2::new(center.x, center.z);
+    Ok(d.dot(&dir).abs() <= rw.length()? / 2. && d.perp(&dir).abs() <= rw.width()? / 2.)
+}
+
+impl Db {
+    /// follow a weapon that may crater a runway
+    pub fn weapon_fired(&mut self, e: &Shot) -> Result<()> {
+        let cfg = match self.ephemeral.cfg.cratering.as_ref() {
+            Some(cfg) => cfg,
+            None => return Ok(()),
+        };
+        if !cfg.weapons.contains(&e.weapon_name) {
+            return Ok(());
+        }
+        let id = e.initiator.object_id()?;
+        let (side, by) = match self.player_in_unit(false, &id) {
+            Some(ucid) => (self.player(&ucid).map(|p| p.side), Some(ucid)),
+            None => (
+                self.ephemeral
+                    .get_uid_by_object_id(&id)
+                    .and_then(|uid| self.persisted.units.get(uid))
+                    .map(|u| u.side),
+                None,
+            ),
+        };
+        let side = match side {
+            Some(side) => side,
+            None => return Ok(()),
+        };
+        let obj = e.weapon.as_object()?;
+        let weapon = FallingWeapon {
+            side,
+            by,
+            pos: obj.get_point()?.0,
+            velocity: obj.get_velocity()?.0,
+        };
+        self.ephemeral
+            .falling_weapons
+            .insert(e.weapon.object_id()?, weapon);
+        Ok(())
+    }
+
+    /// the enemy airbase whose runway pos is on, if any
+    fn runway_at(&self, lua: MizLua, side: Side, pos: Vector2) -> Result<Option<ObjectiveId>> {
+        for (oid, obj) in &self.persisted.objectives {
+            if obj.owner != side
+                && obj.owner != Side::Neutral
+                && obj.is_airbase()
+                && obj.zone.contains(pos)
+                && let Some(id) = self.ephemeral.airbase_by_oid.get(oid)
+            {
+                let airbase = Airbase::get_instance(lua, id).context("getting airbase")?;
+                for rw in airbase.get_runways().context("getting runways")? {
+                    if on_runway(&rw?, pos)? {
+                        return Ok(Some(*oid));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn crater_runway(
+        &mut self,
+        oid: ObjectiveId,
+        by: Option<Ucid>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let cfg = self
+            .ephemeral
+            .cfg
+            .cratering
+            .clone()
+            .ok_or_else(|| anyhow!("cratering is not configured"))?;
+        let obj = objective_mut!(self, oid)?;
+        obj.runway.craters = obj.runway.craters.saturating_add(1);
+        obj.runway.last_crater = Some(now);
+        obj.runway.seq = obj.runway.seq.wrapping_add(1);
+        let craters = obj.runway.craters;
+        self.ephemeral.stat(Stat::RunwayCratered {
+            id: oid,
+            by,
+            craters,
+            seq: obj.runway.seq,
+        });
+        // more craters on a closed airfield restart the closure
+        if craters >= cfg.craters {
+            let closed_until = now + Duration::minutes(cfg.closed_for as i64);
+            let obj = objective_mut!(self, oid)?;
+            let reclosed = obj.runway.closed_until.is_some();
+            obj.runway.closed_until = Some(closed_until);
+            obj.runway.repairs_needed = cfg.repair_crates;
+            if !reclosed {
+                let msg = format_compact!(
+                    "the runway at {} is cratered, the airfield is closed for {} minutes",
+                    obj.name,
+                    cfg.closed_for
+                );
+                self.ephemeral.msgs().panel_to_all(10, false, msg);
+            }
+            self.ephemeral.stat(Stat::AirfieldClosed {
+                id: oid,
+                closed_until: Some(closed_until),
+            });
+        }
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    fn reopen_airfield(&mut self, oid: ObjectiveId) -> Result<()> {
+        let obj = objective_mut!(self, oid)?;
+        obj.runway.clear();
+        let msg = format_compact!(
+            "the runway at {} is repaired, the airfield is open",
+            obj.name
+        );
+        self.ephemeral.msgs().panel_to_all(10, false, msg);
+        self.ephemeral.stat(Stat::AirfieldClosed {
+            id: oid,
+            closed_until: None,
+        });
+        self.ephemeral.dirty();
+        Ok(())
+    }
+
+    /// a player delivered a repair crate to a closed airfield, returns
+    /// how many more are needed to reopen it
+    pub fn runway_repair(&mut self, ucid: &Ucid, oid: ObjectiveId) -> Result<u8> {
+        let obj = objective_mut!(self, oid)?;
+        obj.runway.repairs_needed = obj.runway.repairs_needed.saturating_sub(1);
+        let needed = obj.runway.repairs_needed;
+        self.ephemeral.stat(Stat::Repair { id: oid, by: *ucid });
+        if let Some(amount) = self
+            .ephemeral
+            .cfg
+            .points
+            .as_ref()
+            .map(|p| p.logistics_repair)
+        {
+            self.adjust_points(ucid, amount as i32, "for runway repair");
+        }
+        if needed == 0 {
+            self.reopen_airfield(oid)?;
+        } else {
+            self.ephemeral.dirty();
+        }
+        Ok(needed)
+    }
+
+    /// fill in one crater on each open runway that hasn't been hit for
+    /// the decay period
+    fn decay_craters(&mut self, now: DateTime<Utc>) -> Result<()> {
+        let decay = match self
+            .ephemeral
+            .cfg
+            .cratering
+            .as_ref()
+            .and_then(|c| c.crater_decay)
+        {
+            Some(decay) => Duration::minutes(decay as i64),
+            None => return Ok(()),
+        };
+        let decayed: SmallVec<[ObjectiveId; 4]> = self
+            .persisted
+            .objectives
+            .into_iter()
+            .filter(|(_, obj)| {
+                obj.runway.craters > 0
+                    && obj.runway.closed_until.is_none()
+                    && obj.runway.last_crater.is_none_or(|ts| now - ts >= decay)
+            })
+            .map(|(oid, _)| *oid)
+            .collect();
+        for oid in decayed {
+            let obj = objective_mut!(self, oid)?;
+            obj.runway.craters -= 1;
+            obj.runway.last_crater = Some(now);
+            self.ephemeral.dirty();
+        }
+        Ok(())
+    }
+
+    /// follow falling weapons, crater the runways they hit, reopen
+    /// airfields whose closure is over, and fill in old craters
+    pub fn run_cratering(&mut self, lua: MizLua, now: DateTime<Utc>) -> Result<()> {
+        let reopen: SmallVec<[ObjectiveId; 4]> = self
+            .persisted
+            .objectives
+            .into_iter()
+            .filter(|(_, obj)| obj.runway.closed_until.is_some_and(|ts| ts <= now))
+            .map(|(oid, _)| *oid)
+            .collect();
+        for oid in reopen {
+            self.reopen_airfield(oid)?;
+        }
+        self.decay_craters(now)?;
+        if self.ephemeral.falling_weapons.is_empty() {
+            return Ok(());
+        }
+        let mut landed: SmallVec<[FallingWeapon; 8]> = SmallVec::new();
+        self.ephemeral.falling_weapons.retain(|id, w| {
+            let obj = match Weapon::get_instance(lua, id).and_then(|w| w.as_object()) {
+                Ok(obj) => obj,
+                Err(_) => {
+                    landed.push(w.clone());
+                    return false;
+                }
+            };
+            match (obj.get_point(), obj.get_velocity()) {
+                (Ok(pos), Ok(velocity)) => {
+                    w.pos = pos.0;
+                    w.velocity = velocity.0;
+                    true
+                }
+                (Err(_), _) | (_, Err(_)) => false,
+            }
+        });
+        let land = Land::singleton(lua)?;
+        for w in landed {
+            if let Err(e) = self.weapon_landed(lua, &land, &w, now) {
+                error!("could not crater a runway with {w:?} {e:?}")
+            }
+        }
+        Ok(())
+    }
+
+    fn weapon_landed(
+        &mut self,
+        lua: MizLua,
+        land: &Land,
+        w: &FallingWeapon,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let pos = w.impact(land)?;
+        if let Some(oid) = self.runway_at(lua, w.side, pos)? {
+            self.crater_runway(oid, w.by, now)?;
+            let obj = objective!(self, oid)?;
+            if let Some(ucid) = w.by {
+                let msg = format_compact!("you hit the runway at {}", obj.name);
+                self.ephemeral
+                    .panel_to_player(&self.persisted, 10, &ucid, msg);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::sim::test::{db, group, objective, objective_owner, sim, troops, ucid};
+    use bfprotocols::{cfg::Cfg, db::objective::ObjectiveKind};
+
+    #[test]
+    fn capture_opens_a_cratered_runway() {
+        let (mut db, from_db) = db(Cfg::default());
+        let player = ucid(1);
+        db.register_player(player, "blue".into(), Side::Blue)
+            .unwrap();
+        let pos = Vector2::new(20000., 0.);
+        let oid = objective(&mut db, "target", ObjectiveKind::Fob, Side::Red, pos);
+        let obj = db.persisted.objectives.get_mut_cow(&oid).unwrap();
+        obj.logi = 0;
+        obj.runway.craters = 3;
+        obj.runway.seq = 3;
+        obj.runway.closed_until = Some(Utc::now() + Duration::hours(1));
+        let origin = troops(&db, Side::Blue, player);
+        group(&mut db, Side::Blue, origin, pos, 4);
+        let mut sim = sim(db, from_db);
+        sim.step(Duration::seconds(60)).unwrap();
+        assert_eq!(objective_owner(&sim, "target"), Side::Blue);
+        let runway = &sim.db.persisted.objectives[&oid].runway;
+        assert_eq!(runway.craters, 0);
+        assert!(runway.closed_until.is_none());
+        assert_eq!(runway.seq, 3);
+    }
+}
@@ -0,0 +1,691 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! A headless campaign harness. The sim loads a save file and a
+//! config, and then drives the parts of the campaign that don't need
+//! dcs (logistics, repairs, captures, victory) on a simulated
+//! clock. Scripted events stand in for the things players and dcs
+//! would normally do.
+
+use super::{
+    Db,
+    ephemeral::{Equipment, Production},
+    logistics::LogiStage,
+    player::RegErr,
+};
+use crate::{bg::Task, msgq::MsgQ, objective, unit};
+use anyhow::{Context, Result, anyhow, bail};
+use bfprotocols::{
+    cfg::{Cfg, UnitTag},
+    db::{group::UnitId, objective::ObjectiveId},
+    perf::PerfInner,
+    shots::{Dead, Who},
+    stats::{RoundEndReason, Stat},
+};
+use chrono::{Duration, prelude::*};
+use dcso3::{String, coalition::Side, net::Ucid};
+use fxhash::FxHashMap;
+use log::error;
+use serde_derive::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::{
+    cmp::max,
+    fs::File,
+    io::{BufWriter, Write},
+    mem,
+    path::Path,
+    sync::Arc,
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SimEvent {
+    /// register a new player
+    Register {
+        ucid: Ucid,
+        name: String,
+        side: Side,
+    },
+    /// adjust a player's points
+    Points {
+        ucid: Ucid,
+        amount: i32,
+        reason: String,
+    },
+    /// a kill, scored exactly as it would be in game. If the victim
+    /// is an ai unit it will also be marked dead.
+    Kill(Dead),
+    /// a unit died with no one to credit
+    UnitDead { unit: String },
+    /// kill percent of the remaining units at an objective
+    Damage { objective: String, percent: u8 },
+    /// a player unpacked a logistics repair crate at an objective
+    LogisticsRepair { objective: String, by: Ucid },
+    /// side captured an objective, crediting the players in by
+    Capture {
+        objective: String,
+        side: Side,
+        #[serde(default)]
+        by: SmallVec<[Ucid; 1]>,
+    },
+    /// run a logistics tick and a production delivery immediately
+    DeliverNow,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptedEvent {
+    /// seconds after the start of the simulation
+    pub at: u64,
+    pub event: SimEvent,
+}
+
+fn default_step() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimScript {
+    /// the simulated start time, defaults to now
+    #[serde(default)]
+    pub start: Option<DateTime<Utc>>,
+    /// the length of a simulation step in seconds
+    #[serde(default = "default_step")]
+    pub step: u64,
+    /// how long to run in seconds
+    pub duration: u64,
+    /// stop as soon as a side has met the victory condition
+    #[serde(default)]
+    pub stop_on_victory: bool,
+    #[serde(default)]
+    pub events: Vec<ScriptedEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimObjective {
+    pub name: String,
+    pub owner: Side,
+    pub health: u8,
+    pub logi: u8,
+    pub supply: u8,
+    pub fuel: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimPlayer {
+    pub name: String,
+    pub side: Side,
+    pub points: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimReport {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub victory: Option<(DateTime<Utc>, Side, RoundEndReason)>,
+    pub objectives: Vec<SimObjective>,
+    pub players: Vec<SimPlayer>,
+    pub stats: Vec<(DateTime<Utc>, Stat)>,
+}
+
+impl Db {
+    /// Without dcs there is no warehouse resource map to read
+    /// production from, so reconstruct it from the capacity of the
+    /// logistics hubs in the save file.
+    fn init_headless_production(&mut self) -> Result<()> {
+        let whcfg = match self.ephemeral.cfg.warehouse.as_ref() {
+            Some(w) => w,
+            None => return Ok(()),
+        };
+        if whcfg.hub_max == 0 {
+            bail!("hub_max must be greater than 0")
+        }
+        let mut production: FxHashMap<Side, Production> = FxHashMap::default();
+        for lid in &self.persisted.logistics_hubs {
+            let hub = objective!(self, lid)?;
+            let prod = production.entry(hub.owner).or_default();
+            for (name, inv) in &hub.warehouse.equipment {
+                let qty = inv.capacity / whcfg.hub_max;
+                if qty > 0 {
+                    let eq = prod
+                        .equipment
+                        .entry(name.clone())
+                        .or_insert(Equipment { production: 0 });
+                    eq.production = max(eq.production, qty);
+                }
+            }
+            for (name, inv) in &hub.warehouse.liquids {
+                let qty = inv.capacity / whcfg.hub_max;
+                if qty > 0 {
+                    let l = prod.liquids.entry(*name).or_default();
+                    *l = max(*l, qty);
+                }
+            }
+        }
+        self.ephemeral.production_by_side = production
+            .into_iter()
+            .map(|(side, prod)| (side, Arc::new(prod)))
+            .collect();
+        Ok(())
+    }
+
+    fn objective_by_name(&self, name: &str) -> Result<ObjectiveId> {
+        self.persisted
+            .objectives_by_name
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("no such objective {name}"))
+    }
+}
+
+pub struct Sim {
+    pub(super) db: Db,
+    start: DateTime<Utc>,
+    now: DateTime<Utc>,
+    perf: PerfInner,
+    from_db: UnboundedReceiver<Task>,
+    stats: Vec<(DateTime<Utc>, Stat)>,
+}
+
+impl Sim {
+    pub fn new(cfg: Cfg, save: &Path, start: DateTime<Utc>) -> Result<Self> {
+        let (to_bg, from_db) = mpsc::unbounded_channel();
+        let db = Db::load_headless(to_bg, Arc::new(cfg), save).context("loading save")?;
+        Self::init(db, from_db, start)
+    }
+
+    fn init(mut db: Db, from_db: UnboundedReceiver<Task>, start: DateTime<Utc>) -> Result<Self> {
+        db.init_headless_production()
+            .context("initializing production")?;
+        db.setup_supply_lines().context("setting up supply lines")?;
+        let mut t = Self {
+            db,
+            start,
+            now: start,
+            perf: PerfInner::default(),
+            from_db,
+            stats: vec![],
+        };
+        t.drain();
+        Ok(t)
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    pub fn victory(&self) -> Option<(DateTime<Utc>, Side, RoundEndReason)> {
+        self.db.ephemeral.victory.clone()
+    }
+
+    fn drain(&mut self) {
+        while let Ok(task) = self.from_db.try_recv() {
+            if let Task::Stat(st) = task {
+                self.stats.push((self.now, st))
+            }
+        }
+        // nobody is listening to messages, don't let them pile up
+        self.db.ephemeral.msgs = MsgQ::default();
+    }
+
+    fn kill_unit(&mut self, uid: UnitId) -> Result<()> {
+        if !unit!(self.db, uid)?.dead {
+            self.db.mark_unit_dead(uid, self.now)?
+        }
+        Ok(())
+    }
+
+    pub fn apply(&mut self, ev: &SimEvent) -> Result<()> {
+        let now = self.now;
+        match ev {
+            SimEvent::Register { ucid, name, side } => {
+                match self.db.register_player(*ucid, name.clone(), *side) {
+                    Ok(()) => (),
+                    Err(RegErr::AlreadyOn(_)) => (),
+                    Err(RegErr::AlreadyRegistered(_, side)) => {
+                        bail!("{name} is already registered on {side}")
+                    }
+                }
+            }
+            SimEvent::Points {
+                ucid,
+                amount,
+                reason,
+            } => self.db.adjust_points(ucid, *amount, reason),
+            SimEvent::Kill(dead) => {
+                if let Some(points) = self.db.ephemeral.cfg.points.clone() {
+                    self.db.award_kill_points(&points, &mut dead.clone())
+                }
+                if let Who::AI { uid, .. } = &dead.victim {
+                    self.kill_unit(*uid)?
+                }
+            }
+            SimEvent::UnitDead { unit } => {
+                let uid = *self
+                    .db
+                    .persisted
+                    .units_by_name
+                    .get(unit)
+                    .ok_or_else(|| anyhow!("no such unit {unit}"))?;
+                self.kill_unit(uid)?
+            }
+            SimEvent::Damage { objective, percent } => {
+                let oid = self.db.objective_by_name(objective)?;
+                let obj = objective!(self.db, oid)?;
+                let mut alive: Vec<UnitId> = vec![];
+                if let Some(groups) = obj.groups.get(&obj.owner) {
+                    for gid in groups {
+                        for uid in &self.db.group(gid)?.units {
+                            let unit = unit!(self.db, uid)?;
+                            if !unit.dead && !unit.tags.contains(UnitTag::Invincible) {
+                                alive.push(*uid)
+                            }
+                        }
+                    }
+                }
+                let n = (alive.len() as f32 * (*percent as f32 / 100.)).ceil() as usize;
+                for uid in alive.into_iter().take(n) {
+                    self.kill_unit(uid)?
+                }
+            }
+            SimEvent::LogisticsRepair { objective, by } => {
+                let oid = self.db.objective_by_name(objective)?;
+                self.db.logistics_repair(by, oid, now)?
+            }
+            SimEvent::Capture {
+                objective,
+                side,
+                by,
+            } => {
+                let oid = self.db.objective_by_name(objective)?;
+                self.db.capture_objective(oid, *side, by.clone(), now)?
+            }
+            SimEvent::DeliverNow => {
+                self.db.admin_deliver_now();
+                self.logistics()?
+            }
+        }
+        self.drain();
+        Ok(())
+    }
+
+    fn logistics(&mut self) -> Result<()> {
+        if self.db.ephemeral.cfg.warehouse.is_none() {
+            return Ok(());
+        }
+        // without dcs every step completes synchronously, so run
+        // until the state machine goes idle
+        loop {
+            self.db
+                .logistics_step(None, &mut self.perf, self.now)
+                .context("logistics step")?;
+            if let LogiStage::Complete { .. } = &self.db.ephemeral.logistics_stage {
+                break Ok(());
+            }
+        }
+    }
+
+    /// advance the clock by dt and run the periodic campaign logic
+    pub fn step(&mut self, dt: Duration) -> Result<()> {
+        self.now += dt;
+        self.logistics()?;
+        self.db
+            .maybe_do_repairs(self.now)
+            .context("doing repairs")?;
+        self.db
+            .resolve_captures(self.now)
+            .context("checking captures")?;
+        self.db.check_victory(self.now);
+        self.drain();
+        Ok(())
+    }
+
+    pub fn run(&mut self, script: &SimScript) -> Result<SimReport> {
+        if script.step == 0 {
+            bail!("step must be greater than 0")
+        }
+        let step = Duration::seconds(script.step as i64);
+        let end = self.start + Duration::seconds(script.duration as i64);
+        let mut events = script.events.clone();
+        events.sort_by_key(|e| e.at);
+        let mut events = events.into_iter().peekable();
+        while self.now < end {
+            while let Some(ev) =
+                events.next_if(|e| self.start + Duration::seconds(e.at as i64) <= self.now)
+            {
+                if let Err(e) = self.apply(&ev.event) {
+                    error!("scripted event at {} {:?} failed {e:?}", ev.at, ev.event)
+                }
+            }
+            self.step(step)?;
+            if script.stop_on_victory && self.victory().is_some() {
+                break;
+            }
+        }
+        Ok(self.report())
+    }
+
+    pub fn report(&mut self) -> SimReport {
+        let objectives = self
+            .db
+            .persisted
+            .objectives
+            .into_iter()
+            .map(|(_, obj)| SimObjective {
+                name: obj.name.clone(),
+                owner: obj.owner,
+                health: obj.health,
+                logi: obj.logi,
+                supply: obj.supply,
+                fuel: obj.fuel,
+            })
+            .collect();
+        let players = self
+            .db
+            .persisted
+            .players
+            .into_iter()
+            .map(|(_, p)| SimPlayer {
+                name: p.name.clone(),
+                side: p.side,
+                points: p.points,
+            })
+            .collect();
+        SimReport {
+            start: self.start,
+            end: self.now,
+            victory: self.victory(),
+            objectives,
+            players,
+            stats: mem::take(&mut self.stats),
+        }
+    }
+
+    /// write the current state of the campaign as a save file
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        self.db.ephemeral.dirty();
+        let persisted = self
+            .db
+            .maybe_snapshot()
+            .ok_or_else(|| anyhow!("nothing to save"))?;
+        let file = File::create(path).with_context(|| format!("creating {path:?}"))?;
+        let mut file = zstd::stream::Encoder::new(BufWriter::new(file), 9)?.auto_finish();
+        serde_json::to_writer(&mut file, &persisted).context("encoding save")?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// campaign scenarios run on the sim. The dcs lua module can't be
+/// loaded outside of dcs, so run these with `cargo test -p bflib
+/// --no-default-features --features mlua/vendored`
+#[cfg(test)]
+pub(super) mod test {
+    use super::*;
+    use crate::db::{
+        MapS, Set, SetS,
+        ephemeral::Ephemeral,
+        group::{DeployKind, SpawnedGroup, SpawnedUnit},
+        logistics::Warehouse,
+        objective::{ObjGroupClass, Objective, RunwayState, Zone},
+        persisted::Persisted,
+    };
+    use bfprotocols::{
+        cfg::{UnitTags, Vehicle},
+        db::{group::GroupId, objective::ObjectiveKind},
+    };
+    use compact_str::format_compact;
+    use dcso3::{Position3, Vector2, Vector3};
+    use enumflags2::BitFlags;
+
+    pub(in crate::db) fn ucid(n: u8) -> Ucid {
+        format!("{n:032x}").parse().unwrap()
+    }
+
+    /// an empty campaign using cfg
+    pub(in crate::db) fn db(cfg: Cfg) -> (Db, UnboundedReceiver<Task>) {
+        let (to_bg, from_db) = mpsc::unbounded_channel();
+        let mut db = Db {
+            persisted: Persisted::default(),
+            ephemeral: Ephemeral::default(),
+        };
+        db.ephemeral.set_cfg_headless(Arc::new(cfg), to_bg).unwrap();
+        (db, from_db)
+    }
+
+    pub(in crate::db) fn sim(db: Db, from_db: UnboundedReceiver<Task>) -> Sim {
+        Sim::init(db, from_db, Utc::now()).unwrap()
+    }
+
+    pub(in crate::db) fn objective(
+        db: &mut Db,
+        name: &str,
+        kind: ObjectiveKind,
+        owner: Side,
+        pos: Vector2,
+    ) -> ObjectiveId {
+        let id = ObjectiveId::new();
+        let now = Utc::now();
+        let hub = kind.is_hub();
+        let obj = Objective {
+            id,
+            name: name.into(),
+            owner,
+            kind,
+            groups: MapS::from_iter([(Side::Red, Set::new()), (Side::Blue, Set::new())]),
+            health: 100,
+            logi: 100,
+            supply: 0,
+            fuel: 0,
+            threatened: false,
+            last_threatened_ts: now,
+            last_change_ts: now,
+            warehouse: Warehouse::default(),
+            zone: Zone::Circle { pos, radius: 2000. },
+            logistics_detached: false,
+            points: 0,
+            destroyed: MapS::new(),
+            runway: RunwayState::default(),
+            spawned: false,
+            enabled: false,
+            last_activate: now,
+            threat_pos3: Vector3::default(),
+        };
+        db.persisted.objectives.insert_cow(id, obj);
+        db.persisted.objectives_by_name.insert_cow(name.into(), id);
+        if hub {
+            db.persisted.logistics_hubs.insert_cow(id);
+        }
+        id
+    }
+
+    /// a group of n units standing at pos
+    pub(in crate::db) fn group(
+        db: &mut Db,
+        side: Side,
+        origin: DeployKind,
+        pos: Vector2,
+        n: usize,
+    ) -> GroupId {
+        let id = GroupId::new();
+        let name = String::from(format_compact!("test-{id}"));
+        let group = SpawnedGroup {
+            id,
+            name: name.clone(),
+            template_name: name.clone(),
+            side,
+            kind: None,
+            class: ObjGroupClass::Other,
+            origin,
+            units: SetS::new(),
+            tags: UnitTags(BitFlags::empty()),
+        };
+        let units = (0..n)
+            .map(|_| {
+                let uid = UnitId::new();
+                let mut position = Position3::default();
+                position.p.x = pos.x;
+                position.p.z = pos.y;
+                SpawnedUnit {
+                    name: String::from(format_compact!("{name}-{uid}")),
+                    id: uid,
+                    group: id,
+                    side,
+                    typ: Vehicle::from("Soldier M4"),
+                    tags: UnitTags(BitFlags::empty()),
+                    template_name: name.clone(),
+                    spawn_pos: pos,
+                    spawn_heading: 0.,
+                    spawn_position: position,
+                    pos,
+                    heading: 0.,
+                    position,
+                    dead: false,
+                    moved: None,
+                    airborne_velocity: None,
+                }
+            })
+            .collect();
+        db.insert_group(group, units).unwrap()
+    }
+
+    pub(in crate::db) fn troops(db: &Db, side: Side, player: Ucid) -> DeployKind {
+        let spec = db.ephemeral.cfg.troops[&side]
+            .iter()
+            .find(|tr| tr.can_capture)
+            .unwrap()
+            .clone();
+        DeployKind::Troop {
+            player,
+            origin: None,
+            moved_by: None,
+            spec,
+            cost_fraction: 1.,
+        }
+    }
+
+    pub(in crate::db) fn objective_owner(sim: &Sim, name: &str) -> Side {
+        let oid = sim.db.objective_by_name(name).unwrap();
+        sim.db.persisted.objectives[&oid].owner
+    }
+
+    #[test]
+    fn troops_capture_an_uncontested_objective() {
+        let (mut db, from_db) = db(Cfg::default());
+        let player = ucid(1);
+        db.register_player(player, "blue".into(), Side::Blue)
+            .unwrap();
+        let pos = Vector2::new(20000., 0.);
+        objective(
+            &mut db,
+            "hub",
+            ObjectiveKind::Logistics,
+            Side::Blue,
+            Vector2::zeros(),
+        );
+        let oid = objective(&mut db, "target", ObjectiveKind::Fob, Side::Red, pos);
+        db.persisted.objectives.get_mut_cow(&oid).unwrap().logi = 0;
+        let origin = troops(&db, Side::Blue, player);
+        let gid = group(&mut db, Side::Blue, origin, pos, 4);
+        let points = db.persisted.players[&player].points;
+        let mut sim = sim(db, from_db);
+        sim.step(Duration::seconds(60)).unwrap();
+        assert_eq!(objective_owner(&sim, "target"), Side::Blue);
+        assert!(sim.db.persisted.groups.get(&gid).is_none());
+        assert!(sim.db.persisted.players[&player].points > points);
+        let captured = sim.report().stats.iter().any(|(_, st)| {
+            matches!(st, Stat::Capture { id, side: Side::Blue, by } if *id == oid && by[..] == [player])
+        });
+        assert!(captured);
+    }
+
+    #[test]
+    fn contested_objectives_are_not_captured() {
+        let (mut db, from_db) = db(Cfg::default());
+        let pos = Vector2::new(20000., 0.);
+        objective(&mut db, "target", ObjectiveKind::Fob, Side::Neutral, pos);
+        let oid = db.objective_by_name("target").unwrap();
+        db.persisted.objectives.get_mut_cow(&oid).unwrap().logi = 0;
+        let origin = troops(&db, Side::Blue, ucid(1));
+        let blue = group(&mut db, Side::Blue, origin, pos, 4);
+        let origin = troops(&db, Side::Red, ucid(2));
+        let red = group(&mut db, Side::Red, origin, pos + Vector2::new(100., 0.), 4);
+        assert!(db.pending_captures().unwrap().is_empty());
+        let mut sim = sim(db, from_db);
+        sim.step(Duration::seconds(60)).unwrap();
+        assert_eq!(objective_owner(&sim, "target"), Side::Neutral);
+        assert!(sim.db.persisted.groups.get(&blue).is_some());
+        assert!(sim.db.persisted.groups.get(&red).is_some());
+    }
+
+    #[test]
+    fn pending_captures_does_not_change_state() {
+        let (mut db, _from_db) = db(Cfg::default());
+        let pos = Vector2::new(20000., 0.);
+        let oid = objective(&mut db, "target", ObjectiveKind::Fob, Side::Red, pos);
+        db.persisted.objectives.get_mut_cow(&oid).unwrap().logi = 0;
+        let origin = troops(&db, Side::Blue, ucid(1));
+        let gid = group(&mut db, Side::Blue, origin, pos, 4);
+        let pending = db.pending_captures().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!((pending[0].0, pending[0].1), (Side::Blue, oid));
+        assert_eq!(db.persisted.objectives[&oid].owner, Side::Red);
+        assert!(db.persisted.groups.get(&gid).is_some());
+    }
+
+    #[test]
+    fn logistics_hubs_resupply_objectives() {
+        let (mut db, from_db) = db(Cfg::default());
+        let hub = objective(
+            &mut db,
+            "hub",
+            ObjectiveKind::Logistics,
+            Side::Blue,
+            Vector2::zeros(),
+        );
+        let fob = objective(
+            &mut db,
+            "fob",
+            ObjectiveKind::Fob,
+            Side::Blue,
+            Vector2::new(20000., 0.),
+        );
+        let mk82 = String::from("weapons.bombs.Mk_82");
+        let hub_max = db.ephemeral.cfg.warehouse.as_ref().unwrap().hub_max;
+        let wh = &mut db.persisted.objectives.get_mut_cow(&hub).unwrap().warehouse;
+        let inv = wh.equipment.get_or_default_cow(mk82.clone());
+        inv.capacity = 10 * hub_max;
+        inv.stored = 10 * hub_max;
+        let wh = &mut db.persisted.objectives.get_mut_cow(&fob).unwrap().warehouse;
+        wh.equipment.get_or_default_cow(mk82.clone()).capacity = 100;
+        let mut sim = sim(db, from_db);
+        // the first tick only syncs the warehouses
+        sim.step(Duration::seconds(60)).unwrap();
+        sim.apply(&SimEvent::DeliverNow).unwrap();
+        let fob = &sim.db.persisted.objectives[&fob];
+        assert!(fob.warehouse.equipment[&mk82].stored > 0);
+        assert!(fob.supply > 0);
+        let delivered = sim.report().stats.iter().any(|(_, st)| {
+            matches!(
+                st,
+                Stat::Production {
+                    side: Side::Blue,
+                    ..
+                }
+            )
+        });
+        assert!(delivered);
+    }
+}
@@ -0,0 +1,161 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! partially built staged deployables, see cfg::Recipe
+
+use super::{Db, MapS};
+use anyhow::{Result, anyhow};
+use bfprotocols::{cfg::Deployable, db::objective::ObjectiveId};
+use chrono::{Duration, prelude::*};
+use compact_str::format_compact;
+use dcso3::{String, Vector2, coalition::Side, net::Ucid};
+use serde_derive::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+/// minutes a site waits for a delivery if the recipe doesn't say
+const SITE_TIMEOUT: u32 = 120;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Site {
+    pub deployable: String,
+    pub side: Side,
+    pub pos: Vector2,
+    /// who started building the site
+    pub player: Ucid,
+    /// where the first crates came from
+    pub origin: ObjectiveId,
+    /// how many of each required crate have been delivered
+    pub delivered: MapS<String, u32>,
+    #[serde(default = "Utc::now")]
+    pub last_delivery: DateTime<Utc>,
+}
+
+impl Site {
+    pub fn delivered(&self, name: &str) -> u32 {
+        self.delivered.get(name).copied().unwrap_or(0)
+    }
+
+    /// the number of crates delivered and the number required
+    pub fn progress(&self, spec: &Deployable) -> (u32, u32) {
+        spec.crates.iter().fold((0, 0), |(have, need), cr| {
+            (
+                have + self.delivered(&cr.name).min(cr.required),
+                need + cr.required,
+            )
+        })
+    }
+}
+
+impl Db {
+    /// the site building dep closest to pos within max_dist
+    pub(super) fn site_near(
+        &self,
+        side: Side,
+        dep: &str,
+        pos: Vector2,
+        max_dist: f64,
+    ) -> Option<u32> {
+        let max_dist = max_dist.powi(2);
+        self.persisted
+            .sites
+            .into_iter()
+            .filter(|(_, site)| site.side == side && site.deployable.as_str() == dep)
+            .map(|(id, site)| (*id, na::distance_squared(&site.pos.into(), &pos.into())))
+            .filter(|(_, d)| *d <= max_dist)
+            .min_by(|(_, d0), (_, d1)| d0.total_cmp(d1))
+            .map(|(id, _)| id)
+    }
+
+    pub(super) fn add_site(&mut self, site: Site) -> u32 {
+        let id = self
+            .persisted
+            .sites
+            .into_iter()
+            .next_back()
+            .map(|(id, _)| id + 1)
+            .unwrap_or(0);
+        self.persisted.sites.insert_cow(id, site);
+        self.ephemeral.dirty();
+        id
+    }
+
+    pub(super) fn delete_site(&mut self, id: u32) {
+        self.persisted.sites.remove_cow(&id);
+        if let Some(mid) = self.ephemeral.site_marks.remove(&id) {
+            self.ephemeral.msgs.delete_mark(mid)
+        }
+        self.ephemeral.dirty();
+    }
+
+    pub(super) fn mark_site(&mut self, id: u32) -> Result<()> {
+        if let Some(mid) = self.ephemeral.site_marks.remove(&id) {
+            self.ephemeral.msgs.delete_mark(mid)
+        }
+        let site = self
+            .persisted
+            .sites
+            .get(&id)
+            .ok_or_else(|| anyhow!("no such site {id}"))?;
+        let spec = self
+            .ephemeral
+            .deployable_idx
+            .get(&site.side)
+            .and_then(|idx| idx.deployables_by_name.get(&site.deployable))
+            .ok_or_else(|| anyhow!("no such deployable {}", site.deployable))?;
+        let (have, need) = site.progress(spec);
+        let by = self
+            .persisted
+            .players
+            .get(&site.player)
+            .map(|p| p.name.clone())
+            .unwrap_or_default();
+        let msg = format_compact!(
+            "{} site {id} started by {by}, {have} of {need} crates delivered",
+            site.deployable
+        );
+        let mid = self
+            .ephemeral
+            .msgs
+            .mark_to_side(site.side, site.pos, true, msg);
+        self.ephemeral.site_marks.insert(id, mid);
+        Ok(())
+    }
+
+    /// abandon sites that haven't had a delivery within their timeout
+    pub fn expire_sites(&mut self, now: DateTime<Utc>) {
+        let expired = self
+            .persisted
+            .sites
+            .into_iter()
+            .filter(|(_, site)| {
+                let timeout = self
+                    .ephemeral
+                    .deployable_idx
+                    .get(&site.side)
+                    .and_then(|idx| idx.deployables_by_name.get(&site.deployable))
+                    .and_then(|spec| spec.recipe.site_timeout)
+                    .unwrap_or(SITE_TIMEOUT);
+                now - site.last_delivery >= Duration::minutes(timeout as i64)
+            })
+            .map(|(id, site)| (*id, site.side, site.deployable.clone()))
+            .collect::<SmallVec<[_; 4]>>();
+        for (id, side, dep) in expired {
+            let msg = format_compact!("{dep} site {id} was abandoned");
+            self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+            self.delete_site(id)
+        }
+    }
+}
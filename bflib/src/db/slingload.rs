@@ -0,0 +1,256 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! dcs does not raise events for sling load cargo, so crates near
+//! airborne helicopters are polled to find out when one is hooked or
+//! dropped
+
+use super::{Db, group::DeployKind};
+use crate::{group, unit, unit_mut};
+use anyhow::{Result, anyhow};
+use bfprotocols::{
+    cfg::{Crate, UnitTag},
+    db::group::GroupId,
+};
+use compact_str::format_compact;
+use dcso3::{
+    LuaVec2, MizLua, String, Vector2, Vector3,
+    coalition::{Side, Static},
+    land::Land,
+    net::Ucid,
+    static_object::StaticObject,
+};
+use log::error;
+use smallvec::SmallVec;
+
+/// a crate higher than this is off the ground (meters)
+const AIRBORNE_AGL: f64 = 2.;
+/// a crate slower than this has come to rest (m/s)
+const AT_REST: f64 = 0.5;
+/// a helicopter this close to an airborne crate is carrying it (meters)
+const HOOK_RANGE: f64 = 50.;
+/// crates further than this from every airborne helicopter on their
+/// side aren't polled (meters)
+const POLL_RANGE: f64 = 200.;
+
+/// a crate that is off the ground under a helicopter, or falling
+/// after being released
+#[derive(Debug, Clone)]
+pub(super) struct SlungCrate {
+    by: Ucid,
+    /// height above the ground when last seen with the carrier
+    release_agl: f64,
+    /// the last speed seen
+    speed: f64,
+}
+
+struct Sample {
+    gid: GroupId,
+    spec: Crate,
+    pos: Vector3,
+    agl: f64,
+    speed: f64,
+}
+
+/// the position, height above ground, and speed of a crate
+fn sample_crate(land: &Land, lua: MizLua, name: &String) -> Result<Option<(Vector3, f64, f64)>> {
+    let obj = match StaticObject::get_by_name(lua, name) {
+        Ok(Static::Static(obj)) => obj.as_object()?,
+        Ok(Static::Airbase(_)) | Err(_) => return Ok(None),
+    };
+    let pos = obj.get_point()?.0;
+    let ground = land.get_height(LuaVec2(Vector2::new(pos.x, pos.z)))?;
+    Ok(Some((
+        pos,
+        pos.y - ground,
+        obj.get_velocity()?.0.magnitude(),
+    )))
+}
+
+impl Db {
+    /// the friendly player in the air closest to pos within hook range
+    fn carrier(&self, side: Side, pos: Vector3) -> Option<Ucid> {
+        self.instanced_players()
+            .filter(|(_, player, inst)| player.side == side && inst.in_air)
+            .map(|(ucid, _, inst)| (*ucid, (inst.position.p.0 - pos).magnitude()))
+            .filter(|(_, d)| *d <= HOOK_RANGE)
+            .min_by(|(_, d0), (_, d1)| d0.total_cmp(d1))
+            .map(|(ucid, _)| ucid)
+    }
+
+    /// where each side's helicopters in the air are
+    fn airborne_helicopters(&self) -> SmallVec<[(Side, Vector2); 8]> {
+        self.instanced_players()
+            .filter(|(_, _, inst)| {
+                inst.in_air
+                    && self
+                        .ephemeral
+                        .cfg
+                        .unit_classification
+                        .get(&inst.typ)
+                        .is_some_and(|tags| tags.contains(UnitTag::Helicopter))
+            })
+            .map(|(_, player, inst)| {
+                let p = inst.position.p.0;
+                (player.side, Vector2::new(p.x, p.z))
+            })
+            .collect()
+    }
+
+    /// sample the crates that are slung, or that a helicopter could
+    /// be about to hook
+    fn sample_crates(&self, lua: MizLua) -> Result<SmallVec<[Sample; 16]>> {
+        let mut res: SmallVec<[Sample; 16]> = SmallVec::new();
+        let helicopters = self.airborne_helicopters();
+        if helicopters.is_empty() && self.ephemeral.slung.is_empty() {
+            return Ok(res);
+        }
+        let land = Land::singleton(lua)?;
+        for gid in &self.persisted.crates {
+            let group = match group!(self, gid) {
+                Ok(group) => group,
+                Err(e) => {
+                    error!("could not find crate group {gid} {e:?}");
+                    continue;
+                }
+            };
+            let spec = match &group.origin {
+                DeployKind::Crate { spec, .. } => spec,
+                _ => continue,
+            };
+            let slung = self.ephemeral.slung.contains_key(gid);
+            for uid in &group.units {
+                let unit = match unit!(self, uid) {
+                    Ok(unit) => unit,
+                    Err(e) => {
+                        error!("could not find crate unit {uid} {e:?}");
+                        continue;
+                    }
+                };
+                let near = || {
+                    helicopters.iter().any(|(side, pos)| {
+                        *side == group.side && (pos - unit.pos).magnitude() <= POLL_RANGE
+                    })
+                };
+                if !slung && !near() {
+                    continue;
+                }
+                match sample_crate(&land, lua, &unit.name) {
+                    Ok(None) => (),
+                    Ok(Some((pos, agl, speed))) => res.push(Sample {
+                        gid: *gid,
+                        spec: spec.clone(),
+                        pos,
+                        agl,
+                        speed,
+                    }),
+                    Err(e) => error!("could not sample crate {} {e:?}", unit.name),
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn crate_landed(&mut self, s: &Sample, slung: SlungCrate) -> Result<()> {
+        let max_agl = s.spec.max_drop_height_agl as f64;
+        let max_speed = s.spec.max_drop_speed as f64;
+        if slung.release_agl > max_agl || slung.speed > max_speed {
+            self.delete_group(&s.gid)?;
+            let msg = format_compact!(
+                "your {} crate was destroyed, it was released at {:.0} m agl and hit the ground at {:.0} m/s. Crates must be released at or below {} m and {} m/s",
+                s.spec.name,
+                slung.release_agl,
+                slung.speed,
+                max_agl,
+                max_speed
+            );
+            self.ephemeral
+                .panel_to_player(&self.persisted, 10, &slung.by, msg);
+        } else {
+            let group = group!(self, s.gid)?;
+            let uids: SmallVec<[_; 1]> = group.units.into_iter().copied().collect();
+            for uid in uids {
+                let unit = unit_mut!(self, uid)?;
+                unit.pos = Vector2::new(s.pos.x, s.pos.z);
+                unit.position.p.0 = s.pos;
+            }
+            let msg = format_compact!("{} crate delivered", s.spec.name);
+            self.ephemeral
+                .panel_to_player(&self.persisted, 10, &slung.by, msg);
+            self.ephemeral.dirty();
+        }
+        Ok(())
+    }
+
+    /// follow crates carried by sling load, and apply the crate drop
+    /// limits when they are set down
+    pub fn run_sling_load(&mut self, lua: MizLua) -> Result<()> {
+        if !self.ephemeral.cfg.sling_load {
+            return Ok(());
+        }
+        let crates = &self.persisted.crates;
+        self.ephemeral.slung.retain(|gid, _| crates.contains(gid));
+        for s in self.sample_crates(lua)? {
+            if let Err(e) = self.track_crate(&s) {
+                error!("could not track crate {} {e:?}", s.gid)
+            }
+        }
+        Ok(())
+    }
+
+    fn track_crate(&mut self, s: &Sample) -> Result<()> {
+        let side = group!(self, s.gid)?.side;
+        let carrier = if s.agl > AIRBORNE_AGL {
+            self.carrier(side, s.pos)
+        } else {
+            None
+        };
+        match self.ephemeral.slung.remove(&s.gid) {
+            None => {
+                if let Some(by) = carrier {
+                    let msg = format_compact!("{} crate hooked", s.spec.name);
+                    self.ephemeral
+                        .panel_to_player(&self.persisted, 10, &by, msg);
+                    let slung = SlungCrate {
+                        by,
+                        release_agl: s.agl,
+                        speed: s.speed,
+                    };
+                    self.ephemeral.slung.insert(s.gid, slung);
+                }
+            }
+            Some(mut slung) => {
+                if s.agl <= AIRBORNE_AGL && s.speed <= AT_REST {
+                    self.crate_landed(s, slung)?
+                } else {
+                    if carrier.is_some() {
+                        slung.release_agl = s.agl;
+                    }
+                    if s.agl > AIRBORNE_AGL {
+                        slung.speed = s.speed;
+                    }
+                    self.ephemeral.slung.insert(s.gid, slung);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// true if the crate is being carried by sling load
+    pub(super) fn is_slung(&self, gid: &GroupId) -> bool {
+        self.ephemeral.slung.contains_key(gid)
+    }
+}
@@ -0,0 +1,389 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+//! deployed groups use up ammunition as they fire and fuel as time
+//! passes. They are resupplied by crate, or from the warehouse of a
+//! nearby friendly objective
+
+use super::{Db, group::DeployKind};
+use crate::group;
+use anyhow::{Context, Result, anyhow};
+use bfprotocols::{
+    cfg::UpkeepCfg,
+    db::{group::GroupId, objective::ObjectiveId},
+};
+use chrono::{Duration, prelude::*};
+use compact_str::{CompactString, format_compact};
+use dcso3::{
+    MizLua, String, Vector2, centroid2d,
+    coalition::Side,
+    controller::{AiOption, GroundOption, GroundRoe},
+    event::{ShootingEnd, Shot, WeaponUse},
+    group::Group,
+    object::{DcsObject, DcsOid},
+    unit::ClassUnit,
+};
+use log::{error, warn};
+use serde_derive::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupplyStatus {
+    Supplied,
+    Low,
+    /// the group holds its fire
+    NoAmmo,
+    /// the group is switched off
+    NoFuel,
+}
+
+impl SupplyStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Supplied => "supplied",
+            Self::Low => "low on supplies",
+            Self::NoAmmo => "out of ammo",
+            Self::NoFuel => "out of fuel",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Upkeep {
+    /// shots left
+    pub ammo: u32,
+    /// when the group runs out of fuel
+    pub fuel: DateTime<Utc>,
+}
+
+impl Upkeep {
+    fn full(cfg: &UpkeepCfg, now: DateTime<Utc>) -> Self {
+        Self {
+            ammo: cfg.ammo,
+            fuel: now + Duration::minutes(cfg.fuel as i64),
+        }
+    }
+
+    /// the percent of ammo and fuel left
+    fn percent(&self, cfg: &UpkeepCfg, now: DateTime<Utc>) -> (u8, u8) {
+        let pct = |n: i64, max: i64| {
+            if max <= 0 {
+                100
+            } else {
+                (n.clamp(0, max) * 100 / max) as u8
+            }
+        };
+        let fuel = (self.fuel - now).num_seconds();
+        (
+            pct(self.ammo as i64, cfg.ammo as i64),
+            pct(fuel, cfg.fuel as i64 * 60),
+        )
+    }
+
+    fn status(&self, cfg: &UpkeepCfg, now: DateTime<Utc>) -> SupplyStatus {
+        let (ammo, fuel) = self.percent(cfg, now);
+        if cfg.fuel > 0 && self.fuel <= now {
+            SupplyStatus::NoFuel
+        } else if cfg.ammo > 0 && self.ammo == 0 {
+            SupplyStatus::NoAmmo
+        } else if ammo.min(fuel) < cfg.low {
+            SupplyStatus::Low
+        } else {
+            SupplyStatus::Supplied
+        }
+    }
+}
+
+impl Db {
+    /// the deployed group that needs upkeep that id belongs to
+    fn upkeep_group(&self, id: &DcsOid<ClassUnit>) -> Option<GroupId> {
+        self.ephemeral.cfg.upkeep.as_ref()?;
+        let uid = self.ephemeral.get_uid_by_object_id(id)?;
+        let gid = self.persisted.units.get(uid)?.group;
+        self.persisted.upkeep.get(&gid).map(|_| gid)
+    }
+
+    fn use_ammo(&mut self, gid: &GroupId, shots: u32) {
+        if let Some(upkeep) = self.persisted.upkeep.get_mut_cow(gid) {
+            upkeep.ammo = upkeep.ammo.saturating_sub(shots);
+            self.ephemeral.dirty();
+        }
+    }
+
+    /// count a shot fired by a deployed group against its ammunition
+    pub fn upkeep_shot(&mut self, e: &Shot) -> Result<()> {
+        if let Some(gid) = self.upkeep_group(&e.initiator.object_id()?) {
+            self.use_ammo(&gid, 1)
+        }
+        Ok(())
+    }
+
+    /// a deployed group started firing its guns
+    pub fn upkeep_shooting_start(&mut self, e: &WeaponUse) -> Result<()> {
+        let unit = match e.initiator.as_ref().and_then(|o| o.as_unit().ok()) {
+            Some(unit) => unit,
+            None => return Ok(()),
+        };
+        let id = unit.object_id()?;
+        if self.upkeep_group(&id).is_some() {
+            self.ephemeral.gun_bursts.insert(id, e.time.0);
+        }
+        Ok(())
+    }
+
+    /// count each second of a gun burst by a deployed group as a shot
+    pub fn upkeep_shooting_end(&mut self, e: &ShootingEnd) -> Result<()> {
+        let id = e.initiator.object_id()?;
+        let start = match self.ephemeral.gun_bursts.remove(&id) {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+        if let Some(gid) = self.upkeep_group(&id) {
+            let secs = (e.time.0 - start).max(0.).ceil() as u32;
+            self.use_ammo(&gid, secs.max(1))
+        }
+        Ok(())
+    }
+
+    /// the supply status of gid, if it needs upkeep
+    pub fn supply_status(&self, gid: &GroupId) -> Option<SupplyStatus> {
+        let cfg = self.ephemeral.cfg.upkeep.as_ref()?;
+        let upkeep = self.persisted.upkeep.get(gid)?;
+        Some(upkeep.status(cfg, Utc::now()))
+    }
+
+    /// a description of the supply state of gid, if it needs upkeep
+    pub fn upkeep_report(&self, gid: &GroupId) -> Option<CompactString> {
+        let cfg = self.ephemeral.cfg.upkeep.as_ref()?;
+        let upkeep = self.persisted.upkeep.get(gid)?;
+        let now = Utc::now();
+        let (ammo, fuel) = upkeep.percent(cfg, now);
+        Some(format_compact!(
+            "supply: {}, ammo {ammo}% fuel {fuel}%",
+            upkeep.status(cfg, now).name()
+        ))
+    }
+
+    /// a line for each of side's ewrs that isn't fully supplied
+    pub fn ewr_supply_report(&self, side: Side) -> SmallVec<[CompactString; 4]> {
+        self.persisted
+            .ewrs
+            .into_iter()
+            .filter_map(|gid| {
+                let group = self.persisted.groups.get(gid)?;
+                match self.ephemeral.supply_status.get(gid)? {
+                    SupplyStatus::Supplied => None,
+                    _ if group.side != side => None,
+                    SupplyStatus::NoFuel => {
+                        Some(format_compact!("EWR {gid} is out of fuel and silent"))
+                    }
+                    status => Some(format_compact!("EWR {gid} is {}", status.name())),
+                }
+            })
+            .collect()
+    }
+
+    /// true if gid is out of fuel and has gone silent
+    pub(super) fn is_silent(&self, gid: &GroupId) -> bool {
+        self.ephemeral.supply_status.get(gid) == Some(&SupplyStatus::NoFuel)
+    }
+
+    fn resupply(&mut self, gid: &GroupId, now: DateTime<Utc>) {
+        let cfg = Arc::clone(&self.ephemeral.cfg);
+        if let Some(cfg) = cfg.upkeep.as_ref()
+            && let Some(upkeep) = self.persisted.upkeep.get_mut_cow(gid)
+        {
+            *upkeep = Upkeep::full(cfg, now);
+            self.ephemeral.dirty();
+        }
+    }
+
+    /// resupply the closest of side's deployed groups to pos within
+    /// max_dist, returning its id and name
+    pub(super) fn resupply_near(
+        &mut self,
+        side: Side,
+        pos: Vector2,
+        max_dist: f64,
+    ) -> Option<(GroupId, String)> {
+        let max_dist = max_dist.powi(2);
+        let (gid, name) = self
+            .persisted
+            .upkeep
+            .into_iter()
+            .filter_map(|(gid, _)| {
+                let group = self.persisted.groups.get(gid)?;
+                let name = match &group.origin {
+                    DeployKind::Deployed { spec, .. } if group.side == side => {
+                        spec.path.last()?.clone()
+                    }
+                    _ => return None,
+                };
+                let dist = group
+                    .units
+                    .into_iter()
+                    .filter_map(|uid| self.persisted.units.get(uid))
+                    .map(|u| na::distance_squared(&u.pos.into(), &pos.into()))
+                    .min_by(|d0, d1| d0.total_cmp(d1))?;
+                Some((*gid, name, dist))
+            })
+            .filter(|(_, _, d)| *d <= max_dist)
+            .min_by(|(_, _, d0), (_, _, d1)| d0.total_cmp(d1))
+            .map(|(gid, name, _)| (gid, name))?;
+        self.resupply(&gid, Utc::now());
+        Some((gid, name))
+    }
+
+    /// the closest friendly objective to pos within range that has
+    /// supply and fuel to spare
+    fn supplier_near(&self, side: Side, pos: Vector2, range: f64) -> Option<ObjectiveId> {
+        let range = range.powi(2);
+        self.persisted
+            .objectives
+            .into_iter()
+            .filter(|(_, obj)| obj.owner == side && obj.supply > 0 && obj.fuel > 0)
+            .map(|(oid, obj)| {
+                let dist = na::distance_squared(&obj.zone.pos().into(), &pos.into());
+                (*oid, dist)
+            })
+            .filter(|(_, d)| *d <= range)
+            .min_by(|(_, d0), (_, d1)| d0.total_cmp(d1))
+            .map(|(oid, _)| oid)
+    }
+
+    fn apply_supply_status(&self, lua: MizLua, gid: &GroupId, status: SupplyStatus) -> Result<()> {
+        let oid = match self.ephemeral.object_id_by_gid.get(gid) {
+            Some(oid) => oid,
+            None => return Ok(()),
+        };
+        let con = Group::get_instance(lua, oid)?
+            .get_controller()
+            .context("get controller")?;
+        let roe = match status {
+            SupplyStatus::NoFuel => return con.set_on_off(false),
+            SupplyStatus::NoAmmo => GroundRoe::WeaponHold,
+            SupplyStatus::Low | SupplyStatus::Supplied => GroundRoe::OpenFire,
+        };
+        con.set_on_off(true)?;
+        con.set_option(AiOption::Ground(GroundOption::Roe(roe)))
+    }
+
+    /// take the cost of resupplying gid out of the warehouse at oid,
+    /// logging a failure
+    fn draw_supply(
+        &mut self,
+        lua: MizLua,
+        cfg: &UpkeepCfg,
+        gid: &GroupId,
+        oid: ObjectiveId,
+    ) -> Result<()> {
+        if cfg.draw_cost > 0 && self.ephemeral.cfg.warehouse.is_some() {
+            self.consume_supply(lua, oid, cfg.draw_cost)
+                .with_context(|| format_compact!("resupplying {gid} from {oid}"))
+                .inspect_err(|e| error!("{e:?}"))?
+        }
+        Ok(())
+    }
+
+    /// burn fuel, draw supplies from nearby objectives, and hold the
+    /// fire of or switch off groups that have run out
+    pub fn run_upkeep(&mut self, lua: MizLua, now: DateTime<Utc>) -> Result<()> {
+        let cfg = Arc::clone(&self.ephemeral.cfg);
+        let cfg = match cfg.upkeep.as_ref() {
+            Some(cfg) => cfg,
+            None => return Ok(()),
+        };
+        let upkeep = &self.persisted.upkeep;
+        self.ephemeral
+            .supply_status
+            .retain(|gid, _| upkeep.get(gid).is_some());
+        self.ephemeral
+            .supply_applied
+            .retain(|gid, _| upkeep.get(gid).is_some());
+        let ephemeral = &mut self.ephemeral;
+        let by_object_id = &ephemeral.uid_by_object_id;
+        ephemeral
+            .gun_bursts
+            .retain(|id, _| by_object_id.contains_key(id));
+        let deployed: SmallVec<[GroupId; 64]> =
+            self.persisted.deployed.into_iter().copied().collect();
+        for gid in deployed {
+            let group = group!(self, gid)?;
+            let name = match &group.origin {
+                DeployKind::Deployed { spec, .. } => match spec.path.last() {
+                    Some(name) if !cfg.exempt.contains(name) => name.clone(),
+                    Some(_) | None => continue,
+                },
+                _ => continue,
+            };
+            let side = group.side;
+            let pos = centroid2d(
+                group
+                    .units
+                    .into_iter()
+                    .filter_map(|uid| self.persisted.units.get(uid))
+                    .map(|u| u.pos),
+            );
+            let (mut status, new) = match self.persisted.upkeep.get(&gid) {
+                Some(upkeep) => (upkeep.status(cfg, now), false),
+                None => {
+                    self.persisted
+                        .upkeep
+                        .insert_cow(gid, Upkeep::full(cfg, now));
+                    self.ephemeral.dirty();
+                    (SupplyStatus::Supplied, true)
+                }
+            };
+            if status != SupplyStatus::Supplied
+                && cfg.draw_range > 0
+                && let Some(oid) = self.supplier_near(side, pos, cfg.draw_range as f64)
+                && let Ok(()) = self.draw_supply(lua, cfg, &gid, oid)
+            {
+                self.resupply(&gid, now);
+                status = SupplyStatus::Supplied;
+                let obj = self.persisted.objectives[&oid].name.clone();
+                let msg = format_compact!("{name} {gid} resupplied from {obj}");
+                self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+            }
+            let prev = self.ephemeral.supply_status.insert(gid, status);
+            let changed = prev.is_some_and(|prev| prev != status);
+            if changed || new {
+                self.mark_group(&gid)?;
+            }
+            if changed && status != SupplyStatus::Supplied {
+                let msg = format_compact!("{name} {gid} is {}", status.name());
+                self.ephemeral.msgs().panel_to_side(10, false, side, msg);
+            }
+            // a respawned group comes back fully working, so reapply
+            // a degraded state to it
+            let degraded = matches!(status, SupplyStatus::NoAmmo | SupplyStatus::NoFuel);
+            let object = self.ephemeral.object_id_by_gid.get(&gid);
+            let respawned = degraded && object != self.ephemeral.supply_applied.get(&gid);
+            if changed || respawned {
+                match self.apply_supply_status(lua, &gid, status) {
+                    Err(e) => warn!("could not apply supply status to {gid} {e:?}"),
+                    Ok(()) => {
+                        if let Some(object) = object.cloned() {
+                            self.ephemeral.supply_applied.insert(gid, object);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
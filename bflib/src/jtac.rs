@@ -15,7 +15,11 @@ for more details.
 */
 
 use crate::{
-    db::{Db, JtDesc, group::SpawnedUnit, player::InstancedPlayer},
+    db::{
+        Db, JtDesc,
+        group::{DeployKind, SpawnedUnit},
+        player::InstancedPlayer,
+    },
     landcache::LandCache,
 };
 use anyhow::{Context, Result, anyhow, bail};
@@ -341,6 +345,11 @@ impl Jtac {
             self.location.distance / 1000.,
             db.objective(&self.location.oid)?.name
         )?;
+        if let JtId::Group(gid) = &self.gid
+            && let Some(supply) = db.upkeep_report(gid)
+        {
+            write!(msg, "{supply}\n\n")?;
+        }
         if self.contacts.is_empty() {
             write!(msg, "No enemies in sight")?;
         } else {
@@ -1350,6 +1359,42 @@ impl Jtacs {
         })
     }
 
+    /// the players whose jtacs or drones have the unit as a contact
+    pub fn spotters(&self, db: &Db, id: &DcsOid<ClassUnit>) -> Vec<Ucid> {
+        let ctid = match db.ephemeral.player_in_unit(id) {
+            Some(ucid) => EnId::Player(*ucid),
+            None => match db.ephemeral.get_uid_by_object_id(id) {
+                Some(uid) => EnId::Unit(*uid),
+                None => return vec![],
+            },
+        };
+        let mut spotters = vec![];
+        for jt in self.jtacs() {
+            if !jt.contacts.contains_key(&ctid) {
+                continue;
+            }
+            let owner = match jt.gid {
+                JtId::Slot(sl) => db.ephemeral.player_in_slot(&sl).copied(),
+                JtId::Group(gid) => db.group(&gid).ok().and_then(|g| match &g.origin {
+                    DeployKind::Action { player, .. } => *player,
+                    DeployKind::Deployed { player, .. } | DeployKind::Troop { player, .. } => {
+                        Some(*player)
+                    }
+                    DeployKind::Crate { .. }
+                    | DeployKind::Objective { .. }
+                    | DeployKind::Convoy { .. }
+                    | DeployKind::ObjectiveDeprecated => None,
+                }),
+            };
+            if let Some(ucid) = owner
+                && !spotters.contains(&ucid)
+            {
+                spotters.push(ucid)
+            }
+        }
+        spotters
+    }
+
     pub fn contacts_near_point<'a>(
         &'a self,
         side: Side,
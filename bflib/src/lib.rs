@@ -15,6 +15,7 @@ for more details.
 */
 
 mod admin;
+mod api;
 mod bg;
 mod chatcmd;
 mod db;
@@ -26,6 +27,8 @@ mod msgq;
 mod shots;
 mod spawnctx;
 
+pub use db::{group, logistics, migrate, objective, persisted, player, sim};
+
 extern crate nalgebra as na;
 use crate::db::player::SlotAuth;
 use admin::{run_admin_commands, AdminCommand, AdminResult};
@@ -53,11 +56,11 @@ use dcso3::{
         miz::{Miz, UnitId},
         Env,
     },
-    event::Event,
+    event::{Event, WeaponUse},
     hooks::UserHooks,
     lfs::Lfs,
     net::{DcsLuaEnvironment, Net, PlayerId, SlotId, Ucid},
-    object::{DcsObject, DcsOid},
+    object::{DcsObject, DcsOid, ObjectCategory},
     perf::record_perf,
     timer::Timer,
     trigger::Trigger,
@@ -242,6 +245,7 @@ struct Context {
     db: Db,
     external_admin_commands: Arc<SegQueue<(AdminCommand, oneshot::Sender<Value>)>>,
     admin_commands: Vec<(admin::Caller, AdminCommand)>,
+    api: api::Pending,
     action_commands: Vec<(PlayerId, String)>,
     jtac_commands: Vec<(PlayerId, JtId, String)>,
     to_background: Option<UnboundedSender<bg::Task>>,
@@ -442,6 +446,26 @@ fn process_slot_rejection(ctx: &mut Context, id: PlayerId, ucid: Ucid, rej: Slot
             let msg = format_compact!("Objective is capturable");
             ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), msg);
         }
+        SlotAuth::RunwayDestroyed => {
+            let msg =
+                format_compact!("Objective's runway is destroyed, only helicopters can spawn");
+            ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), msg);
+        }
+        SlotAuth::AirfieldClosed { until, repairs } => {
+            let msg = format_compact!(
+                "The runway is cratered, the airfield reopens in {} or after {} repair crates",
+                chatcmd::format_duration(until - Utc::now()),
+                repairs
+            );
+            ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), msg);
+        }
+        SlotAuth::Blocked(until) => {
+            let msg = format_compact!(
+                "you are blocked from slotting for {} for attacking your own side",
+                chatcmd::format_duration(until - Utc::now())
+            );
+            ctx.db.ephemeral.msgs().send(MsgTyp::Chat(Some(id)), msg);
+        }
         SlotAuth::ObjectiveNotOwned(side) => {
             let msg = String::from(format_compact!(
                 "{:?} does not own the objective associated with this slot",
@@ -541,6 +565,8 @@ fn unit_killed(
 ) -> Result<()> {
     ctx.recently_landed.remove(&id);
     ctx.shots_out.dead(id.clone(), now);
+    ctx.shots_out
+        .spotted(id.clone(), ctx.jtac.spotters(&ctx.db, &id));
     if let Err(e) = ctx.jtac.unit_dead(lua, &mut ctx.db, &id) {
         error!("jtac unit dead failed for {:?} {:?}", id, e)
     }
@@ -550,6 +576,52 @@ fn unit_killed(
     Ok(())
 }
 
+fn weapon_used(
+    lua: MizLua,
+    ctx: &mut Context,
+    start_ts: DateTime<Utc>,
+    e: WeaponUse,
+) -> Result<()> {
+    if let Some(target) = e.target.as_ref().and_then(|t| t.as_unit().ok()) {
+        let dead = target.get_life()? < 1;
+        if let Some(shooter) = e.initiator.and_then(|u| u.as_unit().ok()) {
+            if let Err(e) =
+                ctx.db
+                    .friendly_fire(start_ts, &shooter.object_id()?, &target.object_id()?)
+            {
+                error!("error processing friendly fire {:?}", e)
+            }
+            if let Err(e) =
+                ctx.shots_out
+                    .hit(&ctx.db, start_ts, dead, &target, &shooter, e.weapon_name)
+            {
+                error!("error processing hit event {:?}", e)
+            }
+        }
+        if dead {
+            if let Err(e) = unit_killed(lua, ctx, target.object_id()?, start_ts) {
+                error!("0 unit killed failed {:?}", e)
+            }
+        }
+    } else if let Some(target) = e.target.as_ref().and_then(|t| t.as_static().ok()) {
+        if target.get_life()? < 1 {
+            if let Some(shooter) = e.initiator.and_then(|u| u.as_unit().ok())
+                && let Err(e) = ctx.db.friendly_static_destroyed(
+                    start_ts,
+                    &shooter.object_id()?,
+                    &target.object_id()?,
+                )
+            {
+                error!("error processing friendly static destroyed {:?}", e)
+            }
+            if let Err(e) = ctx.db.static_dead(&target.object_id()?, start_ts) {
+                error!("static dead failed {e:?}")
+            }
+        }
+    }
+    Ok(())
+}
+
 fn on_event(lua: MizLua, ev: Event) -> Result<()> {
     let start_ts = Utc::now();
     let ctx = unsafe { Context::get_mut() };
@@ -606,42 +678,35 @@ fn on_event(lua: MizLua, ev: Event) -> Result<()> {
                 error!("player leave unit with no unit")
             }
         }
-        Event::Hit(e) | Event::Kill(e) => {
-            if let Some(target) = e.target.as_ref().and_then(|t| t.as_unit().ok()) {
-                let dead = target.get_life()? < 1;
-                if let Some(shooter) = e.initiator.and_then(|u| u.as_unit().ok()) {
-                    if let Err(e) = ctx.shots_out.hit(
-                        &ctx.db,
-                        start_ts,
-                        dead,
-                        &target,
-                        &shooter,
-                        e.weapon_name,
-                    ) {
-                        error!("error processing hit event {:?}", e)
-                    }
-                }
-                if dead {
-                    if let Err(e) = unit_killed(lua, ctx, target.object_id()?, start_ts) {
-                        error!("0 unit killed failed {:?}", e)
-                    }
-                }
-            } else if let Some(target) =
-                e.target.as_ref().and_then(|t| t.as_static().ok())
-            {
-                if target.get_life()? < 1 {
-                    if let Err(e) = ctx.db.static_dead(&target.object_id()?, start_ts) {
-                        error!("static dead failed {e:?}")
-                    }
-                }
+        Event::Hit(e) => {
+            if let Err(e) = ctx.shots_out.weapon_hit(&ctx.db, start_ts, &e) {
+                error!("error processing weapon hit {:?}", e)
             }
+            weapon_used(lua, ctx, start_ts, e)?
         }
+        Event::Kill(e) => weapon_used(lua, ctx, start_ts, e)?,
         Event::Shot(e) => {
             if let Err(e) = ctx.shots_out.shot(&ctx.db, start_ts, &e) {
                 error!("error processing shot event {:?}", e)
             }
+            if let Err(e) = ctx.db.weapon_fired(&e) {
+                error!("error tracking weapon {:?}", e)
+            }
+            if let Err(e) = ctx.db.upkeep_shot(&e) {
+                error!("error tracking upkeep {:?}", e)
+            }
             ()
         }
+        Event::ShootingStart(e) => {
+            if let Err(e) = ctx.db.upkeep_shooting_start(&e) {
+                error!("error tracking upkeep {:?}", e)
+            }
+        }
+        Event::ShootingEnd(e) => {
+            if let Err(e) = ctx.db.upkeep_shooting_end(&e) {
+                error!("error tracking upkeep {:?}", e)
+            }
+        }
         Event::Dead(e) | Event::UnitLost(e) | Event::PilotDead(e) => {
             if let Some(unit) = e.initiator.as_ref().and_then(|u| u.as_unit().ok()) {
                 let id = unit.object_id()?;
@@ -653,16 +718,34 @@ fn on_event(lua: MizLua, ev: Event) -> Result<()> {
                 if let Err(e) = ctx.db.static_dead(&st.object_id()?, start_ts) {
                     error!("static killed failed {e:?}")
                 }
+                if let Err(e) = ctx.db.structure_dead(lua, &st.get_name()?) {
+                    error!("structure dead failed {e:?}")
+                }
+            } else if let Some(obj) = e.initiator.as_ref()
+                && let ObjectCategory::Scenery = obj.get_category()?
+                && let Err(e) = ctx.db.structure_dead(lua, &obj.get_name()?)
+            {
+                error!("structure dead failed {e:?}")
             }
         }
         Event::Ejection(e) => {
             if let Ok(unit) = e.initiator.as_unit() {
+                if let Err(e) = ctx.db.pilot_ejected(&unit, &e.target) {
+                    error!("could not track ejected pilot {e:?}")
+                }
                 let id = unit.object_id()?;
                 if let Err(e) = unit_killed(lua, ctx, id, start_ts) {
                     error!("2 unit killed failed {}", e)
                 }
             }
         }
+        Event::LandingAfterEjection(e) => {
+            if let Some(pilot) = e.initiator.as_ref()
+                && let Err(e) = ctx.db.pilot_landed(lua, &ctx.idx, pilot)
+            {
+                error!("could not spawn downed pilot {e:?}")
+            }
+        }
         Event::Takeoff(e) | Event::PostponedTakeoff(e) => {
             if let Ok(unit) = e.initiator.as_unit() {
                 let id = unit.object_id()?;
@@ -793,6 +876,9 @@ fn return_lives(lua: MizLua, ctx: &mut Context, ts: DateTime<Utc>) {
             let unit = or_false!(Unit::get_instance(lua, id));
             let pos = or_false!(unit.get_ground_position());
             let slot = or_false!(unit.slot());
+            if let Err(e) = db.deliver_rescued_pilots(lua, &slot, pos.0) {
+                error!("could not deliver rescued pilots {e:?}")
+            }
             if let Some(typ) = db.land(slot.clone(), pos.0, &unit) {
                 returned.push((typ, slot));
                 return false;
@@ -909,8 +995,8 @@ fn check_auto_shutdown(
             return admin::admin_shutdown(ctx, lua, None);
         }
     }
-    if let Some(victor) = ctx.db.check_victory(now) {
-        return admin::admin_shutdown(ctx, lua, Some(Some(victor)));
+    if let Some((victor, reason)) = ctx.db.check_victory(now) {
+        return admin::admin_shutdown(ctx, lua, Some((Some(victor), reason)));
     }
     Ok(AdminResult::Continue)
 }
@@ -1046,17 +1132,28 @@ fn run_slow_timed_events(
         {
             // report kills
             let cfg = Arc::clone(&ctx.db.ephemeral.cfg);
-            for dead in ctx.shots_out.bring_out_your_dead(ts) {
+            for mut dead in ctx.shots_out.bring_out_your_dead(ts) {
                 info!("kill {:?}", dead);
+                if let Err(e) = ctx.db.friendly_kill(&dead) {
+                    error!("could not record friendly kill {e:?}")
+                }
                 if let Some(points) = cfg.points.as_ref() {
-                    ctx.db.award_kill_points(points, &dead)
+                    ctx.db.award_kill_points(points, &mut dead)
                 }
                 ctx.do_bg_task(Task::Stat(Stat::Kill(dead)));
             }
+            ctx.db.close_sorties(ts);
         }
         if let Err(e) = ctx.db.maybe_do_repairs(ts) {
             error!("error doing repairs {:?}", e)
         }
+        if let Err(e) = ctx.db.run_csar(ts) {
+            error!("error running csar {:?}", e)
+        }
+        ctx.db.expire_sites(ts);
+        if let Err(e) = ctx.db.run_upkeep(lua, ts) {
+            error!("error running upkeep {:?}", e)
+        }
         record_perf(&mut perf.do_repairs, start_ts);
         if let Err(e) = ctx.db.advance_actions(lua, &ctx.idx, &ctx.jtac, start_ts) {
             error!("could not advance actions {e:?}")
@@ -1097,11 +1194,26 @@ fn run_slow_timed_events(
             }
         }
         record_perf(&mut perf.unit_culling, ts);
+        let players = ctx.connected.info_by_player_id.len();
+        if let Err(e) = ctx.db.run_offensives(lua, &ctx.idx, players, start_ts) {
+            error!("could not run offensives {e:?}")
+        }
+        if let Err(e) = ctx.db.run_convoys(lua, &ctx.idx, start_ts) {
+            error!("could not run convoys {e:?}")
+        }
         let ts = Utc::now();
         if let Err(e) = ctx.db.update_objectives_markup() {
             error!("could not remark objectives {e}")
         }
         record_perf(&mut perf.remark_objectives, ts);
+        if ctx.db.ephemeral.cfg.netidx_base.is_some() {
+            ctx.do_bg_task(Task::LogSupply(ctx.db.supply_network()));
+            if let (Some(red), Some(blue)) =
+                (ctx.db.tickets(Side::Red), ctx.db.tickets(Side::Blue))
+            {
+                ctx.do_bg_task(Task::LogTickets { red, blue });
+            }
+        }
         let ts = Utc::now();
         update_jtac_contacts(ctx, lua);
         record_perf(&mut perf.update_jtac_contacts, ts);
@@ -1196,11 +1308,29 @@ fn run_timed_events(
         }
     }
     record_perf(&mut perf.jtac_target_positions, now);
+    if let Err(e) = ctx.db.run_cratering(lua, now) {
+        error!("error running runway cratering {:?}", e)
+    }
+    if let Err(e) = ctx.db.run_sling_load(lua) {
+        error!("error running sling load {:?}", e)
+    }
+    if let Err(e) = ctx.db.run_lost_cargo(lua, &ctx.idx) {
+        error!("error running lost cargo {:?}", e)
+    }
+    ctx.shots_out.weapons_in_flight(lua, now);
+    for en in ctx.shots_out.take_engagements() {
+        ctx.do_bg_task(Task::Stat(Stat::Engagement(en)));
+    }
+    for (ucid, until) in ctx.db.take_grief_bans() {
+        if let Err(e) = admin::ban(ctx, lua, ucid, until) {
+            error!("could not ban {ucid} {e:?}")
+        }
+    }
     let now = Utc::now();
     let max_rate = ctx.db.ephemeral.cfg.max_msgs_per_second;
     ctx.db.ephemeral.msgs().process(max_rate, &net, &act);
     record_perf(&mut perf.process_messages, now);
-    if let Err(e) = ctx.db.logistics_step(lua, perf, ts) {
+    if let Err(e) = ctx.db.logistics_step(Some(lua), perf, ts) {
         error!("error running logistics events {e:?}")
     }
     match run_admin_commands(ctx, lua) {
@@ -1413,13 +1543,15 @@ fn init_miz(lua: MizLua) -> Result<()> {
     Ok(())
 }
 
-#[mlua::lua_module]
-fn bflib(lua: &Lua) -> LuaResult<LuaTable<'_>> {
+#[cfg_attr(feature = "module", mlua::lua_module)]
+pub fn bflib(lua: &Lua) -> LuaResult<LuaTable<'_>> {
     // ensure we capture backtraces on panic
     let _ = unsafe {
         std::env::set_var("RUST_BACKTRACE", "1"); // bactrace for panics
         std::env::set_var("RUST_LIB_BACKTRACE", "0"); // no backtrace for Error
     };
     unsafe { Context::get_mut() }.init_async_bg(lua.inner()).map_err(dcso3::lua_err)?;
-    dcso3::create_root_module(lua, init_hooks, init_miz)
+    let exports = dcso3::create_root_module(lua, init_hooks, init_miz)?;
+    exports.set("api", api::create(lua)?)?;
+    Ok(exports)
 }
@@ -473,6 +473,7 @@ fn add_action_menu(lua: MizLua, arg: ArgTriple<Ucid, GroupId, SlotId>) -> Result
                 }
                 DeployKind::Crate { .. }
                 | DeployKind::Objective { .. }
+                | DeployKind::Convoy { .. }
                 | DeployKind::ObjectiveDeprecated => None,
             };
             if let Some(key) = key {
@@ -37,6 +37,9 @@ fn unpakistan(lua: MizLua, gid: GroupId) -> Result<()> {
     let (side, slot) = slot_for_group(lua, ctx, &gid).context("getting slot for group")?;
     match ctx.db.unpakistan(lua, &ctx.idx, &slot) {
         Ok(unpakistan) => {
+            if let Some(ucid) = ctx.db.ephemeral.player_in_slot(&slot).copied() {
+                ctx.db.with_sortie(&ucid, |s| s.crates += 1);
+            }
             let player = player_name(&ctx.db, &slot);
             let msg = format_compact!("{player} {unpakistan}");
             ctx.db.ephemeral.msgs().panel_to_side(10, false, side, msg);
@@ -332,6 +335,19 @@ pub(super) fn add_cargo_menu_for_group(
             },
         )?;
     }
+    if let Some(upkeep) = &cfg.upkeep {
+        let cr = &upkeep.resupply_crate[side];
+        mc.add_command_for_group(
+            group,
+            cr.name.clone(),
+            Some(logi.clone()),
+            spawn_crate,
+            ArgTuple {
+                fst: group,
+                snd: cr.name.clone(),
+            },
+        )?;
+    }
     let mut created_menus: FxHashMap<String, GroupSubMenu> = FxHashMap::default();
     for dep in cfg.deployables.get(side).unwrap_or(&vec![]) {
         if dep.crates.is_empty() && dep.repair_crate.is_none() {
@@ -58,6 +58,9 @@ fn ewr_report(lua: MizLua, gid: GroupId) -> Result<()> {
                 for braa in chickens {
                     write!(report, "{braa}\n")?;
                 }
+                for line in ctx.db.ewr_supply_report(player.side) {
+                    writeln!(report, "{line}")?;
+                }
             }
         }
     }
@@ -718,6 +718,7 @@ pub(super) fn add_menu_for_jtac(
                 },
                 DeployKind::Objective { .. }
                 | DeployKind::ObjectiveDeprecated
+                | DeployKind::Convoy { .. }
                 | DeployKind::Crate { .. } => format_compact!("{gid}"),
             },
         },
@@ -0,0 +1,82 @@
+/*
+Copyright 2024 Eric Stokes.
+
+This file is part of bflib.
+
+bflib is free software: you can redistribute it and/or modify it under
+the terms of the GNU Affero Public License as published by the Free
+Software Foundation, either version 3 of the License, or (at your
+option) any later version.
+
+bflib is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
+for more details.
+*/
+
+use super::slot_for_group;
+use crate::Context;
+use anyhow::{Context as ErrContext, Result, anyhow};
+use chrono::prelude::*;
+use compact_str::CompactString;
+use dcso3::{MizLua, Vector2, env::miz::GroupId, mission_commands::MissionCommands};
+
+fn send_report(ctx: &mut Context, gid: GroupId, lines: Vec<CompactString>) {
+    ctx.db
+        .ephemeral
+        .msgs()
+        .panel_to_group(20, false, gid, lines.join("\n"))
+}
+
+fn supply_network(lua: MizLua, gid: GroupId) -> Result<()> {
+    let ctx = unsafe { Context::get_mut() };
+    let (side, _) = slot_for_group(lua, ctx, &gid).context("getting slot for group")?;
+    let lines = ctx.db.supply_report(side, Utc::now());
+    send_report(ctx, gid, lines);
+    Ok(())
+}
+
+fn nearest_objective_supply(lua: MizLua, gid: GroupId) -> Result<()> {
+    let ctx = unsafe { Context::get_mut() };
+    let (side, slot) = slot_for_group(lua, ctx, &gid).context("getting slot for group")?;
+    let pos = ctx
+        .db
+        .ephemeral
+        .player_in_slot(&slot)
+        .and_then(|ucid| ctx.db.player(ucid))
+        .and_then(|player| player.current_slot.as_ref())
+        .and_then(|(_, inst)| inst.as_ref())
+        .map(|inst| Vector2::new(inst.position.p.x, inst.position.p.z))
+        .ok_or_else(|| anyhow!("player position is unknown"))?;
+    let nearest = ctx
+        .db
+        .objectives()
+        .filter(|(_, obj)| obj.owner() == side)
+        .map(|(oid, obj)| (na::distance(&pos.into(), &obj.pos().into()), *oid))
+        .min_by(|(d0, _), (d1, _)| d0.total_cmp(d1));
+    let lines = match nearest {
+        None => vec![CompactString::from("your side holds no objectives")],
+        Some((_, oid)) => ctx.db.explain_supply(&oid, Utc::now())?,
+    };
+    send_report(ctx, gid, lines);
+    Ok(())
+}
+
+pub(super) fn add_logistics_menu_for_group(mc: &MissionCommands, group: GroupId) -> Result<()> {
+    let root = mc.add_submenu_for_group(group, "Logistics".into(), None)?;
+    mc.add_command_for_group(
+        group,
+        "Supply Network".into(),
+        Some(root.clone()),
+        supply_network,
+        group,
+    )?;
+    mc.add_command_for_group(
+        group,
+        "Nearest Objective Supply".into(),
+        Some(root.clone()),
+        nearest_objective_supply,
+        group,
+    )?;
+    Ok(())
+}
@@ -18,6 +18,7 @@ pub mod action;
 pub mod cargo;
 mod ewr;
 pub mod jtac;
+mod logistics;
 mod troop;
 
 use crate::{db::Db, Context};
@@ -266,10 +267,12 @@ pub(super) fn init_for_slot(ctx: &mut Context, lua: MizLua, slot: &SlotId) -> Re
                 .get_slot_info(slot)
                 .context("getting slot info")?;
             mc.remove_submenu_for_group(si.miz_gid, GroupSubMenu::from(vec!["EWR".into()]))?;
+            mc.remove_submenu_for_group(si.miz_gid, GroupSubMenu::from(vec!["Logistics".into()]))?;
             mc.remove_submenu_for_group(si.miz_gid, GroupSubMenu::from(vec!["Cargo".into()]))?;
             mc.remove_submenu_for_group(si.miz_gid, GroupSubMenu::from(vec!["Troops".into()]))?;
             mc.remove_submenu_for_group(si.miz_gid, GroupSubMenu::from(vec!["Actions".into()]))?;
             ewr::add_ewr_menu_for_group(&mc, si.miz_gid)?;
+            logistics::add_logistics_menu_for_group(&mc, si.miz_gid)?;
             let cap = CarryCap::from_typ(&cfg, si.typ.as_str());
             if cap.crates && ctx.db.ephemeral.cfg.rules.cargo.check(&ucid) {
                 cargo::add_cargo_menu_for_group(&cfg, &mc, &si.side, si.miz_gid)?
@@ -17,16 +17,18 @@ for more details.
 //! Lets not bicker and argue about oo killed oo
 use crate::db::{Db, group::DeployKind};
 use anyhow::Result;
-use bfprotocols::shots::{Dead, Shot, Who};
+use bfprotocols::shots::{Dead, Engagement, Shot, Who};
 use chrono::{Duration, prelude::*};
 use dcso3::{
-    String,
-    event::Shot as ShotEvent,
+    MizLua, String,
+    event::{Shot as ShotEvent, WeaponUse},
+    net::Ucid,
     object::{DcsObject, DcsOid},
     unit::{ClassUnit, Unit},
+    weapon::{ClassWeapon, Weapon},
 };
 use fxhash::FxHashMap;
-use std::collections::hash_map::Entry;
+use std::{collections::hash_map::Entry, mem};
 
 #[derive(Debug, Clone, Default)]
 pub struct ShotDb {
@@ -34,6 +36,12 @@ pub struct ShotDb {
     dead: FxHashMap<DcsOid<ClassUnit>, DateTime<Utc>>,
     recently_dead: FxHashMap<DcsOid<ClassUnit>, DateTime<Utc>>,
     last_gc: DateTime<Utc>,
+    in_flight: FxHashMap<DcsOid<ClassWeapon>, Engagement>,
+    /// untracked hits (e.g. guns) by shooter, weapon and target, and
+    /// when the last one landed
+    bursts: FxHashMap<Burst, (DateTime<Utc>, Engagement)>,
+    engagements: Vec<Engagement>,
+    spotters: FxHashMap<DcsOid<ClassUnit>, Vec<Ucid>>,
 }
 
 macro_rules! ok {
@@ -54,6 +62,11 @@ macro_rules! some {
     };
 }
 
+type Burst = (DcsOid<ClassUnit>, String, Option<DcsOid<ClassUnit>>);
+
+/// untracked hits closer together than this are one burst
+const BURST_GAP: Duration = Duration::seconds(2);
+
 fn who(db: &Db, id: DcsOid<ClassUnit>) -> Option<Who> {
     match db.ephemeral.get_uid_by_object_id(&id) {
         Some(uid) => db.unit(uid).ok().map(|u| Who::AI {
@@ -67,6 +80,7 @@ fn who(db: &Db, id: DcsOid<ClassUnit>) -> Option<Who> {
                 DeployKind::Troop { player, .. } => Some(*player),
                 DeployKind::Crate { .. }
                 | DeployKind::Objective { .. }
+                | DeployKind::Convoy { .. }
                 | DeployKind::ObjectiveDeprecated => None,
             }),
         }),
@@ -84,6 +98,24 @@ fn who(db: &Db, id: DcsOid<ClassUnit>) -> Option<Who> {
     }
 }
 
+/// the slant range and target aspect angle from the shooter to the target
+fn geometry(shooter: &Unit, target: &Unit) -> Result<(f64, f64)> {
+    let from = shooter.get_point()?.0;
+    let target = target.get_position()?;
+    let los = from - target.p.0;
+    let range = los.norm();
+    let off_nose = if range > 0. {
+        (target.x.0.dot(&los) / range).clamp(-1., 1.).acos()
+    } else {
+        0.
+    };
+    Ok((range, 180. - off_nose.to_degrees()))
+}
+
+fn seconds(d: Duration) -> f64 {
+    d.num_milliseconds() as f64 / 1000.
+}
+
 impl ShotDb {
     pub fn dead(&mut self, target: DcsOid<ClassUnit>, time: DateTime<Utc>) {
         if let Entry::Vacant(e) = self.dead.entry(target) {
@@ -91,7 +123,50 @@ impl ShotDb {
         }
     }
 
+    /// the players whose jtac or drone had the target spotted when it died
+    pub fn spotted(&mut self, target: DcsOid<ClassUnit>, by: Vec<Ucid>) {
+        if !by.is_empty() {
+            self.spotters.insert(target, by);
+        }
+    }
+
     pub fn shot(&mut self, db: &Db, now: DateTime<Utc>, e: &ShotEvent) -> Result<()> {
+        self.track_weapon(db, now, e)?;
+        self.target_shot(db, now, e)
+    }
+
+    /// follow a fired weapon until it hits something or is lost
+    fn track_weapon(&mut self, db: &Db, now: DateTime<Utc>, e: &ShotEvent) -> Result<()> {
+        let shooter = some!(who(db, e.initiator.object_id()?));
+        let target = e.weapon.get_target()?.and_then(|t| t.as_unit().ok());
+        let (range, aspect) = match target.as_ref().map(|t| geometry(&e.initiator, t)) {
+            Some(Ok((range, aspect))) => (Some(range), Some(aspect)),
+            Some(Err(_)) | None => (None, None),
+        };
+        let (target, target_typ) = match target {
+            None => (None, None),
+            Some(t) => (who(db, t.object_id()?), Some(t.get_type_name()?)),
+        };
+        self.in_flight.insert(
+            e.weapon.object_id()?,
+            Engagement {
+                shooter,
+                shooter_typ: e.initiator.get_type_name()?,
+                weapon_name: e.weapon_name.clone(),
+                target,
+                target_typ,
+                range,
+                aspect,
+                fired: now,
+                time_of_flight: None,
+                hit: false,
+                hits: 0,
+            },
+        );
+        Ok(())
+    }
+
+    fn target_shot(&mut self, db: &Db, now: DateTime<Utc>, e: &ShotEvent) -> Result<()> {
         if db.ephemeral.cfg.weapon_target_exclusions.contains(&e.weapon_name) {
             return Ok(())
         }
@@ -149,9 +224,95 @@ impl ShotDb {
         Ok(())
     }
 
+    /// a weapon hit something, resolve the engagement it belongs to, or
+    /// add it to a burst if it was never tracked (e.g. guns)
+    pub fn weapon_hit(&mut self, db: &Db, now: DateTime<Utc>, e: &WeaponUse) -> Result<()> {
+        let shooter = ok!(some!(e.initiator.as_ref()).as_unit());
+        let shooter_oid = shooter.object_id()?;
+        let target = e.target.as_ref().and_then(|t| t.as_unit().ok());
+        let (target_oid, target_who, target_typ) = match &target {
+            None => (None, None, None),
+            Some(t) => {
+                let oid = t.object_id()?;
+                (Some(oid.clone()), who(db, oid), Some(t.get_type_name()?))
+            }
+        };
+        let tracked = match e.weapon.as_ref().map(|w| w.as_weapon()) {
+            Some(Ok(w)) => self.in_flight.remove(&w.object_id()?),
+            Some(Err(_)) | None => None,
+        };
+        if let Some(mut en) = tracked {
+            en.time_of_flight = Some(seconds(now - en.fired));
+            en.target = target_who;
+            en.target_typ = target_typ;
+            en.hit = true;
+            en.hits = 1;
+            self.engagements.push(en);
+            return Ok(());
+        }
+        let key = (shooter_oid.clone(), e.weapon_name.clone(), target_oid);
+        if let Some((last, en)) = self.bursts.get_mut(&key)
+            && now - *last <= BURST_GAP
+        {
+            *last = now;
+            en.hits += 1;
+            return Ok(());
+        }
+        if let Some((_, en)) = self.bursts.remove(&key) {
+            self.engagements.push(en);
+        }
+        let (range, aspect) = match target.as_ref().map(|t| geometry(&shooter, t)) {
+            Some(Ok((range, aspect))) => (Some(range), Some(aspect)),
+            Some(Err(_)) | None => (None, None),
+        };
+        let en = Engagement {
+            shooter: some!(who(db, shooter_oid)),
+            shooter_typ: shooter.get_type_name()?,
+            weapon_name: e.weapon_name.clone(),
+            target: target_who,
+            target_typ,
+            range,
+            aspect,
+            fired: now,
+            time_of_flight: None,
+            hit: true,
+            hits: 1,
+        };
+        self.bursts.insert(key, (now, en));
+        Ok(())
+    }
+
+    /// weapons that no longer exist without hitting anything missed,
+    /// and bursts that stopped hitting are over
+    pub fn weapons_in_flight(&mut self, lua: MizLua, now: DateTime<Utc>) {
+        const TEN_MIN: Duration = Duration::minutes(10);
+        let engagements = &mut self.engagements;
+        self.in_flight.retain(|id, en| {
+            let lost = now - en.fired > TEN_MIN || Weapon::get_instance(lua, id).is_err();
+            if lost {
+                en.time_of_flight = Some(seconds(now - en.fired));
+                engagements.push(en.clone());
+            }
+            !lost
+        });
+        self.bursts.retain(|_, (last, en)| {
+            let over = now - *last > BURST_GAP;
+            if over {
+                engagements.push(en.clone());
+            }
+            !over
+        });
+    }
+
+    /// engagements that were resolved since the last call
+    pub fn take_engagements(&mut self) -> Vec<Engagement> {
+        mem::take(&mut self.engagements)
+    }
+
     pub fn bring_out_your_dead(&mut self, now: DateTime<Utc>) -> Vec<Dead> {
         let mut dead = Vec::with_capacity(self.dead.len());
         for (target, time) in self.dead.drain() {
+            let spotters = self.spotters.remove(&target).unwrap_or_default();
             if let Some(shots) = self.by_target.remove(&target) {
                 if shots.len() > 0 {
                     let victim = shots[0].target.clone();
@@ -159,6 +320,8 @@ impl ShotDb {
                         victim,
                         time,
                         shots,
+                        spotters,
+                        credit: vec![],
                     });
                 }
             }
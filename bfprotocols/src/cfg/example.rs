@@ -180,6 +180,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Radar SAMs".into(), "SA 11 Buk".into()],
@@ -229,6 +230,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Radar SAMs".into(), "SA15 Tor".into()],
@@ -253,6 +255,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Radar SAMs".into(), "SA8 Osa".into()],
@@ -277,6 +280,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["AAA".into(), "ZU23 Emplacement".into()],
@@ -301,6 +305,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["AAA".into(), "Shilka".into()],
@@ -325,6 +330,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["AAA".into(), "Tunguska".into()],
@@ -349,6 +355,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["IR SAMs".into(), "SA13 Strela".into()],
@@ -373,6 +380,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Ground Units".into(), "SPH 2S19 Msta 152MM".into()],
@@ -397,6 +405,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Ground Units".into(), "T72".into()],
@@ -424,6 +433,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             }),
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Ground Units".into(), "BMP3".into()],
@@ -451,6 +461,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             }),
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Ground Units".into(), "Ammo Truck".into()],
@@ -475,6 +486,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["EWRs".into(), "1L13".into()],
@@ -499,6 +511,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["FARP".into()],
@@ -532,6 +545,7 @@ fn default_red_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
     ]
 }
@@ -561,6 +575,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Radar SAMs".into(), "Hawk System".into()],
@@ -618,6 +633,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["IR SAMs".into(), "Avenger".into()],
@@ -642,6 +658,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["IR SAMs".into(), "Linebacker".into()],
@@ -666,6 +683,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["AAA".into(), "Flakpanzergepard".into()],
@@ -690,6 +708,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["AAA".into(), "Vulkan".into()],
@@ -714,6 +733,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Ground Units".into(), "Firtina 155MM".into()],
@@ -738,6 +758,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Ground Units".into(), "M2A2 Bradley".into()],
@@ -765,6 +786,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             }),
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Ground Units".into(), "2A6M Leopard".into()],
@@ -792,6 +814,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             }),
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["Ground Units".into(), "Ammo Truck".into()],
@@ -816,6 +839,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["EWRs".into(), "AN/FPS-117".into()],
@@ -840,6 +864,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
         Deployable {
             path: vec!["FARP".into()],
@@ -873,6 +898,7 @@ fn default_blue_deployables() -> Vec<Deployable> {
             jtac: None,
             deprecated_logistics: None,
             deprecated_template: None,
+            recipe: Recipe::default(),
         },
     ]
 }
@@ -1871,7 +1897,9 @@ impl Default for Cfg {
                 "f279deb7a6b62c96a78eca3ddb2bd8d0".parse().unwrap(),
                 "REAPER 32 | EvilKipper".into(),
             )]),
+            trusted_lua_api: false,
             banned: FxHashMap::default(),
+            griefing: None,
             max_msgs_per_second: 3,
             repair_time: 1800,
             repair_crate: default_repair_crate(),
@@ -1898,7 +1926,12 @@ impl Default for Cfg {
                 weapon_cost: FxHashMap::default(),
                 strict: false,
                 periodic_point_gain: (0, 0),
+                assists: None,
             }),
+            tickets: None,
+            offensives: None,
+            cratering: None,
+            structures: FxHashMap::default(),
             warehouse: Some(WarehouseConfig {
                 hub_max: 25,
                 airbase_max: 5,
@@ -1911,6 +1944,8 @@ impl Default for Cfg {
                     (Side::Red, "RINVENTORY".into()),
                 ]),
                 exempt_airframes: FxHashSet::from_iter(["Su-30SM".into()]),
+                convoy: None,
+                industry: None,
             }),
             weapon_target_exclusions: FxHashSet::default(),
             logistics_exclusion: 10000,
@@ -1945,6 +1980,10 @@ impl Default for Cfg {
                 (Side::Red, "RCRATE".into()),
                 (Side::Blue, "BCRATE".into()),
             ]),
+            sling_load: false,
+            cargo_loss: None,
+            csar: None,
+            upkeep: None,
             deployables: FxHashMap::from_iter([
                 (Side::Red, default_red_deployables()),
                 (Side::Blue, default_blue_deployables()),
@@ -14,10 +14,11 @@ FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero Public License
 for more details.
 */
 
+use crate::db::objective::ObjectiveKind;
 use anyhow::{anyhow, bail, Context, Result};
 use chrono::prelude::*;
 use compact_str::format_compact;
-use dcso3::{coalition::Side, controller::AltType, net::Ucid, String};
+use dcso3::{coalition::Side, controller::AltType, net::Ucid, warehouse::LiquidType, String};
 use enumflags2::{bitflags, BitFlags};
 use fxhash::{FxBuildHasher, FxHashMap, FxHashSet};
 use indexmap::IndexMap;
@@ -386,6 +387,29 @@ fn default_deployable_kind() -> DeployableKind {
     }
 }
 
+/// Alternate ways to build a deployable
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+    /// Other crates, by name, that may stand in for a required crate,
+    /// keyed by the name of the required crate
+    #[serde(default)]
+    pub substitutes: FxHashMap<String, Vec<String>>,
+    /// If true the crates don't have to be unpacked all at once. Each
+    /// unpack delivers the crates to a partially built site that persists
+    /// until every required crate has been delivered
+    #[serde(default)]
+    pub staged: bool,
+    /// The number of minutes a partially built site waits for another
+    /// delivery before it is abandoned. Default 120
+    #[serde(default)]
+    pub site_timeout: Option<u32>,
+    /// The name of the deployable that this one upgrades. It can only be
+    /// unpacked within crate_spread of one, which it replaces
+    #[serde(default)]
+    pub upgrade_from: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Deployable {
@@ -415,6 +439,9 @@ pub struct Deployable {
     pub ewr: Option<DeployableEwr>,
     /// Is this unit a jtac
     pub jtac: Option<DeployableJtac>,
+    /// Substitute crates, staged builds, and upgrades
+    #[serde(default)]
+    pub recipe: Recipe,
     #[serde(default)]
     #[serde(rename = "template")]
     pub deprecated_template: Option<String>,
@@ -484,6 +511,121 @@ pub struct WarehouseConfig {
     /// warehouse check
     #[serde(default)]
     pub exempt_airframes: FxHashSet<String>,
+    /// Move supplies between land connected objectives by truck convoy
+    /// instead of instantly. The default is to transfer instantly
+    #[serde(default)]
+    pub convoy: Option<ConvoyCfg>,
+    /// Make production depend on the factories, refineries, and ports
+    /// each side owns. The default is to deliver the supply_source
+    /// production no matter what
+    #[serde(default)]
+    pub industry: Option<IndustryCfg>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConvoyRoute {
+    /// The name of an objective at one end of the route
+    pub from: String,
+    /// The name of the objective at the other end of the route
+    pub to: String,
+    /// The names of trigger zones the convoy drives through on the way
+    /// from `from` to `to`. They are driven in reverse in the other
+    /// direction
+    #[serde(default)]
+    pub waypoints: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConvoyCfg {
+    /// The group template for the supply trucks
+    pub template: FxHashMap<Side, String>,
+    /// The group template for the convoy escort. The default is to
+    /// send convoys without an escort
+    #[serde(default)]
+    pub escort: FxHashMap<Side, String>,
+    /// The land routes. Transfers between objectives that are not
+    /// connected by a route still happen instantly
+    pub routes: Vec<ConvoyRoute>,
+    /// How many minutes a convoy has to arrive before it is considered
+    /// stuck, in which case it is removed and its cargo returned
+    pub timeout: u32,
+    /// Drive on roads instead of cross country
+    #[serde(default)]
+    pub use_roads: bool,
+}
+
+impl ConvoyCfg {
+    /// the waypoints from source to target if they are land connected
+    pub fn route(&self, source: &str, target: &str) -> Option<Vec<&str>> {
+        self.routes.iter().find_map(|r| {
+            if r.from.as_str() == source && r.to.as_str() == target {
+                Some(r.waypoints.iter().map(|w| w.as_str()).collect())
+            } else if r.from.as_str() == target && r.to.as_str() == source {
+                Some(r.waypoints.iter().rev().map(|w| w.as_str()).collect())
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndustryOutput {
+    /// Equipment produced every delivery, by warehouse item name
+    #[serde(default)]
+    pub equipment: FxHashMap<String, u32>,
+    /// Fuel produced every delivery
+    #[serde(default)]
+    pub liquids: FxHashMap<LiquidType, u32>,
+}
+
+fn default_base_production() -> u8 {
+    100
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndustryCfg {
+    /// The output of a factory
+    #[serde(default)]
+    pub factory: IndustryOutput,
+    /// The output of a refinery
+    #[serde(default)]
+    pub refinery: IndustryOutput,
+    /// The output of a port
+    #[serde(default)]
+    pub port: IndustryOutput,
+    /// Outputs of specific industry objectives by name, replacing the
+    /// output of their kind
+    #[serde(default)]
+    pub objectives: FxHashMap<String, IndustryOutput>,
+    /// The percentage of the supply_source production a side receives
+    /// regardless of the industry it owns. Items must be present in the
+    /// supply_source warehouse to be produced at all, as that is what
+    /// sets warehouse capacity
+    #[serde(default = "default_base_production")]
+    pub base_production: u8,
+}
+
+impl IndustryCfg {
+    /// the full output of an undamaged industry objective
+    pub fn output(&self, kind: &ObjectiveKind, name: &str) -> Option<&IndustryOutput> {
+        if !kind.is_industry() {
+            return None;
+        }
+        self.objectives.get(name).or(match kind {
+            ObjectiveKind::Factory => Some(&self.factory),
+            ObjectiveKind::Refinery => Some(&self.refinery),
+            ObjectiveKind::Port => Some(&self.port),
+            ObjectiveKind::Airbase
+            | ObjectiveKind::Fob
+            | ObjectiveKind::Logistics
+            | ObjectiveKind::Farp { .. } => None,
+        })
+    }
 }
 
 impl WarehouseConfig {
@@ -548,6 +690,237 @@ pub struct PointsCfg {
     /// interval must be positive. The default is (0, 0)
     #[serde(default)]
     pub periodic_point_gain: (i32, u32),
+    /// If specified kill points are split between the killer and the
+    /// players who helped. Otherwise everyone who hit the target
+    /// shares the points equally.
+    #[serde(default)]
+    pub assists: Option<AssistCfg>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssistCfg {
+    /// Only shots this close to the kill earn a share (Seconds)
+    pub window: u32,
+    /// Percent of the kill points that go to the last player to hit
+    /// the target
+    pub killer: u8,
+    /// Percent shared by the other players who hit the target, in
+    /// proportion to how many times they hit it
+    pub damage: u8,
+    /// Percent shared by the players who shot at the target but
+    /// didn't hit it
+    pub support: u8,
+    /// Percent shared by the players whose jtac or drone had the
+    /// target spotted when it died
+    pub spotter: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GriefKind {
+    /// killed a friendly player
+    TeamKill,
+    /// hit a friendly player or unit
+    FriendlyFire,
+    /// destroyed a friendly ai unit, deployable, or crate
+    FriendlyAsset,
+}
+
+impl fmt::Display for GriefKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::TeamKill => "team kill",
+            Self::FriendlyFire => "friendly fire",
+            Self::FriendlyAsset => "destroyed friendly asset",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GriefPenalty {
+    /// Warn the player that further offenses will be penalized
+    Warn,
+    /// Take one life of the type the player is flying
+    LoseLife,
+    /// Force the player to spectators, and keep them out of slots
+    /// for this long (Minutes)
+    BlockSlot(u32),
+    /// Ban the player for this long, or forever if None (Hours)
+    Ban(Option<u32>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GriefingCfg {
+    /// How long an offense counts against a player (Hours)
+    pub window: u32,
+    /// How much each kind of offense adds to a player's score. Kinds
+    /// that aren't listed are tracked but don't add to the score.
+    pub weight: FxHashMap<GriefKind, u32>,
+    /// A list of (score, penalty). Each penalty is applied when a
+    /// player's score reaches it.
+    pub penalties: Vec<(u32, GriefPenalty)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TicketCfg {
+    /// How many tickets each side starts the round with
+    pub initial: u32,
+    /// Tickets can't be refilled past this. The default is initial
+    #[serde(default)]
+    pub max: Option<u32>,
+    /// Tickets lost when a player dies
+    pub player_death: u32,
+    /// Tickets lost when a deployed group or troop is destroyed
+    pub deployable_loss: u32,
+    /// Tickets lost when an objective is captured by the enemy
+    pub objective_loss: u32,
+    /// Tickets gained for capturing an objective
+    pub capture: u32,
+    /// Tickets gained each time production is delivered to a side's
+    /// logistics hubs
+    pub delivery: u32,
+}
+
+impl TicketCfg {
+    pub fn max(&self) -> u32 {
+        self.max.unwrap_or(self.initial)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OffensiveCfg {
+    /// How often, in seconds, each side's commander considers launching
+    /// a ground offensive
+    pub interval: u32,
+    /// Only launch offensives while at most this many players are
+    /// connected. The default is to launch them at any population
+    #[serde(default)]
+    pub max_players: Option<u32>,
+    /// The furthest, in meters, an enemy objective can be from the
+    /// friendly objective that attacks it
+    pub range: u32,
+    /// The troop, by name from the side's troops, that offensives are
+    /// made of. It must be able to capture
+    pub troop: FxHashMap<Side, String>,
+    /// The most groups a single offensive will send
+    pub max_groups: u32,
+    /// The most offensive groups a side may have in the field at once
+    pub max_active: u32,
+    /// Each group sent consumes this percentage of the origin
+    /// objective's supply and fuel capacity. An objective will not send
+    /// more groups than it has stock for
+    pub supply_per_group: u8,
+    /// Drive to the staging point on roads instead of cross country
+    #[serde(default)]
+    pub use_roads: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CrateringCfg {
+    /// The weapons, by name, that crater a runway they hit
+    pub weapons: FxHashSet<String>,
+    /// How many craters close an airfield to fixed wing aircraft
+    pub craters: u8,
+    /// How long an airfield stays closed (Minutes)
+    pub closed_for: u32,
+    /// How many repair crates reopen a closed airfield early
+    pub repair_crates: u8,
+    /// How long it takes to fill in one crater on an open runway
+    /// (Minutes). If None craters stay until the airfield is closed
+    #[serde(default)]
+    pub crater_decay: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CsarCfg {
+    /// The template of the downed pilot group for each side
+    pub template: FxHashMap<Side, String>,
+    /// How much weight a rescued pilot adds to the carrier unit
+    pub weight: u32,
+    /// Points awarded for returning a downed pilot to a friendly objective
+    pub rescue_points: u32,
+    /// Points the downed pilot loses if they are captured
+    pub capture_cost: u32,
+    /// Enemy ground units this close to a downed pilot capture them (Meters)
+    pub capture_distance: u32,
+    /// How long a downed pilot waits to be rescued before they are lost (Minutes)
+    pub timeout: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpkeepCfg {
+    /// The crate that resupplies the deployed group it is unpacked near,
+    /// for each side
+    pub resupply_crate: FxHashMap<Side, Crate>,
+    /// How many shots a fully supplied group can fire before it is out
+    /// of ammunition and holds its fire. Each second of gunfire counts
+    /// as a shot. 0 for unlimited
+    pub ammo: u32,
+    /// How long a fully supplied group can run before it is out of fuel
+    /// and goes silent (Minutes). 0 for unlimited
+    pub fuel: u32,
+    /// Groups with low supply that are this close to a friendly objective
+    /// draw supplies from it automatically (Meters). 0 disables the draw
+    pub draw_range: u32,
+    /// Each draw consumes this percentage of the objective's supply and
+    /// fuel capacity
+    pub draw_cost: u8,
+    /// Supply is low below this percentage
+    pub low: u8,
+    /// Deployables, by name, that don't need upkeep
+    #[serde(default)]
+    pub exempt: FxHashSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CargoLossCfg {
+    /// The percent chance that troops on board survive a survivable crash
+    pub troop_survival: u8,
+    /// A crash is survivable if the aircraft was last seen at or below
+    /// this speed (km/h)
+    pub survivable_speed: u32,
+    /// and at or below this height above the ground (Meters)
+    pub survivable_agl: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StructureKind {
+    /// the objective can't refuel aircraft
+    FuelDepot,
+    /// the objective can't rearm aircraft
+    AmmoBunker,
+    /// the owner is not warned of enemies near the objective
+    Radar,
+    /// fixed wing aircraft can't spawn at the objective
+    Runway,
+}
+
+impl StructureKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::FuelDepot => "fuel depot",
+            Self::AmmoBunker => "ammo bunker",
+            Self::Radar => "radar",
+            Self::Runway => "runway",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StructureCfg {
+    /// The name of a static object in the miz, or the id of a map
+    /// scenery object
+    pub name: String,
+    pub kind: StructureKind,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -719,15 +1092,36 @@ impl NameFilter {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum VictoryCondition {
     /// Victory is triggered when the specified percentage of the map
     /// is owned by a given team, or is neutral. Every objective is
     /// considered equally in this calculation. Must be between 0 and 1
     MapOwned { fraction: f64 },
+    /// Victory is triggered when a team owns every one of the named
+    /// objectives
+    KeyObjectives { objectives: Vec<String> },
+    /// Victory is triggered when the enemy team owns no logistics
+    /// hubs
+    LogisticsHubsDestroyed,
+    /// Victory is triggered when the points of all the players on a
+    /// team add up to at least the threshold
+    Points { threshold: i64 },
+    /// Once the round has gone on for the specified number of hours
+    /// the team owning the most objectives wins. If the teams own the
+    /// same number of objectives the round goes on until one of them
+    /// is ahead.
+    TimeLimit { hours: f64 },
+    /// Victory is triggered when the enemy team has no tickets
+    /// left. Requires tickets to be configured.
+    TicketsExhausted,
+    /// Victory is triggered when a team meets all of the conditions
+    All(Vec<VictoryCondition>),
+    /// Victory is triggered when a team meets any of the conditions
+    Any(Vec<VictoryCondition>),
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AutoResetOnVictory {
     /// What victory condition triggers an automatic reset
     pub condition: VictoryCondition,
@@ -769,9 +1163,21 @@ pub struct Cfg {
     /// ucids in this list are able to run admin commands
     #[serde(default)]
     pub admins: FxHashMap<Ucid, String>,
+    /// allow lua api calls that don't name a player as the caller,
+    /// and lua api commands and actions at all. Nil callers run as the
+    /// server, without an admin check, and any script can claim to be
+    /// any player, so only enable this if every script that can reach
+    /// the api is trusted
+    #[serde(default)]
+    pub trusted_lua_api: bool,
     /// ucids in this list are banned
     #[serde(default)]
     pub banned: FxHashMap<Ucid, (Option<DateTime<Utc>>, String)>,
+    /// if specified teamkills, friendly fire, and destroying friendly
+    /// assets are tracked per player, and penalized with escalating
+    /// severity.
+    #[serde(default)]
+    pub griefing: Option<GriefingCfg>,
     /// who can do what
     #[serde(default)]
     pub rules: Rules,
@@ -789,6 +1195,24 @@ pub struct Cfg {
     /// how many points are various actions worth (if any)
     #[serde(default)]
     pub points: Option<PointsCfg>,
+    /// if specified each side has a pool of tickets that drains as it
+    /// takes losses and refills as it captures and delivers supplies
+    #[serde(default)]
+    pub tickets: Option<TicketCfg>,
+    /// if specified an ai commander periodically sends ground troops
+    /// from each side's frontline objectives to capture nearby enemy
+    /// objectives
+    #[serde(default)]
+    pub offensives: Option<OffensiveCfg>,
+    /// if specified weapons that hit an enemy airbase's runway
+    /// crater it, closing the airfield to fixed wing aircraft
+    #[serde(default)]
+    pub cratering: Option<CrateringCfg>,
+    /// critical structures by objective name. Destroying one takes
+    /// away a capability of the objective until it is repaired with a
+    /// repair crate.
+    #[serde(default)]
+    pub structures: FxHashMap<String, Vec<StructureCfg>>,
     /// do not attempt to get the target of any weapon in this list
     #[serde(default)]
     pub weapon_target_exclusions: FxHashSet<String>,
@@ -860,6 +1284,26 @@ pub struct Cfg {
     /// The name of the crate group for each side
     #[serde(default)]
     pub crate_template: FxHashMap<Side, String>,
+    /// if true crates are spawned as native sling load cargo that
+    /// helicopters can hook and carry. The crate template must be a
+    /// cargo static object. The menu crate flow still works.
+    #[serde(default)]
+    pub sling_load: bool,
+    /// if specified troops on board an aircraft that crashes may survive
+    /// and be stranded at the crash site until someone extracts them.
+    /// Otherwise all cargo on board is lost.
+    #[serde(default)]
+    pub cargo_loss: Option<CargoLossCfg>,
+    /// if specified players who eject are spawned on the ground as a
+    /// downed pilot that friendly helicopters can pick up and return to
+    /// a friendly objective, returning the pilot's life
+    #[serde(default)]
+    pub csar: Option<CsarCfg>,
+    /// if specified deployed groups use up ammunition as they fire and
+    /// fuel as time passes, and must be resupplied by crate or from a
+    /// nearby friendly objective
+    #[serde(default)]
+    pub upkeep: Option<UpkeepCfg>,
     /// deployables configuration for each side
     #[serde(default)]
     pub deployables: FxHashMap<Side, Vec<Deployable>>,
@@ -15,27 +15,53 @@ pub enum ObjectiveKind {
         #[serde(default)]
         mobile: bool,
     },
+    Factory,
+    Refinery,
+    Port,
 }
 
 impl ObjectiveKind {
     pub fn is_airbase(&self) -> bool {
         match self {
             Self::Airbase => true,
-            Self::Farp { .. } | Self::Fob | Self::Logistics => false,
+            Self::Farp { .. }
+            | Self::Fob
+            | Self::Logistics
+            | Self::Factory
+            | Self::Refinery
+            | Self::Port => false,
         }
     }
 
     pub fn is_farp(&self) -> bool {
         match self {
             Self::Farp { .. } => true,
-            Self::Airbase | Self::Fob | Self::Logistics => false,
+            Self::Airbase
+            | Self::Fob
+            | Self::Logistics
+            | Self::Factory
+            | Self::Refinery
+            | Self::Port => false,
         }
     }
 
     pub fn is_hub(&self) -> bool {
         match self {
             Self::Logistics => true,
-            Self::Airbase | Self::Farp { .. } | Self::Fob => false,
+            Self::Airbase
+            | Self::Farp { .. }
+            | Self::Fob
+            | Self::Factory
+            | Self::Refinery
+            | Self::Port => false,
+        }
+    }
+
+    /// factories, refineries, and ports produce supplies for their owner
+    pub fn is_industry(&self) -> bool {
+        match self {
+            Self::Factory | Self::Refinery | Self::Port => true,
+            Self::Airbase | Self::Farp { .. } | Self::Fob | Self::Logistics => false,
         }
     }
 
@@ -45,6 +71,9 @@ impl ObjectiveKind {
             Self::Fob => "FOB",
             Self::Farp { .. } => "FARP",
             Self::Logistics => "Logistics Hub",
+            Self::Factory => "Factory",
+            Self::Refinery => "Refinery",
+            Self::Port => "Port",
         }
     }
 }
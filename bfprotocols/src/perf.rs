@@ -24,7 +24,7 @@ use log::info;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct PerfStat {
     pub frame: HistStat,
     pub timed_events: HistStat,
@@ -195,7 +195,7 @@ pub struct PerfInner {
 }
 
 impl PerfInner {
-    fn stat(&self, frame: &HistogramSer) -> PerfStat {
+    pub fn stat(&self, frame: &HistogramSer) -> PerfStat {
         let Self {
             timed_events,
             slow_timed,
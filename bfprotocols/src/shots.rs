@@ -35,6 +35,13 @@ impl Who {
         }
     }
 
+    pub fn unit(&self) -> &DcsOid<ClassUnit> {
+        match self {
+            Self::AI { unit, .. } => unit,
+            Self::Player { unit, .. } => unit,
+        }
+    }
+
     pub fn ucid(&self) -> Option<&Ucid> {
         match self {
             Self::AI { ucid, .. } => ucid.as_ref(),
@@ -69,6 +76,25 @@ pub struct Dead {
     pub victim: Who,
     pub time: DateTime<Utc>,
     pub shots: Vec<Shot>,
+    /// players whose jtac or drone had the victim spotted when it died
+    #[serde(default)]
+    pub spotters: Vec<Ucid>,
+    /// who got a share of the kill points and why
+    #[serde(default)]
+    pub credit: Vec<(Ucid, Credit)>,
+}
+
+/// how a player earned a share of a kill
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Credit {
+    /// landed the killing blow
+    Kill,
+    /// hit the victim before it died
+    Damage,
+    /// shot at the victim but didn't hit it
+    Support,
+    /// spotted the victim with a jtac or drone
+    Spotting,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,3 +107,27 @@ pub struct Shot {
     pub time: DateTime<Utc>,
     pub hit: bool,
 }
+
+/// a weapon that was fired or hit something, and what became of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Engagement {
+    pub shooter: Who,
+    pub shooter_typ: String,
+    pub weapon_name: String,
+    pub target: Option<Who>,
+    pub target_typ: Option<String>,
+    /// slant range from the shooter to the target in meters when the
+    /// weapon was fired
+    pub range: Option<f64>,
+    /// the target aspect angle in degrees when the weapon was fired, 0
+    /// is the target's tail and 180 is its nose
+    pub aspect: Option<f64>,
+    pub fired: DateTime<Utc>,
+    /// seconds from launch until the weapon hit or was lost, None for
+    /// hits with no shot event, e.g. guns
+    pub time_of_flight: Option<f64>,
+    pub hit: bool,
+    /// how many rounds of a gun burst hit, 1 for a weapon that hit
+    #[serde(default)]
+    pub hits: u32,
+}
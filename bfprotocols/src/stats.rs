@@ -5,7 +5,7 @@ use crate::{
         objective::{ObjectiveId, ObjectiveKind},
     },
     perf::PerfInner,
-    shots::Dead,
+    shots::{Dead, Engagement},
 };
 use chrono::prelude::*;
 use dcso3::{
@@ -24,8 +24,70 @@ use std::fmt;
 /// the path in the archive of the stats
 pub const PATH: &str = "/stats";
 
+/// a stat as it is written to the stats log files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatEntry<S = Stat> {
+    /// strictly increasing within a log
+    pub seq: DateTime<Utc>,
+    pub time: DateTime<Utc>,
+    pub kind: S,
+}
+
 pub type MapS<K, V> = immutable_chunkmap::map::Map<K, V, 16>;
 
+/// the victory conditions, see cfg::VictoryCondition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VictoryReason {
+    MapOwned,
+    KeyObjectives,
+    LogisticsHubsDestroyed,
+    Points,
+    TimeLimit,
+    TicketsExhausted,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundEndReason {
+    /// the round ended before reasons were recorded
+    #[default]
+    Unknown,
+    /// an admin reset the round
+    Admin,
+    /// the winner met these victory conditions
+    Victory(SmallVec<[VictoryReason; 2]>),
+}
+
+impl fmt::Display for RoundEndReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown => write!(f, "unknown"),
+            Self::Admin => write!(f, "reset by an admin"),
+            Self::Victory(reasons) => {
+                for (i, r) in reasons.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " and ")?
+                    }
+                    match r {
+                        VictoryReason::MapOwned => write!(f, "owned the map")?,
+                        VictoryReason::KeyObjectives => write!(f, "held the key objectives")?,
+                        VictoryReason::LogisticsHubsDestroyed => {
+                            write!(f, "took the enemy logistics hubs")?
+                        }
+                        VictoryReason::Points => write!(f, "reached the points threshold")?,
+                        VictoryReason::TimeLimit => {
+                            write!(f, "owned the most objectives at the time limit")?
+                        }
+                        VictoryReason::TicketsExhausted => {
+                            write!(f, "exhausted the enemy tickets")?
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum EnId {
     Player(Ucid),
@@ -50,6 +112,53 @@ pub struct Pos {
     pub velocity: Vector3,
 }
 
+/// how a sortie ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortieEnd {
+    /// landed at a friendly objective
+    Landed,
+    Died,
+    /// left the aircraft anywhere else, e.g. ejected or changed slots
+    Left,
+}
+
+/// what a player did during one sortie, from takeoff until it ended
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Debrief {
+    pub vehicle: Vehicle,
+    pub takeoff: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub ended: SortieEnd,
+    pub kills: u32,
+    pub assists: u32,
+    pub points_earned: u32,
+    pub points_spent: u32,
+    /// crates unpacked, or used for repairs and supply transfers
+    pub crates: u32,
+    pub troops: u32,
+    pub captures: u32,
+    pub lives_used: u8,
+}
+
+impl Debrief {
+    pub fn new(vehicle: Vehicle, takeoff: DateTime<Utc>) -> Self {
+        Self {
+            vehicle,
+            takeoff,
+            end: takeoff,
+            ended: SortieEnd::Left,
+            kills: 0,
+            assists: 0,
+            points_earned: 0,
+            points_spent: 0,
+            crates: 0,
+            troops: 0,
+            captures: 0,
+            lives_used: 0,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[bitflags]
 #[repr(u8)]
@@ -65,6 +174,8 @@ pub enum Stat {
     },
     RoundEnd {
         winner: Option<Side>,
+        #[serde(default)]
+        reason: RoundEndReason,
     },
     SessionStart {
         stop: Option<DateTime<Utc>>,
@@ -116,6 +227,32 @@ pub enum Stat {
         troop: String,
         gid: GroupId,
     },
+    /// cargo on board an aircraft that went down was lost. cost is
+    /// the points paid for it, which are not refunded
+    CargoLost {
+        by: Ucid,
+        origin: Option<ObjectiveId>,
+        cargo: String,
+        troops: bool,
+        cost: u32,
+    },
+    /// troops survived the crash of the aircraft carrying them and are
+    /// stranded at the crash site
+    TroopsStranded {
+        by: Ucid,
+        troop: String,
+        gid: GroupId,
+    },
+    /// a downed pilot was returned to a friendly objective
+    Rescue {
+        by: Ucid,
+        pilot: Ucid,
+    },
+    /// a downed pilot was captured by enemy ground units
+    PilotCaptured {
+        pilot: Ucid,
+        by: Side,
+    },
     DeployGroup {
         by: Ucid,
         gid: GroupId,
@@ -140,6 +277,21 @@ pub enum Stat {
     ObjectiveDestroyed {
         id: ObjectiveId,
     },
+    /// a weapon cratered the runway of an airbase
+    RunwayCratered {
+        id: ObjectiveId,
+        by: Option<Ucid>,
+        craters: u8,
+        /// numbers the craters made at this airbase
+        #[serde(default)]
+        seq: u32,
+    },
+    /// an airbase closed because its runway was cratered, or reopened
+    /// if closed_until is None
+    AirfieldClosed {
+        id: ObjectiveId,
+        closed_until: Option<DateTime<Utc>>,
+    },
     Register {
         name: String,
         id: Ucid,
@@ -196,11 +348,27 @@ pub enum Stat {
         lives: MapS<LifeType, (DateTime<Utc>, u8)>,
     },
     Kill(Dead),
+    Engagement(Engagement),
+    Debrief {
+        id: Ucid,
+        debrief: Debrief,
+    },
     Points {
         id: Ucid,
         points: i32,
         reason: String,
     },
+    Tickets {
+        side: Side,
+        tickets: u32,
+        change: i32,
+        reason: String,
+    },
+    Production {
+        side: Side,
+        equipment: Vec<(String, u32)>,
+        liquids: Vec<(LiquidType, u32)>,
+    },
     PointsTransfer {
         from: Ucid,
         to: Ucid,
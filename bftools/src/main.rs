@@ -4,6 +4,7 @@ use serde_derive::Serialize;
 use std::path::PathBuf;
 
 mod mission_edit;
+mod save;
 
 #[derive(Args, Clone, Debug, Serialize)]
 struct MizCmd {
@@ -31,6 +32,9 @@ struct MizCmd {
 #[derive(Subcommand, Clone, Debug, Serialize)]
 enum Tools {
     Miz(MizCmd),
+    /// inspect, edit, upgrade, validate, and compare campaign save files
+    #[clap(subcommand)]
+    Save(save::SaveCmd),
 }
 
 #[derive(Parser)]
@@ -45,6 +49,7 @@ fn main() -> Result<()> {
 
     match bftools_args.tool {
         Tools::Miz(cfg) => mission_edit::run(&cfg)?,
+        Tools::Save(cmd) => save::run(&cmd)?,
     };
     Ok(())
 }
@@ -0,0 +1,455 @@
+use anyhow::{bail, Context, Result};
+use bflib::{
+    group::DeployKind,
+    migrate::{self, SAVE_VERSION},
+    persisted::Persisted,
+};
+use clap::{Args, Subcommand};
+use dcso3::coalition::Side;
+use serde_derive::Serialize;
+use serde_json::Value;
+use std::{fs, path::PathBuf};
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct UpgradeCmd {
+    /// the save file to upgrade
+    save: PathBuf,
+    /// write the upgraded save here instead of replacing the
+    /// original. When the original is replaced it is kept as
+    /// <save>.v<version>
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct ValidateCmd {
+    /// the save files to check
+    #[clap(required = true)]
+    saves: Vec<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct DiffCmd {
+    /// the old save
+    old: PathBuf,
+    /// the new save
+    new: PathBuf,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct ShowCmd {
+    /// the save file
+    save: PathBuf,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct GroupsCmd {
+    /// the save file
+    save: PathBuf,
+    /// only list groups of this side
+    #[clap(long)]
+    side: Option<Side>,
+    /// only list groups of this kind, one of objective, deployed,
+    /// troop, crate, or action
+    #[clap(long)]
+    kind: Option<String>,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct ObjectiveCmd {
+    /// the save file
+    save: PathBuf,
+    /// the objective name or id
+    objective: String,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct PlayerCmd {
+    /// the save file
+    save: PathBuf,
+    /// the player name or ucid
+    player: String,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct EditOutput {
+    /// write the edited save here instead of replacing the
+    /// original. When the original is replaced it is rotated into
+    /// the backups the same way the server does it
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct SetOwnerCmd {
+    #[clap(flatten)]
+    objective: ObjectiveCmd,
+    /// the new owner
+    side: Side,
+    /// bring the new owner's groups back to life
+    #[clap(long)]
+    repair: bool,
+    #[clap(flatten)]
+    output: EditOutput,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct SetInventoryCmd {
+    #[clap(flatten)]
+    objective: ObjectiveCmd,
+    /// the equipment name, or liquid (JetFuel, Avgas, MW50, Diesel)
+    item: String,
+    /// the amount to store
+    stored: u32,
+    /// also set the capacity, required to add a new item
+    #[clap(long)]
+    capacity: Option<u32>,
+    #[clap(flatten)]
+    output: EditOutput,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct SetPointsCmd {
+    #[clap(flatten)]
+    player: PlayerCmd,
+    /// the new points balance
+    #[clap(allow_hyphen_values = true)]
+    points: i32,
+    #[clap(flatten)]
+    output: EditOutput,
+}
+
+#[derive(Args, Clone, Debug, Serialize)]
+pub struct ResetLivesCmd {
+    #[clap(flatten)]
+    player: PlayerCmd,
+    #[clap(flatten)]
+    output: EditOutput,
+}
+
+#[derive(Subcommand, Clone, Debug, Serialize)]
+pub enum SaveCmd {
+    /// migrate a save file to the current format
+    Upgrade(UpgradeCmd),
+    /// check that save files can be loaded and are consistent
+    Validate(ValidateCmd),
+    /// show what changed between two save files
+    Diff(DiffCmd),
+    /// list objectives with their owner, health, logi and supply
+    Objectives(ShowCmd),
+    /// show the inventory of an objective
+    Inventory(ObjectiveCmd),
+    /// list groups by side and kind
+    Groups(GroupsCmd),
+    /// show a player's points and lives
+    Player(PlayerCmd),
+    /// change the owner of an objective
+    ///
+    /// Warehouse capacity and supply lines are left as they were. They
+    /// depend on the mission, so the server sets them up for the new
+    /// owner when it next loads the save.
+    SetOwner(SetOwnerCmd),
+    /// set the stored amount of an item at an objective
+    SetInventory(SetInventoryCmd),
+    /// set a player's points
+    SetPoints(SetPointsCmd),
+    /// give a player all their lives back
+    ResetLives(ResetLivesCmd),
+}
+
+/// read a save and bring it up to the current version in memory
+fn read_upgraded(path: &PathBuf) -> Result<(u32, Value)> {
+    let mut save = migrate::read(path)?;
+    let version = migrate::version(&save)?;
+    for m in migrate::upgrade(&mut save).with_context(|| format!("upgrading {path:?}"))? {
+        println!("{path:?}: migrated from version {}, {}", m.from, m.name)
+    }
+    Ok((version, save))
+}
+
+fn load(path: &PathBuf) -> Result<Persisted> {
+    let (_, save) = read_upgraded(path)?;
+    migrate::decode(save).with_context(|| format!("decoding {path:?}"))
+}
+
+/// write an edited save back, after checking it is still consistent
+fn store(path: &PathBuf, output: &EditOutput, persisted: &Persisted) -> Result<()> {
+    let problems = persisted.check();
+    if !problems.is_empty() {
+        for problem in &problems {
+            println!("{problem}")
+        }
+        bail!("the edited save has {} problems, not writing it", problems.len())
+    }
+    let path = output.output.as_ref().unwrap_or(path);
+    migrate::write(path, &serde_json::to_value(persisted)?)?;
+    println!("wrote {path:?}");
+    Ok(())
+}
+
+fn deploy_kind(kind: &DeployKind) -> &'static str {
+    match kind {
+        DeployKind::Objective { .. } | DeployKind::ObjectiveDeprecated => "objective",
+        DeployKind::Deployed { .. } => "deployed",
+        DeployKind::Troop { .. } => "troop",
+        DeployKind::Crate { .. } => "crate",
+        DeployKind::Action { .. } => "action",
+        DeployKind::Convoy { .. } => "convoy",
+    }
+}
+
+fn objectives(cmd: &ShowCmd) -> Result<()> {
+    let persisted = load(&cmd.save)?;
+    println!(
+        "{:>5} {:<24} {:<10} {:<8} {:>6} {:>4} {:>6} {:>4}",
+        "id", "name", "kind", "owner", "health", "logi", "supply", "fuel"
+    );
+    for (oid, obj) in &persisted.objectives {
+        let kind = format!("{:?}", obj.kind());
+        let kind = kind.split([' ', '{']).next().unwrap_or("");
+        println!(
+            "{:>5} {:<24} {:<10} {:<8} {:>6} {:>4} {:>6} {:>4}",
+            oid.to_string(),
+            obj.name(),
+            kind,
+            obj.owner().to_string(),
+            obj.health(),
+            obj.logi(),
+            obj.supply(),
+            obj.fuel()
+        )
+    }
+    Ok(())
+}
+
+fn inventory(cmd: &ObjectiveCmd) -> Result<()> {
+    let persisted = load(&cmd.save)?;
+    let oid = persisted.find_objective(&cmd.objective)?;
+    let obj = &persisted.objectives[&oid];
+    println!("{} owned by {}", obj.name(), obj.owner());
+    println!("{:<32} {:>8} {:>8}", "item", "stored", "capacity");
+    for (name, inv) in obj.liquids() {
+        println!("{:<32} {:>8} {:>8}", format!("{name:?}"), inv.stored, inv.capacity)
+    }
+    for (name, inv) in obj.equipment() {
+        println!("{:<32} {:>8} {:>8}", name.as_str(), inv.stored, inv.capacity)
+    }
+    Ok(())
+}
+
+fn groups(cmd: &GroupsCmd) -> Result<()> {
+    let persisted = load(&cmd.save)?;
+    for side in Side::ALL {
+        if cmd.side.map(|s| s != side).unwrap_or(false) {
+            continue;
+        }
+        let gids = match persisted.groups_by_side.get(&side) {
+            Some(gids) => gids,
+            None => continue,
+        };
+        println!("{side}");
+        for gid in gids {
+            let group = match persisted.groups.get(gid) {
+                Some(group) => group,
+                None => continue,
+            };
+            let kind = deploy_kind(&group.origin);
+            if cmd.kind.as_ref().map(|k| k != kind).unwrap_or(false) {
+                continue;
+            }
+            let alive = group
+                .units
+                .into_iter()
+                .filter(|uid| persisted.units.get(uid).map(|u| !u.dead).unwrap_or(false))
+                .count();
+            println!(
+                "    {:>6} {:<10} {:<32} {:?} {}/{} alive",
+                gid.to_string(),
+                kind,
+                group.name.as_str(),
+                group.class,
+                alive,
+                group.units.len()
+            )
+        }
+    }
+    Ok(())
+}
+
+fn player(cmd: &PlayerCmd) -> Result<()> {
+    let persisted = load(&cmd.save)?;
+    let ucid = persisted.find_player(&cmd.player)?;
+    let player = &persisted.players[&ucid];
+    println!("{} {ucid}", player.name);
+    println!("side: {}", player.side);
+    println!("points: {}", player.points);
+    if player.lives.len() == 0 {
+        println!("lives: all")
+    } else {
+        println!("lives:");
+        for (typ, (reset, n)) in &player.lives {
+            println!("    {typ}: {n}, resets after {reset}")
+        }
+    }
+    Ok(())
+}
+
+fn set_owner(cmd: &SetOwnerCmd) -> Result<()> {
+    let path = &cmd.objective.save;
+    let mut persisted = load(path)?;
+    let oid = persisted.find_objective(&cmd.objective.objective)?;
+    persisted.set_objective_owner(oid, cmd.side, cmd.repair)?;
+    let obj = &persisted.objectives[&oid];
+    println!(
+        "{} is now owned by {}, health {} logi {}",
+        obj.name(),
+        obj.owner(),
+        obj.health(),
+        obj.logi()
+    );
+    store(path, &cmd.output, &persisted)
+}
+
+fn set_inventory(cmd: &SetInventoryCmd) -> Result<()> {
+    let path = &cmd.objective.save;
+    let mut persisted = load(path)?;
+    let oid = persisted.find_objective(&cmd.objective.objective)?;
+    persisted.set_inventory(oid, &cmd.item, cmd.stored, cmd.capacity)?;
+    store(path, &cmd.output, &persisted)
+}
+
+fn set_points(cmd: &SetPointsCmd) -> Result<()> {
+    let path = &cmd.player.save;
+    let mut persisted = load(path)?;
+    let ucid = persisted.find_player(&cmd.player.player)?;
+    persisted.set_player_points(&ucid, cmd.points)?;
+    store(path, &cmd.output, &persisted)
+}
+
+fn reset_lives(cmd: &ResetLivesCmd) -> Result<()> {
+    let path = &cmd.player.save;
+    let mut persisted = load(path)?;
+    let ucid = persisted.find_player(&cmd.player.player)?;
+    persisted.reset_player_lives(&ucid)?;
+    store(path, &cmd.output, &persisted)
+}
+
+fn upgrade(cmd: &UpgradeCmd) -> Result<()> {
+    let (version, save) = read_upgraded(&cmd.save)?;
+    let persisted = migrate::decode(save.clone())
+        .with_context(|| format!("decoding upgraded {:?}", cmd.save))?;
+    for problem in persisted.check() {
+        println!("{:?}: warning, {problem}", cmd.save)
+    }
+    match &cmd.output {
+        Some(output) => migrate::write(output, &save)?,
+        None if version == SAVE_VERSION => {
+            println!("{:?} is already version {SAVE_VERSION}", cmd.save);
+            return Ok(());
+        }
+        None => {
+            let mut backup = cmd.save.clone().into_os_string();
+            backup.push(format!(".v{version}"));
+            fs::copy(&cmd.save, &backup)
+                .with_context(|| format!("backing up {:?} to {backup:?}", cmd.save))?;
+            migrate::write(&cmd.save, &save)?
+        }
+    }
+    println!(
+        "upgraded {:?} from version {version} to {SAVE_VERSION}",
+        cmd.save
+    );
+    Ok(())
+}
+
+fn validate(cmd: &ValidateCmd) -> Result<()> {
+    let mut failed = 0;
+    for path in &cmd.saves {
+        let res = read_upgraded(path).and_then(|(version, save)| {
+            let persisted = migrate::decode(save)?;
+            Ok((version, persisted.check()))
+        });
+        match res {
+            Err(e) => {
+                failed += 1;
+                println!("{path:?}: invalid, {e:?}")
+            }
+            Ok((version, problems)) if problems.is_empty() => {
+                println!("{path:?}: ok, version {version}")
+            }
+            Ok((version, problems)) => {
+                failed += 1;
+                println!("{path:?}: version {version}, {} problems", problems.len());
+                for problem in problems {
+                    println!("    {problem}")
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {} save files are invalid", cmd.saves.len())
+    }
+    Ok(())
+}
+
+fn diff_value(path: &mut Vec<String>, old: &Value, new: &Value, out: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(o), Value::Object(n)) => {
+            let mut keys: Vec<&String> = o.keys().chain(n.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for k in keys {
+                path.push(k.clone());
+                match (o.get(k), n.get(k)) {
+                    (Some(o), Some(n)) => diff_value(path, o, n, out),
+                    (Some(o), None) => out.push(format!("- {}: {o}", path.join("."))),
+                    (None, Some(n)) => out.push(format!("+ {}: {n}", path.join("."))),
+                    (None, None) => unreachable!(),
+                }
+                path.pop();
+            }
+        }
+        (Value::Array(o), Value::Array(n)) => {
+            for i in 0..o.len().max(n.len()) {
+                path.push(i.to_string());
+                match (o.get(i), n.get(i)) {
+                    (Some(o), Some(n)) => diff_value(path, o, n, out),
+                    (Some(o), None) => out.push(format!("- {}: {o}", path.join("."))),
+                    (None, Some(n)) => out.push(format!("+ {}: {n}", path.join("."))),
+                    (None, None) => unreachable!(),
+                }
+                path.pop();
+            }
+        }
+        (o, n) if o != n => out.push(format!("~ {}: {o} -> {n}", path.join("."))),
+        (_, _) => (),
+    }
+}
+
+fn diff(cmd: &DiffCmd) -> Result<()> {
+    let (_, old) = read_upgraded(&cmd.old)?;
+    let (_, new) = read_upgraded(&cmd.new)?;
+    let mut out = vec![];
+    diff_value(&mut vec![], &old, &new, &mut out);
+    for line in &out {
+        println!("{line}")
+    }
+    println!("{} differences", out.len());
+    Ok(())
+}
+
+pub fn run(cmd: &SaveCmd) -> Result<()> {
+    match cmd {
+        SaveCmd::Upgrade(cmd) => upgrade(cmd),
+        SaveCmd::Validate(cmd) => validate(cmd),
+        SaveCmd::Diff(cmd) => diff(cmd),
+        SaveCmd::Objectives(cmd) => objectives(cmd),
+        SaveCmd::Inventory(cmd) => inventory(cmd),
+        SaveCmd::Groups(cmd) => groups(cmd),
+        SaveCmd::Player(cmd) => player(cmd),
+        SaveCmd::SetOwner(cmd) => set_owner(cmd),
+        SaveCmd::SetInventory(cmd) => set_inventory(cmd),
+        SaveCmd::SetPoints(cmd) => set_points(cmd),
+        SaveCmd::ResetLives(cmd) => reset_lives(cmd),
+    }
+}
@@ -76,6 +76,7 @@ pub struct WeaponUse<'lua> {
     pub time: Time,
     pub initiator: Option<Object<'lua>>,
     pub target: Option<Object<'lua>>,
+    pub weapon: Option<Object<'lua>>,
     pub weapon_name: String,
 }
 
@@ -86,6 +87,7 @@ impl<'lua> FromLua<'lua> for WeaponUse<'lua> {
             time: tbl.raw_get("time")?,
             initiator: tbl.raw_get("initiator")?,
             target: tbl.raw_get("target")?,
+            weapon: tbl.raw_get("weapon")?,
             weapon_name: tbl.raw_get("weapon_name")?,
         })
     }
@@ -236,7 +238,7 @@ pub enum Event<'lua> {
     Kill(WeaponUse<'lua>),
     Score(UnitEvent<'lua>),
     UnitLost(UnitEvent<'lua>),
-    LandingAfterEjection,
+    LandingAfterEjection(UnitEvent<'lua>),
     ParatrooperLanding,
     DiscardChairAfterEjection,
     WeaponAdd(WeaponAdd<'lua>),
@@ -302,7 +304,7 @@ fn translate<'a, 'lua: 'a>(
         28 => Event::Kill(WeaponUse::from_lua(value, lua)?),
         29 => Event::Score(UnitEvent::from_lua(value, lua)?),
         30 => Event::UnitLost(UnitEvent::from_lua(value, lua)?),
-        31 => Event::LandingAfterEjection,
+        31 => Event::LandingAfterEjection(UnitEvent::from_lua(value, lua)?),
         32 => Event::ParatrooperLanding,
         33 => Event::DiscardChairAfterEjection,
         34 => Event::WeaponAdd(WeaponAdd::from_lua(value, lua)?),
@@ -168,7 +168,7 @@ impl<'a> Snap<'a> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct HistStat {
     pub name: &'static str,
     pub unit: &'static str,
@@ -260,43 +260,7 @@ pub struct PerfInner {
     pub timer_get_time0: HistogramSer,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct Perf(pub Arc<PerfInner>);
-
-static mut PERF: Option<Perf> = None;
-
-impl Deref for Perf {
-    type Target = PerfInner;
-
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-
-impl Clone for Perf {
-    fn clone(&self) -> Self {
-        Self(Arc::clone(&self.0))
-    }
-}
-
-impl Perf {
-    pub unsafe fn get_mut() -> &'static mut Perf {
-        #[allow(static_mut_refs)]
-        let perf = PERF.as_mut();
-        match perf {
-            Some(perf) => perf,
-            None => {
-                PERF = Some(Perf::default());
-                #[allow(static_mut_refs)]
-                PERF.as_mut().unwrap()
-            }
-        }
-    }
-
-    pub unsafe fn reset() {
-        PERF = None;
-    }
-
+impl PerfInner {
     pub fn stat(&self) -> PerfStat {
         let PerfInner {
             get_position,
@@ -316,7 +280,7 @@ impl Perf {
             timer_get_time,
             timer_get_abs_time,
             timer_get_time0,
-        } = &*self.0;
+        } = self;
         PerfStat {
             get_position: HistStat::new(&get_position, "Unit.getPosition", false),
             add_group: HistStat::new(&add_group, "Coalition.addGroup", false),
@@ -351,7 +315,49 @@ impl Perf {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Perf(pub Arc<PerfInner>);
+
+static mut PERF: Option<Perf> = None;
+
+impl Deref for Perf {
+    type Target = PerfInner;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Clone for Perf {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl Perf {
+    pub unsafe fn get_mut() -> &'static mut Perf {
+        #[allow(static_mut_refs)]
+        let perf = PERF.as_mut();
+        match perf {
+            Some(perf) => perf,
+            None => {
+                PERF = Some(Perf::default());
+                #[allow(static_mut_refs)]
+                PERF.as_mut().unwrap()
+            }
+        }
+    }
+
+    pub unsafe fn reset() {
+        PERF = None;
+    }
+
+    pub fn stat(&self) -> PerfStat {
+        self.0.stat()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct PerfStat {
     pub get_position: HistStat,
     pub get_point: HistStat,